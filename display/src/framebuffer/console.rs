@@ -0,0 +1,165 @@
+/*
+    Text console on top of framebuffer. Characters are drawn with the built-in font, every glyph line is drawn twice,
+    so a character cell is 8x16 pixels like in VGA text mode. When output reaches the bottom of the screen,
+    the screen is scrolled by one cell line.
+
+    Supported control characters: new line, carriage return, tab, backspace.
+*/
+use framebuffer::{Framebuffer, Rgb};
+use framebuffer::font::{self, GLYPH_WIDTH, GLYPH_HEIGHT};
+use vga::color::ColorVariant;
+use core::fmt;
+
+const VERTICAL_SCALE: usize = 2;
+pub const CELL_WIDTH: usize = GLYPH_WIDTH;
+pub const CELL_HEIGHT: usize = GLYPH_HEIGHT * VERTICAL_SCALE;
+const TAB_WIDTH: usize = 8;
+const BACKSPACE: u8 = 0x08;
+
+pub struct FramebufferConsole {
+    framebuffer: Framebuffer,
+    columns: usize,
+    rows: usize,
+    row: usize,
+    column_position: usize,
+    foreground: Rgb,
+    background: Rgb,
+}
+
+impl FramebufferConsole {
+    /// Creates console that covers the whole framebuffer and clears the screen
+    pub fn new(framebuffer: Framebuffer) -> FramebufferConsole {
+        let columns = framebuffer.width() / CELL_WIDTH;
+        let rows = framebuffer.height() / CELL_HEIGHT;
+
+        assert!(columns > 0 && rows > 0, "Framebuffer {}x{} can't fit a single character", framebuffer.width(), framebuffer.height());
+
+        let mut result = FramebufferConsole {
+            framebuffer,
+            columns,
+            rows,
+            row: 0,
+            column_position: 0,
+            foreground: Rgb::from_color_variant(ColorVariant::Green),
+            background: Rgb::from_color_variant(ColorVariant::Black),
+        };
+
+        result.clear();
+
+        result
+    }
+
+    /// Returns number of character columns and rows
+    pub fn size(&self) -> (usize, usize) {
+        (self.columns, self.rows)
+    }
+
+    /// Returns cursor row and column
+    pub fn cursor_position(&self) -> (usize, usize) {
+        (self.row, self.column_position)
+    }
+
+    pub fn framebuffer(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    /// Sets color of the following output
+    pub fn set_color(&mut self, foreground: ColorVariant, background: ColorVariant) {
+        self.set_rgb_color(Rgb::from_color_variant(foreground), Rgb::from_color_variant(background))
+    }
+
+    pub fn set_rgb_color(&mut self, foreground: Rgb, background: Rgb) {
+        self.foreground = foreground;
+        self.background = background;
+    }
+
+    /// Fills the screen with background color and moves cursor to the top left corner
+    pub fn clear(&mut self) {
+        let background = self.background;
+
+        self.framebuffer.fill(background);
+        self.row = 0;
+        self.column_position = 0;
+    }
+
+    pub fn print_string(&mut self, string: &str) {
+        for character in string.chars() {
+            if character.is_ascii() {
+                self.write_byte(character as u8);
+            } else {
+                // drawn with replacement glyph
+                self.put_character(0xff);
+            }
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.column_position = 0,
+            b'\t' => {
+                let next_stop = (self.column_position / TAB_WIDTH + 1) * TAB_WIDTH;
+
+                while self.column_position < next_stop && self.column_position < self.columns {
+                    self.put_character(b' ');
+                }
+            }
+            BACKSPACE => {
+                if self.column_position > 0 {
+                    self.column_position -= 1;
+
+                    let (row, column) = (self.row, self.column_position);
+                    self.draw_character(row, column, b' ');
+                }
+            }
+            _ => self.put_character(byte),
+        }
+    }
+
+    fn put_character(&mut self, character: u8) {
+        if self.column_position >= self.columns {
+            self.new_line();
+        }
+
+        let (row, column) = (self.row, self.column_position);
+
+        self.draw_character(row, column, character);
+        self.column_position += 1;
+    }
+
+    fn draw_character(&mut self, row: usize, column: usize, character: u8) {
+        let glyph = font::glyph(character);
+        let (foreground, background) = (self.foreground, self.background);
+        let x = column * CELL_WIDTH;
+        let y = row * CELL_HEIGHT;
+
+        for (glyph_line, bits) in glyph.iter().enumerate() {
+            for pixel in 0..GLYPH_WIDTH {
+                let color = if *bits & (1 << pixel) != 0 { foreground } else { background };
+
+                for repeat in 0..VERTICAL_SCALE {
+                    self.framebuffer.put_pixel(x + pixel, y + glyph_line * VERTICAL_SCALE + repeat, color);
+                }
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.column_position = 0;
+
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            let background = self.background;
+
+            self.framebuffer.scroll_up(CELL_HEIGHT, background);
+        }
+    }
+}
+
+impl fmt::Write for FramebufferConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.print_string(s);
+        Ok(())
+    }
+}
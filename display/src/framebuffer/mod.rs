@@ -0,0 +1,218 @@
+/*
+    Linear framebuffer set up by bootloader. Framebuffer is `height` lines of `pitch` bytes,
+    every line starts with `width` pixels of `bytes_per_pixel` bytes, pixels store rgb channels at positions given by PixelFormat.
+    Lines can be longer than `width` pixels, so addresses are always computed with `pitch`.
+*/
+pub mod console;
+pub mod font;
+
+use vga::color::ColorVariant;
+use core::cmp;
+use core::ptr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Rgb {
+    pub const fn new(red: u8, green: u8, blue: u8) -> Rgb {
+        Rgb { red, green, blue }
+    }
+
+    /// Returns color that VGA text mode shows for `color`
+    pub fn from_color_variant(color: ColorVariant) -> Rgb {
+        match color {
+            ColorVariant::Black => Rgb::new(0x00, 0x00, 0x00),
+            ColorVariant::Blue => Rgb::new(0x00, 0x00, 0xaa),
+            ColorVariant::Green => Rgb::new(0x00, 0xaa, 0x00),
+            ColorVariant::Cyan => Rgb::new(0x00, 0xaa, 0xaa),
+            ColorVariant::Red => Rgb::new(0xaa, 0x00, 0x00),
+            ColorVariant::Magenta => Rgb::new(0xaa, 0x00, 0xaa),
+            ColorVariant::Brown => Rgb::new(0xaa, 0x55, 0x00),
+            ColorVariant::LightGray => Rgb::new(0xaa, 0xaa, 0xaa),
+            ColorVariant::DarkGray => Rgb::new(0x55, 0x55, 0x55),
+            ColorVariant::LightBlue => Rgb::new(0x55, 0x55, 0xff),
+            ColorVariant::LightGreen => Rgb::new(0x55, 0xff, 0x55),
+            ColorVariant::LightCyan => Rgb::new(0x55, 0xff, 0xff),
+            ColorVariant::LightRed => Rgb::new(0xff, 0x55, 0x55),
+            ColorVariant::Pink => Rgb::new(0xff, 0x55, 0xff),
+            ColorVariant::Yellow => Rgb::new(0xff, 0xff, 0x55),
+            ColorVariant::White => Rgb::new(0xff, 0xff, 0xff),
+        }
+    }
+}
+
+/// Position and size in bits of every color channel inside of pixel
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PixelFormat {
+    pub red_position: u8,
+    pub red_size: u8,
+    pub green_position: u8,
+    pub green_size: u8,
+    pub blue_position: u8,
+    pub blue_size: u8,
+}
+
+impl PixelFormat {
+    /// 32 bit pixels, blue in the lowest byte
+    pub const fn bgr32() -> PixelFormat {
+        PixelFormat {
+            red_position: 16,
+            red_size: 8,
+            green_position: 8,
+            green_size: 8,
+            blue_position: 0,
+            blue_size: 8,
+        }
+    }
+
+    /// Returns pixel value of `color`, channels are truncated to their size
+    pub fn encode(&self, color: Rgb) -> u32 {
+        PixelFormat::encode_channel(color.red, self.red_position, self.red_size)
+            | PixelFormat::encode_channel(color.green, self.green_position, self.green_size)
+            | PixelFormat::encode_channel(color.blue, self.blue_position, self.blue_size)
+    }
+
+    fn encode_channel(value: u8, position: u8, size: u8) -> u32 {
+        let size = cmp::min(size, 8);
+
+        ((value as u32) >> (8 - size)) << position
+    }
+}
+
+pub struct Framebuffer {
+    address: usize,
+    pitch: usize,
+    width: usize,
+    height: usize,
+    bytes_per_pixel: usize,
+    format: PixelFormat,
+}
+
+impl Framebuffer {
+    /// # Arguments
+    /// * `address` - virtual address of framebuffer memory
+    /// * `pitch` - bytes per line
+    /// * `width` - pixels per line
+    /// * `height` - number of lines
+    /// * `bits_per_pixel` - pixel size, 15, 16, 24 and 32 are supported
+    /// * `format` - position of color channels inside of pixel
+    /// # Why unsafe
+    /// Framebuffer writes to `pitch` * `height` bytes at `address`, they must be mapped and not used by anything else
+    pub unsafe fn new(address: usize, pitch: usize, width: usize, height: usize, bits_per_pixel: u8, format: PixelFormat) -> Framebuffer {
+        let bytes_per_pixel = (bits_per_pixel as usize + 7) / 8;
+
+        assert!(bytes_per_pixel >= 2 && bytes_per_pixel <= 4, "Framebuffer with {} bits per pixel is not supported", bits_per_pixel);
+        assert!(width * bytes_per_pixel <= pitch, "Framebuffer line of {} pixels doesn't fit into pitch {}", width, pitch);
+
+        Framebuffer {
+            address,
+            pitch,
+            width,
+            height,
+            bytes_per_pixel,
+            format,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Sets color of pixel, pixels outside of the screen are ignored
+    pub fn put_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            let value = self.format.encode(color);
+
+            unsafe { self.write_pixel(x, y, value) }
+        }
+    }
+
+    /// Returns raw value of pixel
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        assert!(x < self.width && y < self.height, "Pixel {}x{} is outside of the screen", x, y);
+
+        let address = self.pixel_address(x, y);
+        let mut result = 0;
+
+        for i in 0..self.bytes_per_pixel {
+            result |= (unsafe { ptr::read_volatile((address + i) as *const u8) } as u32) << (i * 8);
+        }
+
+        result
+    }
+
+    /// Fills rectangle with color, the part outside of the screen is ignored
+    /// # Arguments
+    /// * `x` - left column
+    /// * `y` - top line
+    /// * `width` - width in pixels
+    /// * `height` - height in pixels
+    /// * `color` - fill color
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Rgb) {
+        let value = self.format.encode(color);
+        let x_end = cmp::min(x.saturating_add(width), self.width);
+        let y_end = cmp::min(y.saturating_add(height), self.height);
+
+        for line in y..y_end {
+            for column in x..x_end {
+                unsafe { self.write_pixel(column, line, value) }
+            }
+        }
+    }
+
+    /// Clears screen with color
+    pub fn fill(&mut self, color: Rgb) {
+        let (width, height) = (self.width, self.height);
+
+        self.fill_rect(0, 0, width, height, color)
+    }
+
+    /// Moves content of the screen up, freed lines at the bottom are filled with `color`
+    /// # Arguments
+    /// * `lines` - number of pixel lines to scroll by
+    /// * `color` - fill color of freed lines
+    pub fn scroll_up(&mut self, lines: usize, color: Rgb) {
+        let lines = cmp::min(lines, self.height);
+        let moved_lines = self.height - lines;
+
+        unsafe {
+            ptr::copy(
+                (self.address + lines * self.pitch) as *const u8,
+                self.address as *mut u8,
+                moved_lines * self.pitch,
+            );
+        }
+
+        let width = self.width;
+
+        self.fill_rect(0, moved_lines, width, lines, color)
+    }
+
+    fn pixel_address(&self, x: usize, y: usize) -> usize {
+        self.address + y * self.pitch + x * self.bytes_per_pixel
+    }
+
+    unsafe fn write_pixel(&mut self, x: usize, y: usize, value: u32) {
+        let address = self.pixel_address(x, y);
+
+        if self.bytes_per_pixel == 4 {
+            ptr::write_volatile(address as *mut u32, value);
+        } else {
+            for i in 0..self.bytes_per_pixel {
+                ptr::write_volatile((address + i) as *mut u8, (value >> (i * 8)) as u8);
+            }
+        }
+    }
+}
@@ -1,6 +1,9 @@
 #![feature(lang_items)]
 #![feature(asm)]
+#![feature(const_fn)]
 #![no_std]
 
+extern crate hardware;
 
-pub mod vga;
\ No newline at end of file
+pub mod vga;
+pub mod framebuffer;
@@ -2,7 +2,7 @@ use vga::color::Color;
 use vga::color::ColorVariant;
 
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct Character {
     pub character_code: u8,
     pub color: Color,
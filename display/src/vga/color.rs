@@ -1,5 +1,5 @@
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct Color {
     pub value: u8,
 }
@@ -8,10 +8,19 @@ impl Color {
     pub fn new(foreground: ColorVariant, background: ColorVariant) -> Color {
         Color { value: (((background as u8) << 4) | foreground as u8) }
     }
+
+    pub fn foreground(&self) -> ColorVariant {
+        ColorVariant::from_value(self.value & 0x0f)
+    }
+
+    pub fn background(&self) -> ColorVariant {
+        ColorVariant::from_value(self.value >> 4)
+    }
 }
 
 #[allow(dead_code)]
 #[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum ColorVariant {
     Black = 0,
     Blue = 1,
@@ -29,4 +38,33 @@ pub enum ColorVariant {
     Pink = 13,
     Yellow = 14,
     White = 15,
-}
\ No newline at end of file
+}
+
+impl ColorVariant {
+    /// Returns color by its 4 bit VGA value, higher bits are ignored
+    pub fn from_value(value: u8) -> ColorVariant {
+        match value & 0x0f {
+            0 => ColorVariant::Black,
+            1 => ColorVariant::Blue,
+            2 => ColorVariant::Green,
+            3 => ColorVariant::Cyan,
+            4 => ColorVariant::Red,
+            5 => ColorVariant::Magenta,
+            6 => ColorVariant::Brown,
+            7 => ColorVariant::LightGray,
+            8 => ColorVariant::DarkGray,
+            9 => ColorVariant::LightBlue,
+            10 => ColorVariant::LightGreen,
+            11 => ColorVariant::LightCyan,
+            12 => ColorVariant::LightRed,
+            13 => ColorVariant::Pink,
+            14 => ColorVariant::Yellow,
+            _ => ColorVariant::White,
+        }
+    }
+
+    /// Returns bright version of the color, e.g. LightBlue for Blue
+    pub fn bright(&self) -> ColorVariant {
+        ColorVariant::from_value(*self as u8 | 8)
+    }
+}
@@ -1,36 +1,192 @@
+/*
+    VGA text mode console. Output goes to a shadow copy of the screen, which is copied to VGA memory
+    unless the user scrolled back. Lines that leave the top of the screen are kept in scrollback ring.
+
+    Supported control characters: new line, carriage return, tab, backspace.
+    Supported ANSI escape sequences (ESC [ parameters final byte):
+    m - select graphic rendition: 0 reset, 1 bright foreground, 30-37 / 90-97 foreground, 40-47 / 100-107 background,
+        39 / 49 default foreground / background
+    J - clear screen (only parameter 2 is supported)
+    H - move cursor to row;column, 1 based
+    K - clear from cursor to the end of line
+    Characters outside of ASCII are mapped to code page 437 of VGA font when possible.
+*/
 use vga::character::Character;
 use vga::color::Color;
 use vga::color::ColorVariant;
+use hardware::x86_64::port;
+use core::cmp;
 use core::fmt;
 
 const VGA_ADDRESS: usize = 0xb8000;
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
+pub const SCROLLBACK_LINES: usize = 200;
+const TAB_WIDTH: usize = 8;
+
+// CRT controller registers that hold hardware cursor position
+const CRTC_ADDRESS_PORT: u16 = 0x3d4;
+const CRTC_DATA_PORT: u16 = 0x3d5;
+const CURSOR_LOCATION_HIGH: u8 = 0x0e;
+const CURSOR_LOCATION_LOW: u8 = 0x0f;
+
+const ESCAPE: u8 = 0x1b;
+const BACKSPACE: u8 = 0x08;
+const MAX_ESCAPE_PARAMETERS: usize = 8;
+// shown instead of characters that don't exist in code page 437
+const REPLACEMENT_CHARACTER: u8 = 0xfe;
+
+// ANSI color index to VGA color
+const ANSI_COLORS: [ColorVariant; 8] = [
+    ColorVariant::Black,
+    ColorVariant::Red,
+    ColorVariant::Green,
+    ColorVariant::Brown,
+    ColorVariant::Blue,
+    ColorVariant::Magenta,
+    ColorVariant::Cyan,
+    ColorVariant::LightGray,
+];
+
+type Row = [Character; BUFFER_WIDTH];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EscapeState {
+    Normal,
+    Escape,
+    ControlSequence,
+}
 
 pub struct Writer {
+    row: usize,
     column_position: usize,
-    chars: &'static mut [[Character; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    color: Color,
+    default_color: Color,
+    // live screen content
+    screen: [Row; BUFFER_HEIGHT],
+    // ring of lines that left the top of the screen
+    scrollback: [Row; SCROLLBACK_LINES],
+    scrollback_end: usize,
+    scrollback_count: usize,
+    // number of lines the view is scrolled back by, 0 shows live screen
+    view_offset: usize,
+    escape_state: EscapeState,
+    escape_parameters: [u16; MAX_ESCAPE_PARAMETERS],
+    escape_parameters_count: usize,
+    hardware_cursor: bool,
+    chars: &'static mut [Row; BUFFER_HEIGHT],
 }
 
 impl Writer {
+    /// Creates console that writes to VGA memory and clears the screen
     pub fn new() -> Writer {
-        Writer {
+        unsafe { Writer::with_buffer(VGA_ADDRESS, true) }
+    }
+
+    /// Creates console that writes to text buffer at `address` and clears it
+    /// # Arguments
+    /// * `address` - address of BUFFER_HEIGHT * BUFFER_WIDTH characters
+    /// * `hardware_cursor` - update VGA hardware cursor position
+    /// # Why unsafe
+    /// Writes to arbitrary memory
+    pub unsafe fn with_buffer(address: usize, hardware_cursor: bool) -> Writer {
+        let default_color = Color::new(ColorVariant::Green, ColorVariant::Black);
+        let blank = Character::new(b' ', default_color);
+
+        let mut result = Writer {
+            row: 0,
             column_position: 0,
-            chars: unsafe { &mut (*(VGA_ADDRESS as *mut _)) },
+            color: default_color,
+            default_color,
+            screen: [[blank; BUFFER_WIDTH]; BUFFER_HEIGHT],
+            scrollback: [[blank; BUFFER_WIDTH]; SCROLLBACK_LINES],
+            scrollback_end: 0,
+            scrollback_count: 0,
+            view_offset: 0,
+            escape_state: EscapeState::Normal,
+            escape_parameters: [0; MAX_ESCAPE_PARAMETERS],
+            escape_parameters_count: 0,
+            hardware_cursor,
+            chars: &mut (*(address as *mut _)),
+        };
+
+        result.clear();
+
+        result
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Sets color of the following output
+    pub fn set_color(&mut self, foreground: ColorVariant, background: ColorVariant) {
+        self.color = Color::new(foreground, background)
+    }
+
+    pub fn reset_color(&mut self) {
+        self.color = self.default_color
+    }
+
+    /// Returns cursor row and column
+    pub fn cursor_position(&self) -> (usize, usize) {
+        (self.row, self.column_position)
+    }
+
+    /// Returns character that is shown at `row` and `column`
+    pub fn character_at(&self, row: usize, column: usize) -> Character {
+        self.chars[row][column]
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.scrollback_count
+    }
+
+    /// Blanks the screen and moves cursor to the top left corner, scrollback is kept
+    pub fn clear(&mut self) {
+        for row in 0..BUFFER_HEIGHT {
+            self.clear_row(row);
         }
+
+        self.row = 0;
+        self.column_position = 0;
+        self.view_offset = 0;
+
+        self.redraw();
+        self.update_cursor();
+    }
+
+    /// Shows older output
+    /// # Arguments
+    /// * `lines` - number of lines to scroll by, view stops at the oldest line of scrollback
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.view_offset = cmp::min(self.view_offset + lines, self.scrollback_count);
+        self.redraw();
+    }
+
+    /// Shows newer output, view returns to live screen at the end
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.view_offset = self.view_offset.saturating_sub(lines);
+        self.redraw();
+    }
+
+    /// Writes string with the given colors, color of the following output is not changed
+    pub fn write_colored(&mut self, string: &str, foreground: ColorVariant, background: ColorVariant) {
+        let color = self.color;
+
+        self.set_color(foreground, background);
+        self.print_string(string);
+        self.color = color;
     }
 
     pub fn print_char(&mut self, character: Character) -> () {
         if character.is_new_line() {
             self.new_line();
         } else {
-            if self.column_position >= BUFFER_WIDTH {
-                self.new_line();
-            }
-
-            self.chars[BUFFER_HEIGHT - 1][self.column_position] = character;
-            self.column_position += 1;
+            self.put_character(character);
         }
+
+        self.update_cursor();
     }
 
     pub fn println_char(&mut self, character: Character) -> () {
@@ -39,13 +195,16 @@ impl Writer {
     }
 
     pub fn print_string(&mut self, string: &str) -> () {
-        let string_as_chars =
-            string
-                .bytes()
-                .map(|e| Character::new(e, Color::new(ColorVariant::Green, ColorVariant::Black)));
-        for c in string_as_chars {
-            self.print_char(c);
+        for character in string.chars() {
+            if character.is_ascii() {
+                self.write_byte(character as u8);
+            } else {
+                let color = self.color;
+                self.put_character(Character::new(Writer::code_page_437(character), color));
+            }
         }
+
+        self.update_cursor();
     }
 
     pub fn println_string(&mut self, string: &str) -> () {
@@ -53,22 +212,206 @@ impl Writer {
         self.print_string(string);
     }
 
-    fn new_line(&mut self) -> () {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let buf = self.chars[row][col];
-                self.chars[row - 1][col] = buf;
+    fn write_byte(&mut self, byte: u8) {
+        match self.escape_state {
+            EscapeState::Normal => match byte {
+                ESCAPE => self.escape_state = EscapeState::Escape,
+                b'\n' => self.new_line(),
+                b'\r' => self.column_position = 0,
+                b'\t' => {
+                    let next_stop = cmp::min((self.column_position / TAB_WIDTH + 1) * TAB_WIDTH, BUFFER_WIDTH);
+
+                    while self.column_position < next_stop {
+                        self.write_byte(b' ');
+                    }
+                }
+                BACKSPACE => {
+                    if self.column_position > 0 {
+                        self.column_position -= 1;
+
+                        let (row, column, color) = (self.row, self.column_position, self.color);
+                        self.set_cell(row, column, Character::new(b' ', color));
+                    }
+                }
+                0x20..=0x7e => {
+                    let color = self.color;
+                    self.put_character(Character::new(byte, color));
+                }
+                _ => {
+                    let color = self.color;
+                    self.put_character(Character::new(REPLACEMENT_CHARACTER, color));
+                }
+            },
+            EscapeState::Escape => {
+                if byte == b'[' {
+                    self.escape_parameters = [0; MAX_ESCAPE_PARAMETERS];
+                    self.escape_parameters_count = 0;
+                    self.escape_state = EscapeState::ControlSequence;
+                } else {
+                    self.escape_state = EscapeState::Normal;
+                }
             }
+            EscapeState::ControlSequence => match byte {
+                b'0'..=b'9' => {
+                    if self.escape_parameters_count == 0 {
+                        self.escape_parameters_count = 1;
+                    }
+
+                    let last = &mut self.escape_parameters[self.escape_parameters_count - 1];
+                    *last = last.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                }
+                b';' => {
+                    // empty parameters count as zeros
+                    self.escape_parameters_count = cmp::min(cmp::max(self.escape_parameters_count, 1) + 1, MAX_ESCAPE_PARAMETERS);
+                }
+                _ => {
+                    self.escape_state = EscapeState::Normal;
+                    self.execute_control_sequence(byte);
+                }
+            },
+        }
+    }
+
+    fn execute_control_sequence(&mut self, final_byte: u8) {
+        let parameters_count = self.escape_parameters_count;
+        let parameters = self.escape_parameters;
+
+        match final_byte {
+            b'm' => {
+                if parameters_count == 0 {
+                    self.reset_color();
+                }
+
+                for parameter in parameters[..parameters_count].iter() {
+                    self.select_graphic_rendition(*parameter);
+                }
+            }
+            b'J' => {
+                if parameters[0] == 2 {
+                    self.clear();
+                }
+            }
+            b'H' => {
+                // parameters are 1 based, missing ones mean the first row or column
+                self.row = cmp::min(cmp::max(parameters[0] as usize, 1), BUFFER_HEIGHT) - 1;
+                self.column_position = cmp::min(cmp::max(parameters[1] as usize, 1), BUFFER_WIDTH) - 1;
+            }
+            b'K' => {
+                let (row, color) = (self.row, self.color);
+
+                for column in self.column_position..BUFFER_WIDTH {
+                    self.set_cell(row, column, Character::new(b' ', color));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, parameter: u16) {
+        let foreground = self.color.foreground();
+        let background = self.color.background();
+        let default_color = self.default_color;
+
+        match parameter {
+            0 => self.reset_color(),
+            1 => self.set_color(foreground.bright(), background),
+            30..=37 => self.set_color(ANSI_COLORS[(parameter - 30) as usize], background),
+            90..=97 => self.set_color(ANSI_COLORS[(parameter - 90) as usize].bright(), background),
+            39 => self.set_color(default_color.foreground(), background),
+            40..=47 => self.set_color(foreground, ANSI_COLORS[(parameter - 40) as usize]),
+            100..=107 => self.set_color(foreground, ANSI_COLORS[(parameter - 100) as usize].bright()),
+            49 => self.set_color(foreground, default_color.background()),
+            _ => (),
+        }
+    }
+
+    fn put_character(&mut self, character: Character) {
+        if self.column_position >= BUFFER_WIDTH {
+            self.new_line();
+        }
+
+        let (row, column) = (self.row, self.column_position);
+
+        self.set_cell(row, column, character);
+        self.column_position += 1;
+    }
+
+    fn set_cell(&mut self, row: usize, column: usize, character: Character) {
+        // new output returns the view to live screen
+        if self.view_offset > 0 {
+            self.view_offset = 0;
+            self.redraw();
         }
 
+        self.screen[row][column] = character;
+        self.chars[row][column] = character;
+    }
+
+    fn new_line(&mut self) -> () {
         self.column_position = 0;
+
+        if self.row + 1 < BUFFER_HEIGHT {
+            self.row += 1;
+            return;
+        }
+
+        self.scrollback[self.scrollback_end] = self.screen[0];
+        self.scrollback_end = (self.scrollback_end + 1) % SCROLLBACK_LINES;
+        self.scrollback_count = cmp::min(self.scrollback_count + 1, SCROLLBACK_LINES);
+
+        for row in 1..BUFFER_HEIGHT {
+            self.screen[row - 1] = self.screen[row];
+        }
+
         self.clear_row(BUFFER_HEIGHT - 1);
+        self.view_offset = 0;
+        self.redraw();
     }
 
     fn clear_row(&mut self, row: usize) -> () {
-        for col in 0..BUFFER_WIDTH {
-            let blank_char = Character::blank();
-            self.chars[row][col] = blank_char;
+        self.screen[row] = [Character::new(b' ', self.color); BUFFER_WIDTH];
+    }
+
+    // copies visible lines to the text buffer: the last `view_offset` scrollback lines followed by the top of live screen
+    fn redraw(&mut self) {
+        for row in 0..BUFFER_HEIGHT {
+            let line = if row < self.view_offset {
+                let index = (self.scrollback_end + SCROLLBACK_LINES - self.view_offset + row) % SCROLLBACK_LINES;
+                self.scrollback[index]
+            } else {
+                self.screen[row - self.view_offset]
+            };
+
+            self.chars[row] = line;
+        }
+    }
+
+    fn update_cursor(&self) {
+        if !self.hardware_cursor {
+            return;
+        }
+
+        let position = self.row * BUFFER_WIDTH + cmp::min(self.column_position, BUFFER_WIDTH - 1);
+
+        unsafe {
+            port::outb(CRTC_ADDRESS_PORT, CURSOR_LOCATION_HIGH);
+            port::outb(CRTC_DATA_PORT, (position >> 8) as u8);
+            port::outb(CRTC_ADDRESS_PORT, CURSOR_LOCATION_LOW);
+            port::outb(CRTC_DATA_PORT, position as u8);
+        }
+    }
+
+    // code page 437 codes of common non ASCII characters
+    fn code_page_437(character: char) -> u8 {
+        match character {
+            'Ç' => 0x80, 'ü' => 0x81, 'é' => 0x82, 'â' => 0x83, 'ä' => 0x84, 'à' => 0x85,
+            'ç' => 0x87, 'ê' => 0x88, 'è' => 0x8a, 'ö' => 0x94, 'Ä' => 0x8e, 'Ö' => 0x99, 'Ü' => 0x9a,
+            'ß' => 0xe1, 'µ' => 0xe6, '°' => 0xf8, '±' => 0xf1, '·' => 0xfa, '²' => 0xfd,
+            '░' => 0xb0, '▒' => 0xb1, '▓' => 0xb2, '█' => 0xdb, '■' => 0xfe,
+            '│' => 0xb3, '┤' => 0xb4, '┐' => 0xbf, '└' => 0xc0, '┴' => 0xc1, '┬' => 0xc2,
+            '├' => 0xc3, '─' => 0xc4, '┼' => 0xc5, '┘' => 0xd9, '┌' => 0xda,
+            '║' => 0xba, '═' => 0xcd, '╔' => 0xc9, '╗' => 0xbb, '╚' => 0xc8, '╝' => 0xbc,
+            _ => REPLACEMENT_CHARACTER,
         }
     }
 }
@@ -78,4 +421,4 @@ impl fmt::Write for Writer {
         self.print_string(s);
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,81 @@
+/*
+    Block devices are read and written in whole sectors of `SECTOR_SIZE` bytes, addressed by sector number.
+    File systems and other users work with `BlockDevice` trait and don't know which driver is behind it.
+*/
+use core::fmt;
+
+pub const SECTOR_SIZE : usize = 512;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlockError {
+    /// buffer length is not a multiple of `SECTOR_SIZE`
+    UnalignedBuffer(usize),
+    /// request goes past the last sector of the device
+    OutOfRange { sector : u64, count : u64 },
+    /// device doesn't accept writes
+    ReadOnly,
+    /// device failed to complete request
+    Io,
+    /// device doesn't support request
+    Unsupported,
+    /// there is no memory for request
+    NoMemory
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BlockError::UnalignedBuffer(length)      => write!(f, "buffer of {} bytes is not a whole number of sectors", length),
+            BlockError::OutOfRange { sector, count } => write!(f, "{} sectors from {} are out of device", count, sector),
+            BlockError::ReadOnly                     => write!(f, "device is read only"),
+            BlockError::Io                           => write!(f, "I/O error"),
+            BlockError::Unsupported                  => write!(f, "request is not supported"),
+            BlockError::NoMemory                     => write!(f, "out of memory")
+        }
+    }
+}
+
+pub trait BlockDevice {
+
+    /// Returns number of sectors
+    fn sector_count(&self) -> u64;
+
+    fn is_read_only(&self) -> bool;
+
+    /// Reads consecutive sectors
+    /// # Arguments
+    /// * `sector` - number of the first sector
+    /// * `buffer` - receives sector data, its length is a multiple of `SECTOR_SIZE`
+    fn read_sectors(&mut self, sector : u64, buffer : &mut [u8]) -> Result<(), BlockError>;
+
+    /// Writes consecutive sectors
+    /// # Arguments
+    /// * `sector` - number of the first sector
+    /// * `buffer` - sector data, its length is a multiple of `SECTOR_SIZE`
+    fn write_sectors(&mut self, sector : u64, buffer : &[u8]) -> Result<(), BlockError>;
+
+    /// Returns device size in bytes
+    fn size(&self) -> u64 {
+        self.sector_count() * SECTOR_SIZE as u64
+    }
+}
+
+/// Checks that request fits the device
+/// # Arguments
+/// * `sector_count` - number of sectors of the device
+/// * `sector` - number of the first requested sector
+/// * `length` - request length in bytes
+/// # Returns
+/// Number of requested sectors
+pub fn check_request(sector_count : u64, sector : u64, length : usize) -> Result<u64, BlockError> {
+    if length % SECTOR_SIZE != 0 {
+        return Err(BlockError::UnalignedBuffer(length))
+    }
+
+    let count = (length / SECTOR_SIZE) as u64;
+
+    match sector.checked_add(count) {
+        Some(end) if end <= sector_count => Ok(count),
+        _                                => Err(BlockError::OutOfRange { sector, count })
+    }
+}
@@ -0,0 +1,8 @@
+#![no_std]
+
+extern crate hardware;
+extern crate stdx;
+extern crate stdx_memory;
+
+pub mod block;
+pub mod virtio;
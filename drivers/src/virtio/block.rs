@@ -0,0 +1,309 @@
+/*
+    Virtio block device. Every request is a chain of three buffers:
+
+    header - request type (0 - read, 1 - write), reserved 32 bits, number of the first sector (64 bit)
+    data   - sectors, device writes them for reads and reads them for writes
+    status - single byte written by device: 0 - done, 1 - I/O error, 2 - unsupported request
+
+    Device configuration starts with capacity, 64 bit number of 512 byte sectors.
+
+    Driver sends one request at a time through a bounce buffer of `REQUEST_DATA_SIZE` bytes, larger transfers are split.
+    Bounce buffer, header and status share one allocation of physical memory, thus callers can pass any buffers.
+    Driver either polls used ring or halts CPU until the next interrupt and checks used ring again,
+    interrupt handler only has to acknowledge interrupts of the device through its `InterruptStatus`.
+    Used ring is checked with interrupts disabled and they are enabled together with halting,
+    otherwise completion interrupt coming right after the check would leave the CPU halted until some other interrupt.
+    Request that isn't completed in `REQUEST_TIMEOUT_SECONDS` by the monotonic clock fails with I/O error,
+    device still owns its descriptors, so it is marked as failed and later requests fail right away.
+    Timeout works only while the clock runs, e.g. it never fires before the timer is set up.
+*/
+use core::ptr;
+use core::sync::atomic;
+use core::time::Duration;
+use hardware::x86_64::interrupts;
+use stdx::time::MonotonicClock;
+use stdx_memory::MemoryAllocator;
+use block;
+use block::{BlockDevice, BlockError, SECTOR_SIZE};
+use virtio;
+use virtio::{Transport, VirtioError, STATUS_DRIVER_OK, STATUS_FAILED};
+use virtio::pci::MODERN_DEVICE_ID_BASE;
+use virtio::queue::{Buffer, UsedChain, Virtqueue, MAX_QUEUE_SIZE, QUEUE_ALIGN};
+
+pub const DEVICE_TYPE_BLOCK      : u16 = 2;
+pub const LEGACY_DEVICE_ID       : u16 = 0x1001;
+pub const MODERN_DEVICE_ID       : u16 = MODERN_DEVICE_ID_BASE + DEVICE_TYPE_BLOCK;
+
+/// Device is read only
+pub const VIRTIO_BLK_F_RO        : u64 = 1 << 5;
+
+/// Max number of bytes transferred by one request
+pub const REQUEST_DATA_SIZE      : usize = 4096;
+
+/// Time device has to complete a request
+pub const REQUEST_TIMEOUT_SECONDS : u64 = 5;
+
+pub const REQUEST_IN             : u32 = 0;
+pub const REQUEST_OUT            : u32 = 1;
+
+pub const REQUEST_STATUS_OK      : u8 = 0;
+pub const REQUEST_STATUS_IOERR   : u8 = 1;
+pub const REQUEST_STATUS_UNSUPP  : u8 = 2;
+
+pub const CONFIG_CAPACITY        : usize = 0;
+
+const REQUEST_QUEUE              : u16 = 0;
+// one request takes 3 descriptors, so a small queue is enough when device lets driver choose its size
+const PREFERRED_QUEUE_SIZE       : u16 = 16;
+
+// request memory: data first so it is page aligned, then header and status
+const HEADER_OFFSET              : usize = REQUEST_DATA_SIZE;
+const HEADER_SIZE                : usize = 16;
+const STATUS_OFFSET              : usize = HEADER_OFFSET + HEADER_SIZE;
+const REQUEST_MEMORY_SIZE        : usize = STATUS_OFFSET + 1;
+// status device never writes, tells that request wasn't completed
+const STATUS_NOT_SET             : u8 = 0xff;
+
+/// How driver waits for completion of requests
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Completion {
+    /// driver spins on used ring, device is asked not to interrupt
+    Polling,
+    /// driver halts CPU until an interrupt, device interrupts must be acknowledged by interrupt handler.
+    /// Driver polls while interrupts are disabled.
+    Interrupt
+}
+
+pub struct VirtioBlock<T> where T : Transport {
+    transport    : T,
+    queue        : Virtqueue,
+    request      : usize,
+    sector_count : u64,
+    read_only    : bool,
+    completion   : Completion,
+    clock        : &'static MonotonicClock,
+    // device didn't complete a request in time
+    failed       : bool
+}
+
+impl<T> VirtioBlock<T> where T : Transport {
+
+    /// Initializes device, on failure device is marked as failed
+    /// # Arguments
+    /// * `transport` - device registers
+    /// * `allocator` - allocator of 1 to 1 mapped physical memory, queue and request memory come from it
+    /// * `completion` - how driver waits for requests
+    /// * `clock` - clock that measures request timeout
+    pub fn new<A>(mut transport : T, allocator : &mut A, completion : Completion, clock : &'static MonotonicClock) -> Result<Self, VirtioError> where A : MemoryAllocator {
+        let (features, queue, request) = match VirtioBlock::set_up(&mut transport, allocator, completion) {
+            Ok(result) => result,
+            Err(error) => {
+                transport.add_status(STATUS_FAILED);
+                return Err(error)
+            }
+        };
+
+        let sector_count = transport.read_config_u64(CONFIG_CAPACITY);
+
+        transport.add_status(STATUS_DRIVER_OK);
+
+        Ok(VirtioBlock {
+            transport,
+            queue,
+            request,
+            sector_count,
+            read_only : features & VIRTIO_BLK_F_RO != 0,
+            completion,
+            clock,
+            failed    : false
+        })
+    }
+
+    /// Resets device and gives its memory back to the allocator it came from
+    pub fn release<A>(mut self, allocator : &mut A) where A : MemoryAllocator {
+        self.transport.reset();
+
+        allocator.free(self.request);
+        self.queue.release(allocator);
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn completion(&self) -> Completion {
+        self.completion
+    }
+
+    fn set_up<A>(transport : &mut T, allocator : &mut A, completion : Completion) -> Result<(u64, Virtqueue, usize), VirtioError> where A : MemoryAllocator {
+        let features = virtio::negotiate_features(transport, VIRTIO_BLK_F_RO)?;
+        let max_size = transport.max_queue_size(REQUEST_QUEUE);
+        let size     = if transport.can_resize_queues() { max_size.min(PREFERRED_QUEUE_SIZE) } else { max_size };
+
+        if size == 0 {
+            return Err(VirtioError::QueueUnavailable(REQUEST_QUEUE))
+        }
+
+        if !size.is_power_of_two() || size > MAX_QUEUE_SIZE {
+            return Err(VirtioError::InvalidQueueSize(size))
+        }
+
+        let mut queue = Virtqueue::new(REQUEST_QUEUE, size, allocator).ok_or(VirtioError::NoMemory)?;
+
+        let request = match allocator.allocate_aligned(REQUEST_MEMORY_SIZE, QUEUE_ALIGN) {
+            Some(request) => request,
+            None          => {
+                queue.release(allocator);
+                return Err(VirtioError::NoMemory)
+            }
+        };
+
+        queue.set_interrupts_enabled(completion == Completion::Interrupt);
+
+        if let Err(error) = transport.setup_queue(&queue) {
+            // device forgets the queue before its memory is freed
+            transport.reset();
+            allocator.free(request);
+            queue.release(allocator);
+
+            return Err(error)
+        }
+
+        Ok((features, queue, request))
+    }
+
+    /// Sends request and waits for its completion
+    /// # Arguments
+    /// * `request_type` - `REQUEST_IN` or `REQUEST_OUT`
+    /// * `sector` - the first sector
+    /// * `length` - number of bytes in bounce buffer, up to `REQUEST_DATA_SIZE`
+    fn transfer(&mut self, request_type : u32, sector : u64, length : usize) -> Result<(), BlockError> {
+        if self.failed {
+            return Err(BlockError::Io)
+        }
+
+        self.write(HEADER_OFFSET, request_type);
+        self.write(HEADER_OFFSET + 4, 0u32);
+        self.write(HEADER_OFFSET + 8, sector);
+        self.write(STATUS_OFFSET, STATUS_NOT_SET);
+
+        let data = if request_type == REQUEST_IN {
+            Buffer::writable(self.request, length)
+        }
+        else {
+            Buffer::readable(self.request, length)
+        };
+
+        let buffers = [Buffer::readable(self.request + HEADER_OFFSET, HEADER_SIZE), data, Buffer::writable(self.request + STATUS_OFFSET, 1)];
+
+        // there is only one request at a time, so descriptors can run out only if device keeps them
+        let head = self.queue.add_buffers(&buffers).ok_or(BlockError::Io)?;
+
+        self.transport.notify(REQUEST_QUEUE);
+
+        let chain = match self.wait_for_completion() {
+            Some(chain) => chain,
+            None        => {
+                self.failed = true;
+                self.transport.add_status(STATUS_FAILED);
+
+                return Err(BlockError::Io)
+            }
+        };
+
+        debug_assert!(chain.head == head, "Virtio block device completed request {} instead of {}", chain.head, head);
+
+        match self.read::<u8>(STATUS_OFFSET) {
+            REQUEST_STATUS_OK     => Ok(()),
+            REQUEST_STATUS_UNSUPP => Err(BlockError::Unsupported),
+            _                     => Err(BlockError::Io)
+        }
+    }
+
+    // returns None if request wasn't completed in time
+    fn wait_for_completion(&mut self) -> Option<UsedChain> {
+        let halts    = self.completion == Completion::Interrupt && interrupts::are_enabled();
+        let deadline = self.clock.uptime() + Duration::from_secs(REQUEST_TIMEOUT_SECONDS);
+
+        loop {
+            if halts {
+                interrupts::disable_interrupts();
+            }
+
+            if let Some(chain) = self.queue.pop_used() {
+                if halts {
+                    interrupts::enable_interrupts();
+                }
+
+                return Some(chain)
+            }
+
+            if self.clock.uptime() >= deadline {
+                if halts {
+                    interrupts::enable_interrupts();
+                }
+
+                return None
+            }
+
+            if halts {
+                interrupts::enable_interrupts_and_wait();
+            }
+            else {
+                atomic::spin_loop_hint();
+            }
+        }
+    }
+
+    // request memory is shared with the device
+    fn read<V>(&self, offset : usize) -> V {
+        unsafe { ptr::read_volatile((self.request + offset) as *const V) }
+    }
+
+    fn write<V>(&mut self, offset : usize, value : V) {
+        unsafe { ptr::write_volatile((self.request + offset) as *mut V, value) }
+    }
+}
+
+impl<T> BlockDevice for VirtioBlock<T> where T : Transport {
+
+    fn sector_count(&self) -> u64 {
+        self.sector_count
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn read_sectors(&mut self, sector : u64, buffer : &mut [u8]) -> Result<(), BlockError> {
+        block::check_request(self.sector_count, sector, buffer.len())?;
+
+        for (index, chunk) in buffer.chunks_mut(REQUEST_DATA_SIZE).enumerate() {
+            let chunk_sector = sector + (index * REQUEST_DATA_SIZE / SECTOR_SIZE) as u64;
+
+            self.transfer(REQUEST_IN, chunk_sector, chunk.len())?;
+
+            unsafe { ptr::copy_nonoverlapping(self.request as *const u8, chunk.as_mut_ptr(), chunk.len()); }
+        }
+
+        Ok(())
+    }
+
+    fn write_sectors(&mut self, sector : u64, buffer : &[u8]) -> Result<(), BlockError> {
+        if self.read_only {
+            return Err(BlockError::ReadOnly)
+        }
+
+        block::check_request(self.sector_count, sector, buffer.len())?;
+
+        for (index, chunk) in buffer.chunks(REQUEST_DATA_SIZE).enumerate() {
+            let chunk_sector = sector + (index * REQUEST_DATA_SIZE / SECTOR_SIZE) as u64;
+
+            unsafe { ptr::copy_nonoverlapping(chunk.as_ptr(), self.request as *mut u8, chunk.len()); }
+
+            self.transfer(REQUEST_OUT, chunk_sector, chunk.len())?;
+        }
+
+        Ok(())
+    }
+}
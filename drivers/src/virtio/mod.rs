@@ -0,0 +1,154 @@
+/*
+    Virtio devices. Device and driver talk through virtqueues in shared memory and through a few registers,
+    which transport (PCI legacy I/O ports or PCI modern memory mapped structures) provides. Initialization:
+
+    1. reset by writing 0 to status
+    2. set ACKNOWLEDGE, then DRIVER status bits
+    3. read device features, write the ones driver accepts
+    4. set FEATURES_OK and check that device kept it (modern devices only, legacy ones don't have it)
+    5. set up virtqueues and read device configuration
+    6. set DRIVER_OK, the device is live afterwards
+
+    On failure driver sets FAILED status bit.
+*/
+pub mod block;
+pub mod pci;
+pub mod queue;
+
+use core::fmt;
+use self::queue::Virtqueue;
+
+pub const VIRTIO_VENDOR_ID         : u16 = 0x1af4;
+
+pub const STATUS_ACKNOWLEDGE       : u8 = 1;
+pub const STATUS_DRIVER            : u8 = 2;
+pub const STATUS_DRIVER_OK         : u8 = 4;
+pub const STATUS_FEATURES_OK       : u8 = 8;
+pub const STATUS_FAILED            : u8 = 128;
+
+/// Device conforms to virtio 1.0, modern transports must negotiate it
+pub const VIRTIO_F_VERSION_1       : u64 = 1 << 32;
+
+/// Interrupt status bit, device returned buffers to used ring
+pub const ISR_QUEUE                : u8 = 1 << 0;
+/// Interrupt status bit, device configuration changed
+pub const ISR_CONFIGURATION        : u8 = 1 << 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VirtioError {
+    /// device didn't accept negotiated features
+    FeaturesRejected,
+    /// device doesn't have the queue or it is already enabled
+    QueueUnavailable(u16),
+    /// size device requires for the queue can't be used
+    InvalidQueueSize(u16),
+    /// there is no memory for queues or requests
+    NoMemory,
+    /// PCI device doesn't provide transport the driver can use
+    InvalidTransport(&'static str)
+}
+
+impl fmt::Display for VirtioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VirtioError::FeaturesRejected        => write!(f, "features were rejected"),
+            VirtioError::QueueUnavailable(queue) => write!(f, "queue {} is not available", queue),
+            VirtioError::InvalidQueueSize(size)  => write!(f, "queue size {} is not supported", size),
+            VirtioError::NoMemory                => write!(f, "out of memory"),
+            VirtioError::InvalidTransport(error) => write!(f, "invalid transport: {}", error)
+        }
+    }
+}
+
+/// Registers of virtio device
+pub trait Transport {
+
+    /// Returns true for virtio 1.0 transport, `VIRTIO_F_VERSION_1` must be negotiated with it
+    fn is_modern(&self) -> bool;
+
+    fn device_features(&mut self) -> u64;
+
+    fn set_driver_features(&mut self, features : u64);
+
+    fn status(&mut self) -> u8;
+
+    fn set_status(&mut self, status : u8);
+
+    /// Returns max number of descriptors of queue, 0 if there is no such queue
+    fn max_queue_size(&mut self, queue : u16) -> u16;
+
+    /// Returns true if driver can choose size of queues, otherwise `max_queue_size` must be used
+    fn can_resize_queues(&self) -> bool;
+
+    /// Tells device where the queue is and enables it
+    fn setup_queue(&mut self, queue : &Virtqueue) -> Result<(), VirtioError>;
+
+    /// Tells device that queue has new available buffers
+    fn notify(&mut self, queue : u16);
+
+    /// Reads interrupt status, reading acknowledges the interrupt
+    fn read_isr(&mut self) -> u8;
+
+    /// Returns counter that changes whenever device configuration changes, it is always 0 for legacy devices
+    fn config_generation(&mut self) -> u8;
+
+    fn read_config_u8(&mut self, offset : usize) -> u8;
+
+    fn read_config_u32(&mut self, offset : usize) -> u32;
+
+    /// Reads 64 bit field of device configuration, which is accessed by two halves
+    fn read_config_u64(&mut self, offset : usize) -> u64 {
+        loop {
+            let generation = self.config_generation();
+            let value      = self.read_config_u32(offset) as u64 | (self.read_config_u32(offset + 4) as u64) << 32;
+
+            if generation == self.config_generation() {
+                return value
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.set_status(0)
+    }
+
+    fn add_status(&mut self, status : u8) {
+        let current = self.status();
+
+        self.set_status(current | status)
+    }
+}
+
+/// Resets device and negotiates features, steps 1-4 of initialization
+/// # Arguments
+/// * `transport` - device registers
+/// * `supported_features` - features the driver can work with, `VIRTIO_F_VERSION_1` is added for modern transports
+/// # Returns
+/// Features both device and driver support
+pub fn negotiate_features<T>(transport : &mut T, supported_features : u64) -> Result<u64, VirtioError> where T : Transport + ?Sized {
+    transport.reset();
+    transport.add_status(STATUS_ACKNOWLEDGE);
+    transport.add_status(STATUS_DRIVER);
+
+    let supported_features = if transport.is_modern() { supported_features | VIRTIO_F_VERSION_1 } else { supported_features };
+    let device_features    = transport.device_features();
+    let features           = device_features & supported_features;
+
+    if transport.is_modern() && features & VIRTIO_F_VERSION_1 == 0 {
+        transport.add_status(STATUS_FAILED);
+        return Err(VirtioError::FeaturesRejected)
+    }
+
+    transport.set_driver_features(features);
+
+    if transport.is_modern() {
+        transport.add_status(STATUS_FEATURES_OK);
+
+        if transport.status() & STATUS_FEATURES_OK == 0 {
+            transport.add_status(STATUS_FAILED);
+            return Err(VirtioError::FeaturesRejected)
+        }
+    }
+
+    Ok(features)
+}
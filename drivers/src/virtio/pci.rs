@@ -0,0 +1,515 @@
+/*
+    Virtio over PCI. Transitional devices (device ids 0x1000 - 0x103f) have legacy registers in I/O BAR 0
+    and usually modern structures as well, modern devices (0x1040 + device type) have only modern structures.
+
+    Legacy registers, offsets in BAR 0:
+
+    0x00 - device features, 32 bits
+    0x04 - driver features, 32 bits
+    0x08 - physical page number of the selected queue, 0 disables the queue
+    0x0c - size of the selected queue, set by the device
+    0x0e - queue select
+    0x10 - queue notify, written with queue number
+    0x12 - device status
+    0x13 - interrupt status
+    0x14 - device configuration, MSI-X is never enabled so it doesn't move
+
+    Modern structures are described by vendor specific PCI capabilities:
+
+    +0x3  - structure type: 1 - common configuration, 2 - notifications, 3 - interrupt status, 4 - device configuration
+    +0x4  - BAR index
+    +0x8  - offset in BAR
+    +0xc  - length
+    +0x10 - notification offset multiplier, only in notification capability
+
+    Common configuration structure:
+
+    0x00 - device feature select, 0x04 - 32 bits of device features chosen by the select register
+    0x08 - driver feature select, 0x0c - 32 bits of driver features
+    0x12 - number of queues, 0x14 - device status, 0x15 - configuration generation
+    0x16 - queue select, 0x18 - queue size, 0x1c - queue enable, 0x1e - queue notification offset
+    0x20 - descriptor table, 0x28 - available ring, 0x30 - used ring addresses of the selected queue
+
+    Queue is notified by writing its number to notification structure + queue notification offset * multiplier.
+*/
+use core::ptr;
+use hardware::x86_64::pci::{Bar, PciDevice, MAX_BARS};
+use hardware::x86_64::pci::capability::Capability;
+use hardware::x86_64::pci::config::ConfigSpace;
+use hardware::x86_64::port;
+use virtio::{Transport, VirtioError};
+use virtio::queue::{Virtqueue, QUEUE_ALIGN};
+
+/// Modern device id is this base plus device type, transitional devices have lower ids
+pub const MODERN_DEVICE_ID_BASE         : u16 = 0x1040;
+
+const LEGACY_DEVICE_FEATURES            : u16 = 0x00;
+const LEGACY_DRIVER_FEATURES            : u16 = 0x04;
+const LEGACY_QUEUE_ADDRESS              : u16 = 0x08;
+const LEGACY_QUEUE_SIZE                 : u16 = 0x0c;
+const LEGACY_QUEUE_SELECT               : u16 = 0x0e;
+const LEGACY_QUEUE_NOTIFY               : u16 = 0x10;
+const LEGACY_STATUS                     : u16 = 0x12;
+const LEGACY_ISR                        : u16 = 0x13;
+const LEGACY_DEVICE_CONFIG              : u16 = 0x14;
+
+const CAPABILITY_TYPE                   : u16 = 0x3;
+const CAPABILITY_BAR                    : u16 = 0x4;
+const CAPABILITY_OFFSET                 : u16 = 0x8;
+const CAPABILITY_LENGTH                 : u16 = 0xc;
+const CAPABILITY_NOTIFY_MULTIPLIER      : u16 = 0x10;
+
+const STRUCTURE_COMMON                  : u8 = 1;
+const STRUCTURE_NOTIFY                  : u8 = 2;
+const STRUCTURE_ISR                     : u8 = 3;
+const STRUCTURE_DEVICE                  : u8 = 4;
+
+const COMMON_DEVICE_FEATURE_SELECT      : usize = 0x00;
+const COMMON_DEVICE_FEATURE             : usize = 0x04;
+const COMMON_DRIVER_FEATURE_SELECT      : usize = 0x08;
+const COMMON_DRIVER_FEATURE             : usize = 0x0c;
+const COMMON_NUM_QUEUES                 : usize = 0x12;
+const COMMON_DEVICE_STATUS              : usize = 0x14;
+const COMMON_CONFIG_GENERATION          : usize = 0x15;
+const COMMON_QUEUE_SELECT               : usize = 0x16;
+const COMMON_QUEUE_SIZE                 : usize = 0x18;
+const COMMON_QUEUE_ENABLE               : usize = 0x1c;
+const COMMON_QUEUE_NOTIFY_OFFSET        : usize = 0x1e;
+const COMMON_QUEUE_DESCRIPTORS          : usize = 0x20;
+const COMMON_QUEUE_AVAILABLE            : usize = 0x28;
+const COMMON_QUEUE_USED                 : usize = 0x30;
+
+/// Interrupt status register of a device. Reading it acknowledges the interrupt,
+/// it is separate from transport so that interrupt handler doesn't need the whole device.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InterruptStatus {
+    register : Register
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Register {
+    Port(u16),
+    Memory(usize)
+}
+
+impl InterruptStatus {
+
+    /// Reads and acknowledges interrupt status, see `ISR_QUEUE` and `ISR_CONFIGURATION`
+    pub fn read(&self) -> u8 {
+        match self.register {
+            Register::Port(port)      => unsafe { port::inb(port) },
+            Register::Memory(address) => read_volatile(address)
+        }
+    }
+}
+
+/// Legacy transport, registers are I/O ports
+pub struct LegacyTransport {
+    port : u16
+}
+
+impl LegacyTransport {
+
+    /// # Arguments
+    /// * `port` - the first port of BAR 0
+    /// # Why unsafe
+    /// `port` must be legacy registers of virtio device
+    pub unsafe fn new(port : u16) -> Self {
+        LegacyTransport { port }
+    }
+
+    /// Finds legacy registers of device
+    /// # Arguments
+    /// * `bars` - sized BARs of the device
+    /// # Why unsafe
+    /// BARs must belong to transitional virtio device
+    pub unsafe fn from_bars(bars : &[Option<Bar>; MAX_BARS]) -> Result<Self, VirtioError> {
+        match bars[0] {
+            Some(Bar::Io { port, .. }) => Ok(LegacyTransport::new(port)),
+            _                          => Err(VirtioError::InvalidTransport("BAR 0 is not I/O space"))
+        }
+    }
+
+    pub fn interrupt_status(&self) -> InterruptStatus {
+        InterruptStatus { register : Register::Port(self.port + LEGACY_ISR) }
+    }
+
+    fn select_queue(&mut self, queue : u16) {
+        unsafe { port::outw(self.port + LEGACY_QUEUE_SELECT, queue) }
+    }
+}
+
+impl Transport for LegacyTransport {
+
+    fn is_modern(&self) -> bool {
+        false
+    }
+
+    fn device_features(&mut self) -> u64 {
+        unsafe { port::inl(self.port + LEGACY_DEVICE_FEATURES) as u64 }
+    }
+
+    fn set_driver_features(&mut self, features : u64) {
+        unsafe { port::outl(self.port + LEGACY_DRIVER_FEATURES, features as u32) }
+    }
+
+    fn status(&mut self) -> u8 {
+        unsafe { port::inb(self.port + LEGACY_STATUS) }
+    }
+
+    fn set_status(&mut self, status : u8) {
+        unsafe { port::outb(self.port + LEGACY_STATUS, status) }
+    }
+
+    fn max_queue_size(&mut self, queue : u16) -> u16 {
+        self.select_queue(queue);
+
+        unsafe { port::inw(self.port + LEGACY_QUEUE_SIZE) }
+    }
+
+    fn can_resize_queues(&self) -> bool {
+        false
+    }
+
+    fn setup_queue(&mut self, queue : &Virtqueue) -> Result<(), VirtioError> {
+        let size = self.max_queue_size(queue.index());
+
+        if size == 0 || unsafe { port::inl(self.port + LEGACY_QUEUE_ADDRESS) } != 0 {
+            return Err(VirtioError::QueueUnavailable(queue.index()))
+        }
+
+        if queue.size() != size {
+            return Err(VirtioError::InvalidQueueSize(queue.size()))
+        }
+
+        let page_number = queue.descriptors_address() / QUEUE_ALIGN;
+
+        if page_number > u32::max_value() as usize {
+            return Err(VirtioError::InvalidTransport("queue is out of reach of legacy device"))
+        }
+
+        unsafe { port::outl(self.port + LEGACY_QUEUE_ADDRESS, page_number as u32) }
+
+        Ok(())
+    }
+
+    fn notify(&mut self, queue : u16) {
+        unsafe { port::outw(self.port + LEGACY_QUEUE_NOTIFY, queue) }
+    }
+
+    fn read_isr(&mut self) -> u8 {
+        self.interrupt_status().read()
+    }
+
+    fn config_generation(&mut self) -> u8 {
+        0
+    }
+
+    fn read_config_u8(&mut self, offset : usize) -> u8 {
+        unsafe { port::inb(self.port + LEGACY_DEVICE_CONFIG + offset as u16) }
+    }
+
+    fn read_config_u32(&mut self, offset : usize) -> u32 {
+        unsafe { port::inl(self.port + LEGACY_DEVICE_CONFIG + offset as u16) }
+    }
+}
+
+/// Modern transport, registers are memory mapped structures
+pub struct ModernTransport {
+    common            : usize,
+    notify            : usize,
+    notify_multiplier : u32,
+    isr               : usize,
+    device            : usize
+}
+
+impl ModernTransport {
+
+    /// Finds modern structures of device, only structures in memory BARs are used
+    /// # Arguments
+    /// * `device` - virtio device
+    /// * `bars` - sized BARs of the device
+    /// * `config` - configuration space
+    /// * `map` - makes physical memory range given by start address and size accessible at the same virtual address,
+    /// returns false if it failed
+    /// # Why unsafe
+    /// `device` must be virtio device, its BARs are used as they are
+    pub unsafe fn from_device<C, M>(device : &PciDevice, bars : &[Option<Bar>; MAX_BARS], config : &C, mut map : M) -> Result<Self, VirtioError>
+        where C : ConfigSpace + ?Sized, M : FnMut(usize, usize) -> bool {
+        let mut common            = None;
+        let mut notify            = None;
+        let mut isr               = None;
+        let mut device_config     = None;
+        let mut notify_multiplier = 0;
+
+        for capability in device.capabilities(config) {
+            let offset = match capability {
+                Capability::VendorSpecific(offset) => offset,
+                _                                  => continue
+            };
+
+            let structure_type = config.read_u8(device.address, offset + CAPABILITY_TYPE);
+
+            let structure = match structure_type {
+                STRUCTURE_COMMON => &mut common,
+                STRUCTURE_NOTIFY => &mut notify,
+                STRUCTURE_ISR    => &mut isr,
+                STRUCTURE_DEVICE => &mut device_config,
+                _                => continue
+            };
+
+            // the first structure of a type is the preferred one
+            if structure.is_some() {
+                continue
+            }
+
+            let bar = config.read_u8(device.address, offset + CAPABILITY_BAR) as usize;
+
+            let bar_address = match bars.get(bar) {
+                Some(Some(Bar::Memory { address, .. })) => *address,
+                _                                       => continue
+            };
+
+            let address = bar_address + config.read(device.address, offset + CAPABILITY_OFFSET) as usize;
+            let length  = config.read(device.address, offset + CAPABILITY_LENGTH) as usize;
+
+            if !map(address, length) {
+                return Err(VirtioError::InvalidTransport("failed to map device memory"))
+            }
+
+            if structure_type == STRUCTURE_NOTIFY {
+                notify_multiplier = config.read(device.address, offset + CAPABILITY_NOTIFY_MULTIPLIER);
+            }
+
+            *structure = Some(address);
+        }
+
+        Ok(ModernTransport {
+            common  : common.ok_or(VirtioError::InvalidTransport("no common configuration structure"))?,
+            notify  : notify.ok_or(VirtioError::InvalidTransport("no notification structure"))?,
+            notify_multiplier,
+            isr     : isr.ok_or(VirtioError::InvalidTransport("no interrupt status structure"))?,
+            device  : device_config.ok_or(VirtioError::InvalidTransport("no device configuration structure"))?
+        })
+    }
+
+    pub fn interrupt_status(&self) -> InterruptStatus {
+        InterruptStatus { register : Register::Memory(self.isr) }
+    }
+
+    fn read_common<T>(&self, offset : usize) -> T {
+        read_volatile(self.common + offset)
+    }
+
+    fn write_common<T>(&mut self, offset : usize, value : T) {
+        write_volatile(self.common + offset, value)
+    }
+
+    // 64 bit registers are written by two halves, the lower one first
+    fn write_common_u64(&mut self, offset : usize, value : u64) {
+        self.write_common(offset, value as u32);
+        self.write_common(offset + 4, (value >> 32) as u32);
+    }
+}
+
+impl Transport for ModernTransport {
+
+    fn is_modern(&self) -> bool {
+        true
+    }
+
+    fn device_features(&mut self) -> u64 {
+        self.write_common(COMMON_DEVICE_FEATURE_SELECT, 0u32);
+        let low = self.read_common::<u32>(COMMON_DEVICE_FEATURE);
+
+        self.write_common(COMMON_DEVICE_FEATURE_SELECT, 1u32);
+        let high = self.read_common::<u32>(COMMON_DEVICE_FEATURE);
+
+        low as u64 | (high as u64) << 32
+    }
+
+    fn set_driver_features(&mut self, features : u64) {
+        self.write_common(COMMON_DRIVER_FEATURE_SELECT, 0u32);
+        self.write_common(COMMON_DRIVER_FEATURE, features as u32);
+
+        self.write_common(COMMON_DRIVER_FEATURE_SELECT, 1u32);
+        self.write_common(COMMON_DRIVER_FEATURE, (features >> 32) as u32);
+    }
+
+    fn status(&mut self) -> u8 {
+        self.read_common(COMMON_DEVICE_STATUS)
+    }
+
+    fn set_status(&mut self, status : u8) {
+        self.write_common(COMMON_DEVICE_STATUS, status)
+    }
+
+    fn max_queue_size(&mut self, queue : u16) -> u16 {
+        if queue >= self.read_common::<u16>(COMMON_NUM_QUEUES) {
+            return 0
+        }
+
+        self.write_common(COMMON_QUEUE_SELECT, queue);
+        self.read_common(COMMON_QUEUE_SIZE)
+    }
+
+    fn can_resize_queues(&self) -> bool {
+        true
+    }
+
+    fn setup_queue(&mut self, queue : &Virtqueue) -> Result<(), VirtioError> {
+        let max_size = self.max_queue_size(queue.index());
+
+        if max_size == 0 || self.read_common::<u16>(COMMON_QUEUE_ENABLE) != 0 {
+            return Err(VirtioError::QueueUnavailable(queue.index()))
+        }
+
+        if queue.size() > max_size {
+            return Err(VirtioError::InvalidQueueSize(queue.size()))
+        }
+
+        self.write_common(COMMON_QUEUE_SIZE, queue.size());
+        self.write_common_u64(COMMON_QUEUE_DESCRIPTORS, queue.descriptors_address() as u64);
+        self.write_common_u64(COMMON_QUEUE_AVAILABLE, queue.available_ring_address() as u64);
+        self.write_common_u64(COMMON_QUEUE_USED, queue.used_ring_address() as u64);
+        self.write_common(COMMON_QUEUE_ENABLE, 1u16);
+
+        Ok(())
+    }
+
+    fn notify(&mut self, queue : u16) {
+        self.write_common(COMMON_QUEUE_SELECT, queue);
+
+        let notify_offset = self.read_common::<u16>(COMMON_QUEUE_NOTIFY_OFFSET) as usize;
+
+        write_volatile(self.notify + notify_offset * self.notify_multiplier as usize, queue)
+    }
+
+    fn read_isr(&mut self) -> u8 {
+        self.interrupt_status().read()
+    }
+
+    fn config_generation(&mut self) -> u8 {
+        self.read_common(COMMON_CONFIG_GENERATION)
+    }
+
+    fn read_config_u8(&mut self, offset : usize) -> u8 {
+        read_volatile(self.device + offset)
+    }
+
+    fn read_config_u32(&mut self, offset : usize) -> u32 {
+        read_volatile(self.device + offset)
+    }
+}
+
+/// Transport of virtio PCI device, modern one is preferred
+pub enum PciTransport {
+    Legacy(LegacyTransport),
+    Modern(ModernTransport)
+}
+
+impl PciTransport {
+
+    /// Uses modern structures of device if it has them, otherwise legacy registers
+    /// # Arguments
+    /// * `device` - virtio device
+    /// * `bars` - sized BARs of the device
+    /// * `config` - configuration space
+    /// * `map` - makes physical memory range accessible at the same virtual address, see `ModernTransport::from_device`
+    /// # Why unsafe
+    /// `device` must be virtio device
+    pub unsafe fn from_device<C, M>(device : &PciDevice, bars : &[Option<Bar>; MAX_BARS], config : &C, map : M) -> Result<Self, VirtioError>
+        where C : ConfigSpace + ?Sized, M : FnMut(usize, usize) -> bool {
+        ModernTransport::from_device(device, bars, config, map)
+            .map(PciTransport::Modern)
+            .or_else(|error| {
+                // modern only devices have nothing to fall back to
+                if device.device_id >= MODERN_DEVICE_ID_BASE {
+                    return Err(error)
+                }
+
+                LegacyTransport::from_bars(bars).map(PciTransport::Legacy)
+            })
+    }
+
+    pub fn interrupt_status(&self) -> InterruptStatus {
+        match *self {
+            PciTransport::Legacy(ref transport) => transport.interrupt_status(),
+            PciTransport::Modern(ref transport) => transport.interrupt_status()
+        }
+    }
+
+    fn transport(&mut self) -> &mut dyn Transport {
+        match *self {
+            PciTransport::Legacy(ref mut transport) => transport,
+            PciTransport::Modern(ref mut transport) => transport
+        }
+    }
+}
+
+impl Transport for PciTransport {
+
+    fn is_modern(&self) -> bool {
+        match *self {
+            PciTransport::Legacy(_) => false,
+            PciTransport::Modern(_) => true
+        }
+    }
+
+    fn device_features(&mut self) -> u64 {
+        self.transport().device_features()
+    }
+
+    fn set_driver_features(&mut self, features : u64) {
+        self.transport().set_driver_features(features)
+    }
+
+    fn status(&mut self) -> u8 {
+        self.transport().status()
+    }
+
+    fn set_status(&mut self, status : u8) {
+        self.transport().set_status(status)
+    }
+
+    fn max_queue_size(&mut self, queue : u16) -> u16 {
+        self.transport().max_queue_size(queue)
+    }
+
+    fn can_resize_queues(&self) -> bool {
+        self.is_modern()
+    }
+
+    fn setup_queue(&mut self, queue : &Virtqueue) -> Result<(), VirtioError> {
+        self.transport().setup_queue(queue)
+    }
+
+    fn notify(&mut self, queue : u16) {
+        self.transport().notify(queue)
+    }
+
+    fn read_isr(&mut self) -> u8 {
+        self.transport().read_isr()
+    }
+
+    fn config_generation(&mut self) -> u8 {
+        self.transport().config_generation()
+    }
+
+    fn read_config_u8(&mut self, offset : usize) -> u8 {
+        self.transport().read_config_u8(offset)
+    }
+
+    fn read_config_u32(&mut self, offset : usize) -> u32 {
+        self.transport().read_config_u32(offset)
+    }
+}
+
+// device registers are read and written exactly as the code says
+fn read_volatile<T>(address : usize) -> T {
+    unsafe { ptr::read_volatile(address as *const T) }
+}
+
+fn write_volatile<T>(address : usize, value : T) {
+    unsafe { ptr::write_volatile(address as *mut T, value) }
+}
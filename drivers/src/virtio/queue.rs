@@ -0,0 +1,272 @@
+/*
+    Split virtqueue. Driver puts chains of buffer descriptors into available ring, device takes them,
+    processes and returns them through used ring. Legacy memory layout is used, modern devices accept it too:
+
+    descriptor table - 16 bytes per descriptor: buffer address, length, flags, index of the next descriptor in chain
+    available ring   - flags, index, ring of chain heads, used event
+    padding to `QUEUE_ALIGN`
+    used ring        - flags, index, ring of (chain head, length written by device) elements, available event
+
+    Ring indices only grow and wrap at 2^16, position in the ring is index modulo queue size,
+    so the size must be a power of two. Unused descriptors form a free list linked through their `next` fields.
+
+    Queue memory is shared with the device, thus it must be physically contiguous and mapped 1 to 1,
+    as heap memory is, so that virtual addresses of the queue and of buffers are also their physical addresses.
+*/
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
+use stdx::math;
+use stdx_memory::MemoryAllocator;
+
+/// Alignment of the queue and of its used ring
+pub const QUEUE_ALIGN          : usize = 4096;
+pub const MAX_QUEUE_SIZE       : u16 = 32768;
+
+const DESCRIPTOR_SIZE          : usize = 16;
+const USED_ELEMENT_SIZE        : usize = 8;
+// flags and index in front of ring entries
+const RING_HEADER_SIZE         : usize = 4;
+// event field after ring entries
+const RING_FOOTER_SIZE         : usize = 2;
+
+const DESCRIPTOR_NEXT          : u16 = 1 << 0;
+const DESCRIPTOR_WRITE         : u16 = 1 << 1;
+
+const AVAILABLE_NO_INTERRUPT   : u16 = 1 << 0;
+
+/// Buffer given to device
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Buffer {
+    /// physical address
+    pub address         : usize,
+    pub length          : u32,
+    /// device writes to the buffer, otherwise it only reads from it
+    pub device_writable : bool
+}
+
+impl Buffer {
+
+    pub fn readable(address : usize, length : usize) -> Self {
+        Buffer { address, length : length as u32, device_writable : false }
+    }
+
+    pub fn writable(address : usize, length : usize) -> Self {
+        Buffer { address, length : length as u32, device_writable : true }
+    }
+}
+
+/// Descriptor chain returned by device
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UsedChain {
+    /// the first descriptor of the chain, as returned by `Virtqueue::add_buffers`
+    pub head   : u16,
+    /// number of bytes device wrote to the writable buffers
+    pub length : u32
+}
+
+pub struct Virtqueue {
+    index           : u16,
+    size            : u16,
+    address         : usize,
+    free_head       : u16,
+    free_count      : u16,
+    // copies of ring indices, the device only reads the available one and only writes the used one
+    available_index : u16,
+    last_used_index : u16
+}
+
+impl Virtqueue {
+
+    /// Returns size of memory taken by queue with `size` descriptors
+    pub fn memory_size(size : u16) -> usize {
+        Virtqueue::used_ring_offset(size) + math::align_up(RING_HEADER_SIZE + USED_ELEMENT_SIZE * size as usize + RING_FOOTER_SIZE, QUEUE_ALIGN)
+    }
+
+    /// Allocates and clears queue memory
+    /// # Arguments
+    /// * `index` - queue number in the device
+    /// * `size` - number of descriptors, must be a power of two
+    /// * `allocator` - allocator of 1 to 1 mapped physical memory
+    /// # Returns
+    /// `None` if there is no memory
+    pub fn new<A>(index : u16, size : u16, allocator : &mut A) -> Option<Virtqueue> where A : MemoryAllocator {
+        assert!(size.is_power_of_two() && size <= MAX_QUEUE_SIZE, "Virtqueue size {} is not a power of two up to {}", size, MAX_QUEUE_SIZE);
+
+        let memory_size = Virtqueue::memory_size(size);
+        let address     = allocator.allocate_aligned(memory_size, QUEUE_ALIGN)?;
+
+        unsafe { ptr::write_bytes(address as *mut u8, 0, memory_size); }
+
+        let mut queue = Virtqueue {
+            index,
+            size,
+            address,
+            free_head       : 0,
+            free_count      : size,
+            available_index : 0,
+            last_used_index : 0
+        };
+
+        // next of the last descriptor is never followed, free list is limited by `free_count`
+        for descriptor in 0 .. size {
+            queue.set_descriptor_next(descriptor, descriptor.wrapping_add(1));
+        }
+
+        Some(queue)
+    }
+
+    /// Gives queue memory back to the allocator it came from, device must not use the queue anymore
+    pub fn release<A>(self, allocator : &mut A) where A : MemoryAllocator {
+        allocator.free(self.address)
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    pub fn free_descriptors(&self) -> u16 {
+        self.free_count
+    }
+
+    pub fn descriptors_address(&self) -> usize {
+        self.address
+    }
+
+    pub fn available_ring_address(&self) -> usize {
+        self.address + DESCRIPTOR_SIZE * self.size as usize
+    }
+
+    pub fn used_ring_address(&self) -> usize {
+        self.address + Virtqueue::used_ring_offset(self.size)
+    }
+
+    /// Asks device not to interrupt when it returns chains, the device may ignore it
+    pub fn set_interrupts_enabled(&mut self, enabled : bool) {
+        let flags   = if enabled { 0 } else { AVAILABLE_NO_INTERRUPT };
+        let address = self.available_ring_address();
+
+        self.write(address, flags)
+    }
+
+    /// Makes chain of buffers available to device, device must be notified afterwards
+    /// # Arguments
+    /// * `buffers` - buffers in the order device handles them, device readable buffers must go before writable ones
+    /// # Returns
+    /// The first descriptor of the chain, `None` if there are not enough free descriptors
+    pub fn add_buffers(&mut self, buffers : &[Buffer]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.free_count as usize {
+            return None
+        }
+
+        let head           = self.free_head;
+        let mut descriptor = head;
+
+        for (position, buffer) in buffers.iter().enumerate() {
+            let next      = self.descriptor_next(descriptor);
+            let writable  = if buffer.device_writable { DESCRIPTOR_WRITE } else { 0 };
+            let chained   = if position + 1 < buffers.len() { DESCRIPTOR_NEXT } else { 0 };
+            let address   = self.descriptor_address(descriptor);
+
+            self.write(address, buffer.address as u64);
+            self.write(address + 8, buffer.length);
+            self.write(address + 12, writable | chained);
+
+            if position + 1 < buffers.len() {
+                descriptor = next;
+            }
+            else {
+                self.free_head = next;
+            }
+        }
+
+        self.free_count -= buffers.len() as u16;
+
+        let available = self.available_ring_address();
+        let slot      = self.available_index % self.size;
+        self.write(available + RING_HEADER_SIZE + 2 * slot as usize, head);
+
+        // device must see the chain before it sees the new index, and the index before it is notified
+        fence(Ordering::SeqCst);
+        self.available_index = self.available_index.wrapping_add(1);
+
+        let index = self.available_index;
+        self.write(available + 2, index);
+        fence(Ordering::SeqCst);
+
+        Some(head)
+    }
+
+    /// Checks if device returned a chain that wasn't popped yet
+    pub fn has_used(&self) -> bool {
+        self.used_index() != self.last_used_index
+    }
+
+    /// Takes the next chain returned by device and frees its descriptors
+    pub fn pop_used(&mut self) -> Option<UsedChain> {
+        if !self.has_used() {
+            return None
+        }
+
+        // ring element is read only after the index that covers it
+        fence(Ordering::SeqCst);
+
+        let slot    = self.last_used_index % self.size;
+        let element = self.used_ring_address() + RING_HEADER_SIZE + USED_ELEMENT_SIZE * slot as usize;
+        let head    = self.read::<u32>(element) as u16;
+        let length  = self.read::<u32>(element + 4);
+
+        self.last_used_index = self.last_used_index.wrapping_add(1);
+        self.free_chain(head);
+
+        Some(UsedChain { head, length })
+    }
+
+    fn free_chain(&mut self, head : u16) {
+        let mut last  = head;
+        let mut count = 1;
+
+        while self.read::<u16>(self.descriptor_address(last) + 12) & DESCRIPTOR_NEXT != 0 {
+            last   = self.descriptor_next(last);
+            count += 1;
+        }
+
+        self.set_descriptor_next(last, self.free_head);
+        self.free_head   = head;
+        self.free_count += count;
+    }
+
+    fn used_index(&self) -> u16 {
+        self.read(self.used_ring_address() + 2)
+    }
+
+    fn used_ring_offset(size : u16) -> usize {
+        math::align_up(DESCRIPTOR_SIZE * size as usize + RING_HEADER_SIZE + 2 * size as usize + RING_FOOTER_SIZE, QUEUE_ALIGN)
+    }
+
+    fn descriptor_address(&self, descriptor : u16) -> usize {
+        self.address + DESCRIPTOR_SIZE * descriptor as usize
+    }
+
+    fn descriptor_next(&self, descriptor : u16) -> u16 {
+        self.read(self.descriptor_address(descriptor) + 14)
+    }
+
+    fn set_descriptor_next(&mut self, descriptor : u16, next : u16) {
+        let address = self.descriptor_address(descriptor);
+
+        self.write(address + 14, next)
+    }
+
+    // queue memory is shared with the device, so every access is volatile
+    fn read<T>(&self, address : usize) -> T {
+        unsafe { ptr::read_volatile(address as *const T) }
+    }
+
+    fn write<T>(&mut self, address : usize, value : T) {
+        unsafe { ptr::write_volatile(address as *mut T, value) }
+    }
+}
@@ -6,5 +6,6 @@
 #[macro_use]
 extern crate bitflags;
 extern crate pic8259_simple;
+extern crate stdx;
 
 pub mod x86_64;
\ No newline at end of file
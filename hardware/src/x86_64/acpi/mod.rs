@@ -0,0 +1,246 @@
+/*
+    Minimal ACPI table reader. Root system description pointer (RSDP) gives physical address of the root table:
+    RSDT with 32 bit table addresses or, since ACPI 2.0, XSDT with 64 bit ones. Every table starts with 36 byte header:
+
+    0  - signature, 4 ascii characters, e.g. "MCFG"
+    4  - length of the whole table in bytes
+    8  - revision
+    9  - checksum, all bytes of the table sum up to zero
+    10 - OEM id, OEM table id, OEM revision, creator id and revision
+
+    Tables are located in physical memory that is usually not mapped, so lookups take a function that maps memory.
+    Only tables the kernel needs are decoded.
+*/
+use core::slice;
+
+pub const SDT_HEADER_SIZE     : usize = 36;
+
+const RSDP_SIGNATURE          : &[u8] = b"RSD PTR ";
+// ACPI 1.0 part of RSDP covered by the first checksum
+const RSDP_V1_SIZE            : usize = 20;
+const RSDP_V2_SIZE            : usize = 36;
+const RSDP_REVISION_OFFSET    : usize = 15;
+const RSDP_RSDT_OFFSET        : usize = 16;
+const RSDP_LENGTH_OFFSET      : usize = 20;
+const RSDP_XSDT_OFFSET        : usize = 24;
+
+const SDT_LENGTH_OFFSET       : usize = 4;
+
+// MCFG has 8 reserved bytes after the header, then 16 byte entries
+const MCFG_ENTRIES_OFFSET     : usize = SDT_HEADER_SIZE + 8;
+const MCFG_ENTRY_SIZE         : usize = 16;
+
+pub const MCFG_SIGNATURE      : &[u8; 4] = b"MCFG";
+
+/// Table that lists all other tables
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RootTable {
+    /// physical address of RSDT
+    Rsdt(usize),
+    /// physical address of XSDT
+    Xsdt(usize)
+}
+
+impl RootTable {
+
+    /// Parses RSDP, XSDT is preferred if RSDP has it
+    /// # Arguments
+    /// * `rsdp` - RSDP bytes, e.g. copy given by bootloader
+    /// # Returns
+    /// `None` if signature or checksum is wrong
+    pub fn from_rsdp(rsdp : &[u8]) -> Option<RootTable> {
+        if rsdp.len() < RSDP_V1_SIZE || &rsdp[..RSDP_SIGNATURE.len()] != RSDP_SIGNATURE || !is_checksum_valid(&rsdp[..RSDP_V1_SIZE]) {
+            return None
+        }
+
+        if rsdp[RSDP_REVISION_OFFSET] >= 2 && rsdp.len() >= RSDP_V2_SIZE {
+            let length = read_u32(rsdp, RSDP_LENGTH_OFFSET) as usize;
+
+            if length <= rsdp.len() && is_checksum_valid(&rsdp[..length]) {
+                return Some(RootTable::Xsdt(read_u64(rsdp, RSDP_XSDT_OFFSET) as usize))
+            }
+        }
+
+        Some(RootTable::Rsdt(read_u32(rsdp, RSDP_RSDT_OFFSET) as usize))
+    }
+
+    pub fn address(&self) -> usize {
+        match *self {
+            RootTable::Rsdt(address) => address,
+            RootTable::Xsdt(address) => address
+        }
+    }
+
+    fn entry_size(&self) -> usize {
+        match *self {
+            RootTable::Rsdt(_) => 4,
+            RootTable::Xsdt(_) => 8
+        }
+    }
+
+    /// Finds table by signature
+    /// # Arguments
+    /// * `signature` - table signature
+    /// * `map` - makes physical memory range given by start address and size accessible at the same virtual address
+    /// # Why unsafe
+    /// Reads physical memory at addresses given by firmware
+    pub unsafe fn find_table<M>(&self, signature : &[u8; 4], mut map : M) -> Option<Table<'static>> where M : FnMut(usize, usize) {
+        let root        = Table::at_address(self.address(), &mut map)?;
+        let entry_size  = self.entry_size();
+        let entries     = root.data();
+
+        for index in 0 .. entries.len() / entry_size {
+            let address = if entry_size == 4 {
+                read_u32(entries, index * entry_size) as usize
+            }
+            else {
+                read_u64(entries, index * entry_size) as usize
+            };
+
+            if let Some(table) = Table::at_address(address, &mut map) {
+                if table.signature() == signature {
+                    return Some(table)
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// ACPI table with valid checksum
+pub struct Table<'a> {
+    bytes : &'a [u8]
+}
+
+impl<'a> Table<'a> {
+
+    /// # Arguments
+    /// * `bytes` - table bytes, starting with header
+    /// # Returns
+    /// `None` if length or checksum is wrong
+    pub fn new(bytes : &'a [u8]) -> Option<Table<'a>> {
+        if bytes.len() < SDT_HEADER_SIZE {
+            return None
+        }
+
+        let length = read_u32(bytes, SDT_LENGTH_OFFSET) as usize;
+
+        if length < SDT_HEADER_SIZE || length > bytes.len() || !is_checksum_valid(&bytes[..length]) {
+            return None
+        }
+
+        Some(Table { bytes : &bytes[..length] })
+    }
+
+    /// Reads table from physical memory
+    /// # Why unsafe
+    /// `address` must point to ACPI table
+    pub unsafe fn at_address<M>(address : usize, map : &mut M) -> Option<Table<'static>> where M : FnMut(usize, usize) {
+        if address == 0 {
+            return None
+        }
+
+        map(address, SDT_HEADER_SIZE);
+
+        let length = read_u32(slice::from_raw_parts(address as *const u8, SDT_HEADER_SIZE), SDT_LENGTH_OFFSET) as usize;
+
+        if length < SDT_HEADER_SIZE {
+            return None
+        }
+
+        map(address, length);
+
+        Table::new(slice::from_raw_parts(address as *const u8, length))
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.bytes[..4]
+    }
+
+    pub fn length(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns table content after the header
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[SDT_HEADER_SIZE..]
+    }
+
+    /// Returns PCI configuration regions if this is MCFG table
+    pub fn mcfg_regions(&self) -> McfgRegions<'a> {
+        let entries = if self.signature() == MCFG_SIGNATURE && self.bytes.len() >= MCFG_ENTRIES_OFFSET {
+            &self.bytes[MCFG_ENTRIES_OFFSET..]
+        }
+        else {
+            &[]
+        };
+
+        McfgRegions { entries, index : 0 }
+    }
+}
+
+/// Memory mapped PCI configuration space of a segment, described by MCFG entry
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EcamRegion {
+    /// physical address of configuration space of bus 0, even if `start_bus` is not 0
+    pub base_address : usize,
+    pub segment      : u16,
+    pub start_bus    : u8,
+    pub end_bus      : u8
+}
+
+impl EcamRegion {
+
+    /// Returns physical address of configuration space of `start_bus`, the region is mapped from it
+    pub fn start_address(&self) -> usize {
+        self.base_address + ((self.start_bus as usize) << 20)
+    }
+
+    /// Returns size of configuration space of all buses of the region
+    pub fn size(&self) -> usize {
+        (self.end_bus as usize - self.start_bus as usize + 1) << 20
+    }
+}
+
+pub struct McfgRegions<'a> {
+    entries : &'a [u8],
+    index   : usize
+}
+
+impl<'a> Iterator for McfgRegions<'a> {
+    type Item = EcamRegion;
+
+    fn next(&mut self) -> Option<EcamRegion> {
+        let offset = self.index * MCFG_ENTRY_SIZE;
+
+        if offset + MCFG_ENTRY_SIZE > self.entries.len() {
+            return None
+        }
+
+        self.index += 1;
+
+        Some(EcamRegion {
+            base_address : read_u64(self.entries, offset) as usize,
+            segment      : read_u16(self.entries, offset + 8),
+            start_bus    : self.entries[offset + 10],
+            end_bus      : self.entries[offset + 11]
+        })
+    }
+}
+
+fn is_checksum_valid(bytes : &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+fn read_u16(bytes : &[u8], offset : usize) -> u16 {
+    bytes[offset] as u16 | (bytes[offset + 1] as u16) << 8
+}
+
+fn read_u32(bytes : &[u8], offset : usize) -> u32 {
+    read_u16(bytes, offset) as u32 | (read_u16(bytes, offset + 2) as u32) << 16
+}
+
+fn read_u64(bytes : &[u8], offset : usize) -> u64 {
+    read_u32(bytes, offset) as u64 | (read_u32(bytes, offset + 4) as u64) << 32
+}
@@ -0,0 +1,11 @@
+/// Returns initial local APIC id of the current processor, CPUID leaf 1 EBX bits 24-31.
+/// Ids are assigned by firmware and are unique per processor, but aren't necessarily contiguous.
+#[inline(always)]
+pub fn apic_id() -> u8 {
+    let _eax : u32;
+    let ebx  : u32;
+
+    unsafe { asm!("cpuid" : "={eax}" (_eax), "={ebx}" (ebx) : "{eax}" (1) : "ecx" "edx" : "volatile") };
+
+    (ebx >> 24) as u8
+}
@@ -7,6 +7,9 @@ use core::ops::{Index, IndexMut};
 #[repr(u8)]
 pub enum HardwareInterrupts {
     Timer = PIC_1_OFFSET,
+    Keyboard = PIC_1_OFFSET + 1,
+    Com2 = PIC_1_OFFSET + 3,
+    Com1 = PIC_1_OFFSET + 4,
 }
 
 /// Describes entry of interrupt descriptor table (IDT).
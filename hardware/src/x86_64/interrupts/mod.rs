@@ -3,6 +3,10 @@ pub mod handler;
 pub mod pic;
 
 use ::x86_64::interrupts::idt::InterruptTable;
+use ::x86_64::registers;
+
+// interrupt enable flag (IF) of RFLAGS
+const INTERRUPT_FLAG : u64 = 1 << 9;
 
 /// Tells the processor to stop handling interrupts
 #[inline(always)]
@@ -20,6 +24,56 @@ pub fn enable_interrupts() {
     }
 }
 
+/// Stops the processor until the next interrupt
+#[inline(always)]
+pub fn wait_for_interrupt() {
+    unsafe {
+        asm!("hlt" :::: "volatile");
+    }
+}
+
+/// Enables interrupts and stops the processor until the next interrupt. `sti` takes effect only after
+/// the next instruction, so an interrupt that is pending when interrupts get enabled wakes up `hlt`
+/// instead of being handled before it. Lets code check a condition with interrupts disabled and wait for it without missing the interrupt.
+#[inline(always)]
+pub fn enable_interrupts_and_wait() {
+    unsafe {
+        asm!("sti; hlt" :::: "volatile");
+    }
+}
+
+/// Tells if the processor currently handles maskable interrupts
+#[inline(always)]
+pub fn are_enabled() -> bool {
+    registers::rflags() & INTERRUPT_FLAG != 0
+}
+
+/// Disables interrupts while alive and restores previous interrupt state when dropped,
+/// thus guards can be nested and can be taken with interrupts already disabled.
+/// Code that shares data with interrupt handlers takes the guard before locking the data,
+/// otherwise a handler on the same CPU can spin forever on the lock held by the code it interrupted.
+pub struct InterruptGuard {
+    were_enabled : bool
+}
+
+impl InterruptGuard {
+    pub fn new() -> Self {
+        let were_enabled = are_enabled();
+
+        disable_interrupts();
+
+        InterruptGuard { were_enabled }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if self.were_enabled {
+            enable_interrupts()
+        }
+    }
+}
+
 /// Loads interrupt table address into interrupt descriptor table address register (IDTR).
 /// This should be done before calling `enable_interrupts`, otherwise no interrupts will get handled and processor will restart.
 #[inline(always)]
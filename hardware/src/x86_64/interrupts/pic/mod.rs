@@ -1,12 +1,47 @@
 use pic8259_simple::ChainedPics;
+use ::x86_64::port;
 
 pub(crate) const PIC_1_OFFSET: u8 = 32;
 pub(crate) const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
+const PIC_1_DATA : u16 = 0x21;
+const PIC_2_DATA : u16 = 0xa1;
+// line of master PIC the slave one is connected to
+const CASCADE_IRQ : u8 = 2;
+
 pub const unsafe fn new() -> ChainedPics {
     ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET)
 }
 
 pub unsafe fn initialize(pic : &mut ChainedPics) {
     pic.initialize();
-}
\ No newline at end of file
+}
+
+/// Allows PIC to deliver interrupts of `irq` line, firmware can leave lines of unused devices masked
+/// # Arguments
+/// * `irq` - PIC line, 0 - 7 for master PIC, 8 - 15 for slave
+pub unsafe fn unmask(irq : u8) {
+    assert!(irq < 16, "PIC line {} doesn't exist", irq);
+
+    if irq < 8 {
+        port::outb(PIC_1_DATA, port::inb(PIC_1_DATA) & !(1 << irq));
+    }
+    else {
+        port::outb(PIC_2_DATA, port::inb(PIC_2_DATA) & !(1 << (irq - 8)));
+        unmask(CASCADE_IRQ);
+    }
+}
+
+/// Returns interrupt vector PIC raises for `irq` line
+/// # Arguments
+/// * `irq` - PIC line, 0 - 7 for master PIC, 8 - 15 for slave
+pub fn vector(irq : u8) -> u8 {
+    assert!(irq < 16, "PIC line {} doesn't exist", irq);
+
+    if irq < 8 {
+        PIC_1_OFFSET + irq
+    }
+    else {
+        PIC_2_OFFSET + irq - 8
+    }
+}
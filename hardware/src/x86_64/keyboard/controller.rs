@@ -0,0 +1,163 @@
+/*
+    Driver of 8042 PS/2 controller. Data port 0x60 transfers bytes to and from devices,
+    port 0x64 is status register on read and command register on write.
+    Controller configuration byte:
+
+    bit 0 - interrupt of the first port (IRQ 1)
+    bit 1 - interrupt of the second port (IRQ 12)
+    bit 4 - clock of the first port is disabled
+    bit 6 - translation of scancode set 2 into set 1
+*/
+use ::x86_64::port;
+use ::x86_64::keyboard::scancode::ScancodeSet;
+
+const DATA_PORT                  : u16 = 0x60;
+const STATUS_PORT                : u16 = 0x64;
+const COMMAND_PORT               : u16 = 0x64;
+
+const STATUS_OUTPUT_FULL         : u8 = 1 << 0;
+const STATUS_INPUT_FULL          : u8 = 1 << 1;
+
+const COMMAND_READ_CONFIG        : u8 = 0x20;
+const COMMAND_WRITE_CONFIG       : u8 = 0x60;
+const COMMAND_DISABLE_SECOND     : u8 = 0xa7;
+const COMMAND_SELF_TEST          : u8 = 0xaa;
+const COMMAND_TEST_FIRST         : u8 = 0xab;
+const COMMAND_DISABLE_FIRST      : u8 = 0xad;
+const COMMAND_ENABLE_FIRST       : u8 = 0xae;
+
+const CONFIG_FIRST_INTERRUPT     : u8 = 1 << 0;
+const CONFIG_SECOND_INTERRUPT    : u8 = 1 << 1;
+const CONFIG_TRANSLATION         : u8 = 1 << 6;
+
+const SELF_TEST_PASSED           : u8 = 0x55;
+const PORT_TEST_PASSED           : u8 = 0x00;
+
+const KEYBOARD_ENABLE_SCANNING   : u8 = 0xf4;
+const KEYBOARD_ACKNOWLEDGE       : u8 = 0xfa;
+
+// number of status polls before giving up, controller answers in microseconds
+const TIMEOUT_POLLS              : usize = 100_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ps2Error {
+    /// controller didn't accept or return a byte in time
+    Timeout,
+    /// controller self test returned the value instead of 0x55
+    SelfTestFailed(u8),
+    /// first port test returned the error code
+    PortTestFailed(u8),
+    /// keyboard answered the value instead of acknowledge
+    NoAcknowledge(u8),
+}
+
+pub struct Ps2Controller;
+
+impl Ps2Controller {
+
+    /// Tests controller and keyboard port, enables keyboard interrupt and scanning.
+    /// Interrupts must be disabled or IRQ 1 masked, otherwise keyboard interrupt handler steals answers of the controller.
+    /// # Returns
+    /// Scancode set the keyboard interrupt handler receives
+    /// # Why unsafe
+    /// Writes to I/O ports
+    pub unsafe fn initialize(&self) -> Result<ScancodeSet, Ps2Error> {
+        self.send_command(COMMAND_DISABLE_FIRST)?;
+        self.send_command(COMMAND_DISABLE_SECOND)?;
+        self.flush();
+
+        let config = self.read_config()? & !(CONFIG_FIRST_INTERRUPT | CONFIG_SECOND_INTERRUPT);
+        self.write_config(config)?;
+
+        self.send_command(COMMAND_SELF_TEST)?;
+        let self_test = self.read_data()?;
+
+        if self_test != SELF_TEST_PASSED {
+            return Err(Ps2Error::SelfTestFailed(self_test))
+        }
+
+        // self test can reset controller on some machines
+        self.write_config(config)?;
+
+        self.send_command(COMMAND_TEST_FIRST)?;
+        let port_test = self.read_data()?;
+
+        if port_test != PORT_TEST_PASSED {
+            return Err(Ps2Error::PortTestFailed(port_test))
+        }
+
+        self.send_command(COMMAND_ENABLE_FIRST)?;
+        self.write_data(KEYBOARD_ENABLE_SCANNING)?;
+
+        let answer = self.read_data()?;
+
+        if answer != KEYBOARD_ACKNOWLEDGE {
+            return Err(Ps2Error::NoAcknowledge(answer))
+        }
+
+        self.write_config(config | CONFIG_FIRST_INTERRUPT)?;
+
+        if config & CONFIG_TRANSLATION != 0 {
+            Ok(ScancodeSet::Set1)
+        }
+        else {
+            Ok(ScancodeSet::Set2)
+        }
+    }
+
+    /// Reads byte without waiting, used by keyboard interrupt handler when the byte is known to be there
+    /// # Why unsafe
+    /// Reads from I/O port
+    pub unsafe fn read_data_unchecked(&self) -> u8 {
+        port::inb(DATA_PORT)
+    }
+
+    unsafe fn read_config(&self) -> Result<u8, Ps2Error> {
+        self.send_command(COMMAND_READ_CONFIG)?;
+        self.read_data()
+    }
+
+    unsafe fn write_config(&self, config : u8) -> Result<(), Ps2Error> {
+        self.send_command(COMMAND_WRITE_CONFIG)?;
+        self.write_data(config)
+    }
+
+    unsafe fn send_command(&self, command : u8) -> Result<(), Ps2Error> {
+        self.wait_for_status(STATUS_INPUT_FULL, 0)?;
+
+        port::outb(COMMAND_PORT, command);
+
+        Ok(())
+    }
+
+    unsafe fn write_data(&self, value : u8) -> Result<(), Ps2Error> {
+        self.wait_for_status(STATUS_INPUT_FULL, 0)?;
+
+        port::outb(DATA_PORT, value);
+
+        Ok(())
+    }
+
+    unsafe fn read_data(&self) -> Result<u8, Ps2Error> {
+        self.wait_for_status(STATUS_OUTPUT_FULL, STATUS_OUTPUT_FULL)?;
+
+        Ok(port::inb(DATA_PORT))
+    }
+
+    // drops bytes left in output buffer
+    unsafe fn flush(&self) {
+        while port::inb(STATUS_PORT) & STATUS_OUTPUT_FULL != 0 {
+            port::inb(DATA_PORT);
+        }
+    }
+
+    unsafe fn wait_for_status(&self, mask : u8, value : u8) -> Result<(), Ps2Error> {
+        for _ in 0..TIMEOUT_POLLS {
+            if port::inb(STATUS_PORT) & mask == value {
+                return Ok(())
+            }
+        }
+
+        Err(Ps2Error::Timeout)
+    }
+}
@@ -0,0 +1,103 @@
+use ::x86_64::keyboard::scancode::{KeyCode, Modifiers, SHIFT, CAPS_LOCK, NUM_LOCK};
+
+/// Keyboard layout, tells which character a key produces
+pub trait Keymap {
+    /// Returns character of `code` pressed with `modifiers`, `None` for keys that don't produce characters
+    fn character(&self, code : KeyCode, modifiers : Modifiers) -> Option<char>;
+}
+
+/// US QWERTY layout
+pub struct UsKeymap;
+
+impl UsKeymap {
+    // returns unshifted and shifted character
+    fn characters(code : KeyCode) -> Option<(char, char)> {
+        let result = match code {
+            KeyCode::Digit1       => ('1', '!'),
+            KeyCode::Digit2       => ('2', '@'),
+            KeyCode::Digit3       => ('3', '#'),
+            KeyCode::Digit4       => ('4', '$'),
+            KeyCode::Digit5       => ('5', '%'),
+            KeyCode::Digit6       => ('6', '^'),
+            KeyCode::Digit7       => ('7', '&'),
+            KeyCode::Digit8       => ('8', '*'),
+            KeyCode::Digit9       => ('9', '('),
+            KeyCode::Digit0       => ('0', ')'),
+            KeyCode::Minus        => ('-', '_'),
+            KeyCode::Equals       => ('=', '+'),
+            KeyCode::LeftBracket  => ('[', '{'),
+            KeyCode::RightBracket => (']', '}'),
+            KeyCode::Semicolon    => (';', ':'),
+            KeyCode::Quote        => ('\'', '"'),
+            KeyCode::Backquote    => ('`', '~'),
+            KeyCode::Backslash    => ('\\', '|'),
+            KeyCode::Comma        => (',', '<'),
+            KeyCode::Period       => ('.', '>'),
+            KeyCode::Slash        => ('/', '?'),
+            KeyCode::Space        => (' ', ' '),
+            KeyCode::Tab          => ('\t', '\t'),
+            KeyCode::Enter        => ('\n', '\n'),
+            KeyCode::KeypadEnter  => ('\n', '\n'),
+            KeyCode::Backspace    => ('\x08', '\x08'),
+            KeyCode::KeypadPlus   => ('+', '+'),
+            KeyCode::KeypadMinus  => ('-', '-'),
+            KeyCode::KeypadStar   => ('*', '*'),
+            KeyCode::KeypadSlash  => ('/', '/'),
+            _                     => return None
+        };
+
+        Some(result)
+    }
+
+    fn letter(code : KeyCode) -> Option<char> {
+        let result = match code {
+            KeyCode::A => 'a', KeyCode::B => 'b', KeyCode::C => 'c', KeyCode::D => 'd',
+            KeyCode::E => 'e', KeyCode::F => 'f', KeyCode::G => 'g', KeyCode::H => 'h',
+            KeyCode::I => 'i', KeyCode::J => 'j', KeyCode::K => 'k', KeyCode::L => 'l',
+            KeyCode::M => 'm', KeyCode::N => 'n', KeyCode::O => 'o', KeyCode::P => 'p',
+            KeyCode::Q => 'q', KeyCode::R => 'r', KeyCode::S => 's', KeyCode::T => 't',
+            KeyCode::U => 'u', KeyCode::V => 'v', KeyCode::W => 'w', KeyCode::X => 'x',
+            KeyCode::Y => 'y', KeyCode::Z => 'z',
+            _          => return None
+        };
+
+        Some(result)
+    }
+
+    // keypad digits produce characters only when num lock is on
+    fn keypad_digit(code : KeyCode) -> Option<char> {
+        let result = match code {
+            KeyCode::Keypad0      => '0',
+            KeyCode::Keypad1      => '1',
+            KeyCode::Keypad2      => '2',
+            KeyCode::Keypad3      => '3',
+            KeyCode::Keypad4      => '4',
+            KeyCode::Keypad5      => '5',
+            KeyCode::Keypad6      => '6',
+            KeyCode::Keypad7      => '7',
+            KeyCode::Keypad8      => '8',
+            KeyCode::Keypad9      => '9',
+            KeyCode::KeypadPeriod => '.',
+            _                     => return None
+        };
+
+        Some(result)
+    }
+}
+
+impl Keymap for UsKeymap {
+    fn character(&self, code : KeyCode, modifiers : Modifiers) -> Option<char> {
+        let shift = modifiers.contains(SHIFT);
+
+        if let Some(letter) = UsKeymap::letter(code) {
+            // caps lock inverts shift for letters only
+            return if shift != modifiers.contains(CAPS_LOCK) { Some(letter.to_ascii_uppercase()) } else { Some(letter) }
+        }
+
+        if let Some(digit) = UsKeymap::keypad_digit(code) {
+            return if modifiers.contains(NUM_LOCK) { Some(digit) } else { None }
+        }
+
+        UsKeymap::characters(code).map(|(unshifted, shifted)| if shift { shifted } else { unshifted })
+    }
+}
@@ -0,0 +1,122 @@
+/*
+    PS/2 keyboard driver. Keyboard interrupt handler reads scancode bytes from PS/2 controller,
+    decoder turns them into key events and keymap gives characters of pressed keys.
+    Decoded input is kept in a fixed size queue until the kernel takes it, nothing is allocated in the interrupt handler.
+*/
+pub mod controller;
+pub mod keymap;
+pub mod scancode;
+
+use ::x86_64::interrupts::InterruptGuard;
+use self::controller::{Ps2Controller, Ps2Error};
+use self::keymap::Keymap;
+use self::scancode::{KeyEvent, KeyState, ScancodeDecoder, ScancodeSet};
+
+/// PIC line of the first PS/2 port
+pub const KEYBOARD_IRQ     : u8 = 1;
+pub const INPUT_QUEUE_SIZE : usize = 64;
+
+/// Key event together with the character it produces, sent to the process that reads keyboard
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyInput {
+    pub event     : KeyEvent,
+    // only pressed keys produce characters
+    pub character : Option<char>,
+}
+
+pub struct Keyboard<K> where K : Keymap {
+    controller     : Ps2Controller,
+    decoder        : ScancodeDecoder,
+    keymap         : K,
+    queue          : [Option<KeyInput>; INPUT_QUEUE_SIZE],
+    queue_start    : usize,
+    queue_length   : usize,
+    // input lost because queue was full
+    dropped_inputs : usize,
+}
+
+impl<K> Keyboard<K> where K : Keymap {
+
+    /// Creates keyboard that must be initialized before use
+    pub const fn new(keymap : K) -> Self {
+        Keyboard {
+            controller     : Ps2Controller,
+            decoder        : ScancodeDecoder::new(ScancodeSet::Set1),
+            keymap,
+            queue          : [None; INPUT_QUEUE_SIZE],
+            queue_start    : 0,
+            queue_length   : 0,
+            dropped_inputs : 0,
+        }
+    }
+
+    /// Initializes PS/2 controller, keyboard interrupt is enabled on success
+    /// # Why unsafe
+    /// Writes to I/O ports
+    pub unsafe fn initialize(&mut self) -> Result<ScancodeSet, Ps2Error> {
+        let set = self.controller.initialize()?;
+
+        self.decoder.set_scancode_set(set);
+
+        Ok(set)
+    }
+
+    pub fn scancode_set(&self) -> ScancodeSet {
+        self.decoder.set()
+    }
+
+    /// Reads scancode byte from controller, must be called by keyboard interrupt handler
+    pub fn handle_interrupt(&mut self) {
+        let byte = unsafe { self.controller.read_data_unchecked() };
+
+        self.handle_byte(byte);
+    }
+
+    /// Decodes scancode byte and queues input if it completes a key event
+    /// # Returns
+    /// Decoded input
+    pub fn handle_byte(&mut self, byte : u8) -> Option<KeyInput> {
+        let input = self.decoder.feed(byte).map(|event| {
+            let character = if event.state == KeyState::Pressed {
+                self.keymap.character(event.code, event.modifiers)
+            }
+            else {
+                None
+            };
+
+            KeyInput { event, character }
+        });
+
+        if let Some(input) = input {
+            if self.queue_length == INPUT_QUEUE_SIZE {
+                self.dropped_inputs += 1;
+            }
+            else {
+                self.queue[(self.queue_start + self.queue_length) % INPUT_QUEUE_SIZE] = Some(input);
+                self.queue_length += 1;
+            }
+        }
+
+        input
+    }
+
+    /// Takes the oldest queued input
+    pub fn read_input(&mut self) -> Option<KeyInput> {
+        let _guard = InterruptGuard::new();
+
+        if self.queue_length == 0 {
+            return None
+        }
+
+        let result = self.queue[self.queue_start].take();
+
+        self.queue_start   = (self.queue_start + 1) % INPUT_QUEUE_SIZE;
+        self.queue_length -= 1;
+
+        result
+    }
+
+    pub fn dropped_inputs(&self) -> usize {
+        self.dropped_inputs
+    }
+}
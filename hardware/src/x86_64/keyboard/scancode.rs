@@ -0,0 +1,415 @@
+/*
+    Translates scancodes into key events. Keyboard sends one or more bytes per key press and release:
+
+    set 1 - make code, break code is make code with the highest bit set, e.g. 0x1e / 0x9e for A
+    set 2 - make code, break code is 0xf0 followed by make code, e.g. 0x1c / 0xf0 0x1c for A
+
+    In both sets keys added after the original PC keyboard (arrows, right control etc.) are prefixed by 0xe0.
+    PS/2 controller translates set 2 into set 1 by default. Decoder keeps state between bytes and
+    tracks modifier keys, so every event carries modifiers active at its moment.
+*/
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyCode {
+    Escape,
+    Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9, Digit0,
+    Minus, Equals, Backspace, Tab,
+    Q, W, E, R, T, Y, U, I, O, P, LeftBracket, RightBracket, Enter,
+    A, S, D, F, G, H, J, K, L, Semicolon, Quote, Backquote,
+    Backslash, Z, X, C, V, B, N, M, Comma, Period, Slash, Space,
+    LeftShift, RightShift, LeftControl, RightControl, LeftAlt, RightAlt,
+    CapsLock, NumLock, ScrollLock,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Insert, Delete, Home, End, PageUp, PageDown,
+    ArrowUp, ArrowDown, ArrowLeft, ArrowRight,
+    Keypad0, Keypad1, Keypad2, Keypad3, Keypad4, Keypad5, Keypad6, Keypad7, Keypad8, Keypad9,
+    KeypadPeriod, KeypadPlus, KeypadMinus, KeypadStar, KeypadSlash, KeypadEnter,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyState {
+    Pressed,
+    Released,
+}
+
+bitflags! {
+    pub struct Modifiers : u8 {
+        const SHIFT     = 1 << 0;
+        const CONTROL   = 1 << 1;
+        const ALT       = 1 << 2;
+        const CAPS_LOCK = 1 << 3;
+        const NUM_LOCK  = 1 << 4;
+    }
+}
+
+/// Key press or release with modifiers that were active at that moment
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyEvent {
+    pub code      : KeyCode,
+    pub state     : KeyState,
+    pub modifiers : Modifiers,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScancodeSet {
+    Set1,
+    Set2,
+}
+
+const EXTENDED_PREFIX    : u8 = 0xe0;
+const SET2_BREAK_PREFIX  : u8 = 0xf0;
+const SET1_BREAK_BIT     : u8 = 0x80;
+
+pub struct ScancodeDecoder {
+    set           : ScancodeSet,
+    // 0xe0 was received
+    extended      : bool,
+    // 0xf0 was received, set 2 only
+    release       : bool,
+    left_shift    : bool,
+    right_shift   : bool,
+    left_control  : bool,
+    right_control : bool,
+    left_alt      : bool,
+    right_alt     : bool,
+    caps_lock     : bool,
+    num_lock      : bool,
+}
+
+impl ScancodeDecoder {
+
+    pub const fn new(set : ScancodeSet) -> Self {
+        ScancodeDecoder {
+            set,
+            extended      : false,
+            release       : false,
+            left_shift    : false,
+            right_shift   : false,
+            left_control  : false,
+            right_control : false,
+            left_alt      : false,
+            right_alt     : false,
+            caps_lock     : false,
+            num_lock      : false,
+        }
+    }
+
+    pub fn set(&self) -> ScancodeSet {
+        self.set
+    }
+
+    /// Switches to another scancode set, partially received scancode is dropped
+    pub fn set_scancode_set(&mut self, set : ScancodeSet) {
+        self.set      = set;
+        self.extended = false;
+        self.release  = false;
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        let mut result = Modifiers::empty();
+
+        if self.left_shift || self.right_shift {
+            result |= SHIFT;
+        }
+
+        if self.left_control || self.right_control {
+            result |= CONTROL;
+        }
+
+        if self.left_alt || self.right_alt {
+            result |= ALT;
+        }
+
+        if self.caps_lock {
+            result |= CAPS_LOCK;
+        }
+
+        if self.num_lock {
+            result |= NUM_LOCK;
+        }
+
+        result
+    }
+
+    /// Takes next byte from keyboard
+    /// # Returns
+    /// Key event if `byte` completes a scancode, `None` if more bytes are expected or scancode is unknown
+    pub fn feed(&mut self, byte : u8) -> Option<KeyEvent> {
+        if byte == EXTENDED_PREFIX {
+            self.extended = true;
+            return None
+        }
+
+        if self.set == ScancodeSet::Set2 && byte == SET2_BREAK_PREFIX {
+            self.release = true;
+            return None
+        }
+
+        let extended = self.extended;
+        let (code, state) = match self.set {
+            ScancodeSet::Set1 => {
+                let state = if byte & SET1_BREAK_BIT != 0 { KeyState::Released } else { KeyState::Pressed };
+
+                (ScancodeDecoder::set1_key(byte & !SET1_BREAK_BIT, extended), state)
+            },
+            ScancodeSet::Set2 => {
+                let state = if self.release { KeyState::Released } else { KeyState::Pressed };
+
+                (ScancodeDecoder::set2_key(byte, extended), state)
+            }
+        };
+
+        self.extended = false;
+        self.release  = false;
+
+        code.map(|code| {
+            self.update_modifiers(code, state);
+
+            KeyEvent {
+                code,
+                state,
+                modifiers : self.modifiers()
+            }
+        })
+    }
+
+    fn update_modifiers(&mut self, code : KeyCode, state : KeyState) {
+        let pressed = state == KeyState::Pressed;
+
+        match code {
+            KeyCode::LeftShift    => self.left_shift    = pressed,
+            KeyCode::RightShift   => self.right_shift   = pressed,
+            KeyCode::LeftControl  => self.left_control  = pressed,
+            KeyCode::RightControl => self.right_control = pressed,
+            KeyCode::LeftAlt      => self.left_alt      = pressed,
+            KeyCode::RightAlt     => self.right_alt     = pressed,
+            // lock keys toggle on press, auto repeat sends presses without releases
+            KeyCode::CapsLock if pressed => self.caps_lock = !self.caps_lock,
+            KeyCode::NumLock  if pressed => self.num_lock  = !self.num_lock,
+            _ => ()
+        }
+    }
+
+    fn set1_key(code : u8, extended : bool) -> Option<KeyCode> {
+        if extended {
+            // fake shifts that surround print screen and some extended keys are skipped
+            return match code {
+                0x1c => Some(KeyCode::KeypadEnter),
+                0x1d => Some(KeyCode::RightControl),
+                0x35 => Some(KeyCode::KeypadSlash),
+                0x38 => Some(KeyCode::RightAlt),
+                0x47 => Some(KeyCode::Home),
+                0x48 => Some(KeyCode::ArrowUp),
+                0x49 => Some(KeyCode::PageUp),
+                0x4b => Some(KeyCode::ArrowLeft),
+                0x4d => Some(KeyCode::ArrowRight),
+                0x4f => Some(KeyCode::End),
+                0x50 => Some(KeyCode::ArrowDown),
+                0x51 => Some(KeyCode::PageDown),
+                0x52 => Some(KeyCode::Insert),
+                0x53 => Some(KeyCode::Delete),
+                _    => None
+            }
+        }
+
+        match code {
+            0x01 => Some(KeyCode::Escape),
+            0x02 => Some(KeyCode::Digit1),
+            0x03 => Some(KeyCode::Digit2),
+            0x04 => Some(KeyCode::Digit3),
+            0x05 => Some(KeyCode::Digit4),
+            0x06 => Some(KeyCode::Digit5),
+            0x07 => Some(KeyCode::Digit6),
+            0x08 => Some(KeyCode::Digit7),
+            0x09 => Some(KeyCode::Digit8),
+            0x0a => Some(KeyCode::Digit9),
+            0x0b => Some(KeyCode::Digit0),
+            0x0c => Some(KeyCode::Minus),
+            0x0d => Some(KeyCode::Equals),
+            0x0e => Some(KeyCode::Backspace),
+            0x0f => Some(KeyCode::Tab),
+            0x10 => Some(KeyCode::Q),
+            0x11 => Some(KeyCode::W),
+            0x12 => Some(KeyCode::E),
+            0x13 => Some(KeyCode::R),
+            0x14 => Some(KeyCode::T),
+            0x15 => Some(KeyCode::Y),
+            0x16 => Some(KeyCode::U),
+            0x17 => Some(KeyCode::I),
+            0x18 => Some(KeyCode::O),
+            0x19 => Some(KeyCode::P),
+            0x1a => Some(KeyCode::LeftBracket),
+            0x1b => Some(KeyCode::RightBracket),
+            0x1c => Some(KeyCode::Enter),
+            0x1d => Some(KeyCode::LeftControl),
+            0x1e => Some(KeyCode::A),
+            0x1f => Some(KeyCode::S),
+            0x20 => Some(KeyCode::D),
+            0x21 => Some(KeyCode::F),
+            0x22 => Some(KeyCode::G),
+            0x23 => Some(KeyCode::H),
+            0x24 => Some(KeyCode::J),
+            0x25 => Some(KeyCode::K),
+            0x26 => Some(KeyCode::L),
+            0x27 => Some(KeyCode::Semicolon),
+            0x28 => Some(KeyCode::Quote),
+            0x29 => Some(KeyCode::Backquote),
+            0x2a => Some(KeyCode::LeftShift),
+            0x2b => Some(KeyCode::Backslash),
+            0x2c => Some(KeyCode::Z),
+            0x2d => Some(KeyCode::X),
+            0x2e => Some(KeyCode::C),
+            0x2f => Some(KeyCode::V),
+            0x30 => Some(KeyCode::B),
+            0x31 => Some(KeyCode::N),
+            0x32 => Some(KeyCode::M),
+            0x33 => Some(KeyCode::Comma),
+            0x34 => Some(KeyCode::Period),
+            0x35 => Some(KeyCode::Slash),
+            0x36 => Some(KeyCode::RightShift),
+            0x37 => Some(KeyCode::KeypadStar),
+            0x38 => Some(KeyCode::LeftAlt),
+            0x39 => Some(KeyCode::Space),
+            0x3a => Some(KeyCode::CapsLock),
+            0x3b => Some(KeyCode::F1),
+            0x3c => Some(KeyCode::F2),
+            0x3d => Some(KeyCode::F3),
+            0x3e => Some(KeyCode::F4),
+            0x3f => Some(KeyCode::F5),
+            0x40 => Some(KeyCode::F6),
+            0x41 => Some(KeyCode::F7),
+            0x42 => Some(KeyCode::F8),
+            0x43 => Some(KeyCode::F9),
+            0x44 => Some(KeyCode::F10),
+            0x45 => Some(KeyCode::NumLock),
+            0x46 => Some(KeyCode::ScrollLock),
+            0x47 => Some(KeyCode::Keypad7),
+            0x48 => Some(KeyCode::Keypad8),
+            0x49 => Some(KeyCode::Keypad9),
+            0x4a => Some(KeyCode::KeypadMinus),
+            0x4b => Some(KeyCode::Keypad4),
+            0x4c => Some(KeyCode::Keypad5),
+            0x4d => Some(KeyCode::Keypad6),
+            0x4e => Some(KeyCode::KeypadPlus),
+            0x4f => Some(KeyCode::Keypad1),
+            0x50 => Some(KeyCode::Keypad2),
+            0x51 => Some(KeyCode::Keypad3),
+            0x52 => Some(KeyCode::Keypad0),
+            0x53 => Some(KeyCode::KeypadPeriod),
+            0x57 => Some(KeyCode::F11),
+            0x58 => Some(KeyCode::F12),
+            _    => None
+        }
+    }
+
+    fn set2_key(code : u8, extended : bool) -> Option<KeyCode> {
+        if extended {
+            return match code {
+                0x11 => Some(KeyCode::RightAlt),
+                0x14 => Some(KeyCode::RightControl),
+                0x4a => Some(KeyCode::KeypadSlash),
+                0x5a => Some(KeyCode::KeypadEnter),
+                0x69 => Some(KeyCode::End),
+                0x6b => Some(KeyCode::ArrowLeft),
+                0x6c => Some(KeyCode::Home),
+                0x70 => Some(KeyCode::Insert),
+                0x71 => Some(KeyCode::Delete),
+                0x72 => Some(KeyCode::ArrowDown),
+                0x74 => Some(KeyCode::ArrowRight),
+                0x75 => Some(KeyCode::ArrowUp),
+                0x7a => Some(KeyCode::PageDown),
+                0x7d => Some(KeyCode::PageUp),
+                _    => None
+            }
+        }
+
+        match code {
+            0x01 => Some(KeyCode::F9),
+            0x03 => Some(KeyCode::F5),
+            0x04 => Some(KeyCode::F3),
+            0x05 => Some(KeyCode::F1),
+            0x06 => Some(KeyCode::F2),
+            0x07 => Some(KeyCode::F12),
+            0x09 => Some(KeyCode::F10),
+            0x0a => Some(KeyCode::F8),
+            0x0b => Some(KeyCode::F6),
+            0x0c => Some(KeyCode::F4),
+            0x0d => Some(KeyCode::Tab),
+            0x0e => Some(KeyCode::Backquote),
+            0x11 => Some(KeyCode::LeftAlt),
+            0x12 => Some(KeyCode::LeftShift),
+            0x14 => Some(KeyCode::LeftControl),
+            0x15 => Some(KeyCode::Q),
+            0x16 => Some(KeyCode::Digit1),
+            0x1a => Some(KeyCode::Z),
+            0x1b => Some(KeyCode::S),
+            0x1c => Some(KeyCode::A),
+            0x1d => Some(KeyCode::W),
+            0x1e => Some(KeyCode::Digit2),
+            0x21 => Some(KeyCode::C),
+            0x22 => Some(KeyCode::X),
+            0x23 => Some(KeyCode::D),
+            0x24 => Some(KeyCode::E),
+            0x25 => Some(KeyCode::Digit4),
+            0x26 => Some(KeyCode::Digit3),
+            0x29 => Some(KeyCode::Space),
+            0x2a => Some(KeyCode::V),
+            0x2b => Some(KeyCode::F),
+            0x2c => Some(KeyCode::T),
+            0x2d => Some(KeyCode::R),
+            0x2e => Some(KeyCode::Digit5),
+            0x31 => Some(KeyCode::N),
+            0x32 => Some(KeyCode::B),
+            0x33 => Some(KeyCode::H),
+            0x34 => Some(KeyCode::G),
+            0x35 => Some(KeyCode::Y),
+            0x36 => Some(KeyCode::Digit6),
+            0x3a => Some(KeyCode::M),
+            0x3b => Some(KeyCode::J),
+            0x3c => Some(KeyCode::U),
+            0x3d => Some(KeyCode::Digit7),
+            0x3e => Some(KeyCode::Digit8),
+            0x41 => Some(KeyCode::Comma),
+            0x42 => Some(KeyCode::K),
+            0x43 => Some(KeyCode::I),
+            0x44 => Some(KeyCode::O),
+            0x45 => Some(KeyCode::Digit0),
+            0x46 => Some(KeyCode::Digit9),
+            0x49 => Some(KeyCode::Period),
+            0x4a => Some(KeyCode::Slash),
+            0x4b => Some(KeyCode::L),
+            0x4c => Some(KeyCode::Semicolon),
+            0x4d => Some(KeyCode::P),
+            0x4e => Some(KeyCode::Minus),
+            0x52 => Some(KeyCode::Quote),
+            0x54 => Some(KeyCode::LeftBracket),
+            0x55 => Some(KeyCode::Equals),
+            0x58 => Some(KeyCode::CapsLock),
+            0x59 => Some(KeyCode::RightShift),
+            0x5a => Some(KeyCode::Enter),
+            0x5b => Some(KeyCode::RightBracket),
+            0x5d => Some(KeyCode::Backslash),
+            0x66 => Some(KeyCode::Backspace),
+            0x69 => Some(KeyCode::Keypad1),
+            0x6b => Some(KeyCode::Keypad4),
+            0x6c => Some(KeyCode::Keypad7),
+            0x70 => Some(KeyCode::Keypad0),
+            0x71 => Some(KeyCode::KeypadPeriod),
+            0x72 => Some(KeyCode::Keypad2),
+            0x73 => Some(KeyCode::Keypad5),
+            0x74 => Some(KeyCode::Keypad6),
+            0x75 => Some(KeyCode::Keypad8),
+            0x76 => Some(KeyCode::Escape),
+            0x77 => Some(KeyCode::NumLock),
+            0x78 => Some(KeyCode::F11),
+            0x79 => Some(KeyCode::KeypadPlus),
+            0x7a => Some(KeyCode::Keypad3),
+            0x7b => Some(KeyCode::KeypadMinus),
+            0x7c => Some(KeyCode::KeypadStar),
+            0x7d => Some(KeyCode::Keypad9),
+            0x7e => Some(KeyCode::ScrollLock),
+            0x83 => Some(KeyCode::F7),
+            _    => None
+        }
+    }
+}
@@ -1,3 +1,11 @@
+pub mod cpuid;
 pub mod tlb;
 pub mod registers;
-pub mod interrupts;
\ No newline at end of file
+pub mod interrupts;
+pub mod port;
+pub mod serial;
+pub mod keyboard;
+pub mod pit;
+pub mod rtc;
+pub mod acpi;
+pub mod pci;
\ No newline at end of file
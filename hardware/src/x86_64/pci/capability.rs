@@ -0,0 +1,283 @@
+/*
+    PCI capabilities form a linked list in configuration space, every entry starts with capability id and offset of the next one.
+
+    MSI capability:
+
+    +0x2 - message control: bit 0 - enable, bits 1-3 - log2 of supported vectors, bits 4-6 - log2 of enabled vectors,
+           bit 7 - 64 bit address, bit 8 - per vector masking
+    +0x4 - message address, +0x8 - upper half of 64 bit address
+    +0x8 or +0xc - message data
+
+    MSI-X capability:
+
+    +0x2 - message control: bits 0-10 - table size minus 1, bit 14 - function mask, bit 15 - enable
+    +0x4 - table offset in BAR, bits 0-2 are BAR index
+    +0x8 - pending bit array offset in BAR, bits 0-2 are BAR index
+
+    MSI-X table lives in device memory, every 16 byte entry has message address, message data and vector control (bit 0 - masked).
+*/
+use core::ptr;
+use ::x86_64::pci::PciAddress;
+use ::x86_64::pci::config::ConfigSpace;
+
+pub const CAPABILITY_MSI             : u8 = 0x05;
+pub const CAPABILITY_VENDOR_SPECIFIC : u8 = 0x09;
+pub const CAPABILITY_PCI_EXPRESS     : u8 = 0x10;
+pub const CAPABILITY_MSI_X           : u8 = 0x11;
+
+// there is room for 48 capabilities in 256 bytes, more entries mean the list is looped
+const MAX_CAPABILITIES               : usize = 48;
+
+const MSI_CONTROL_ENABLE             : u16 = 1 << 0;
+const MSI_CONTROL_ENABLED_VECTORS    : u16 = 0b111 << 4;
+const MSI_CONTROL_64BIT              : u16 = 1 << 7;
+const MSI_CONTROL_PER_VECTOR_MASKING : u16 = 1 << 8;
+
+const MSI_X_CONTROL_FUNCTION_MASK    : u16 = 1 << 14;
+const MSI_X_CONTROL_ENABLE           : u16 = 1 << 15;
+const MSI_X_BAR_MASK                 : u32 = 0b111;
+
+pub const MSI_X_ENTRY_SIZE           : usize = 16;
+const MSI_X_VECTOR_MASKED            : u32 = 1 << 0;
+
+// local APIC address range that receives message signalled interrupts
+const X86_MESSAGE_ADDRESS            : u64 = 0xfee0_0000;
+
+/// Address and data a device writes to signal interrupt
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MsiMessage {
+    pub address : u64,
+    pub data    : u32
+}
+
+impl MsiMessage {
+
+    /// Message that raises fixed, edge triggered interrupt
+    /// # Arguments
+    /// * `apic_id` - id of local APIC of the destination CPU
+    /// * `vector` - interrupt vector
+    pub fn x86(apic_id : u8, vector : u8) -> Self {
+        MsiMessage {
+            address : X86_MESSAGE_ADDRESS | (apic_id as u64) << 12,
+            data    : vector as u32
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Capability {
+    Msi(MsiCapability),
+    MsiX(MsiXCapability),
+    /// capability defined by device vendor, e.g. virtio structures
+    VendorSpecific(u16),
+    Other { id : u8, offset : u16 }
+}
+
+/// Iterates over capabilities list of a function
+pub struct Capabilities<'a, C> where C : ConfigSpace + ?Sized + 'a {
+    config  : &'a C,
+    address : PciAddress,
+    next    : u16,
+    visited : usize
+}
+
+impl<'a, C> Capabilities<'a, C> where C : ConfigSpace + ?Sized + 'a {
+
+    /// # Arguments
+    /// * `config` - configuration space
+    /// * `address` - function address
+    /// * `first` - offset of the first capability, 0 if there are none
+    pub fn new(config : &'a C, address : PciAddress, first : u16) -> Self {
+        Capabilities { config, address, next : first, visited : 0 }
+    }
+}
+
+impl<'a, C> Iterator for Capabilities<'a, C> where C : ConfigSpace + ?Sized + 'a {
+    type Item = Capability;
+
+    fn next(&mut self) -> Option<Capability> {
+        if self.next == 0 || self.visited == MAX_CAPABILITIES {
+            return None
+        }
+
+        let offset = self.next;
+        let header = self.config.read(self.address, offset);
+        let id     = header as u8;
+
+        self.next     = ((header >> 8) & 0xfc) as u16;
+        self.visited += 1;
+
+        let control = (header >> 16) as u16;
+
+        let capability = match id {
+            CAPABILITY_MSI             => Capability::Msi(MsiCapability { offset, control }),
+            CAPABILITY_MSI_X           => Capability::MsiX(MsiXCapability {
+                offset,
+                control,
+                table : self.config.read(self.address, offset + 4),
+                pba   : self.config.read(self.address, offset + 8)
+            }),
+            CAPABILITY_VENDOR_SPECIFIC => Capability::VendorSpecific(offset),
+            _                          => Capability::Other { id, offset }
+        };
+
+        Some(capability)
+    }
+}
+
+/// Message signalled interrupts, device writes message to memory instead of asserting interrupt pin
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MsiCapability {
+    pub offset  : u16,
+    /// message control register when capability was read
+    pub control : u16
+}
+
+impl MsiCapability {
+
+    pub fn is_64bit(&self) -> bool {
+        self.control & MSI_CONTROL_64BIT != 0
+    }
+
+    pub fn has_per_vector_masking(&self) -> bool {
+        self.control & MSI_CONTROL_PER_VECTOR_MASKING != 0
+    }
+
+    /// Returns number of vectors the function can use
+    pub fn supported_vectors(&self) -> usize {
+        1 << ((self.control >> 1) & 0b111)
+    }
+
+    /// Makes function signal interrupts with `message`, a single vector is used
+    pub fn enable<C>(&self, config : &C, address : PciAddress, message : MsiMessage) where C : ConfigSpace + ?Sized {
+        config.write(address, self.offset + 4, message.address as u32);
+
+        if self.is_64bit() {
+            config.write(address, self.offset + 8, (message.address >> 32) as u32);
+            config.write_u16(address, self.offset + 0xc, message.data as u16);
+        }
+        else {
+            config.write_u16(address, self.offset + 8, message.data as u16);
+        }
+
+        let control = config.read_u16(address, self.offset + 2) & !MSI_CONTROL_ENABLED_VECTORS;
+
+        config.write_u16(address, self.offset + 2, control | MSI_CONTROL_ENABLE);
+    }
+
+    pub fn disable<C>(&self, config : &C, address : PciAddress) where C : ConfigSpace + ?Sized {
+        let control = config.read_u16(address, self.offset + 2);
+
+        config.write_u16(address, self.offset + 2, control & !MSI_CONTROL_ENABLE);
+    }
+}
+
+/// Extended message signalled interrupts, every vector has its own message in a table in device memory
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MsiXCapability {
+    pub offset  : u16,
+    /// message control register when capability was read
+    pub control : u16,
+    table       : u32,
+    pba         : u32
+}
+
+impl MsiXCapability {
+
+    /// Returns number of table entries
+    pub fn table_size(&self) -> usize {
+        (self.control & 0x7ff) as usize + 1
+    }
+
+    /// Returns index of BAR that contains the table
+    pub fn table_bar(&self) -> usize {
+        (self.table & MSI_X_BAR_MASK) as usize
+    }
+
+    /// Returns table offset inside of its BAR
+    pub fn table_offset(&self) -> usize {
+        (self.table & !MSI_X_BAR_MASK) as usize
+    }
+
+    /// Returns index of BAR that contains pending bit array
+    pub fn pba_bar(&self) -> usize {
+        (self.pba & MSI_X_BAR_MASK) as usize
+    }
+
+    /// Returns pending bit array offset inside of its BAR
+    pub fn pba_offset(&self) -> usize {
+        (self.pba & !MSI_X_BAR_MASK) as usize
+    }
+
+    /// Enables MSI-X, vectors signal interrupts unless they or the whole function are masked
+    pub fn enable<C>(&self, config : &C, address : PciAddress) where C : ConfigSpace + ?Sized {
+        let control = config.read_u16(address, self.offset + 2);
+
+        config.write_u16(address, self.offset + 2, control | MSI_X_CONTROL_ENABLE);
+    }
+
+    pub fn disable<C>(&self, config : &C, address : PciAddress) where C : ConfigSpace + ?Sized {
+        let control = config.read_u16(address, self.offset + 2);
+
+        config.write_u16(address, self.offset + 2, control & !MSI_X_CONTROL_ENABLE);
+    }
+
+    /// Masks or unmasks every vector of the function
+    pub fn set_function_mask<C>(&self, config : &C, address : PciAddress, masked : bool) where C : ConfigSpace + ?Sized {
+        let control = config.read_u16(address, self.offset + 2);
+        let control = if masked { control | MSI_X_CONTROL_FUNCTION_MASK } else { control & !MSI_X_CONTROL_FUNCTION_MASK };
+
+        config.write_u16(address, self.offset + 2, control);
+    }
+}
+
+/// MSI-X table in device memory
+pub struct MsiXTable {
+    address : usize,
+    size    : usize
+}
+
+impl MsiXTable {
+
+    /// # Arguments
+    /// * `address` - virtual address of the table, e.g. BAR address plus `MsiXCapability::table_offset`
+    /// * `size` - number of entries
+    /// # Why unsafe
+    /// Table must be mapped uncached at `address`
+    pub unsafe fn new(address : usize, size : usize) -> Self {
+        MsiXTable { address, size }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Sets message of vector and unmasks it
+    pub fn set_entry(&mut self, index : usize, message : MsiMessage) {
+        assert!(index < self.size, "MSI-X vector {} is out of table of size {}", index, self.size);
+
+        // vector is masked while its message is inconsistent
+        self.set_masked(index, true);
+        self.write(index, 0, message.address as u32);
+        self.write(index, 1, (message.address >> 32) as u32);
+        self.write(index, 2, message.data);
+        self.set_masked(index, false);
+    }
+
+    pub fn set_masked(&mut self, index : usize, masked : bool) {
+        assert!(index < self.size, "MSI-X vector {} is out of table of size {}", index, self.size);
+
+        let control = self.read(index, 3);
+        let control = if masked { control | MSI_X_VECTOR_MASKED } else { control & !MSI_X_VECTOR_MASKED };
+
+        self.write(index, 3, control)
+    }
+
+    fn read(&self, index : usize, word : usize) -> u32 {
+        unsafe { ptr::read_volatile((self.address + index * MSI_X_ENTRY_SIZE + word * 4) as *const u32) }
+    }
+
+    fn write(&mut self, index : usize, word : usize, value : u32) {
+        unsafe { ptr::write_volatile((self.address + index * MSI_X_ENTRY_SIZE + word * 4) as *mut u32, value) }
+    }
+}
@@ -0,0 +1,167 @@
+/*
+    PCI configuration space access. Every function has 256 bytes of configuration space (4096 bytes for PCI express)
+    that are reached through one of two mechanisms:
+
+    - legacy port I/O: address of a 32 bit register is written to port 0xcf8, its value is read from or written to port 0xcfc.
+      Address register: bit 31 - enable, bits 16-23 - bus, bits 11-15 - device, bits 8-10 - function, bits 2-7 - register.
+      Only the first 256 bytes and segment 0 are reachable.
+    - ECAM (enhanced configuration access mechanism): configuration spaces of a segment are memory mapped one after another,
+      function's space is at base + (bus - start bus) << 20 | device << 15 | function << 12. Regions are described by ACPI MCFG table.
+*/
+use core::ptr;
+use ::x86_64::port;
+use ::x86_64::pci::PciAddress;
+
+const CONFIG_ADDRESS_PORT : u16 = 0xcf8;
+const CONFIG_DATA_PORT    : u16 = 0xcfc;
+
+const CONFIG_ENABLE       : u32 = 1 << 31;
+
+/// Size of configuration space reachable through ports
+pub const LEGACY_CONFIG_SIZE : u16 = 256;
+/// Size of PCI express configuration space
+pub const ECAM_CONFIG_SIZE   : u16 = 4096;
+
+/// Value of registers of absent functions
+pub const ABSENT             : u32 = 0xffff_ffff;
+
+/// Access to configuration registers, smaller registers are accessed through 32 bit ones that contain them
+pub trait ConfigSpace {
+
+    /// Reads 32 bit register, `offset` is aligned down to 4
+    fn read(&self, address : PciAddress, offset : u16) -> u32;
+
+    /// Writes 32 bit register, `offset` is aligned down to 4
+    fn write(&self, address : PciAddress, offset : u16, value : u32);
+
+    /// Returns the first and the last bus this configuration space covers
+    fn buses(&self) -> (u8, u8);
+
+    fn read_u16(&self, address : PciAddress, offset : u16) -> u16 {
+        (self.read(address, offset) >> ((offset & 2) * 8)) as u16
+    }
+
+    fn read_u8(&self, address : PciAddress, offset : u16) -> u8 {
+        (self.read(address, offset) >> ((offset & 3) * 8)) as u8
+    }
+
+    /// Writes 16 bit register by rewriting the 32 bit one, beware of write-one-to-clear bits in the other half
+    fn write_u16(&self, address : PciAddress, offset : u16, value : u16) {
+        let shift    = (offset & 2) * 8;
+        let register = self.read(address, offset) & !(0xffff << shift);
+
+        self.write(address, offset, register | (value as u32) << shift)
+    }
+}
+
+/// Configuration mechanism #1 through ports 0xcf8 and 0xcfc, available on every PC
+pub struct PortConfigSpace {
+    _private : ()
+}
+
+impl PortConfigSpace {
+
+    /// # Why unsafe
+    /// Address and data ports are accessed separately, so there must be only one user and configuration access
+    /// must not be interrupted by code that uses it too
+    pub const unsafe fn new() -> Self {
+        PortConfigSpace { _private : () }
+    }
+
+    fn register_address(address : PciAddress, offset : u16) -> u32 {
+        CONFIG_ENABLE
+            | (address.bus as u32) << 16
+            | (address.device as u32) << 11
+            | (address.function as u32) << 8
+            | (offset & 0xfc) as u32
+    }
+}
+
+impl ConfigSpace for PortConfigSpace {
+
+    fn read(&self, address : PciAddress, offset : u16) -> u32 {
+        if address.segment != 0 || offset >= LEGACY_CONFIG_SIZE {
+            return ABSENT
+        }
+
+        unsafe {
+            port::outl(CONFIG_ADDRESS_PORT, PortConfigSpace::register_address(address, offset));
+            port::inl(CONFIG_DATA_PORT)
+        }
+    }
+
+    fn write(&self, address : PciAddress, offset : u16, value : u32) {
+        if address.segment != 0 || offset >= LEGACY_CONFIG_SIZE {
+            return
+        }
+
+        unsafe {
+            port::outl(CONFIG_ADDRESS_PORT, PortConfigSpace::register_address(address, offset));
+            port::outl(CONFIG_DATA_PORT, value)
+        }
+    }
+
+    fn buses(&self) -> (u8, u8) {
+        (0, 255)
+    }
+}
+
+/// Memory mapped configuration space of one segment
+pub struct EcamConfigSpace {
+    base_address : usize,
+    segment      : u16,
+    start_bus    : u8,
+    end_bus      : u8
+}
+
+impl EcamConfigSpace {
+
+    /// # Arguments
+    /// * `base_address` - virtual address of configuration space of `start_bus`
+    /// * `segment` - PCI segment
+    /// * `start_bus` - the first bus of the region
+    /// * `end_bus` - the last bus of the region
+    /// # Why unsafe
+    /// Configuration space of all buses of the region must be mapped uncached at `base_address`
+    pub const unsafe fn new(base_address : usize, segment : u16, start_bus : u8, end_bus : u8) -> Self {
+        EcamConfigSpace { base_address, segment, start_bus, end_bus }
+    }
+
+    fn register_address(&self, address : PciAddress, offset : u16) -> Option<usize> {
+        let is_covered = address.segment == self.segment
+            && address.bus >= self.start_bus
+            && address.bus <= self.end_bus
+            && offset < ECAM_CONFIG_SIZE;
+
+        if is_covered {
+            let function_offset = ((address.bus - self.start_bus) as usize) << 20
+                | (address.device as usize) << 15
+                | (address.function as usize) << 12;
+
+            Some(self.base_address + function_offset + (offset & 0xffc) as usize)
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl ConfigSpace for EcamConfigSpace {
+
+    fn read(&self, address : PciAddress, offset : u16) -> u32 {
+        match self.register_address(address, offset) {
+            Some(register) => unsafe { ptr::read_volatile(register as *const u32) },
+            None           => ABSENT
+        }
+    }
+
+    fn write(&self, address : PciAddress, offset : u16, value : u32) {
+        if let Some(register) = self.register_address(address, offset) {
+            unsafe { ptr::write_volatile(register as *mut u32, value) }
+        }
+    }
+
+    fn buses(&self) -> (u8, u8) {
+        (self.start_bus, self.end_bus)
+    }
+}
@@ -0,0 +1,118 @@
+/*
+    PCI driver registry. Driver declares devices it supports by vendor/device ids or by class,
+    after bus enumeration every function is given to the first registered driver that supports it.
+    Registry has fixed capacity, so drivers can be registered before the heap is ready.
+*/
+use ::x86_64::pci::{Bar, PciDevice, MAX_BARS};
+use ::x86_64::pci::config::ConfigSpace;
+
+pub const MAX_DRIVERS : usize = 16;
+
+/// Devices a driver supports, `None` fields match any value
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DeviceId {
+    pub vendor_id : Option<u16>,
+    pub device_id : Option<u16>,
+    pub class     : Option<u8>,
+    pub subclass  : Option<u8>,
+    pub prog_if   : Option<u8>
+}
+
+impl DeviceId {
+
+    /// Matches exact device
+    pub const fn device(vendor_id : u16, device_id : u16) -> Self {
+        DeviceId { vendor_id : Some(vendor_id), device_id : Some(device_id), class : None, subclass : None, prog_if : None }
+    }
+
+    /// Matches every device of class and subclass, e.g. 0x01, 0x06 for SATA controllers
+    pub const fn class(class : u8, subclass : u8) -> Self {
+        DeviceId { vendor_id : None, device_id : None, class : Some(class), subclass : Some(subclass), prog_if : None }
+    }
+
+    pub fn matches(&self, device : &PciDevice) -> bool {
+        fn field_matches<T>(expected : Option<T>, actual : T) -> bool where T : PartialEq {
+            expected.map_or(true, |expected| expected == actual)
+        }
+
+        field_matches(self.vendor_id, device.vendor_id)
+            && field_matches(self.device_id, device.device_id)
+            && field_matches(self.class, device.class)
+            && field_matches(self.subclass, device.subclass)
+            && field_matches(self.prog_if, device.prog_if)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProbeError {
+    /// driver can't work with this particular device, e.g. revision is too old
+    Unsupported,
+    /// device was supported but failed to initialize
+    Failed(&'static str)
+}
+
+pub trait PciDriver {
+
+    fn name(&self) -> &'static str;
+
+    /// Returns devices the driver supports
+    fn supported_devices(&self) -> &'static [DeviceId];
+
+    /// Initializes device, called once for every supported function found on the bus
+    /// # Arguments
+    /// * `device` - supported function
+    /// * `bars` - BARs of the function, already sized, so the driver doesn't have to size them again
+    /// * `config` - configuration space
+    fn probe(&self, device : &PciDevice, bars : &[Option<Bar>; MAX_BARS], config : &dyn ConfigSpace) -> Result<(), ProbeError>;
+
+    fn supports(&self, device : &PciDevice) -> bool {
+        self.supported_devices().iter().any(|id| id.matches(device))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RegistryError {
+    /// registry already has `MAX_DRIVERS` drivers
+    Full,
+    /// driver with the name is already registered
+    AlreadyRegistered(&'static str)
+}
+
+pub struct DriverRegistry {
+    drivers : [Option<&'static dyn PciDriver>; MAX_DRIVERS],
+    count   : usize
+}
+
+impl DriverRegistry {
+
+    pub const fn new() -> Self {
+        DriverRegistry {
+            drivers : [None; MAX_DRIVERS],
+            count   : 0
+        }
+    }
+
+    pub fn register(&mut self, driver : &'static dyn PciDriver) -> Result<(), RegistryError> {
+        if self.drivers().any(|registered| registered.name() == driver.name()) {
+            return Err(RegistryError::AlreadyRegistered(driver.name()))
+        }
+
+        if self.count == MAX_DRIVERS {
+            return Err(RegistryError::Full)
+        }
+
+        self.drivers[self.count] = Some(driver);
+        self.count += 1;
+
+        Ok(())
+    }
+
+    /// Returns the first registered driver that supports device
+    pub fn driver_for(&self, device : &PciDevice) -> Option<&'static dyn PciDriver> {
+        self.drivers().find(|driver| driver.supports(device))
+    }
+
+    pub fn drivers<'a>(&'a self) -> impl Iterator<Item = &'static dyn PciDriver> + 'a {
+        self.drivers[..self.count].iter().filter_map(|driver| *driver)
+    }
+}
@@ -0,0 +1,346 @@
+/*
+    PCI bus. Function is addressed by segment, bus, device (0-31) and function (0-7) numbers,
+    device has function 0 and, if it is multifunction, up to 7 more. Configuration space header:
+
+    0x00 - vendor id (0xffff if function is absent), device id
+    0x04 - command, status
+    0x08 - revision, programming interface, subclass, class
+    0x0c - cache line size, latency timer, header type (bit 7 - multifunction), BIST
+    0x10 - base address registers (6 for general devices, 2 for PCI-to-PCI bridges)
+    0x34 - capabilities pointer
+    0x3c - interrupt line, interrupt pin
+
+    Buses are enumerated by brute force scan of every bus the configuration space covers,
+    this also finds devices behind bridges without walking bridge hierarchy.
+*/
+pub mod capability;
+pub mod config;
+pub mod driver;
+
+use core::fmt;
+use self::capability::Capabilities;
+use self::config::{ConfigSpace, ABSENT};
+
+pub const DEVICES_PER_BUS        : u8 = 32;
+pub const FUNCTIONS_PER_DEVICE   : u8 = 8;
+
+const REGISTER_ID                : u16 = 0x00;
+const REGISTER_COMMAND           : u16 = 0x04;
+const REGISTER_STATUS            : u16 = 0x06;
+const REGISTER_CLASS             : u16 = 0x08;
+const REGISTER_HEADER_TYPE       : u16 = 0x0e;
+const REGISTER_BAR0              : u16 = 0x10;
+const REGISTER_CAPABILITIES      : u16 = 0x34;
+const REGISTER_INTERRUPT_LINE    : u16 = 0x3c;
+const REGISTER_INTERRUPT_PIN     : u16 = 0x3d;
+
+pub const COMMAND_IO_SPACE       : u16 = 1 << 0;
+pub const COMMAND_MEMORY_SPACE   : u16 = 1 << 1;
+pub const COMMAND_BUS_MASTER     : u16 = 1 << 2;
+pub const COMMAND_INTX_DISABLE    : u16 = 1 << 10;
+
+const STATUS_CAPABILITIES_LIST   : u16 = 1 << 4;
+
+const HEADER_TYPE_MASK           : u8 = 0x7f;
+const HEADER_MULTIFUNCTION       : u8 = 1 << 7;
+
+pub const HEADER_TYPE_GENERAL    : u8 = 0;
+pub const HEADER_TYPE_BRIDGE     : u8 = 1;
+
+pub const MAX_BARS               : usize = 6;
+
+const BAR_IO                     : u32 = 1 << 0;
+const BAR_MEMORY_TYPE_MASK       : u32 = 0b110;
+const BAR_MEMORY_64BIT           : u32 = 0b100;
+const BAR_PREFETCHABLE           : u32 = 1 << 3;
+const BAR_IO_ADDRESS_MASK        : u32 = !0b11;
+const BAR_MEMORY_ADDRESS_MASK    : u32 = !0b1111;
+
+/// Location of a function
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PciAddress {
+    pub segment  : u16,
+    pub bus      : u8,
+    pub device   : u8,
+    pub function : u8
+}
+
+impl PciAddress {
+    pub fn new(segment : u16, bus : u8, device : u8, function : u8) -> Self {
+        PciAddress { segment, bus, device, function }
+    }
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}:{:02x}:{:02x}.{}", self.segment, self.bus, self.device, self.function)
+    }
+}
+
+/// Decoded base address register
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bar {
+    /// physical memory range, 64 bit BAR occupies two registers
+    Memory { address : usize, size : usize, prefetchable : bool, is_64bit : bool },
+    /// I/O ports range
+    Io { port : u16, size : u16 }
+}
+
+/// Function found on the bus
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PciDevice {
+    pub address        : PciAddress,
+    pub vendor_id      : u16,
+    pub device_id      : u16,
+    pub class          : u8,
+    pub subclass       : u8,
+    pub prog_if        : u8,
+    pub revision       : u8,
+    pub header_type    : u8,
+    pub multifunction  : bool,
+    /// PIC line the firmware routed legacy interrupt to, 0xff if unknown
+    pub interrupt_line : u8,
+    /// legacy interrupt pin, 1-4 for INTA-INTD, 0 if function doesn't use one
+    pub interrupt_pin  : u8
+}
+
+impl PciDevice {
+
+    /// Reads function header
+    /// # Returns
+    /// `None` if there is no function at `address`
+    pub fn read<C>(config : &C, address : PciAddress) -> Option<PciDevice> where C : ConfigSpace + ?Sized {
+        let id = config.read(address, REGISTER_ID);
+
+        if id == ABSENT || id & 0xffff == 0xffff {
+            return None
+        }
+
+        let class       = config.read(address, REGISTER_CLASS);
+        let header_type = config.read_u8(address, REGISTER_HEADER_TYPE);
+
+        Some(PciDevice {
+            address,
+            vendor_id      : id as u16,
+            device_id      : (id >> 16) as u16,
+            class          : (class >> 24) as u8,
+            subclass       : (class >> 16) as u8,
+            prog_if        : (class >> 8) as u8,
+            revision       : class as u8,
+            header_type    : header_type & HEADER_TYPE_MASK,
+            multifunction  : header_type & HEADER_MULTIFUNCTION != 0,
+            interrupt_line : config.read_u8(address, REGISTER_INTERRUPT_LINE),
+            interrupt_pin  : config.read_u8(address, REGISTER_INTERRUPT_PIN)
+        })
+    }
+
+    pub fn command<C>(&self, config : &C) -> u16 where C : ConfigSpace + ?Sized {
+        config.read_u16(self.address, REGISTER_COMMAND)
+    }
+
+    pub fn set_command<C>(&self, config : &C, command : u16) where C : ConfigSpace + ?Sized {
+        // status half is write-one-to-clear, zeros leave it intact
+        config.write(self.address, REGISTER_COMMAND, command as u32)
+    }
+
+    /// Sets command bits, e.g. `COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER` before driver uses device
+    pub fn enable<C>(&self, config : &C, bits : u16) where C : ConfigSpace + ?Sized {
+        let command = self.command(config) | bits;
+
+        self.set_command(config, command)
+    }
+
+    pub fn status<C>(&self, config : &C) -> u16 where C : ConfigSpace + ?Sized {
+        config.read_u16(self.address, REGISTER_STATUS)
+    }
+
+    /// Returns number of base address registers of this header type
+    pub fn bars_count(&self) -> usize {
+        match self.header_type {
+            HEADER_TYPE_GENERAL => 6,
+            HEADER_TYPE_BRIDGE  => 2,
+            _                   => 0
+        }
+    }
+
+    /// Decodes base address registers. Size is found by writing all ones to a register and reading back
+    /// which address bits stick, decoding is disabled meanwhile so the device doesn't answer at a bogus address.
+    /// # Returns
+    /// Registers by index, the upper half of 64 bit BAR and unused registers are `None`
+    pub fn bars<C>(&self, config : &C) -> [Option<Bar>; MAX_BARS] where C : ConfigSpace + ?Sized {
+        let mut result = [None; MAX_BARS];
+        let command    = self.command(config);
+
+        self.set_command(config, command & !(COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE));
+
+        let mut index = 0;
+        while index < self.bars_count() {
+            let offset = REGISTER_BAR0 + index as u16 * 4;
+            let value  = config.read(self.address, offset);
+            let mask   = self.size_mask(config, offset, value);
+
+            if value & BAR_IO != 0 {
+                let size_mask = mask & BAR_IO_ADDRESS_MASK & 0xffff;
+
+                if size_mask != 0 {
+                    result[index] = Some(Bar::Io {
+                        port : (value & BAR_IO_ADDRESS_MASK) as u16,
+                        size : (!size_mask + 1) as u16
+                    });
+                }
+
+                index += 1;
+            }
+            else {
+                let is_64bit = value & BAR_MEMORY_TYPE_MASK == BAR_MEMORY_64BIT && index + 1 < self.bars_count();
+
+                // upper halves of 32 bit BAR address and size mask are zero and all ones
+                let (high_value, high_mask) = if is_64bit {
+                    let high_offset = offset + 4;
+                    let high_value  = config.read(self.address, high_offset);
+
+                    (high_value, self.size_mask(config, high_offset, high_value))
+                }
+                else {
+                    (0, 0xffff_ffff)
+                };
+
+                let address   = (high_value as u64) << 32 | (value & BAR_MEMORY_ADDRESS_MASK) as u64;
+                let size_mask = (high_mask as u64) << 32 | (mask & BAR_MEMORY_ADDRESS_MASK) as u64;
+
+                // unused BAR has no writable address bits
+                let is_used = if is_64bit { size_mask != 0 } else { mask & BAR_MEMORY_ADDRESS_MASK != 0 };
+
+                if is_used {
+                    result[index] = Some(Bar::Memory {
+                        address      : address as usize,
+                        size         : (!size_mask).wrapping_add(1) as usize,
+                        prefetchable : value & BAR_PREFETCHABLE != 0,
+                        is_64bit
+                    });
+                }
+
+                index += if is_64bit { 2 } else { 1 };
+            }
+        }
+
+        self.set_command(config, command);
+
+        result
+    }
+
+    // writes all ones to BAR and restores its value
+    fn size_mask<C>(&self, config : &C, offset : u16, value : u32) -> u32 where C : ConfigSpace + ?Sized {
+        config.write(self.address, offset, 0xffff_ffff);
+
+        let mask = config.read(self.address, offset);
+
+        config.write(self.address, offset, value);
+
+        mask
+    }
+
+    /// Returns capabilities list, empty if function has none
+    pub fn capabilities<'a, C>(&self, config : &'a C) -> Capabilities<'a, C> where C : ConfigSpace + ?Sized {
+        let first = if self.status(config) & STATUS_CAPABILITIES_LIST != 0 {
+            (config.read_u8(self.address, REGISTER_CAPABILITIES) & 0xfc) as u16
+        }
+        else {
+            0
+        };
+
+        Capabilities::new(config, self.address, first)
+    }
+
+    /// Returns class name, e.g. "mass storage"
+    pub fn class_name(&self) -> &'static str {
+        match self.class {
+            0x00 => "unclassified",
+            0x01 => "mass storage",
+            0x02 => "network",
+            0x03 => "display",
+            0x04 => "multimedia",
+            0x05 => "memory",
+            0x06 => "bridge",
+            0x07 => "communication",
+            0x08 => "system peripheral",
+            0x09 => "input",
+            0x0c => "serial bus",
+            _    => "other"
+        }
+    }
+}
+
+/// Iterates over every function in configuration space
+pub struct Devices<'a, C> where C : ConfigSpace + ?Sized + 'a {
+    config   : &'a C,
+    segment  : u16,
+    // bus is wider than u8, so that the last bus can be passed
+    bus      : u16,
+    last_bus : u16,
+    device   : u8,
+    function : u8
+}
+
+impl<'a, C> Devices<'a, C> where C : ConfigSpace + ?Sized + 'a {
+
+    /// # Arguments
+    /// * `config` - configuration space
+    /// * `segment` - segment to scan, port configuration space has only segment 0
+    pub fn new(config : &'a C, segment : u16) -> Self {
+        let (first_bus, last_bus) = config.buses();
+
+        Devices {
+            config,
+            segment,
+            bus      : first_bus as u16,
+            last_bus : last_bus as u16,
+            device   : 0,
+            function : 0
+        }
+    }
+
+    fn advance(&mut self, next_function : bool) {
+        if next_function && self.function + 1 < FUNCTIONS_PER_DEVICE {
+            self.function += 1;
+        }
+        else {
+            self.function = 0;
+            self.device  += 1;
+
+            if self.device == DEVICES_PER_BUS {
+                self.device = 0;
+                self.bus   += 1;
+            }
+        }
+    }
+}
+
+impl<'a, C> Iterator for Devices<'a, C> where C : ConfigSpace + ?Sized + 'a {
+    type Item = PciDevice;
+
+    fn next(&mut self) -> Option<PciDevice> {
+        while self.bus <= self.last_bus {
+            let address = PciAddress::new(self.segment, self.bus as u8, self.device, self.function);
+
+            match PciDevice::read(self.config, address) {
+                Some(device) => {
+                    // functions other than 0 exist only in multifunction devices
+                    let is_multifunction = self.function != 0 || device.multifunction;
+
+                    self.advance(is_multifunction);
+
+                    return Some(device)
+                },
+                None => {
+                    // function 0 is mandatory, its absence means there is no device
+                    let next_function = self.function != 0;
+
+                    self.advance(next_function)
+                }
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,56 @@
+/*
+    Driver of 8253/8254 programmable interval timer. Channel 0 output is connected to IRQ 0,
+    it raises interrupt every `divisor` cycles of 1.193182 MHz oscillator.
+    Firmware leaves divisor 65536 (~18.2 Hz), the kernel sets its own frequency to measure time.
+
+    Mode/command register:
+
+    bits 6-7 - channel
+    bits 4-5 - access mode, 3 means low byte then high byte of the reload value
+    bits 1-3 - operating mode, 3 means square wave generator
+    bit 0    - BCD counter, binary if zero
+*/
+use ::x86_64::port;
+
+/// Frequency of PIT oscillator in Hz
+pub const BASE_FREQUENCY       : u32 = 1_193_182;
+
+const CHANNEL0_PORT            : u16 = 0x40;
+const COMMAND_PORT             : u16 = 0x43;
+
+const CHANNEL0_SQUARE_WAVE     : u8 = 0b0011_0110;
+
+// reload value 0 means 65536
+const MAX_DIVISOR              : u32 = 0x10000;
+
+/// Makes channel 0 interrupt with the frequency nearest to `frequency`
+/// # Arguments
+/// * `frequency` - interrupts per second, at least 19
+/// # Returns
+/// Divisor of the base frequency that was set, actual frequency differs from requested because divisor is integer
+/// # Why unsafe
+/// Writes to I/O ports
+pub unsafe fn set_frequency(frequency : u32) -> u32 {
+    let divisor = divisor(frequency);
+    let reload  = if divisor == MAX_DIVISOR { 0 } else { divisor as u16 };
+
+    port::outb(COMMAND_PORT, CHANNEL0_SQUARE_WAVE);
+    port::outb(CHANNEL0_PORT, reload as u8);
+    port::outb(CHANNEL0_PORT, (reload >> 8) as u8);
+
+    divisor
+}
+
+/// Returns divisor of the base frequency that gives the nearest frequency to `frequency`
+pub fn divisor(frequency : u32) -> u32 {
+    let divisor = (BASE_FREQUENCY + frequency / 2) / frequency.max(1);
+
+    divisor.max(1).min(MAX_DIVISOR)
+}
+
+/// Returns time between interrupts in nanoseconds
+/// # Arguments
+/// * `divisor` - divisor of the base frequency
+pub fn period_nanoseconds(divisor : u32) -> u64 {
+    divisor as u64 * 1_000_000_000 / BASE_FREQUENCY as u64
+}
@@ -0,0 +1,65 @@
+/// Reads byte from I/O port
+/// # Why unsafe
+/// Reading some ports changes device state
+#[inline(always)]
+pub unsafe fn inb(port : u16) -> u8 {
+    let result : u8;
+
+    asm!("inb %dx, %al" : "={al}" (result) : "{dx}" (port) :: "volatile");
+
+    result
+}
+
+/// Writes byte to I/O port
+/// # Why unsafe
+/// Writing to ports controls devices, which can e.g. overwrite arbitrary memory via DMA
+#[inline(always)]
+pub unsafe fn outb(port : u16, value : u8) {
+    asm!("outb %al, %dx" :: "{dx}" (port), "{al}" (value) :: "volatile");
+}
+
+/// Reads 16 bit value from I/O port
+/// # Why unsafe
+/// Reading some ports changes device state
+#[inline(always)]
+pub unsafe fn inw(port : u16) -> u16 {
+    let result : u16;
+
+    asm!("inw %dx, %ax" : "={ax}" (result) : "{dx}" (port) :: "volatile");
+
+    result
+}
+
+/// Writes 16 bit value to I/O port
+/// # Why unsafe
+/// Writing to ports controls devices, which can e.g. overwrite arbitrary memory via DMA
+#[inline(always)]
+pub unsafe fn outw(port : u16, value : u16) {
+    asm!("outw %ax, %dx" :: "{dx}" (port), "{ax}" (value) :: "volatile");
+}
+
+/// Reads 32 bit value from I/O port
+/// # Why unsafe
+/// Reading some ports changes device state
+#[inline(always)]
+pub unsafe fn inl(port : u16) -> u32 {
+    let result : u32;
+
+    asm!("inl %dx, %eax" : "={eax}" (result) : "{dx}" (port) :: "volatile");
+
+    result
+}
+
+/// Writes 32 bit value to I/O port
+/// # Why unsafe
+/// Writing to ports controls devices, which can e.g. overwrite arbitrary memory via DMA
+#[inline(always)]
+pub unsafe fn outl(port : u16, value : u32) {
+    asm!("outl %eax, %dx" :: "{dx}" (port), "{eax}" (value) :: "volatile");
+}
+
+/// Waits a few microseconds by writing to unused port 0x80, gives slow devices like PIC time to react
+#[inline(always)]
+pub unsafe fn io_wait() {
+    outb(0x80, 0)
+}
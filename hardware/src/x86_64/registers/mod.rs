@@ -26,6 +26,16 @@ pub unsafe fn cr3_write(val: u64) {
     asm!("mov $0, %cr3" :: "r" (val) : "memory");
 }
 
+/// Returns flags register value (RFLAGS)
+#[inline(always)]
+pub fn rflags() -> u64 {
+    let result : u64;
+
+    unsafe { asm!("pushfq; popq $0" : "=r" (result) :: "memory") }
+
+    result
+}
+
 #[inline(always)]
 pub unsafe fn rflags_write(val : u64) { asm!("pushq $0; popfq" :: "r"(val) : "memory" "flags") }
 
@@ -46,4 +56,36 @@ pub fn sp_read() -> u32 {
     unsafe { asm!("mov %esp, $0" : "=r" (result)) }
 
     result
-}
\ No newline at end of file
+}
+const IA32_GS_BASE : u32 = 0xC000_0101;
+
+/// Returns base address of GS segment (IA32_GS_BASE model specific register)
+#[inline(always)]
+pub fn gs_base() -> u64 {
+    let low  : u32;
+    let high : u32;
+
+    unsafe { asm!("rdmsr" : "={eax}" (low), "={edx}" (high) : "{ecx}" (IA32_GS_BASE) :: "volatile") }
+
+    (high as u64) << 32 | low as u64
+}
+
+/// Sets base address of GS segment of the current CPU
+/// # Why unsafe
+/// Code that keeps per CPU data at GS base reads it from the new address
+#[inline(always)]
+pub unsafe fn gs_base_write(address : u64) {
+    asm!("wrmsr" :: "{ecx}" (IA32_GS_BASE), "{eax}" (address as u32), "{edx}" ((address >> 32) as u32) : "memory" : "volatile")
+}
+
+/// Reads 64 bit value at `offset` from GS base
+/// # Why unsafe
+/// GS base must point to valid memory
+#[inline(always)]
+pub unsafe fn gs_read_u64(offset : usize) -> u64 {
+    let result : u64;
+
+    asm!("movq %gs:($1), $0" : "=r" (result) : "r" (offset) :: "volatile");
+
+    result
+}
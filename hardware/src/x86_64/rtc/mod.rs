@@ -0,0 +1,156 @@
+/*
+    Driver of CMOS real time clock. CMOS registers are accessed by writing register index to port 0x70
+    and reading the value from port 0x71, bit 7 of the index disables NMI while the register is selected.
+
+    Registers:
+
+    0x00 - seconds
+    0x02 - minutes
+    0x04 - hours, bit 7 is PM flag in 12 hour mode
+    0x07 - day of month
+    0x08 - month
+    0x09 - year of century
+    0x32 - century, its location comes from ACPI FADT, 0x32 is the usual one
+    0x0a - status A, bit 7 is set while the clock updates registers
+    0x0b - status B, bit 1 - 24 hour mode, bit 2 - values are binary, otherwise BCD
+
+    Registers read during update can be inconsistent, so they are read until two reads in a row give the same values.
+*/
+use ::x86_64::port;
+use stdx::time::DateTime;
+
+const INDEX_PORT              : u16 = 0x70;
+const DATA_PORT               : u16 = 0x71;
+
+const NMI_DISABLE             : u8 = 1 << 7;
+
+const REGISTER_SECOND         : u8 = 0x00;
+const REGISTER_MINUTE         : u8 = 0x02;
+const REGISTER_HOUR           : u8 = 0x04;
+const REGISTER_DAY            : u8 = 0x07;
+const REGISTER_MONTH          : u8 = 0x08;
+const REGISTER_YEAR           : u8 = 0x09;
+const REGISTER_CENTURY        : u8 = 0x32;
+const REGISTER_STATUS_A       : u8 = 0x0a;
+const REGISTER_STATUS_B       : u8 = 0x0b;
+
+const STATUS_A_UPDATING       : u8 = 1 << 7;
+pub const STATUS_B_24_HOUR    : u8 = 1 << 1;
+pub const STATUS_B_BINARY     : u8 = 1 << 2;
+
+const HOUR_PM                 : u8 = 1 << 7;
+
+// used when century register holds garbage
+const DEFAULT_CENTURY         : u16 = 20;
+
+// reads before the clock is considered broken, update takes less than 2 ms
+const MAX_READS               : usize = 1000;
+
+/// Values of clock registers as they are stored in CMOS
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RtcRegisters {
+    pub second   : u8,
+    pub minute   : u8,
+    pub hour     : u8,
+    pub day      : u8,
+    pub month    : u8,
+    pub year     : u8,
+    pub century  : u8,
+    pub status_b : u8
+}
+
+impl RtcRegisters {
+
+    /// Converts register values into date and time
+    /// # Returns
+    /// `None` if values are out of range
+    pub fn date_time(&self) -> Option<DateTime> {
+        let binary = self.status_b & STATUS_B_BINARY != 0;
+        let decode = |value : u8| if binary { value } else { bcd_to_binary(value) };
+
+        let pm   = self.hour & HOUR_PM != 0;
+        let hour = decode(self.hour & !HOUR_PM);
+        let hour = if self.status_b & STATUS_B_24_HOUR != 0 {
+            hour
+        }
+        else {
+            // 12 AM is midnight, 12 PM is noon
+            match (hour, pm) {
+                (12, false)   => 0,
+                (12, true)    => 12,
+                (hour, false) => hour,
+                (hour, true)  => hour + 12
+            }
+        };
+
+        let century = decode(self.century) as u16;
+        let century = if century >= 19 && century <= 99 { century } else { DEFAULT_CENTURY };
+
+        let result = DateTime {
+            year   : century * 100 + decode(self.year) as u16,
+            month  : decode(self.month),
+            day    : decode(self.day),
+            hour,
+            minute : decode(self.minute),
+            second : decode(self.second)
+        };
+
+        let is_valid = result.month >= 1 && result.month <= 12
+            && result.day >= 1 && result.day <= 31
+            && result.hour < 24
+            && result.minute < 60
+            && result.second < 60
+            && result.year >= 1970;
+
+        if is_valid { Some(result) } else { None }
+    }
+}
+
+/// Converts two digit BCD value, e.g. 0x59 into 59
+pub fn bcd_to_binary(value : u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0f)
+}
+
+/// Reads current date and time, it changes once a second
+/// # Returns
+/// `None` if clock keeps updating or gives invalid values
+/// # Why unsafe
+/// Reads I/O ports, interrupts must be disabled because register index and value are accessed separately
+pub unsafe fn read() -> Option<DateTime> {
+    let mut previous : Option<RtcRegisters> = None;
+
+    for _ in 0 .. MAX_READS {
+        if read_register(REGISTER_STATUS_A) & STATUS_A_UPDATING != 0 {
+            continue;
+        }
+
+        let registers = read_registers();
+
+        if previous == Some(registers) {
+            return registers.date_time()
+        }
+
+        previous = Some(registers);
+    }
+
+    None
+}
+
+unsafe fn read_registers() -> RtcRegisters {
+    RtcRegisters {
+        second   : read_register(REGISTER_SECOND),
+        minute   : read_register(REGISTER_MINUTE),
+        hour     : read_register(REGISTER_HOUR),
+        day      : read_register(REGISTER_DAY),
+        month    : read_register(REGISTER_MONTH),
+        year     : read_register(REGISTER_YEAR),
+        century  : read_register(REGISTER_CENTURY),
+        status_b : read_register(REGISTER_STATUS_B)
+    }
+}
+
+unsafe fn read_register(register : u8) -> u8 {
+    port::outb(INDEX_PORT, NMI_DISABLE | register);
+
+    port::inb(DATA_PORT)
+}
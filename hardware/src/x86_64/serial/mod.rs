@@ -0,0 +1,163 @@
+/*
+    Driver of 16550 UART, the serial port of PC compatible machines. Registers are accessed via I/O ports
+    at offsets from port base address:
+
+    0 - transmit holding / receive buffer register, divisor latch low byte when DLAB is set
+    1 - interrupt enable register, divisor latch high byte when DLAB is set
+    2 - FIFO control register
+    3 - line control register, its highest bit is divisor latch access bit (DLAB)
+    4 - modem control register
+    5 - line status register
+
+    Output is written synchronously, waiting until transmitter is ready. Input is interrupt driven:
+    received data interrupt handler moves bytes from UART FIFO into ring buffer, readers take them from there.
+    QEMU forwards COM1 to host terminal when started with `-serial stdio`.
+*/
+use ::x86_64::port;
+use ::x86_64::interrupts::InterruptGuard;
+use stdx::ring_buffer::ByteRingBuffer;
+use core::fmt;
+
+/// UART clock divided by 16, divisor 1 gives max baud rate
+pub const MAX_BAUD_RATE     : u32 = 115200;
+pub const DEFAULT_BAUD_RATE : u32 = 38400;
+
+const DATA_REGISTER             : u16 = 0;
+const INTERRUPT_ENABLE_REGISTER : u16 = 1;
+const FIFO_CONTROL_REGISTER     : u16 = 2;
+const LINE_CONTROL_REGISTER     : u16 = 3;
+const MODEM_CONTROL_REGISTER    : u16 = 4;
+const LINE_STATUS_REGISTER      : u16 = 5;
+
+const DIVISOR_LATCH_ACCESS      : u8 = 1 << 7;
+// 8 data bits, no parity, 1 stop bit
+const LINE_8N1                  : u8 = 0b11;
+// enable and clear FIFOs, interrupt when 14 bytes are received
+const FIFO_ENABLE_CLEAR_14      : u8 = 0xc7;
+// DTR, RTS and OUT2, the latter connects UART interrupt line to PIC
+const MODEM_READY_WITH_IRQ      : u8 = 0x0b;
+const RECEIVED_DATA_INTERRUPT   : u8 = 1;
+
+const LINE_DATA_READY           : u8 = 1;
+const LINE_TRANSMITTER_EMPTY    : u8 = 1 << 5;
+
+/// Standard serial ports with their base I/O port addresses
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum ComPort {
+    Com1 = 0x3f8,
+    Com2 = 0x2f8
+}
+
+impl ComPort {
+    /// Returns PIC line the port raises interrupts on
+    pub fn irq(&self) -> u8 {
+        match *self {
+            ComPort::Com1 => 4,
+            ComPort::Com2 => 3
+        }
+    }
+}
+
+pub struct SerialPort {
+    port           : ComPort,
+    received       : ByteRingBuffer,
+    // bytes lost because ring buffer was full
+    dropped_bytes  : usize
+}
+
+impl SerialPort {
+
+    /// Creates port that must be initialized before use
+    pub const fn new(port : ComPort) -> Self {
+        SerialPort {
+            port,
+            received      : ByteRingBuffer::new(),
+            dropped_bytes : 0
+        }
+    }
+
+    /// Configures UART for 8N1 format with FIFOs and received data interrupt
+    /// # Arguments
+    /// * `baud_rate` - speed in bits per second, must divide `MAX_BAUD_RATE`
+    pub fn initialize(&mut self, baud_rate : u32) {
+        assert!(baud_rate > 0 && baud_rate <= MAX_BAUD_RATE && MAX_BAUD_RATE % baud_rate == 0,
+            "Baud rate {} is not supported, it must divide {}", baud_rate, MAX_BAUD_RATE);
+
+        let divisor = (MAX_BAUD_RATE / baud_rate) as u16;
+
+        unsafe {
+            self.write_register(INTERRUPT_ENABLE_REGISTER, 0);
+
+            self.write_register(LINE_CONTROL_REGISTER, DIVISOR_LATCH_ACCESS);
+            self.write_register(DATA_REGISTER, divisor as u8);
+            self.write_register(INTERRUPT_ENABLE_REGISTER, (divisor >> 8) as u8);
+
+            self.write_register(LINE_CONTROL_REGISTER, LINE_8N1);
+            self.write_register(FIFO_CONTROL_REGISTER, FIFO_ENABLE_CLEAR_14);
+            self.write_register(MODEM_CONTROL_REGISTER, MODEM_READY_WITH_IRQ);
+            self.write_register(INTERRUPT_ENABLE_REGISTER, RECEIVED_DATA_INTERRUPT);
+        }
+    }
+
+    pub fn port(&self) -> ComPort {
+        self.port
+    }
+
+    pub fn dropped_bytes(&self) -> usize {
+        self.dropped_bytes
+    }
+
+    /// Waits until transmitter is ready and sends byte
+    pub fn write_byte(&mut self, byte : u8) {
+        unsafe {
+            while self.read_register(LINE_STATUS_REGISTER) & LINE_TRANSMITTER_EMPTY == 0 {}
+
+            self.write_register(DATA_REGISTER, byte)
+        }
+    }
+
+    /// Takes the oldest received byte
+    pub fn read_byte(&mut self) -> Option<u8> {
+        // interrupt handler pushes into the same buffer
+        let _guard = InterruptGuard::new();
+
+        self.received.pop()
+    }
+
+    /// Moves received bytes from UART into ring buffer, must be called by interrupt handler of the port
+    pub fn handle_interrupt(&mut self) {
+        unsafe {
+            while self.read_register(LINE_STATUS_REGISTER) & LINE_DATA_READY != 0 {
+                let byte = self.read_register(DATA_REGISTER);
+
+                if !self.received.push(byte) {
+                    self.dropped_bytes += 1;
+                }
+            }
+        }
+    }
+
+    unsafe fn read_register(&self, register : u16) -> u8 {
+        port::inb(self.port as u16 + register)
+    }
+
+    unsafe fn write_register(&self, register : u16, value : u8) {
+        port::outb(self.port as u16 + register, value)
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            // terminals expect carriage return before line feed
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+}
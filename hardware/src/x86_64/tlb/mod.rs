@@ -18,4 +18,26 @@ pub unsafe fn flush(virtual_address : usize) {
 /// Cleares all entries from Translation Lookaside Buffer (TLB)
 pub unsafe fn flush_all() {
     cr3_write(cr3())
-}
\ No newline at end of file
+}
+/// Page size that `flush_range` steps with (4 kb, x86-64 spec)
+const PAGE_SIZE : usize = 4096;
+
+/// Max number of pages that are flushed one by one. Bigger ranges will flush whole TLB,
+/// because reloading CR3 is cheaper than issuing INVLPG for every page.
+const FLUSH_ALL_THRESHOLD : usize = 32;
+
+/// Cleares entries of consecutive pages from Translation Lookaside Buffer (TLB) in one go
+///
+/// # Arguments
+/// * `virtual_address_start` - virtual address of the first page
+/// * `pages_count` - number of pages to clear
+pub unsafe fn flush_range(virtual_address_start : usize, pages_count : usize) {
+    if pages_count > FLUSH_ALL_THRESHOLD {
+        flush_all()
+    }
+    else {
+        for i in 0..pages_count {
+            flush(virtual_address_start + i * PAGE_SIZE)
+        }
+    }
+}
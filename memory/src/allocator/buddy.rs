@@ -9,6 +9,7 @@ use stdx::iterator::IteratorExt;
 use allocator::free_list;
 use allocator;
 use stdx::math;
+use core::cmp;
 use stdx::Sequence;
 use paging;
 use paging::page_table;
@@ -52,7 +53,8 @@ pub struct BuddyAllocator {
     start_address          : usize,
     end_address            : usize,
     memory_start_address: usize,
-    page_tables_allocator :  free_list::FreeListAllocator
+    page_tables_allocator :  free_list::FreeListAllocator,
+    maps_pages             : bool
 }
 
 impl BuddyAllocator {
@@ -61,15 +63,61 @@ impl BuddyAllocator {
         self.allocation_sizes[Frame::number_for_address(0)]
     }
 
+    /// Returns number of buddy orders, block of order `n` is FRAME_SIZE * 2 ^ n bytes
+    pub fn orders_count(&self) -> usize {
+        self.buddy_free_lists.length()
+    }
+
+    /// Returns number of free blocks of `order`
+    /// # Arguments
+    /// * `order` - buddy order, must be less than `orders_count`
+    pub fn free_blocks_count(&self, order : usize) -> usize {
+        self.buddy_free_lists[order].free_blocks_count()
+    }
+
     pub fn total_aux_data_structures_size(start_address1 : usize, end_address1 : usize) -> usize {
         let (start_address, end_address)                           = allocator::align_addresses(start_address1, end_address1);
         let total_memory                                                        = allocator::total_memory(start_address, end_address);
-        let (total_frames_count, total_buddy_levels)    = BuddyAllocator::frames_and_buddy_levels(start_address, end_address);
+
+        BuddyAllocator::aux_data_structures_size_for(total_memory)
+    }
+
+    /// Returns size of memory that is placed before allocator working memory: inner data structures
+    /// and page tables reserved to map `total_memory`.
+    /// # Arguments
+    /// * `total_memory` - size of memory the allocator hands out
+    pub fn aux_data_structures_size_for(total_memory : usize) -> usize {
+        let (total_frames_count, total_buddy_levels)    = BuddyAllocator::frames_and_buddy_levels1(total_memory);
         let (array_sizes, buddy_free_lists_size)                = BuddyAllocator::aux_data_structures_size(total_frames_count, total_buddy_levels, total_memory);
 
         let this_aux_structures_size = array_sizes + buddy_free_lists_size;
+        let page_tables_size             = BuddyAllocator::page_tables_count_for(total_memory) * FRAME_SIZE;
+
+        this_aux_structures_size +
+            free_list::FreeListAllocator::aux_data_structures_size_for(buddy_free_lists_size, BuddyMap::cell_size()) +
+            free_list::FreeListAllocator::aux_data_structures_size_for(page_tables_size, FRAME_SIZE) +
+            FRAME_SIZE + // page tables must be frame aligned
+            page_tables_size +
+            BuddyAllocator::memory_align_for(total_memory) - FRAME_SIZE // memory start is aligned by the largest block
+    }
+
+    // alignment of memory start address. Every block is aligned by its size relative to memory start,
+    // so aligning memory start by the largest block makes blocks aligned by their size in absolute addresses too.
+    // Huge page is the limit, otherwise allocator would waste up to a half of its memory on big pools.
+    fn memory_align_for(total_memory : usize) -> usize {
+        let largest_block_size = BuddyAllocator::block_size_from_index(BuddyAllocator::total_buddy_levels(total_memory) - 1);
+
+        cmp::min(largest_block_size, page_table::HUGE_PAGE_SIZE)
+    }
+
+    // max number of page tables needed to map `total_memory` 1 to 1. Memory isn't aligned
+    // by the size a single table covers, so every level can have an extra table on each side.
+    fn page_tables_count_for(total_memory : usize) -> usize {
+        let p1_count = Frame::number_for_address(total_memory) / 512 + 2;
+        let p2_count = p1_count / 512 + 2;
+        let p3_count = p2_count / 512 + 2;
 
-        this_aux_structures_size + free_list::FreeListAllocator::aux_data_structures_size_for(buddy_free_lists_size, BuddyMap::cell_size())
+        p1_count + p2_count + p3_count
     }
 
     fn frames_and_buddy_levels(start_address : usize, end_address : usize) -> (usize, usize) {
@@ -120,6 +168,20 @@ impl BuddyAllocator {
     }
 
     pub fn new(start_address : usize, total_memory : usize, end_address : usize) -> Self {
+        BuddyAllocator::create(start_address, total_memory, end_address, true)
+    }
+
+    /// Creates allocator for memory that is already accessible, e.g. identity mapped by
+    /// the bootloader or not paged at all. Allocated blocks aren't mapped and freed blocks aren't unmapped.
+    /// # Arguments
+    /// * `start_address` - address of allocator inner data structures
+    /// * `total_memory` - size of memory the allocator hands out, must be a power of two
+    /// * `end_address` - end address of the whole range
+    pub fn new_unmapped(start_address : usize, total_memory : usize, end_address : usize) -> Self {
+        BuddyAllocator::create(start_address, total_memory, end_address, false)
+    }
+
+    fn create(start_address : usize, total_memory : usize, end_address : usize, maps_pages : bool) -> Self {
         let (total_frames_count, total_buddy_levels) = BuddyAllocator::frames_and_buddy_levels1(total_memory);
 
         // compute max memory size for inner allocators to work with
@@ -147,12 +209,24 @@ impl BuddyAllocator {
         let idx = if total_buddy_levels > 0 { total_buddy_levels - 1} else { 0 };
         buddy_free_lists_array[idx].set_free(0, &mut free_list_allocator);
 
+        // create page tables allocator, its free list goes right after buddy free lists
+        // and page tables themselves start from the next frame
+        let page_tables_size         = BuddyAllocator::page_tables_count_for(total_memory) * FRAME_SIZE;
+        let page_tables_free_list = free_list_allocator.end_address() + 1;
+        let page_tables_start       = Frame::address_align_up(
+            page_tables_free_list + free_list::FreeListAllocator::aux_data_structures_size_for(page_tables_size, FRAME_SIZE));
+        let page_tables_allocator = free_list::FreeListAllocator::from_size_with_separate_free_list(
+            bump::ConstSizeBumpAllocator::from_size(page_tables_start, page_tables_size, FRAME_SIZE),
+            page_tables_free_list);
+
         // user space memory start
-        let memory_start_address = Frame::address_align_up(free_list_allocator.end_address() + 1);
+        let memory_start_address = math::align_up(page_tables_start + page_tables_size, BuddyAllocator::memory_align_for(total_memory));
 
-        // create page tables allocator
-        let page_tables_allocator = free_list::FreeListAllocator::from_size(free_list_allocator.end_address() + 1, total_memory, FRAME_SIZE);
-        let proper_start_address = page_tables_allocator.object_allocation_start_address();
+        assert!(memory_start_address + total_memory - 1 <= end_address,
+            "Buddy allocator memory doesn't fit into provided range. Memory start : {}, total memory : {}, end address : {}",
+            memory_start_address,
+            total_memory,
+            end_address);
 
         BuddyAllocator {
             allocation_sizes,
@@ -160,9 +234,10 @@ impl BuddyAllocator {
             end_address,
             array_allocator,
             free_list_allocator,
-            start_address : proper_start_address,
+            start_address : memory_start_address,
             memory_start_address,
-            page_tables_allocator
+            page_tables_allocator,
+            maps_pages
         }
     }
 
@@ -341,6 +416,79 @@ impl BuddyAllocator {
     pub fn allocate_frame(&mut self) -> Option<usize> {
         self.allocate(FRAME_SIZE)
     }
+
+    /// Returns size of the block that holds allocation
+    /// # Arguments
+    /// * `pointer` - start address of allocation
+    pub fn allocation_size(&self, pointer : usize) -> usize {
+        let frame_number = Frame::number_for_address(pointer - self.memory_start_address);
+
+        BuddyAllocator::block_size_from_index(self.allocation_sizes[frame_number])
+    }
+
+    /// Grows allocation without moving it. Block can grow only if it is the left one on every
+    /// level up to the requested size and all its right buddies are free, they are taken
+    /// and the block becomes a block of the upper level.
+    /// # Arguments
+    /// * `pointer` - start address of allocation
+    /// * `new_size` - new allocation size
+    /// # Returns
+    /// `true` if allocation now holds at least `new_size` bytes, `false` if it must be moved
+    pub fn grow_in_place(&mut self, pointer : usize, new_size : usize) -> bool {
+        let normalized_pointer   = pointer - self.memory_start_address;
+        let frame_number         = Frame::number_for_address(normalized_pointer);
+        let buddy_list_index     = self.allocation_sizes[frame_number];
+        let new_size_rounded     = BuddyAllocator::allocation_size_rounded(new_size);
+        let new_buddy_list_index = BuddyAllocator::index_from_size(new_size_rounded);
+
+        if new_buddy_list_index <= buddy_list_index {
+            return true
+        }
+
+        if new_size_rounded > self.full_size() {
+            return false
+        }
+
+        let can_grow = (buddy_list_index .. new_buddy_list_index).all(|i| {
+            let block_index = BuddyAllocator::address_to_index(normalized_pointer, i);
+
+            math::is_even(block_index) && self.buddy_free_lists[i].is_free(block_index + 1)
+        });
+
+        if !can_grow {
+            return false
+        }
+
+        for i in buddy_list_index .. new_buddy_list_index {
+            let buddy_index = BuddyAllocator::address_to_index(normalized_pointer, i) + 1;
+
+            self.buddy_free_lists[i].set_in_use(buddy_index, &mut self.free_list_allocator);
+        }
+
+        self.allocation_sizes[frame_number] = new_buddy_list_index;
+
+        let old_block_size = BuddyAllocator::block_size_from_index(buddy_list_index);
+        self.map_pages(pointer + old_block_size, (new_size_rounded - old_block_size) / FRAME_SIZE);
+
+        true
+    }
+
+    fn allocation_size_rounded(size : usize) -> usize {
+        let allocation_size_rounded = (2 as usize).pow(math::log2_align_up(size) as u32);
+
+        if allocation_size_rounded < FRAME_SIZE {
+            FRAME_SIZE
+        } else {
+            allocation_size_rounded
+        }
+    }
+
+    fn map_pages(&mut self, address : usize, frame_count : usize) {
+        if self.maps_pages {
+            let p4_table = paging::p4_table();
+            p4_table.map_pages_1_to_1(address, frame_count, page_table::PRESENT | page_table::WRITABLE, &mut self.page_tables_allocator);
+        }
+    }
 }
 
 
@@ -368,12 +516,7 @@ impl MemoryAllocator for BuddyAllocator {
             None
         }
         else {
-            let allocation_size_rounded0 = (2 as usize).pow(math::log2_align_up(size) as u32);
-            let allocation_size_rounded = if allocation_size_rounded0 < FRAME_SIZE { 
-                FRAME_SIZE
-            } else {
-                allocation_size_rounded0
-            };
+            let allocation_size_rounded = BuddyAllocator::allocation_size_rounded(size);
 
             if allocation_size_rounded > self.full_size() {
                 None
@@ -394,10 +537,9 @@ impl MemoryAllocator for BuddyAllocator {
                     let result_address = result_address + self.memory_start_address;
 
                     // map page frames
-                    let p4_table = paging::p4_table();
                     let frame_count = Frame::number_for_address(allocation_size_rounded);
                     let proper_frame_count =  if frame_count == 0 { 1 } else { frame_count };
-                    p4_table.map_pages_1_to_1(result_address, proper_frame_count , page_table::PRESENT | page_table::WRITABLE, &mut self.page_tables_allocator);
+                    self.map_pages(result_address, proper_frame_count);
 
                     let debug = self.allocation_sizes[Frame::number_for_address(0)];
 
@@ -418,8 +560,32 @@ impl MemoryAllocator for BuddyAllocator {
         self.merge_up(normalized_pointer, buddy_list_index);
 
         // free page frames
-        let p4_table = paging::p4_table();
-        unsafe { p4_table.unmap_pages(pointer, buddy_list_index); }
+        if self.maps_pages {
+            let p4_table = paging::p4_table();
+            let frame_count = BuddyAllocator::block_size_from_index(buddy_list_index) / FRAME_SIZE;
+            unsafe { p4_table.unmap_pages(pointer, frame_count); }
+        }
+    }
+
+    fn allocate_aligned(&mut self, size : usize, align : usize) -> Option<usize> {
+        assert!(math::is_power_of_two(align), "Alignment {} is not a power of two", align);
+
+        // every block is aligned by its size, because memory start address is aligned by the largest block
+        // up to huge page size. Bigger alignment is achieved by allocating block of at least `align` size,
+        // the result has to be checked only for alignments above memory start alignment.
+        if align <= FRAME_SIZE {
+            self.allocate(size)
+        }
+        else {
+            match self.allocate(cmp::max(size, align)) {
+                Some(pointer) if pointer % align == 0 => Some(pointer),
+                Some(pointer) => {
+                    self.free(pointer);
+                    None
+                },
+                None => None
+            }
+        }
     }
 }
 
@@ -484,6 +650,10 @@ impl BuddyFreeList {
         self.buddy_map.0.has_value()
     }    
 
+    fn free_blocks_count(&self) -> usize {
+        self.buddy_map.0.keys_count()
+    }
+
     fn is_merging(&self, block_index : usize) -> bool {
         self.merge_status[block_index]
     }
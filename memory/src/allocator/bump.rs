@@ -4,6 +4,7 @@ use stdx_memory::MemoryAllocatorMeta;
 use core::marker;
 use core::mem;
 use core::ptr;
+use stdx::math;
 use multiboot::multiboot_header::MultibootHeader;
 use multiboot::multiboot_header::tags::memory_map::*;
 use multiboot::multiboot_header::tags::elf;
@@ -173,6 +174,22 @@ impl MemoryAllocator for BumpAllocator {
         }
     }
 
+    fn allocate_aligned(&mut self, size : usize, align : usize) -> Option<usize> {
+        assert!(math::is_power_of_two(align), "Alignment {} is not a power of two", align);
+
+        // memory between current pointer and aligned address is skipped
+        let aligned_pointer = math::align_up(self.current_pointer, align);
+
+        if aligned_pointer + size > self.pointer_end_address {
+            None
+        }
+        else {
+            self.current_pointer = aligned_pointer + size;
+
+            Some(aligned_pointer)
+        }
+    }
+
     fn free(&mut self, size: usize) {
         self.current_pointer -= size;
     }
@@ -0,0 +1,347 @@
+/*
+    Object cache keeps objects of a single type in dedicated slabs, so that frequently created
+    kernel objects don't fragment general purpose heap. Slab is a frame aligned block of `slab_size` bytes
+    taken from the page source:
+
+    | CacheSlab header | stack of free object indices | bitmap of free objects | padding | object 0 | object 1 | ... |
+
+    Slab of an object is found by walking the slab list, so slabs bigger than a frame don't need
+    alignment the page source may not provide. Bitmap tells if the object is free, it catches double frees.
+    If cache has a constructor, objects are constructed once when their slab is created and are kept
+    in constructed state while they are free, like kmem_cache in Linux. Destructor is called when
+    slab is released by `shrink` or when the cache is dropped. Dropped cache gives all its slabs back
+    to the page source, objects that are still in use must not be touched afterwards.
+    Slabs are frame aligned, so objects can't require bigger alignment.
+*/
+use stdx_memory::MemoryAllocator;
+use stdx::math;
+use frame::FRAME_SIZE;
+use core::cmp;
+use core::fmt;
+use core::mem;
+use core::ptr;
+use core::marker::PhantomData;
+
+// slab grows until it fits at least that many objects
+const MIN_OBJECTS_PER_SLAB : usize = 8;
+
+type ObjectIndex = u16;
+
+struct CacheSlab {
+    next       : *mut CacheSlab,
+    // number of free objects, top of the free indices stack
+    free_count : usize
+}
+
+pub struct ObjectCache<T, A> where A : MemoryAllocator {
+    name             : &'static str,
+    constructor      : Option<fn() -> T>,
+    destructor       : Option<fn(T)>,
+    object_size      : usize,
+    objects_offset   : usize,
+    objects_per_slab : usize,
+    slab_size        : usize,
+    slabs            : *mut CacheSlab,
+    slabs_count      : usize,
+    objects_in_use   : usize,
+    page_source      : A,
+    phantom          : PhantomData<T>
+}
+
+impl<T, A> ObjectCache<T, A> where A : MemoryAllocator {
+
+    /// Creates empty cache, slabs are allocated on demand
+    /// # Arguments
+    /// * `name` - cache name used in diagnostics
+    /// * `constructor` - creates object when its slab is created, objects are left uninitialized if not set
+    /// * `destructor` - receives constructed objects when their slab is released
+    /// * `page_source` - allocator that gives memory for slabs, must support frame alignment
+    pub fn new(name : &'static str, constructor : Option<fn() -> T>, destructor : Option<fn(T)>, page_source : A) -> Self {
+        assert!(mem::align_of::<T>() <= FRAME_SIZE, "Cache {} can't align objects by {}, slabs are aligned by {}", name, mem::align_of::<T>(), FRAME_SIZE);
+
+        let object_size = math::align_up(cmp::max(mem::size_of::<T>(), 1), mem::align_of::<T>());
+        let (slab_size, objects_per_slab, objects_offset) = ObjectCache::<T, A>::slab_layout(object_size);
+
+        ObjectCache {
+            name,
+            constructor,
+            destructor,
+            object_size,
+            objects_offset,
+            objects_per_slab,
+            slab_size,
+            slabs            : ptr::null_mut(),
+            slabs_count      : 0,
+            objects_in_use   : 0,
+            page_source,
+            phantom          : PhantomData
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn slab_size(&self) -> usize {
+        self.slab_size
+    }
+
+    pub fn objects_per_slab(&self) -> usize {
+        self.objects_per_slab
+    }
+
+    pub fn slabs_count(&self) -> usize {
+        self.slabs_count
+    }
+
+    pub fn objects_in_use(&self) -> usize {
+        self.objects_in_use
+    }
+
+    /// Takes object from the cache, creates new slab if all slabs are full.
+    /// Object is constructed if cache has a constructor, otherwise its memory is uninitialized.
+    /// # Returns
+    /// Pointer to object or `None` if page source is out of memory
+    pub fn allocate(&mut self) -> Option<ptr::NonNull<T>> {
+        let slab = self.slab_with_free_object().or_else(|| self.grow());
+
+        slab.map(|slab| unsafe {
+            (*slab).free_count -= 1;
+
+            let index = *self.free_indices(slab).offset((*slab).free_count as isize);
+
+            self.set_object_free(slab, index as usize, false);
+            self.objects_in_use += 1;
+
+            ptr::NonNull::new_unchecked(self.object_address(slab, index as usize) as *mut T)
+        })
+    }
+
+    /// Takes object from the cache and moves `value` into it, previous object content is overwritten without drop.
+    /// Meant for caches without constructor.
+    /// # Arguments
+    /// * `value` - initial object value
+    pub fn allocate_with(&mut self, value : T) -> Option<ptr::NonNull<T>> {
+        self.allocate().map(|object| {
+            unsafe { ptr::write(object.as_ptr(), value); }
+
+            object
+        })
+    }
+
+    /// Returns object to the cache. Object must be in constructed state if cache has a constructor,
+    /// otherwise the caller is responsible to drop it before.
+    /// # Arguments
+    /// * `object` - object previously taken from this cache
+    pub fn free(&mut self, object : ptr::NonNull<T>) {
+        let address = object.as_ptr() as usize;
+        let slab    = match self.slab_of(address) {
+            Some(slab) if address >= self.object_address(slab, 0) => slab,
+            _                                                     => panic!("Object {:#x} doesn't belong to cache {}", address, self.name)
+        };
+        let offset  = address - self.object_address(slab, 0);
+        let index   = offset / self.object_size;
+
+        assert!(offset % self.object_size == 0, "Object {:#x} of cache {} doesn't point to the object start", address, self.name);
+        assert!(index < self.objects_per_slab, "Object {:#x} doesn't belong to cache {}", address, self.name);
+        assert!(!self.is_object_free(slab, index), "Cache {} double free of object {:#x}", self.name, address);
+
+        unsafe {
+            self.set_object_free(slab, index, true);
+            *self.free_indices(slab).offset((*slab).free_count as isize) = index as ObjectIndex;
+            (*slab).free_count += 1;
+        }
+
+        self.objects_in_use -= 1;
+    }
+
+    /// Releases slabs that have no objects in use back to the page source
+    /// # Returns
+    /// Size of released memory in bytes
+    pub fn shrink(&mut self) -> usize {
+        let mut released = 0;
+        let mut link     = &mut self.slabs as *mut *mut CacheSlab;
+
+        unsafe {
+            while !(*link).is_null() {
+                let slab = *link;
+
+                if (*slab).free_count == self.objects_per_slab {
+                    *link = (*slab).next;
+
+                    self.release_slab(slab);
+                    released += self.slab_size;
+                }
+                else {
+                    link = &mut (*slab).next as *mut *mut CacheSlab;
+                }
+            }
+        }
+
+        released
+    }
+
+    // picks slab size that fits at least MIN_OBJECTS_PER_SLAB objects,
+    // returns slab size, objects per slab and offset of the first object
+    fn slab_layout(object_size : usize) -> (usize, usize, usize) {
+        let header_size = mem::size_of::<CacheSlab>();
+        let align       = cmp::max(mem::align_of::<T>(), mem::align_of::<ObjectIndex>());
+        let mut slab_size = FRAME_SIZE;
+
+        loop {
+            let mut objects_count = (slab_size - header_size) / (object_size + mem::size_of::<ObjectIndex>());
+            let objects_offset    = |count : usize| math::align_up(
+                header_size + count * mem::size_of::<ObjectIndex>() + ObjectCache::<T, A>::bitmap_size(count),
+                align);
+
+            while objects_count > 0 && objects_offset(objects_count) + objects_count * object_size > slab_size {
+                objects_count -= 1;
+            }
+
+            if objects_count >= MIN_OBJECTS_PER_SLAB {
+                let objects_count = cmp::min(objects_count, ObjectIndex::max_value() as usize);
+
+                return (slab_size, objects_count, objects_offset(objects_count))
+            }
+
+            slab_size *= 2;
+        }
+    }
+
+    fn slab_with_free_object(&self) -> Option<*mut CacheSlab> {
+        let mut slab = self.slabs;
+
+        unsafe {
+            while !slab.is_null() {
+                if (*slab).free_count > 0 {
+                    return Some(slab)
+                }
+
+                slab = (*slab).next;
+            }
+        }
+
+        None
+    }
+
+    // slab which memory contains `address`
+    fn slab_of(&self, address : usize) -> Option<*mut CacheSlab> {
+        let mut slab = self.slabs;
+
+        unsafe {
+            while !slab.is_null() {
+                if address >= slab as usize && address < slab as usize + self.slab_size {
+                    return Some(slab)
+                }
+
+                slab = (*slab).next;
+            }
+        }
+
+        None
+    }
+
+    fn grow(&mut self) -> Option<*mut CacheSlab> {
+        self.page_source.allocate_aligned(self.slab_size, FRAME_SIZE).map(|address| unsafe {
+            let slab = address as *mut CacheSlab;
+
+            ptr::write(slab, CacheSlab {
+                next       : self.slabs,
+                free_count : self.objects_per_slab
+            });
+
+            // lower indices are on top of the stack
+            for i in 0..self.objects_per_slab {
+                *self.free_indices(slab).offset(i as isize) = (self.objects_per_slab - 1 - i) as ObjectIndex;
+                self.set_object_free(slab, i, true);
+            }
+
+            if let Some(constructor) = self.constructor {
+                for i in 0..self.objects_per_slab {
+                    ptr::write(self.object_address(slab, i) as *mut T, constructor());
+                }
+            }
+
+            self.slabs        = slab;
+            self.slabs_count += 1;
+
+            slab
+        })
+    }
+
+    unsafe fn release_slab(&mut self, slab : *mut CacheSlab) {
+        // only free objects created by constructor are in a valid state, objects in use belong to their owners
+        if self.constructor.is_some() {
+            for i in (0..self.objects_per_slab).filter(|i| self.is_object_free(slab, *i)) {
+                let object = ptr::read(self.object_address(slab, i) as *const T);
+
+                match self.destructor {
+                    Some(destructor) => destructor(object),
+                    None             => mem::drop(object)
+                }
+            }
+        }
+
+        self.page_source.free(slab as usize);
+        self.slabs_count -= 1;
+    }
+
+    fn free_indices(&self, slab : *mut CacheSlab) -> *mut ObjectIndex {
+        (slab as usize + mem::size_of::<CacheSlab>()) as *mut ObjectIndex
+    }
+
+    fn object_address(&self, slab : *mut CacheSlab, index : usize) -> usize {
+        slab as usize + self.objects_offset + index * self.object_size
+    }
+
+    fn bitmap_size(objects_count : usize) -> usize {
+        (objects_count + 7) / 8
+    }
+
+    // bitmap goes right after the stack of free indices
+    fn free_bitmap(&self, slab : *mut CacheSlab) -> *mut u8 {
+        (self.free_indices(slab) as usize + self.objects_per_slab * mem::size_of::<ObjectIndex>()) as *mut u8
+    }
+
+    fn is_object_free(&self, slab : *mut CacheSlab, index : usize) -> bool {
+        unsafe { *self.free_bitmap(slab).offset((index / 8) as isize) & (1 << (index % 8)) != 0 }
+    }
+
+    fn set_object_free(&self, slab : *mut CacheSlab, index : usize, free : bool) {
+        unsafe {
+            let byte = self.free_bitmap(slab).offset((index / 8) as isize);
+
+            if free {
+                *byte |= 1 << (index % 8);
+            }
+            else {
+                *byte &= !(1 << (index % 8));
+            }
+        }
+    }
+}
+
+impl<T, A> Drop for ObjectCache<T, A> where A : MemoryAllocator {
+    fn drop(&mut self) {
+        unsafe {
+            while !self.slabs.is_null() {
+                let slab = self.slabs;
+
+                self.slabs = (*slab).next;
+                self.release_slab(slab);
+            }
+        }
+    }
+}
+
+impl<T, A> fmt::Display for ObjectCache<T, A> where A : MemoryAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cache {}: object {} b, {} slabs of {} kb, {} in use, {} free",
+            self.name,
+            self.object_size,
+            self.slabs_count,
+            self.slab_size / 1024,
+            self.objects_in_use,
+            self.slabs_count * self.objects_per_slab - self.objects_in_use)
+    }
+}
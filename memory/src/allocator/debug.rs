@@ -0,0 +1,129 @@
+/*
+    Heap debugging support, enabled by `heap_debug` feature. Every heap allocation is surrounded by
+    guard data:
+
+    | padding | header | red zone | user memory | red zone |
+              ^                   ^
+              |                   pointer returned to the caller, aligned as requested
+              block start + padding - RED_ZONE_SIZE - HEADER_SIZE
+
+    Header stores magic value that tells if the allocation is alive or was freed, thus double free
+    and free of unknown address are detected. It also stores subsystem the allocation is charged to, see `leaks`. Red zones are filled with a known pattern and checked on free
+    to detect writes before the allocation start and past its end. Freed memory is filled with poison pattern, so
+    that use after free reads produce recognizable values.
+    Detected errors are reported via panic with the offending address.
+*/
+use allocator::leaks;
+use stdx::math;
+use core::cmp;
+use core::mem;
+use core::ptr;
+
+pub const RED_ZONE_SIZE        : usize = 16;
+pub const RED_ZONE_BYTE        : u8    = 0xfd;
+pub const POISON_BYTE          : u8    = 0x6b;
+pub const UNINITIALIZED_BYTE   : u8    = 0xa5;
+
+const ALLOCATED_MAGIC : usize = 0xa110_ca7e_d0d0_cafe;
+const FREED_MAGIC     : usize = 0xf7ee_d0d0_dead_beef;
+
+#[repr(C)]
+struct AllocationHeader {
+    magic     : usize,
+    size      : usize,
+    padding   : usize,
+    subsystem : usize
+}
+
+const HEADER_SIZE : usize = 32;
+
+/// Returns size of block that holds allocation of `size` bytes together with guard data
+/// # Arguments
+/// * `size` - requested allocation size
+/// * `align` - requested alignment, must be a power of two
+pub fn block_size_for(size : usize, align : usize) -> usize {
+    padding_for(align) + size + RED_ZONE_SIZE
+}
+
+/// Writes guard data into the block and returns pointer that is handed out to the caller
+/// # Arguments
+/// * `block` - start address of block of `block_size_for(size, align)` bytes, aligned by `align`
+/// * `size` - requested allocation size
+/// * `align` - requested alignment, must be a power of two
+/// # Why unsafe
+/// Writes to arbitrary memory
+pub unsafe fn on_allocate(block : usize, size : usize, align : usize) -> usize {
+    let padding = padding_for(align);
+    let pointer = block + padding;
+
+    ptr::write(header_for(pointer), AllocationHeader {
+        magic     : ALLOCATED_MAGIC,
+        size,
+        padding,
+        subsystem : leaks::charge(size)
+    });
+
+    ptr::write_bytes((pointer - RED_ZONE_SIZE) as *mut u8, RED_ZONE_BYTE, RED_ZONE_SIZE);
+    ptr::write_bytes(pointer as *mut u8, UNINITIALIZED_BYTE, size);
+    ptr::write_bytes((pointer + size) as *mut u8, RED_ZONE_BYTE, RED_ZONE_SIZE);
+
+    pointer
+}
+
+/// Checks guard data of allocation, poisons its memory and returns start address of block to free.
+/// Panics on double free, free of address that wasn't allocated and on corruption of red zones.
+/// # Arguments
+/// * `pointer` - pointer returned from `on_allocate`
+/// # Why unsafe
+/// Reads and writes memory around `pointer`
+pub unsafe fn on_free(pointer : usize) -> usize {
+    let header = &mut *header_for(pointer);
+
+    if header.magic == FREED_MAGIC {
+        panic!("Heap double free of address {:#x}, size {}", pointer, header.size)
+    }
+    else if header.magic != ALLOCATED_MAGIC {
+        panic!("Heap free of address {:#x} that wasn't allocated or its header is corrupted", pointer)
+    }
+
+    if let Some(offset) = red_zone_corruption(pointer - RED_ZONE_SIZE) {
+        panic!("Heap buffer underflow: allocation at {:#x} of size {} was overwritten at {:#x}", pointer, header.size, pointer - RED_ZONE_SIZE + offset)
+    }
+
+    if let Some(offset) = red_zone_corruption(pointer + header.size) {
+        panic!("Heap buffer overflow: allocation at {:#x} of size {} was overwritten at {:#x}", pointer, header.size, pointer + header.size + offset)
+    }
+
+    header.magic = FREED_MAGIC;
+    leaks::uncharge(header.subsystem, header.size);
+    ptr::write_bytes((pointer - RED_ZONE_SIZE) as *mut u8, POISON_BYTE, RED_ZONE_SIZE + header.size + RED_ZONE_SIZE);
+
+    pointer - header.padding
+}
+
+/// Returns size that was requested for the allocation
+/// # Arguments
+/// * `pointer` - pointer returned from `on_allocate`
+/// # Why unsafe
+/// Reads memory before `pointer`
+pub unsafe fn allocation_size(pointer : usize) -> usize {
+    (*header_for(pointer)).size
+}
+
+// header and leading red zone must fit before the pointer, while the pointer keeps requested alignment
+fn padding_for(align : usize) -> usize {
+    assert!(math::is_power_of_two(align), "Alignment {} is not a power of two", align);
+
+    math::align_up(HEADER_SIZE + RED_ZONE_SIZE, cmp::max(align, mem::align_of::<AllocationHeader>()))
+}
+
+fn header_for(pointer : usize) -> *mut AllocationHeader {
+    (pointer - RED_ZONE_SIZE - HEADER_SIZE) as *mut AllocationHeader
+}
+
+// returns offset of the first corrupted byte of red zone that starts at `address`
+unsafe fn red_zone_corruption(address : usize) -> Option<usize> {
+    let red_zone = address as *const u8;
+
+    (0 .. RED_ZONE_SIZE).find(|offset| *red_zone.offset(*offset as isize) != RED_ZONE_BYTE)
+}
@@ -7,6 +7,11 @@ use core::mem;
 
 type LinkedListBlock = LinkedList<usize>;
 
+/// Allocator of constant size blocks that reuses freed blocks before taking new ones from its bump allocator.
+/// It gets `MemoryAllocator` through `ConstantSizeMemoryAllocator`, so `allocate_aligned` is the default one:
+/// blocks are placed back to back from the start address and any block can be on top of the free list,
+/// thus alignment is guaranteed only if start address and block size are multiples of it, otherwise
+/// the taken block is returned and allocation fails.
 #[repr(C)]
 pub struct FreeListAllocator {
     bump_allocator :         ConstSizeBumpAllocator,
@@ -0,0 +1,227 @@
+/*
+    Kernel heap, the global allocator of the kernel. Owns the slab allocator together with
+    per CPU magazines in front of it, so it can live in a static and is initialized once,
+    when memory map is known. Heap is used from interrupt handlers as well, thus every entry point
+    disables interrupts before touching magazines or locking slab allocator, otherwise a handler
+    could interrupt the code that holds the lock and spin on it forever.
+*/
+use stdx_memory::{MemoryAllocator, MemoryAllocatorMeta};
+use allocator::slab::SlabAllocator;
+use allocator::statistics::AllocatorStatistics;
+use allocator::oom::OOM_HANDLER;
+use allocator::magazine::{MagazineAllocator, HardwareCpu};
+#[cfg(feature = "heap_debug")]
+use allocator::debug;
+use hardware::x86_64::interrupts::InterruptGuard;
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "heap_debug")]
+use core::cmp;
+#[cfg(feature = "heap_debug")]
+use core::ptr;
+
+/// Slab allocator shared by all CPUs with per CPU magazines in front of it
+pub type SlabMagazines = MagazineAllocator<SlabAllocator, HardwareCpu>;
+
+const UNINITIALIZED : usize = 0;
+const INITIALIZING  : usize = 1;
+const READY         : usize = 2;
+
+pub struct KernelHeap {
+    state     : AtomicUsize,
+    magazines : UnsafeCell<Option<SlabMagazines>>
+}
+
+// magazines are written only once by `initialize` before state becomes READY,
+// afterwards they are accessed with interrupts disabled
+unsafe impl Sync for KernelHeap {}
+
+impl KernelHeap {
+
+    /// Creates heap that must be initialized before the first allocation
+    pub const fn new() -> Self {
+        KernelHeap {
+            state     : AtomicUsize::new(UNINITIALIZED),
+            magazines : UnsafeCell::new(None)
+        }
+    }
+
+    /// Hands memory of `slab_allocator` over to the heap. Panics if heap is already initialized.
+    /// # Arguments
+    /// * `slab_allocator` - allocator created from the free memory regions
+    pub fn initialize(&self, slab_allocator : SlabAllocator) {
+        if self.state.compare_and_swap(UNINITIALIZED, INITIALIZING, Ordering::Acquire) != UNINITIALIZED {
+            panic!("Kernel heap is already initialized")
+        }
+
+        unsafe { *self.magazines.get() = Some(MagazineAllocator::new(slab_allocator, HardwareCpu::new())); }
+
+        self.state.store(READY, Ordering::Release);
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.state.load(Ordering::Acquire) == READY
+    }
+
+    /// Runs `f` with shared slab allocator locked and interrupts disabled
+    pub fn with_slab_allocator<F, R>(&self, f : F) -> R where F : FnOnce(&mut SlabAllocator) -> R {
+        let magazines = self.magazines();
+        let _guard    = InterruptGuard::new();
+
+        // lock is released before interrupts are enabled back
+        let result = f(&mut *magazines.depot());
+
+        result
+    }
+
+    /// Returns objects cached by the current CPU to slab allocator and checks if it is fully free
+    pub fn is_fully_free(&self) -> bool {
+        let magazines = self.magazines();
+        let _guard    = InterruptGuard::new();
+
+        magazines.drain();
+
+        self.with_slab_allocator(|slab_allocator| slab_allocator.is_fully_free())
+    }
+
+    /// Returns objects cached by the current CPU to slab allocator
+    /// # Returns
+    /// Number of returned objects
+    pub fn drain(&self) -> usize {
+        let magazines = self.magazines();
+        let _guard    = InterruptGuard::new();
+
+        magazines.drain()
+    }
+
+    pub fn statistics(&self) -> AllocatorStatistics {
+        self.with_slab_allocator(|slab_allocator| slab_allocator.statistics())
+    }
+
+    fn magazines(&self) -> &SlabMagazines {
+        if !self.is_initialized() {
+            panic!("Kernel heap is used before initialization")
+        }
+
+        unsafe { (*self.magazines.get()).as_ref().unwrap() }
+    }
+
+    // on failure lets out of memory handler report it and reclaim memory, returns objects cached by
+    // the current CPU to slab allocator and retries once if anything was reclaimed.
+    // Slab allocator isn't locked while shrinkers run, because they free memory into it.
+    unsafe fn allocate_or_reclaim<F>(&self, layout : &Layout, mut allocate : F) -> *mut u8
+    where F : FnMut(&SlabMagazines) -> Option<usize> {
+        let magazines = self.magazines();
+        let _guard    = InterruptGuard::new();
+
+        let result = match allocate(magazines) {
+            Some(pointer) => Some(pointer),
+            None => {
+                let handler_reclaimed = OOM_HANDLER.handle_allocation_failure(layout, || self.statistics());
+                let drained_objects   = magazines.drain();
+
+                if handler_reclaimed || drained_objects > 0 {
+                    allocate(magazines)
+                }
+                else {
+                    None
+                }
+            }
+        };
+
+        result
+            .map(|a| a as * mut u8)
+            .unwrap_or(0 as * mut u8)
+    }
+
+    // heap entry points, with `heap_debug` feature allocations are surrounded by guard data that is checked on free
+
+    #[cfg(not(feature = "heap_debug"))]
+    unsafe fn heap_allocate(magazines : &SlabMagazines, size : usize, align : usize) -> Option<usize> {
+        magazines.allocate(size, align)
+    }
+
+    #[cfg(feature = "heap_debug")]
+    unsafe fn heap_allocate(magazines : &SlabMagazines, size : usize, align : usize) -> Option<usize> {
+        magazines.allocate(debug::block_size_for(size, align), align)
+            .map(|block| debug::on_allocate(block, size, align))
+    }
+
+    #[cfg(not(feature = "heap_debug"))]
+    unsafe fn heap_free(magazines : &SlabMagazines, pointer : usize, size : usize, align : usize) {
+        magazines.free(pointer, size, align)
+    }
+
+    #[cfg(feature = "heap_debug")]
+    unsafe fn heap_free(magazines : &SlabMagazines, pointer : usize, size : usize, align : usize) {
+        let block = debug::on_free(pointer);
+
+        magazines.free(block, debug::block_size_for(size, align), align)
+    }
+
+    // size class changes go through magazines, only memory that bypasses them can be resized in place by slab allocator
+    #[cfg(not(feature = "heap_debug"))]
+    unsafe fn heap_reallocate(magazines : &SlabMagazines, pointer : usize, old_size : usize, align : usize, new_size : usize) -> Option<usize> {
+        magazines.reallocate(pointer, old_size, align, new_size, |slab_allocator| slab_allocator.reallocate(pointer, old_size, align, new_size))
+    }
+
+    #[cfg(feature = "heap_debug")]
+    unsafe fn heap_reallocate(magazines : &SlabMagazines, pointer : usize, old_size : usize, align : usize, new_size : usize) -> Option<usize> {
+        KernelHeap::heap_allocate(magazines, new_size, align).map(|new_pointer| {
+            ptr::copy_nonoverlapping(pointer as *const u8, new_pointer as *mut u8, cmp::min(old_size, new_size));
+
+            KernelHeap::heap_free(magazines, pointer, old_size, align);
+
+            new_pointer
+        })
+    }
+}
+
+// allows to use heap as page source of object caches
+impl<'a> MemoryAllocatorMeta for &'a KernelHeap {
+    fn start_address(&self) -> usize {
+        self.with_slab_allocator(|slab_allocator| slab_allocator.start_address())
+    }
+
+    fn end_address(&self) -> usize {
+        self.with_slab_allocator(|slab_allocator| slab_allocator.end_address())
+    }
+
+    fn aux_data_structures_size(&self) -> usize {
+        self.with_slab_allocator(|slab_allocator| slab_allocator.aux_data_structures_size())
+    }
+}
+
+impl<'a> MemoryAllocator for &'a KernelHeap {
+    fn allocate(&mut self, size : usize) -> Option<usize> {
+        self.with_slab_allocator(|slab_allocator| slab_allocator.allocate(size))
+    }
+
+    fn allocate_aligned(&mut self, size : usize, align : usize) -> Option<usize> {
+        self.with_slab_allocator(|slab_allocator| slab_allocator.allocate_aligned(size, align))
+    }
+
+    fn free(&mut self, pointer : usize) {
+        self.with_slab_allocator(|slab_allocator| slab_allocator.free(pointer))
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate_or_reclaim(&layout, |magazines| KernelHeap::heap_allocate(magazines, layout.size(), layout.align()))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let magazines = self.magazines();
+        let _guard    = InterruptGuard::new();
+
+        KernelHeap::heap_free(magazines, ptr as usize, layout.size(), layout.align())
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+
+        self.allocate_or_reclaim(&new_layout, |magazines| KernelHeap::heap_reallocate(magazines, ptr as usize, layout.size(), layout.align(), new_size))
+    }
+}
@@ -0,0 +1,143 @@
+/*
+    Leak reporting by subsystem, enabled by `heap_debug` feature. Code marks the subsystem it works for
+    with `with_subsystem`, every heap allocation made meanwhile is charged to that subsystem. Debug header
+    of the allocation remembers the subsystem, so free uncharges the same subsystem wherever it happens.
+    Report lists objects and bytes every subsystem still holds, a subsystem that only grows is leaking.
+
+    Allocations made outside of any subsystem aren't tracked. The current subsystem is kernel wide, not per CPU,
+    which is exact as long as subsystems are entered on the boot CPU only.
+*/
+use stdx::sync::SpinLock;
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub const MAX_SUBSYSTEMS : usize = 16;
+
+/// Value of debug header of allocation that isn't charged to any subsystem
+pub const UNTRACKED : usize = 0;
+
+/// Heap user, e.g. a driver. Lives in a static, thus allocations can refer to it by address.
+pub struct Subsystem {
+    name    : &'static str,
+    objects : AtomicUsize,
+    bytes   : AtomicUsize
+}
+
+impl Subsystem {
+    pub const fn new(name : &'static str) -> Self {
+        Subsystem {
+            name,
+            objects : AtomicUsize::new(0),
+            bytes   : AtomicUsize::new(0)
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns number of live allocations charged to the subsystem
+    pub fn objects(&self) -> usize {
+        self.objects.load(Ordering::SeqCst)
+    }
+
+    /// Returns number of bytes the subsystem requested in its live allocations
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::SeqCst)
+    }
+}
+
+// subsystems are registered when they are entered for the first time
+static SUBSYSTEMS : SpinLock<[Option<&'static Subsystem>; MAX_SUBSYSTEMS]> = SpinLock::new([None; MAX_SUBSYSTEMS]);
+
+static CURRENT_SUBSYSTEM : AtomicUsize = AtomicUsize::new(UNTRACKED);
+
+/// Runs `f` charging heap allocations it makes to `subsystem`, calls can be nested
+/// # Arguments
+/// * `subsystem` - subsystem `f` works for, registered on the first call, at most `MAX_SUBSYSTEMS` of them
+pub fn with_subsystem<F, R>(subsystem : &'static Subsystem, f : F) -> R where F : FnOnce() -> R {
+    register(subsystem);
+
+    let previous = CURRENT_SUBSYSTEM.swap(subsystem as *const _ as usize, Ordering::SeqCst);
+    let result   = f();
+
+    CURRENT_SUBSYSTEM.store(previous, Ordering::SeqCst);
+
+    result
+}
+
+/// Charges allocation to the current subsystem
+/// # Arguments
+/// * `size` - requested allocation size
+/// # Returns
+/// Subsystem to uncharge when the allocation is freed, `UNTRACKED` outside of subsystems
+pub fn charge(size : usize) -> usize {
+    let subsystem = CURRENT_SUBSYSTEM.load(Ordering::SeqCst);
+
+    if let Some(subsystem) = subsystem_at(subsystem) {
+        subsystem.objects.fetch_add(1, Ordering::SeqCst);
+        subsystem.bytes.fetch_add(size, Ordering::SeqCst);
+    }
+
+    subsystem
+}
+
+/// Uncharges freed allocation
+/// # Arguments
+/// * `subsystem` - value returned by `charge` for the allocation
+/// * `size` - requested allocation size
+pub fn uncharge(subsystem : usize, size : usize) {
+    if let Some(subsystem) = subsystem_at(subsystem) {
+        subsystem.objects.fetch_sub(1, Ordering::SeqCst);
+        subsystem.bytes.fetch_sub(size, Ordering::SeqCst);
+    }
+}
+
+/// Returns snapshot of heap usage of every registered subsystem
+pub fn report() -> LeakReport {
+    LeakReport { subsystems : *SUBSYSTEMS.lock() }
+}
+
+fn register(subsystem : &'static Subsystem) {
+    let mut subsystems = SUBSYSTEMS.lock();
+
+    if subsystems.iter().any(|registered| registered.map_or(false, |registered| ptr::eq(registered, subsystem))) {
+        return
+    }
+
+    match subsystems.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => *slot = Some(subsystem),
+        None       => panic!("Too many heap subsystems, max is {}", MAX_SUBSYSTEMS)
+    }
+}
+
+fn subsystem_at(address : usize) -> Option<&'static Subsystem> {
+    if address == UNTRACKED {
+        None
+    }
+    else {
+        Some(unsafe { &*(address as *const Subsystem) })
+    }
+}
+
+/// Heap usage of subsystems
+pub struct LeakReport {
+    subsystems : [Option<&'static Subsystem>; MAX_SUBSYSTEMS]
+}
+
+impl LeakReport {
+    pub fn subsystems<'a>(&'a self) -> impl Iterator<Item = &'static Subsystem> + 'a {
+        self.subsystems.iter().filter_map(|subsystem| *subsystem)
+    }
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for subsystem in self.subsystems() {
+            writeln!(f, "{:<16} {} objects, {} b", subsystem.name(), subsystem.objects(), subsystem.bytes())?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+/*
+    Magazine layer on top of a shared allocator (depot), as described in
+    "Magazines and Vmem: Extending the Slab Allocator to Many CPUs and Arbitrary Resources" by Jeff Bonwick.
+
+    Every CPU keeps two magazines (fixed size stacks of free objects) per object size class: loaded and previous.
+    Allocation pops an object from the loaded magazine, free pushes an object into it. When loaded magazine
+    is empty (for allocation) or full (for free) it is swapped with the previous one. Depot is locked only when both
+    magazines can't satisfy the request: then loaded magazine is refilled with a batch of objects from depot,
+    or previous magazine is flushed into depot. Thus most allocations and frees touch only CPU local data.
+
+    Objects bigger than the biggest class go straight to depot.
+    CPU ids (local APIC ids) don't have to be dense, every CPU takes a free slot on its first call.
+    If all `MAX_CPUS` slots are taken, the other CPUs work with depot directly, so they never fail.
+    Per CPU data isn't locked, so the code that uses the allocator must not be interrupted by other code
+    that uses it on the same CPU, e.g. by disabling interrupts around the calls.
+
+    Allocator asks for CPU id on every call, and cpuid is serializing and traps to hypervisor in virtual machines.
+    So every CPU reads its APIC id once and keeps it in a slot that GS base of the CPU points to,
+    one memory read gives the id afterwards.
+*/
+use stdx_memory::MemoryAllocator;
+use stdx::math;
+use stdx::sync::{SpinLock, SpinLockGuard};
+use hardware::x86_64::cpuid;
+use hardware::x86_64::registers;
+use core::cell::UnsafeCell;
+use core::cmp;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Max number of CPUs that get magazines
+pub const MAX_CPUS           : usize = 8;
+pub const MAGAZINE_SIZE      : usize = 16;
+pub const MIN_OBJECT_SIZE    : usize = 32;
+pub const MAX_OBJECT_SIZE    : usize = 2048;
+// object sizes 32, 64 ... 2048
+pub const MAGAZINE_CLASSES   : usize = 7;
+
+/// Tells which CPU the code runs on
+pub trait CpuContext {
+    /// Returns id of the current CPU, ids are unique but may be sparse
+    fn cpu_id(&self) -> usize;
+}
+
+// APIC ids are 8 bit, so every CPU gets a slot
+const MAX_APIC_IDS : usize = 256;
+
+// APIC id of every CPU that was set up by `HardwareCpu`, GS base of a CPU points to its slot.
+// Slot is written once by the CPU that claimed it, before GS base points to it
+static mut APIC_IDS     : [usize; MAX_APIC_IDS] = [0; MAX_APIC_IDS];
+static CLAIMED_APIC_IDS : AtomicUsize = AtomicUsize::new(0);
+
+/// Identifies CPUs by their local APIC id, cached per CPU
+pub struct HardwareCpu;
+
+impl HardwareCpu {
+
+    /// Caches APIC id of the current CPU
+    pub fn new() -> Self {
+        HardwareCpu::set_up_current_cpu();
+
+        HardwareCpu
+    }
+
+    /// Caches APIC id of the current CPU, every CPU must call it before it uses `HardwareCpu`.
+    /// Does nothing for CPU that is already set up.
+    pub fn set_up_current_cpu() {
+        unsafe {
+            let slots   = APIC_IDS.as_ptr() as u64;
+            let gs_base = registers::gs_base();
+
+            if gs_base >= slots && gs_base < slots + (MAX_APIC_IDS * mem::size_of::<usize>()) as u64 {
+                return
+            }
+
+            let slot = CLAIMED_APIC_IDS.fetch_add(1, Ordering::SeqCst);
+
+            assert!(slot < MAX_APIC_IDS, "More than {} CPUs asked for APIC id slot", MAX_APIC_IDS);
+
+            APIC_IDS[slot] = cpuid::apic_id() as usize;
+            registers::gs_base_write(&APIC_IDS[slot] as *const usize as u64);
+        }
+    }
+}
+
+impl CpuContext for HardwareCpu {
+    fn cpu_id(&self) -> usize {
+        // GS base points to the slot of the current CPU since `set_up_current_cpu`
+        unsafe { registers::gs_read_u64(0) as usize }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Magazine {
+    objects : [usize; MAGAZINE_SIZE],
+    count   : usize
+}
+
+impl Magazine {
+    const fn empty() -> Self {
+        Magazine {
+            objects : [0; MAGAZINE_SIZE],
+            count   : 0
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.count == MAGAZINE_SIZE
+    }
+
+    fn push(&mut self, object : usize) {
+        self.objects[self.count] = object;
+        self.count += 1;
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        }
+        else {
+            self.count -= 1;
+
+            Some(self.objects[self.count])
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ClassMagazines {
+    loaded   : Magazine,
+    previous : Magazine
+}
+
+impl ClassMagazines {
+    fn swap(&mut self) {
+        mem::swap(&mut self.loaded, &mut self.previous)
+    }
+}
+
+type CpuMagazines = [ClassMagazines; MAGAZINE_CLASSES];
+
+pub struct MagazineAllocator<A, C> where A : MemoryAllocator, C : CpuContext {
+    depot       : SpinLock<A>,
+    cpus        : UnsafeCell<[CpuMagazines; MAX_CPUS]>,
+    // CPU id + 1 of every slot in `cpus`, 0 marks free slot
+    cpu_ids     : [AtomicUsize; MAX_CPUS],
+    cpu_context : C
+}
+
+// per CPU data is accessed only by its CPU, shared data is protected by depot lock
+unsafe impl<A, C> Sync for MagazineAllocator<A, C> where A : MemoryAllocator + Send, C : CpuContext + Sync {}
+
+impl<A, C> MagazineAllocator<A, C> where A : MemoryAllocator, C : CpuContext {
+
+    /// # Arguments
+    /// * `depot` - shared allocator, magazines are filled from it
+    /// * `cpu_context` - tells which CPU the allocator is called from
+    pub fn new(depot : A, cpu_context : C) -> Self {
+        let empty_class = ClassMagazines {
+            loaded   : Magazine::empty(),
+            previous : Magazine::empty()
+        };
+
+        MagazineAllocator {
+            depot   : SpinLock::new(depot),
+            cpus    : UnsafeCell::new([[empty_class; MAGAZINE_CLASSES]; MAX_CPUS]),
+            cpu_ids : Default::default(),
+            cpu_context
+        }
+    }
+
+    /// Locks shared allocator
+    pub fn depot(&self) -> SpinLockGuard<A> {
+        self.depot.lock()
+    }
+
+    /// Returns size class of objects of `size` and `align`, `None` for objects that bypass magazines
+    pub fn class_for(size : usize, align : usize) -> Option<usize> {
+        let object_size = cmp::max(cmp::max(size, align), MIN_OBJECT_SIZE);
+
+        if object_size > MAX_OBJECT_SIZE {
+            None
+        }
+        else {
+            Some(math::log2_align_up(object_size) - math::log2_align_down(MIN_OBJECT_SIZE))
+        }
+    }
+
+    pub fn allocate(&self, size : usize, align : usize) -> Option<usize> {
+        let class     = MagazineAllocator::<A, C>::class_for(size, align);
+        let magazines = class.and_then(|class| self.cpu_magazines().map(|magazines| &mut magazines[class]));
+
+        match (class, magazines) {
+            (Some(class), Some(magazines)) => self.allocate_from_magazine(magazines, class),
+            _                              => self.depot().allocate_aligned(size, align)
+        }
+    }
+
+    /// Returns object to magazines of the current CPU
+    /// # Arguments
+    /// * `pointer` - object address
+    /// * `size` - size the object was allocated with
+    /// * `align` - alignment the object was allocated with
+    pub fn free(&self, pointer : usize, size : usize, align : usize) {
+        let class     = MagazineAllocator::<A, C>::class_for(size, align);
+        let magazines = class.and_then(|class| self.cpu_magazines().map(|magazines| &mut magazines[class]));
+
+        match magazines {
+            Some(magazines) => self.free_to_magazine(magazines, pointer),
+            None            => self.depot().free(pointer)
+        }
+    }
+
+    /// Resizes object. Object that keeps its size class stays in place, object that changes the class is moved
+    /// through magazines, so that it is later freed into magazines of the class it came from.
+    /// Only objects that bypass magazines both before and after resize are resized by depot.
+    /// # Arguments
+    /// * `pointer` - object address
+    /// * `old_size` - size the object was allocated with
+    /// * `align` - alignment the object was allocated with
+    /// * `new_size` - requested size
+    /// * `resize_in_depot` - resizes object that bypasses magazines, called with depot locked
+    /// # Returns
+    /// Address of resized object or `None` if out of memory, the old object is left intact in that case
+    /// # Why unsafe
+    /// Copies `old_size` bytes from `pointer` when object is moved
+    pub unsafe fn reallocate<F>(&self, pointer : usize, old_size : usize, align : usize, new_size : usize, resize_in_depot : F) -> Option<usize>
+        where F : FnOnce(&mut A) -> Option<usize> {
+        let old_class = MagazineAllocator::<A, C>::class_for(old_size, align);
+        let new_class = MagazineAllocator::<A, C>::class_for(new_size, align);
+
+        match (old_class, new_class) {
+            (Some(old_class), Some(new_class)) if old_class == new_class => Some(pointer),
+            (None, None) => resize_in_depot(&mut *self.depot()),
+            _ => {
+                self.allocate(new_size, align).map(|new_pointer| {
+                    ptr::copy_nonoverlapping(pointer as *const u8, new_pointer as *mut u8, cmp::min(old_size, new_size));
+
+                    self.free(pointer, old_size, align);
+
+                    new_pointer
+                })
+            }
+        }
+    }
+
+    /// Returns every object cached by the current CPU to depot
+    /// # Returns
+    /// Number of returned objects
+    pub fn drain(&self) -> usize {
+        let magazines  = match self.cpu_magazines() {
+            Some(magazines) => magazines,
+            None            => return 0
+        };
+        let mut depot  = self.depot();
+        let mut result = 0;
+
+        for class in magazines.iter_mut() {
+            result += MagazineAllocator::<A, C>::flush(&mut class.loaded, &mut *depot);
+            result += MagazineAllocator::<A, C>::flush(&mut class.previous, &mut *depot);
+        }
+
+        result
+    }
+
+    /// Returns number of objects cached by `cpu`, the value may be outdated if `cpu` is not the current one
+    /// # Arguments
+    /// * `cpu` - CPU id given by `CpuContext`
+    pub fn cached_objects(&self, cpu : usize) -> usize {
+        let cpus = unsafe { &*self.cpus.get() };
+
+        match self.cpu_ids.iter().position(|id| id.load(Ordering::SeqCst) == cpu + 1) {
+            Some(slot) => cpus[slot].iter().map(|class| class.loaded.count + class.previous.count).sum(),
+            None       => 0
+        }
+    }
+
+    fn allocate_from_magazine(&self, magazines : &mut ClassMagazines, class : usize) -> Option<usize> {
+        if magazines.loaded.is_empty() {
+            if magazines.previous.is_empty() {
+                let mut depot = self.depot();
+                let object_size = MagazineAllocator::<A, C>::object_size(class);
+
+                // refill only a half, so that following frees don't flush it right away
+                for _ in 0 .. MAGAZINE_SIZE / 2 {
+                    match depot.allocate_aligned(object_size, object_size) {
+                        Some(object) => magazines.loaded.push(object),
+                        None         => break
+                    }
+                }
+            }
+            else {
+                magazines.swap();
+            }
+        }
+
+        magazines.loaded.pop()
+    }
+
+    fn free_to_magazine(&self, magazines : &mut ClassMagazines, pointer : usize) {
+        if magazines.loaded.is_full() {
+            if magazines.previous.is_full() {
+                MagazineAllocator::<A, C>::flush(&mut magazines.previous, &mut *self.depot());
+            }
+
+            magazines.swap();
+        }
+
+        magazines.loaded.push(pointer)
+    }
+
+    fn flush(magazine : &mut Magazine, depot : &mut A) -> usize {
+        let count = magazine.count;
+
+        while let Some(object) = magazine.pop() {
+            depot.free(object);
+        }
+
+        count
+    }
+
+    fn object_size(class : usize) -> usize {
+        MIN_OBJECT_SIZE << class
+    }
+
+    // magazines of the current CPU, `None` if all slots are taken by other CPUs
+    fn cpu_magazines(&self) -> Option<&mut CpuMagazines> {
+        let id = self.cpu_context.cpu_id() + 1;
+
+        for (slot, slot_id) in self.cpu_ids.iter().enumerate() {
+            let current = slot_id.load(Ordering::SeqCst);
+
+            // free slot can be taken by another CPU at the same time, then search goes on
+            let taken = current == id ||
+                (current == 0 && slot_id.compare_exchange(0, id, Ordering::SeqCst, Ordering::SeqCst).is_ok());
+
+            if taken {
+                return Some(unsafe { &mut (*self.cpus.get())[slot] })
+            }
+        }
+
+        None
+    }
+}
@@ -1,7 +1,17 @@
 #[macro_use]
 pub mod buddy;
 pub mod bump;
+pub mod cache;
+#[cfg(feature = "heap_debug")]
+pub mod debug;
 pub mod free_list;
+pub mod kernel_heap;
+#[cfg(feature = "heap_debug")]
+pub mod leaks;
+pub mod magazine;
+pub mod oom;
+pub mod physical;
+pub mod statistics;
 
 pub mod slab;
 
@@ -0,0 +1,118 @@
+/*
+    Out of memory policy. When heap allocation fails the handler:
+    1) reports failed layout together with allocator statistics via reporter, if any is set
+    2) if reclaim is enabled, asks every registered shrinker to release cached memory
+    3) tells the allocator to retry if anything was reclaimed
+
+    If allocation still fails the allocator returns an error (null pointer for GlobalAlloc),
+    so that fallible callers can handle it, infallible ones end up in `oom` lang item.
+
+    Handler state is behind a spin lock that is held only to copy or change it, so reporter and shrinkers
+    run unlocked. The lock doesn't disable interrupts, handler is configured before interrupt handlers allocate.
+    Statistics are collected only for a reporter, walking the allocator is wasted work otherwise.
+*/
+use allocator::statistics::AllocatorStatistics;
+use stdx::sync::SpinLock;
+use core::alloc::Layout;
+
+pub const MAX_SHRINKERS : usize = 16;
+
+/// Reports failed allocation, e.g. prints it to screen
+pub type OomReporter = fn(layout : &Layout, statistics : &AllocatorStatistics);
+
+/// Releases cached memory back to the heap, returns size of released memory in bytes
+pub type Shrinker = fn() -> usize;
+
+pub static OOM_HANDLER : OomHandler = OomHandler::new();
+
+pub struct OomHandler {
+    state : SpinLock<OomState>
+}
+
+#[derive(Clone, Copy)]
+struct OomState {
+    reporter        : Option<OomReporter>,
+    reclaim         : bool,
+    shrinkers       : [Option<Shrinker>; MAX_SHRINKERS],
+    shrinkers_count : usize
+}
+
+impl OomHandler {
+
+    /// Creates handler without reporter and with reclaim enabled
+    pub const fn new() -> Self {
+        OomHandler {
+            state : SpinLock::new(OomState {
+                reporter        : None,
+                reclaim         : true,
+                shrinkers       : [None; MAX_SHRINKERS],
+                shrinkers_count : 0
+            })
+        }
+    }
+
+    pub fn set_reporter(&self, reporter : OomReporter) {
+        self.state.lock().reporter = Some(reporter)
+    }
+
+    /// Enables or disables calling shrinkers on allocation failure
+    pub fn set_reclaim(&self, reclaim : bool) {
+        self.state.lock().reclaim = reclaim
+    }
+
+    /// Registers function that releases cached memory on allocation failure
+    /// # Arguments
+    /// * `shrinker` - shrinker function
+    /// # Returns
+    /// `false` if there is no space for another shrinker
+    pub fn register_shrinker(&self, shrinker : Shrinker) -> bool {
+        let mut state = self.state.lock();
+
+        if state.shrinkers_count == MAX_SHRINKERS {
+            false
+        }
+        else {
+            let index = state.shrinkers_count;
+
+            state.shrinkers[index] = Some(shrinker);
+            state.shrinkers_count += 1;
+
+            true
+        }
+    }
+
+    /// Calls every registered shrinker
+    /// # Returns
+    /// Total size of released memory in bytes
+    pub fn shrink(&self) -> usize {
+        OomHandler::shrink_with(&self.state())
+    }
+
+    /// Handles failed allocation according to policy
+    /// # Arguments
+    /// * `layout` - layout of failed allocation
+    /// * `statistics` - collects allocator state at the moment of failure, called only if reporter is set
+    /// # Returns
+    /// `true` if some memory was reclaimed and allocation should be retried
+    pub fn handle_allocation_failure<F>(&self, layout : &Layout, statistics : F) -> bool where F : FnOnce() -> AllocatorStatistics {
+        let state = self.state();
+
+        if let Some(reporter) = state.reporter {
+            reporter(layout, &statistics())
+        }
+
+        state.reclaim && OomHandler::shrink_with(&state) > 0
+    }
+
+    fn state(&self) -> OomState {
+        *self.state.lock()
+    }
+
+    fn shrink_with(state : &OomState) -> usize {
+        state.shrinkers[..state.shrinkers_count]
+            .iter()
+            .filter_map(|shrinker| *shrinker)
+            .map(|shrinker| shrinker())
+            .sum()
+    }
+}
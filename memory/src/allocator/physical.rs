@@ -0,0 +1,365 @@
+use stdx_memory::{MemoryAllocator, MemoryAllocatorMeta};
+use allocator::buddy::BuddyAllocator;
+use allocator::bump::ConstSizeBumpAllocator;
+use frame::{Frame, FRAME_SIZE};
+use frame::memory_regions::{MemoryRegions, MemoryRegion};
+use paging;
+use paging::page_table;
+use stdx::math;
+use core::mem::MaybeUninit;
+use core::slice;
+use core::fmt;
+
+/// Max number of buddy allocators the manager can hold. Every memory region is split into
+/// power of two sized pools, so regions usually produce several pools each.
+pub const MAX_POOLS : usize = 64;
+
+/// Smallest pool worth creating, smaller leftovers of regions are not used
+const MIN_POOL_SIZE : usize = FRAME_SIZE * 16;
+
+/// End address (exclusive) of DMA zone, 16 mb
+pub const DMA_ZONE_END : usize = 0x100_0000;
+
+/// End address (exclusive) of DMA32 zone, 4 gb
+pub const DMA32_ZONE_END : usize = 0x1_0000_0000;
+
+/// Physical memory zones. Some devices can only address a part of physical memory,
+/// so memory below their limits is kept in separate pools.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Zone {
+    /// Below 16 mb, reachable by legacy ISA DMA
+    Dma,
+    /// Below 4 gb, reachable by 32 bit devices
+    Dma32,
+    /// The rest of memory
+    Normal
+}
+
+impl Zone {
+
+    /// Returns zone the physical address belongs to
+    pub fn for_address(address : usize) -> Zone {
+        if address < DMA_ZONE_END {
+            Zone::Dma
+        }
+        else if address < DMA32_ZONE_END {
+            Zone::Dma32
+        }
+        else {
+            Zone::Normal
+        }
+    }
+
+    // zone that is tried next when this one has no memory left
+    fn fallback(&self) -> Option<Zone> {
+        match *self {
+            Zone::Normal => Some(Zone::Dma32),
+            Zone::Dma32  => Some(Zone::Dma),
+            Zone::Dma    => None
+        }
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Zone::Dma    => write!(f, "DMA"),
+            Zone::Dma32  => write!(f, "DMA32"),
+            Zone::Normal => write!(f, "Normal"),
+        }
+    }
+}
+
+/*
+    Physical memory manager. Takes every free memory region and splits it into pools, each pool
+    is managed by its own buddy allocator. Buddy allocator can only manage a power of two sized block,
+    so the region is cut greedily into the biggest pools that fit, leftovers go to smaller pools.
+    Regions are split at zone boundaries first, thus every pool belongs to exactly one zone.
+    Allocation is served from the requested zone and falls back to the lower zones,
+    e.g. Normal -> DMA32 -> DMA, so that low memory is used only when the rest is exhausted.
+    Zones are kept here on purpose: the manager is the only owner of physical pools, so it is the one place
+    that knows which addresses a pool covers. Buddy allocators stay zone agnostic, and callers that need
+    low memory only pass a zone instead of searching pools themselves.
+
+    Pool layout:
+    |page tables to map aux structures|buddy aux structures and page tables|buddy working memory|
+*/
+pub struct PhysicalMemoryManager {
+    // only the first `pools_count` pools are initialized
+    pools       : [MaybeUninit<BuddyAllocator>; MAX_POOLS],
+    pool_zones  : [Zone; MAX_POOLS],
+    pools_count : usize
+}
+
+impl PhysicalMemoryManager {
+
+    /// Creates manager that uses all memory of `regions`
+    /// # Arguments
+    /// * `regions` - free physical memory
+    pub fn new(regions : &MemoryRegions) -> Self {
+        let mut result = PhysicalMemoryManager::empty();
+
+        let mut zoned_regions = regions.clone();
+        zoned_regions.split_at(DMA_ZONE_END);
+        zoned_regions.split_at(DMA32_ZONE_END);
+
+        for region in zoned_regions.iter() {
+            result.add_region(region);
+        }
+
+        assert!(result.pools_count > 0, "Cannot create physical memory manager, no free memory");
+
+        result
+    }
+
+    /// Creates manager from pools that are already created, e.g. pools over memory that doesn't need paging
+    /// # Arguments
+    /// * `pools` - buddy allocators and zones they belong to, at most `MAX_POOLS` of them
+    pub fn from_pools<I>(pools : I) -> Self where I : IntoIterator<Item = (BuddyAllocator, Zone)> {
+        let mut result = PhysicalMemoryManager::empty();
+
+        for (pool, zone) in pools {
+            assert!(result.pools_count < MAX_POOLS, "Cannot create physical memory manager, more than {} pools", MAX_POOLS);
+
+            result.add_pool(pool, zone);
+        }
+
+        assert!(result.pools_count > 0, "Cannot create physical memory manager, no free memory");
+
+        result
+    }
+
+    pub fn pools_count(&self) -> usize {
+        self.pools_count
+    }
+
+    pub fn pools(&self) -> &[BuddyAllocator] {
+        unsafe { slice::from_raw_parts(self.pools.as_ptr() as *const BuddyAllocator, self.pools_count) }
+    }
+
+    fn pools_mut(&mut self) -> &mut [BuddyAllocator] {
+        unsafe { slice::from_raw_parts_mut(self.pools.as_mut_ptr() as *mut BuddyAllocator, self.pools_count) }
+    }
+
+    /// Returns size of memory in bytes that can be handed out by all pools
+    pub fn total_memory(&self) -> usize {
+        self.pools().iter().fold(0, |total, pool| total + pool.full_size())
+    }
+
+    /// Returns size of memory in bytes that can be handed out by pools of `zone`
+    pub fn total_memory_in(&self, zone : Zone) -> usize {
+        self.pools()
+            .iter()
+            .zip(self.pool_zones.iter())
+            .filter(|&(_, pool_zone)| *pool_zone == zone)
+            .fold(0, |total, (pool, _)| total + pool.full_size())
+    }
+
+    pub fn allocate_frame(&mut self) -> Option<usize> {
+        self.allocate(FRAME_SIZE)
+    }
+
+    pub fn allocate_frame_in(&mut self, zone : Zone) -> Option<usize> {
+        self.allocate_in(FRAME_SIZE, zone)
+    }
+
+    /// Allocates memory that lies in `zone` or in any zone below it
+    /// # Arguments
+    /// * `size` - allocation size
+    /// * `zone` - highest zone the memory can come from
+    pub fn allocate_in(&mut self, size : usize, zone : Zone) -> Option<usize> {
+        self.allocate_aligned_in(size, 1, zone)
+    }
+
+    /// Allocates memory which address is a multiple of `align` and that lies in `zone` or in any zone below it
+    /// # Arguments
+    /// * `size` - allocation size
+    /// * `align` - alignment, must be a power of two
+    /// * `zone` - highest zone the memory can come from
+    pub fn allocate_aligned_in(&mut self, size : usize, align : usize, zone : Zone) -> Option<usize> {
+        let mut current_zone = Some(zone);
+
+        while let Some(zone) = current_zone {
+            let result = self.allocate_from_zone(size, align, zone);
+
+            if result.is_some() {
+                return result
+            }
+
+            current_zone = zone.fallback();
+        }
+
+        None
+    }
+
+    /// Returns size of the block that holds allocation
+    /// # Arguments
+    /// * `pointer` - start address of allocation
+    pub fn allocation_size(&self, pointer : usize) -> usize {
+        let pool_index = self.pool_index_for(pointer).expect("Pointer doesn't belong to any memory pool");
+
+        self.pools()[pool_index].allocation_size(pointer)
+    }
+
+    /// Grows allocation without moving it, see `BuddyAllocator::grow_in_place`
+    /// # Arguments
+    /// * `pointer` - start address of allocation
+    /// * `new_size` - new allocation size
+    pub fn grow_in_place(&mut self, pointer : usize, new_size : usize) -> bool {
+        let pool_index = self.pool_index_for(pointer).expect("Pointer doesn't belong to any memory pool");
+
+        self.pools_mut()[pool_index].grow_in_place(pointer, new_size)
+    }
+
+    /// Returns max number of buddy orders among pools
+    pub fn orders_count(&self) -> usize {
+        self.pools().iter().map(|pool| pool.orders_count()).max().unwrap_or(0)
+    }
+
+    /// Returns number of free blocks of `order` in all pools
+    /// # Arguments
+    /// * `order` - buddy order, block size is FRAME_SIZE * 2 ^ order
+    pub fn free_blocks_count(&self, order : usize) -> usize {
+        self.pools()
+            .iter()
+            .filter(|pool| order < pool.orders_count())
+            .map(|pool| pool.free_blocks_count(order))
+            .sum()
+    }
+
+    fn allocate_from_zone(&mut self, size : usize, align : usize, zone : Zone) -> Option<usize> {
+        let pool_zones = self.pool_zones;
+
+        self.pools_mut()
+            .iter_mut()
+            .zip(pool_zones.iter())
+            .filter(|&(_, pool_zone)| *pool_zone == zone)
+            .filter_map(|(pool, _)| pool.allocate_aligned(size, align))
+            .next()
+    }
+
+    fn add_region(&mut self, region : &MemoryRegion) {
+        let mut start_address = region.start_address();
+
+        loop {
+            let available_memory = region.end_address() - start_address + 1;
+
+            match PhysicalMemoryManager::pool_size_for(available_memory) {
+                Some(pool_size) => {
+                    assert!(self.pools_count < MAX_POOLS, "Cannot create physical memory manager, more than {} pools, {} bytes at {:#x} are left",
+                        MAX_POOLS, available_memory, start_address);
+
+                    let pool = PhysicalMemoryManager::create_pool(start_address, pool_size);
+
+                    start_address = pool.end_address() + 1;
+
+                    self.add_pool(pool, Zone::for_address(region.start_address()));
+
+                    if start_address > region.end_address() {
+                        return
+                    }
+                },
+                None => return
+            }
+        }
+    }
+
+    fn empty() -> Self {
+        // pools are written one by one and only first `pools_count` of them are ever read,
+        // array of `MaybeUninit` doesn't need initialization
+        PhysicalMemoryManager {
+            pools       : unsafe { MaybeUninit::uninit().assume_init() },
+            pool_zones  : [Zone::Normal; MAX_POOLS],
+            pools_count : 0
+        }
+    }
+
+    fn add_pool(&mut self, pool : BuddyAllocator, zone : Zone) {
+        self.pools[self.pools_count] = MaybeUninit::new(pool);
+        self.pool_zones[self.pools_count] = zone;
+        self.pools_count += 1;
+    }
+
+    // returns the biggest power of two memory size that fits into `available_memory`
+    // together with all pool overhead
+    fn pool_size_for(available_memory : usize) -> Option<usize> {
+        let mut pool_size = 1 << math::log2_align_down(available_memory);
+
+        while pool_size >= MIN_POOL_SIZE {
+            if PhysicalMemoryManager::pool_memory_for(pool_size) <= available_memory {
+                return Some(pool_size)
+            }
+
+            pool_size /= 2;
+        }
+
+        None
+    }
+
+    fn pool_memory_for(pool_size : usize) -> usize {
+        let aux_size = Frame::address_align_up(BuddyAllocator::aux_data_structures_size_for(pool_size));
+
+        PhysicalMemoryManager::premap_page_tables_count(aux_size) * FRAME_SIZE + aux_size + pool_size
+    }
+
+    // page tables to map aux structures, the same estimation as buddy allocator does for its memory
+    fn premap_page_tables_count(aux_size : usize) -> usize {
+        let p1_count = Frame::number_for_address(aux_size) / 512 + 2;
+
+        p1_count + 4
+    }
+
+    fn create_pool(start_address : usize, pool_size : usize) -> BuddyAllocator {
+        let aux_size           = Frame::address_align_up(BuddyAllocator::aux_data_structures_size_for(pool_size));
+        let premap_size      = PhysicalMemoryManager::premap_page_tables_count(aux_size) * FRAME_SIZE;
+        let aux_start_address = start_address + premap_size;
+        let aux_end_address  = aux_start_address + aux_size - 1;
+
+        // buddy allocator maps the memory it hands out, but its own data structures
+        // must be accessible before it is created
+        let mut premap_allocator = ConstSizeBumpAllocator::from_size(start_address, premap_size, FRAME_SIZE);
+        let p4_table = paging::p4_table();
+
+        for frame in Frame::range_inclusive(aux_start_address, aux_end_address) {
+            p4_table.map_page_1_to_1(frame, page_table::PRESENT | page_table::WRITABLE, &mut premap_allocator);
+            Frame::zero_frame(&frame);
+        }
+
+        BuddyAllocator::new(aux_start_address, pool_size, aux_end_address + pool_size)
+    }
+
+    fn pool_index_for(&self, pointer : usize) -> Option<usize> {
+        self.pools().iter().position(|pool| pointer >= pool.start_address() && pointer <= pool.end_address())
+    }
+}
+
+impl MemoryAllocatorMeta for PhysicalMemoryManager {
+    fn start_address(&self) -> usize {
+        self.pools().iter().map(|pool| pool.start_address()).min().unwrap_or(0)
+    }
+
+    fn end_address(&self) -> usize {
+        self.pools().iter().map(|pool| pool.end_address()).max().unwrap_or(0)
+    }
+
+    fn aux_data_structures_size(&self) -> usize {
+        self.pools().iter().fold(0, |total, pool| total + pool.aux_data_structures_size())
+    }
+}
+
+impl MemoryAllocator for PhysicalMemoryManager {
+
+    fn allocate(&mut self, size : usize) -> Option<usize> {
+        self.allocate_in(size, Zone::Normal)
+    }
+
+    fn allocate_aligned(&mut self, size : usize, align : usize) -> Option<usize> {
+        self.allocate_aligned_in(size, align, Zone::Normal)
+    }
+
+    fn free(&mut self, pointer : usize) {
+        let pool_index = self.pool_index_for(pointer).expect("Pointer doesn't belong to any memory pool");
+
+        self.pools_mut()[pool_index].free(pointer)
+    }
+}
@@ -6,6 +6,10 @@ use stdx_memory::trees::avl;
 use allocator::bump;
 use allocator::free_list::FreeListAllocator;
 use allocator::buddy::BuddyAllocator;
+use allocator::physical::PhysicalMemoryManager;
+use allocator::statistics::{AllocatorStatistics, SlabClassStatistics, MAX_SLAB_CLASSES};
+#[cfg(feature = "heap_debug")]
+use allocator::debug;
 use allocator;
 use stdx::iterator::IteratorExt;
 use stdx::{Iterable,Sequence} ;
@@ -14,17 +18,20 @@ use stdx_memory::heap;
 use core::cmp;
 use core::mem;
 use core::alloc::Alloc;
-use core::alloc::GlobalAlloc;
 use core::alloc::Layout;
 use core::alloc::AllocErr;
 use core::ptr;
 use display::vga::writer::Writer;
-use frame::FRAME_SIZE;
+use frame::{Frame, FRAME_SIZE};
 use core::ops::DerefMut;
 use core::ops::Deref;
 
 const MIN_ALLOCATION_SIZE : usize = 32; //bytes
 
+// frame_size_classes value of frames that don't belong to any slab (allocated directly from frame allocator or free),
+// slab frames store slab array index + 1
+const NOT_SLAB_FRAME : u8 = 0;
+
 type ListOfAllocators = DoubleLinkedList<SlabCell, bump::ConstSizeBumpAllocator>;
 type ProperPtr              = heap::RC<ListOfAllocators, bump::ConstSizeBumpAllocator>;
 
@@ -100,7 +107,7 @@ impl cmp::PartialEq for SlabCell {
 }
 
 impl Slab {
-    fn new(allocation_size: usize, frame_allocator: &mut BuddyAllocator) -> Option<Self> {
+    fn new(allocation_size: usize, frame_allocator: &mut PhysicalMemoryManager) -> Option<Self> {
         let a = frame_allocator.allocate_frame();
         let b = frame_allocator.allocate_frame();
 
@@ -114,8 +121,6 @@ impl Slab {
 
                 let mut slab_cell_boxed = unsafe { DoubleLinkedList::new_rc(slab_cell, dlist_alloc.as_mut()) };
 
-                let chck = (&mut slab_cell_boxed.value_mut().value).allocate(10);
-
                 let tree = unsafe { avl::AVLTree::new(heap::RC::clone(&slab_cell_boxed), tree_cell_alloc.as_mut()) };
 
                 Some(Slab {
@@ -129,7 +134,7 @@ impl Slab {
 
     // slab size is passed here to prevent saving it in slab structure, because slab allocator
     // knows what slabs and of what sizes it has
-    fn allocate(&mut self, size : usize, slab_size : usize, frame_allocator : &mut BuddyAllocator) -> Option<usize> {
+    fn allocate(&mut self, size : usize, slab_size : usize, frame_allocator : &mut PhysicalMemoryManager) -> Option<usize> {
         // check if there is any non-full allocators present,
         // if not - create a new one (increase slab size) and allocate from it
         let non_full_allocation_result = self.try_allocate_non_full();
@@ -182,7 +187,8 @@ impl Slab {
                 .unwrap_or(false)
     }
 
-    fn free_from_non_full(&mut self, pointer : usize, frame_allocator : &mut BuddyAllocator) {
+    // returns working frame of the slab cell if the cell became empty and its frames were released
+    fn free_from_non_full(&mut self, pointer : usize, frame_allocator : &mut PhysicalMemoryManager) -> Option<usize> {
         let mut allocator_is_empty = false;
 
         // let the allocator perform free
@@ -199,6 +205,7 @@ impl Slab {
         if allocator_is_empty {
             if let Some(head_cell) = self.non_full.take() {
                 let head_start_addr = (&head_cell.value().value).start_address();
+                let head_aux_addr   = (&head_cell.value().value).free_blocks_allocator().start_address();
 
                 {
                     let prev = head_cell.prev();
@@ -212,13 +219,18 @@ impl Slab {
                 // take head_cell out of a tree (drops the head_cell)
                 self.non_empty.delete_by(head_start_addr, |n| (&n.value().value).start_address());
 
-                // reclaim frame
+                // reclaim working and aux data structures frames
                 frame_allocator.free(head_start_addr);
+                frame_allocator.free(head_aux_addr);
+
+                return Some(head_start_addr)
             }
         }
+
+        None
     }
 
-    fn free_from_non_empty(&mut self, pointer : usize, frame_allocator : &mut BuddyAllocator) {
+    fn free_from_non_empty(&mut self, pointer : usize, frame_allocator : &mut PhysicalMemoryManager) -> Option<usize> {
 
         let mut dlist_opt = self.non_empty.find_by(&pointer,
                                                    |node| node.value().value.start_address(),
@@ -239,6 +251,7 @@ impl Slab {
         if allocator_is_empty {
             if let Some(dlist_cell) = dlist_opt {
                 let start_address = dlist_cell.value().value.start_address();
+                let aux_address   = dlist_cell.value().value.free_blocks_allocator().start_address();
 
                 // take head_cell out of dlist
                 DoubleLinkedList::modify_neighbour_connections(dlist_cell.leak());
@@ -246,20 +259,26 @@ impl Slab {
                 // take head_cell out of a tree (drops the head_cell)
                 self.non_empty.delete_by(start_address, |n| n.value().value.start_address());
 
-                // reclaim frame
+                // reclaim working and aux data structures frames
                 frame_allocator.free(start_address);
+                frame_allocator.free(aux_address);
+
+                return Some(start_address)
             }
         }
+
+        None
     }
 
-    fn free(&mut self, pointer : usize, frame_allocator : &mut BuddyAllocator) {
+    // returns working frame of the slab cell that was released because it became empty
+    fn free(&mut self, pointer : usize, frame_allocator : &mut PhysicalMemoryManager) -> Option<usize> {
         // maybe the pointer belongs to allocator in the head of non_full dlist
         // if not then search for allocator in the address tree
         if  self.address_belongs_to_non_full(pointer)  {
-            self.free_from_non_full(pointer, frame_allocator);
+            self.free_from_non_full(pointer, frame_allocator)
         }
         else {
-            self.free_from_non_empty(pointer, frame_allocator);
+            self.free_from_non_empty(pointer, frame_allocator)
         }
     }
 
@@ -270,30 +289,25 @@ impl Slab {
 
 pub struct SlabAllocator {
     size_to_slab                : Array<Option<Slab>>,
-    //address_to_size         : avl::AVLTree<(usize, usize), FreeListAllocator>,
+    // per frame slab metadata, allows to find owning slab of a pointer without size hint
+    frame_size_classes      : Array<u8>,
+    // allocated objects count per slab
+    objects_in_use             : Array<usize>,
+    // memory handed out, including rounding to slab and buddy block sizes
+    used_memory               : usize,
+    high_water_mark         : usize,
     start_address             : usize,
     end_address               : usize,
     array_allocator         : bump::BumpAllocator,
     tree_allocator            : FreeListAllocator,
     linked_list_allocator :  FreeListAllocator,
-    frame_allocator        : BuddyAllocator,
+    frame_allocator        : PhysicalMemoryManager,
 }
 
 type DlistOfAllocators = DoubleLinkedList<FreeListAllocator, FreeListAllocator>;
 
 impl SlabAllocator {
 
-    pub fn total_aux_data_structures_size(start_address1 : usize, end_address1 : usize) -> usize {
-        let (start_address, end_address)                        = allocator::align_addresses(start_address1, end_address1);
-        let total_slab_count          = SlabAllocator::slab_count(start_address, end_address);
-
-        let (array_size, avl_tree_size, linked_list_size)   = SlabAllocator::aux_data_structures_size(total_slab_count);
-
-        let this_aux_data_structures_size =  array_size + avl_tree_size + linked_list_size;
-
-        this_aux_data_structures_size + BuddyAllocator::total_aux_data_structures_size(start_address + this_aux_data_structures_size + 1, end_address)
-    }
-
     fn avl_tree_cell_size() -> usize {
         avl::AVLTree::<FreeListAllocator, FreeListAllocator>::cell_size()
     }
@@ -318,7 +332,7 @@ impl SlabAllocator {
 
         let array_size             = Array::<Option<Slab>>::mem_size_for(total_slab_count);
         let avl_tree_size        = SlabAllocator::avl_tree_cell_size() * total_slab_count;
-        let linked_list_size    = SlabAllocator::linked_list_cell_size() * (31457281 / 4096);
+        let linked_list_size    = SlabAllocator::linked_list_cell_size() * total_slab_count;
 
         (
             array_size,
@@ -327,42 +341,51 @@ impl SlabAllocator {
         )
     }
 
-    pub fn frame_allocator(&mut self) -> &mut BuddyAllocator {
+    pub fn frame_allocator(&mut self) -> &mut PhysicalMemoryManager {
         &mut self.frame_allocator
     }
 
     pub fn is_fully_free(&self) ->bool {
-        let slabs_are_empty  = self.size_to_slab.iterator().all(|e| e.is_none());
-        //let tree_is_empty       = self.address_to_size.is_empty();
-
-         slabs_are_empty// && tree_is_empty
+        self.size_to_slab.indices().all(|i| self.size_to_slab[i].is_none())
     }
 
-    pub fn new(start_address : usize, total_memory : usize, end_address : usize) -> Self {
+    /// Creates slab allocator that takes frames from `frame_allocator`
+    /// # Arguments
+    /// * `frame_allocator` - physical memory manager, slab allocator owns it from now on
+    pub fn new(mut frame_allocator : PhysicalMemoryManager) -> Self {
 
-        let mut frame_allocator = BuddyAllocator::new(start_address, total_memory, end_address);
+        let total_memory = frame_allocator.total_memory();
 
         let total_slab_count = SlabAllocator::total_slab_count(total_memory);
 
         let (array_size, avl_tree_size, linked_list_size)   = SlabAllocator::aux_data_structures_size(total_slab_count);
 
+        let tree_allocator_size          = FreeListAllocator::aux_data_structures_size_for(avl_tree_size, SlabAllocator::avl_tree_cell_size()) + avl_tree_size;
+        let linked_list_allocator_size = FreeListAllocator::aux_data_structures_size_for(linked_list_size, SlabAllocator::linked_list_cell_size()) + linked_list_size;
+
+        // memory for inner allocators is taken from frame allocator, which maps it
+        let aux_structures_start_address = frame_allocator.allocate(array_size + tree_allocator_size + linked_list_allocator_size)
+            .expect("No memory for slab allocator data structures");
+
         // create inner allocators
-        let mut array_allocator             = bump::BumpAllocator::from_address(start_address, array_size);
+        let mut array_allocator             = bump::BumpAllocator::from_address(aux_structures_start_address, array_size);
         let mut tree_allocator               = FreeListAllocator::from_size(array_allocator.end_address() + 1, avl_tree_size, SlabAllocator::avl_tree_cell_size());
         let mut linked_list_allocator    = FreeListAllocator::from_size(tree_allocator.end_address() + 1, linked_list_size, SlabAllocator::linked_list_cell_size());
 
         // create allocate/free data structures
         let size_to_slab            = Array::<Option<Slab>>::new(total_slab_count, &mut frame_allocator);
-        //let address_to_size     = avl::AVLTree::<(usize, usize), FreeListAllocator>::new_empty();
-
-        // create frame allocator
-        //let frame_allocator = BuddyAllocator::new2(linked_list_allocator.end_address() + 1, total_memory, end_address);
+        let frames_count          = Frame::number_for_address(frame_allocator.full_size());
+        let frame_size_classes   = Array::<u8>::new_fill_value(frames_count, NOT_SLAB_FRAME, &mut frame_allocator);
+        let objects_in_use          = Array::<usize>::new_fill_value(total_slab_count, 0, &mut frame_allocator);
 
         SlabAllocator {
             size_to_slab,
-            //address_to_size,
-            start_address,
-            end_address,
+            frame_size_classes,
+            objects_in_use,
+            used_memory     : 0,
+            high_water_mark : 0,
+            start_address : frame_allocator.start_address(),
+            end_address   : frame_allocator.end_address(),
             array_allocator,
             tree_allocator,
             linked_list_allocator,
@@ -370,30 +393,181 @@ impl SlabAllocator {
         }
     }
 
-    fn free_with_size_hint(&mut self, pointer: usize, size: usize) {
-        if size >= FRAME_SIZE {
-            self.frame_allocator.free(pointer)
-        } else {
-            let slab_array_idx = SlabAllocator::index_from_size(size);
+    /// Takes snapshot of allocator state: slab usage per size class, free buddy blocks per order,
+    /// used memory and its high water mark
+    pub fn statistics(&self) -> AllocatorStatistics {
+        let mut statistics  = AllocatorStatistics::new(self.full_size(), self.used_memory, self.high_water_mark);
+        let mut slabs_count = [0; MAX_SLAB_CLASSES];
 
-            let mut slab_is_fully_free = false;
-            {
-                let linked_list_allocator = &mut self.linked_list_allocator;
-                let frame_allocator = &mut self.frame_allocator;
+        // frames are counted in one pass, there are many more frames than size classes
+        for frame_index in self.frame_size_classes.indices() {
+            let size_class = self.frame_size_classes[frame_index];
 
-                if let Some(ref mut slab) = &mut self.size_to_slab[slab_array_idx] {
-                    slab.free(pointer, frame_allocator);
+            if size_class != NOT_SLAB_FRAME {
+                slabs_count[(size_class - 1) as usize] += 1;
+            }
+        }
 
-                    slab_is_fully_free = slab.is_fully_free();
-                }
+        for slab_array_idx in self.size_to_slab.indices().filter(|i| self.size_to_slab[*i].is_some()) {
+            statistics.add_slab_class(SlabClassStatistics::new(
+                SlabAllocator::slab_object_size(slab_array_idx),
+                slabs_count[slab_array_idx],
+                self.objects_in_use[slab_array_idx]));
+        }
+
+        for order in 0 .. self.frame_allocator.orders_count() {
+            statistics.add_free_blocks(order, self.frame_allocator.free_blocks_count(order));
+        }
+
+        statistics
+    }
+
+    fn slab_object_size(slab_array_idx : usize) -> usize {
+        MIN_ALLOCATION_SIZE << slab_array_idx
+    }
+
+    fn add_used_memory(&mut self, size : usize) {
+        self.used_memory     += size;
+        self.high_water_mark  = cmp::max(self.high_water_mark, self.used_memory);
+    }
+
+    // allocates directly from frame allocator
+    fn allocate_frames(&mut self, size : usize, align : usize) -> Option<usize> {
+        let result = self.frame_allocator.allocate_aligned(size, align);
+
+        if let Some(pointer) = result {
+            let allocation_size = self.frame_allocator.allocation_size(pointer);
+            self.add_used_memory(allocation_size);
+        }
+
+        result
+    }
+
+    // index of the frame in frame_size_classes
+    fn frame_index(&self, pointer : usize) -> usize {
+        Frame::number_for_address(pointer - self.start_address)
+    }
+
+    fn free_from_slab(&mut self, pointer : usize, slab_array_idx : usize) {
+        let mut slab_is_fully_free = false;
+        let mut released_frame = None;
+        {
+            let frame_allocator = &mut self.frame_allocator;
+
+            if let Some(ref mut slab) = &mut self.size_to_slab[slab_array_idx] {
+                released_frame = slab.free(pointer, frame_allocator);
+
+                slab_is_fully_free = slab.is_fully_free();
             }
+        }
+
+        self.objects_in_use[slab_array_idx] -= 1;
+        self.used_memory                    -= SlabAllocator::slab_object_size(slab_array_idx);
 
-            if slab_is_fully_free {
-                self.size_to_slab[slab_array_idx] = None;
+        if let Some(frame_address) = released_frame {
+            let frame_index = self.frame_index(frame_address);
+            self.frame_size_classes[frame_index] = NOT_SLAB_FRAME;
+        }
+
+        if slab_is_fully_free {
+            self.size_to_slab[slab_array_idx] = None;
+        }
+    }
+
+    /// Changes allocation size, moving allocation only if it cannot be resized in place.
+    /// Slab allocation stays in place while new size fits into its slab block, frame allocation
+    /// stays in place while new size fits into its buddy block or the block can be grown in place.
+    /// # Arguments
+    /// * `pointer` - start address of allocation
+    /// * `old_size` - current allocation size
+    /// * `align` - allocation alignment, must be a power of two
+    /// * `new_size` - requested allocation size
+    /// # Returns
+    /// Address of resized allocation or `None` if out of memory, the old allocation is left intact in that case
+    /// # Why unsafe
+    /// Copies `old_size` bytes from `pointer` when allocation is moved
+    pub unsafe fn reallocate(&mut self, pointer : usize, old_size : usize, align : usize, new_size : usize) -> Option<usize> {
+        let frame_index   = self.frame_index(pointer);
+        let size_class    = self.frame_size_classes[frame_index];
+        let required_size = cmp::max(new_size, align);
+
+        let resized_in_place = if size_class == NOT_SLAB_FRAME {
+            let allocation_size = self.frame_allocator.allocation_size(pointer);
+
+            if required_size <= allocation_size {
+                true
+            }
+            else if self.frame_allocator.grow_in_place(pointer, required_size) {
+                let grown_size = self.frame_allocator.allocation_size(pointer);
+                self.add_used_memory(grown_size - allocation_size);
+
+                true
             }
+            else {
+                false
+            }
+        }
+        else {
+            required_size <= SlabAllocator::slab_object_size((size_class - 1) as usize)
+        };
+
+        if resized_in_place {
+            Some(pointer)
+        }
+        else {
+            self.allocate_aligned(new_size, align).map(|new_pointer| {
+                ptr::copy_nonoverlapping(pointer as *const u8, new_pointer as *mut u8, cmp::min(old_size, new_size));
+
+                self.free(pointer);
+
+                new_pointer
+            })
         }
     }
 
+    // heap entry points used by Alloc and GlobalAlloc, with `heap_debug` feature allocations
+    // are surrounded by guard data that is checked on free
+
+    #[cfg(not(feature = "heap_debug"))]
+    unsafe fn heap_allocate(&mut self, size : usize, align : usize) -> Option<usize> {
+        self.allocate_aligned(size, align)
+    }
+
+    #[cfg(feature = "heap_debug")]
+    unsafe fn heap_allocate(&mut self, size : usize, align : usize) -> Option<usize> {
+        self.allocate_aligned(debug::block_size_for(size, align), align)
+            .map(|block| debug::on_allocate(block, size, align))
+    }
+
+    #[cfg(not(feature = "heap_debug"))]
+    unsafe fn heap_free(&mut self, pointer : usize) {
+        self.free(pointer)
+    }
+
+    #[cfg(feature = "heap_debug")]
+    unsafe fn heap_free(&mut self, pointer : usize) {
+        let block = debug::on_free(pointer);
+
+        self.free(block)
+    }
+
+    #[cfg(not(feature = "heap_debug"))]
+    unsafe fn heap_reallocate(&mut self, pointer : usize, old_size : usize, align : usize, new_size : usize) -> Option<usize> {
+        self.reallocate(pointer, old_size, align, new_size)
+    }
+
+    // red zone sits right after allocation end, so resized allocation always gets new guard data
+    #[cfg(feature = "heap_debug")]
+    unsafe fn heap_reallocate(&mut self, pointer : usize, old_size : usize, align : usize, new_size : usize) -> Option<usize> {
+        self.heap_allocate(new_size, align).map(|new_pointer| {
+            ptr::copy_nonoverlapping(pointer as *const u8, new_pointer as *mut u8, cmp::min(old_size, new_size));
+
+            self.heap_free(pointer);
+
+            new_pointer
+        })
+    }
+
     fn buddy_free_list_size(buddy_levels_count : usize, total_memory : usize, tree_cell_size : usize, linked_list_cell_size : usize) -> (usize, usize) {
         let mut tree_size = 0;
         let mut linked_list_size = 0;
@@ -439,7 +613,7 @@ impl SlabAllocator {
     fn allocate0(
         size_rounded : usize,
         slab : &mut Slab,
-        frame_allocator : &mut BuddyAllocator,
+        frame_allocator : &mut PhysicalMemoryManager,
         linked_list_allocator :  &mut FreeListAllocator,
         tree_allocator : &mut FreeListAllocator/*,
         address_to_size : &mut avl::AVLTree<(usize, usize), FreeListAllocator>*/) -> Option<usize> {
@@ -465,83 +639,99 @@ impl MemoryAllocator for SlabAllocator {
             if size_rounded > self.full_size() {
                 None
             }else if size_rounded >= FRAME_SIZE {
-                self.frame_allocator.allocate(size_rounded)
+                self.allocate_frames(size_rounded, 1)
             }
                 else {
                 let size_array_idx = SlabAllocator::index_from_size(size_rounded);
 
-                let  frame_allocator            = &mut self.frame_allocator;
-                let  linked_list_allocator     = &mut self.linked_list_allocator;
-                let  tree_allocator               = &mut self.tree_allocator;
-//                let  address_to_size            = &mut self.address_to_size;
-
-                // check if we have existing slab for requested size,
-                // if not - try create a new slab for this size
-                let result_from_existing_slab = {
-                    let slab_opt = &mut self.size_to_slab[size_array_idx];
-
-                    slab_opt.as_mut().and_then(|slab| {
-
-                        let result = SlabAllocator::allocate0(
-                            size_rounded,
-                            slab,
-                            frame_allocator,
-                            linked_list_allocator,
-                            tree_allocator/*,
-                            address_to_size*/);
-
-                        result
+                let result = {
+                    let  frame_allocator            = &mut self.frame_allocator;
+                    let  linked_list_allocator     = &mut self.linked_list_allocator;
+                    let  tree_allocator               = &mut self.tree_allocator;
+    //                let  address_to_size            = &mut self.address_to_size;
+
+                    // check if we have existing slab for requested size,
+                    // if not - try create a new slab for this size
+                    let result_from_existing_slab = {
+                        let slab_opt = &mut self.size_to_slab[size_array_idx];
+
+                        slab_opt.as_mut().and_then(|slab| {
+
+                            let result = SlabAllocator::allocate0(
+                                size_rounded,
+                                slab,
+                                frame_allocator,
+                                linked_list_allocator,
+                                tree_allocator/*,
+                                address_to_size*/);
+
+                            result
+                        })
+                    };
+
+                    // if no slab is found, then try create a new one
+                    let size_to_slab  = &mut self.size_to_slab;
+                    result_from_existing_slab.or_else(|| {
+                        let new_slab_opt = Slab::new(size_rounded, frame_allocator);
+
+                        // if slab cannot be created - then its oom
+                        new_slab_opt.and_then(|mut new_slab| {
+
+                            let result = SlabAllocator::allocate0(
+                                size_rounded,
+                                &mut new_slab,
+                                frame_allocator,
+                                linked_list_allocator,
+                                tree_allocator/*,
+                                address_to_size*/);
+
+                            size_to_slab.update(size_array_idx, Some(new_slab));
+
+                            result
+                        })
                     })
                 };
 
-                // if no slab is found, then try create a new one
-                let size_to_slab  = &mut self.size_to_slab;
-                result_from_existing_slab.or_else(|| {
-                    let new_slab_opt = Slab::new(size_rounded, frame_allocator);
-
-                    // if slab cannot be created - then its oom
-                    new_slab_opt.and_then(|mut new_slab| {
-
-                        let result = SlabAllocator::allocate0(
-                            size_rounded,
-                            &mut new_slab,
-                            frame_allocator,
-                            linked_list_allocator,
-                            tree_allocator/*,
-                            address_to_size*/);
-
-                        size_to_slab.update(size_array_idx, Some(new_slab));
+                // remember which slab the frame belongs to, so that free() can find it without size hint
+                if let Some(pointer) = result {
+                    let frame_index = self.frame_index(pointer);
+                    self.frame_size_classes[frame_index] = (size_array_idx + 1) as u8;
+                    self.objects_in_use[size_array_idx] += 1;
+                    self.add_used_memory(size_rounded);
+                }
 
-                        result
-                    })
-                })
+                result
             }
         }
     }
 
     fn free(&mut self, pointer: usize) {
-        /*if let Some(was_allocated) = self.address_to_size.find_by(&pointer, |w| w.0,  |x, y| x .1== *y)*/ /*{
-            let slab_array_idx = SlabAllocator::index_from_size(was_allocated.1);
-
-            let mut slab_is_fully_free = false;
-            {
-                let linked_list_allocator  = &mut self.linked_list_allocator;
-                let frame_allocator         = &mut self.frame_allocator;
-
-                if let Some(ref mut slab) = &mut self.size_to_slab[slab_array_idx]  {
+        let frame_index = self.frame_index(pointer);
+        let size_class  = self.frame_size_classes[frame_index];
 
-                    slab.free(pointer, frame_allocator);
+        if size_class == NOT_SLAB_FRAME {
+            // memory was allocated directly from frame allocator
+            let allocation_size = self.frame_allocator.allocation_size(pointer);
 
-                    slab_is_fully_free = slab.is_fully_free();
+            self.used_memory -= allocation_size;
+            self.frame_allocator.free(pointer)
+        }
+        else {
+            self.free_from_slab(pointer, (size_class - 1) as usize)
+        }
+    }
 
-                    //self.address_to_size.delete(was_allocated.leak());
-                }
-            }
+    fn allocate_aligned(&mut self, size : usize, align : usize) -> Option<usize> {
+        assert!(math::is_power_of_two(align), "Alignment {} is not a power of two", align);
 
-            if  slab_is_fully_free {
-                self.size_to_slab[slab_array_idx] = None;
-            }
-        }*/
+        // slab blocks have power of two sizes and start from the beginning of a frame,
+        // thus every block is aligned by its size
+        if align <= FRAME_SIZE {
+            self.allocate(cmp::max(size, align))
+        }
+        else {
+            self.allocate_frames(cmp::max(size, align), align)
+        }
     }
 }
 
@@ -564,43 +754,18 @@ impl MemoryAllocatorMeta for SlabAllocator {
 
 unsafe impl Alloc for SlabAllocator {
     unsafe fn alloc(&mut self, layout: Layout) -> Result<ptr::NonNull<u8>, AllocErr> {
-        self.allocate(layout.size())
+        self.heap_allocate(layout.size(), layout.align())
             .map(|a| Ok(ptr::NonNull::new_unchecked(a as * mut u8)))
             .unwrap_or(Err(AllocErr))
     }
 
     unsafe fn dealloc(&mut self, ptr : ptr::NonNull<u8>, layout: Layout) {
-        self.free(*ptr.as_ref() as usize)
+        self.heap_free(ptr.as_ptr() as usize)
     }
-}
-
-pub struct SlabHelp {
-    pub value : ptr::NonNull<SlabAllocator>
-}
 
-impl SlabHelp {
-
-    pub fn is_fully_free(&self) -> bool {
-        unsafe { self.value.as_ref().is_fully_free() }
+    unsafe fn realloc(&mut self, ptr : ptr::NonNull<u8>, layout: Layout, new_size : usize) -> Result<ptr::NonNull<u8>, AllocErr> {
+        self.heap_reallocate(ptr.as_ptr() as usize, layout.size(), layout.align(), new_size)
+            .map(|a| Ok(ptr::NonNull::new_unchecked(a as * mut u8)))
+            .unwrap_or(Err(AllocErr))
     }
 }
-
-unsafe impl GlobalAlloc for SlabHelp {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        // escape immutable self
-        let mut  v = self.value.clone();
-        let mut escape = v.as_mut();
-
-        escape.allocate(layout.size())
-            .map(|a| a as * mut u8)
-            .unwrap_or(0 as * mut u8)
-    }
-
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        // escape immutable self
-        let mut  v = self.value.clone();
-        let mut escape = v.as_mut();
-
-        escape.free_with_size_hint(ptr as usize, layout.size())
-    }
-}
\ No newline at end of file
@@ -0,0 +1,206 @@
+use frame::FRAME_SIZE;
+use core::fmt;
+
+pub const MAX_SLAB_CLASSES : usize = 64;
+pub const MAX_BUDDY_ORDERS : usize = 64;
+
+/// Usage of a single slab size class
+#[derive(Clone, Copy, Debug)]
+pub struct SlabClassStatistics {
+    object_size    : usize,
+    slabs_count    : usize,
+    objects_in_use : usize
+}
+
+impl SlabClassStatistics {
+
+    /// # Arguments
+    /// * `object_size` - size of objects the slab hands out
+    /// * `slabs_count` - number of frames that hold objects of this class
+    /// * `objects_in_use` - number of allocated objects
+    pub fn new(object_size : usize, slabs_count : usize, objects_in_use : usize) -> Self {
+        SlabClassStatistics {
+            object_size,
+            slabs_count,
+            objects_in_use
+        }
+    }
+
+    fn empty() -> Self {
+        SlabClassStatistics::new(0, 0, 0)
+    }
+
+    pub fn object_size(&self) -> usize {
+        self.object_size
+    }
+
+    pub fn slabs_count(&self) -> usize {
+        self.slabs_count
+    }
+
+    pub fn objects_in_use(&self) -> usize {
+        self.objects_in_use
+    }
+
+    pub fn free_objects(&self) -> usize {
+        self.capacity() - self.objects_in_use
+    }
+
+    /// Total number of objects slabs of this class can hold
+    pub fn capacity(&self) -> usize {
+        self.slabs_count * (FRAME_SIZE / self.object_size)
+    }
+}
+
+impl fmt::Display for SlabClassStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Slab {:>4} b: {} slabs, {} in use, {} free", self.object_size, self.slabs_count, self.objects_in_use, self.free_objects())
+    }
+}
+
+/// Snapshot of heap allocator state. Stored in fixed size arrays, so that taking it
+/// doesn't allocate from the allocator being inspected.
+#[derive(Clone)]
+pub struct AllocatorStatistics {
+    slab_classes       : [SlabClassStatistics; MAX_SLAB_CLASSES],
+    slab_classes_count : usize,
+    // free buddy blocks count per order, block of order `n` is FRAME_SIZE * 2 ^ n bytes
+    free_blocks        : [usize; MAX_BUDDY_ORDERS],
+    buddy_orders_count : usize,
+    total_memory       : usize,
+    used_memory        : usize,
+    high_water_mark    : usize
+}
+
+impl AllocatorStatistics {
+
+    /// # Arguments
+    /// * `total_memory` - size of memory managed by allocator
+    /// * `used_memory` - size of memory handed out, including rounding to slab and buddy block sizes
+    /// * `high_water_mark` - max `used_memory` ever reached
+    pub fn new(total_memory : usize, used_memory : usize, high_water_mark : usize) -> Self {
+        AllocatorStatistics {
+            slab_classes       : [SlabClassStatistics::empty(); MAX_SLAB_CLASSES],
+            slab_classes_count : 0,
+            free_blocks        : [0; MAX_BUDDY_ORDERS],
+            buddy_orders_count : 0,
+            total_memory,
+            used_memory,
+            high_water_mark
+        }
+    }
+
+    pub fn add_slab_class(&mut self, slab_class : SlabClassStatistics) {
+        assert!(self.slab_classes_count < MAX_SLAB_CLASSES, "Too many slab classes, max is {}", MAX_SLAB_CLASSES);
+
+        self.slab_classes[self.slab_classes_count] = slab_class;
+        self.slab_classes_count += 1;
+    }
+
+    /// Adds free buddy blocks of `order`, blocks of different pools are summed up
+    /// # Arguments
+    /// * `order` - buddy order, block size is FRAME_SIZE * 2 ^ order
+    /// * `count` - number of free blocks
+    pub fn add_free_blocks(&mut self, order : usize, count : usize) {
+        assert!(order < MAX_BUDDY_ORDERS, "Buddy order {} is too big, max is {}", order, MAX_BUDDY_ORDERS - 1);
+
+        self.free_blocks[order] += count;
+
+        if order >= self.buddy_orders_count {
+            self.buddy_orders_count = order + 1;
+        }
+    }
+
+    pub fn slab_classes(&self) -> &[SlabClassStatistics] {
+        &self.slab_classes[..self.slab_classes_count]
+    }
+
+    /// Free buddy blocks count indexed by order
+    pub fn free_blocks(&self) -> &[usize] {
+        &self.free_blocks[..self.buddy_orders_count]
+    }
+
+    pub fn total_memory(&self) -> usize {
+        self.total_memory
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark
+    }
+
+    /// Size of memory in free buddy blocks
+    pub fn free_memory(&self) -> usize {
+        self.free_blocks()
+            .iter()
+            .enumerate()
+            .map(|(order, count)| count * AllocatorStatistics::block_size(order))
+            .sum()
+    }
+
+    pub fn largest_free_block(&self) -> usize {
+        self.free_blocks()
+            .iter()
+            .rposition(|count| *count > 0)
+            .map(|order| AllocatorStatistics::block_size(order))
+            .unwrap_or(0)
+    }
+
+    /// Percent of free memory that can't be handed out as a single block,
+    /// 0 means all free memory is in one block
+    pub fn fragmentation(&self) -> usize {
+        let free_memory = self.free_memory();
+
+        if free_memory == 0 {
+            0
+        }
+        else {
+            100 - self.largest_free_block() * 100 / free_memory
+        }
+    }
+
+    /// Percent of slab memory occupied by free objects
+    pub fn slab_fragmentation(&self) -> usize {
+        let (capacity, free) = self.slab_classes()
+            .iter()
+            .fold((0, 0), |(capacity, free), class| (
+                capacity + class.capacity() * class.object_size(),
+                free + class.free_objects() * class.object_size()));
+
+        if capacity == 0 {
+            0
+        }
+        else {
+            free * 100 / capacity
+        }
+    }
+
+    fn block_size(order : usize) -> usize {
+        FRAME_SIZE << order
+    }
+}
+
+impl fmt::Display for AllocatorStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Heap: total {} kb, used {} kb, peak {} kb, free {} kb",
+            self.total_memory / 1024,
+            self.used_memory / 1024,
+            self.high_water_mark / 1024,
+            self.free_memory() / 1024)?;
+
+        writeln!(f, "Fragmentation: buddy {}%, slab {}%", self.fragmentation(), self.slab_fragmentation())?;
+
+        for slab_class in self.slab_classes() {
+            writeln!(f, "{}", slab_class)?;
+        }
+
+        for (order, count) in self.free_blocks().iter().enumerate().filter(|&(_, count)| *count > 0) {
+            writeln!(f, "Order {:>2} ({} kb): {} free blocks", order, AllocatorStatistics::block_size(order) / 1024, count)?;
+        }
+
+        Ok(())
+    }
+}
@@ -3,12 +3,14 @@ use multiboot::multiboot_header::tags::memory_map::*;
 use multiboot::multiboot_header::tags::elf;
 use frame::Frame;
 use frame::FRAME_SIZE;
-use stdx_memory::collections::linked_list::LinkedList;
-use allocator::bump::{BumpAllocator, ConstSizeBumpAllocator};
-use stdx_memory::MemoryAllocator;
+use stdx_memory::collections::array::Array;
+use stdx_memory::collections::frame_bitmap::FrameBitMap;
+use allocator::bump::BumpAllocator;
 use stdx_memory::ConstantSizeMemoryAllocator;
 use stdx_memory::MemoryAllocatorMeta;
-use stdx_memory::heap;
+use stdx_memory::MemoryAllocator;
+use stdx::math;
+use stdx::Sequence;
 use core::fmt;
 use core::mem;
 use core::ptr;
@@ -17,28 +19,21 @@ use core::ptr;
 /*
     Bump allocator with a stack of free frames. Allocates new frames in simple incremental fashion
     not touching multiboot and kernel code. On deallocate places frame into free frame stack and picks
-    frames from it on further allocations if possible. Bump allocation stops entirely when there is no more
-    available memory (allocation try is outside available memory range) and stack of free frames is used after that. 
-    You can think of bump allocation as a meachanism to populate free frame stack, the other option to do it without bump allocation is to 
-    create a big free frame stack that describes all available memory and use only it.  
-*/
+    frames from it on further allocations, thus frames freed during boot are reused. Bump allocation stops entirely
+    when there is no more available memory (allocation try is outside available memory range) and only stack of free frames is used after that.
+    You can think of bump allocation as a meachanism to populate free frame stack.
 
-pub struct FreeListFrameAllocator {
-    multiboot_start_frame: Frame,
-    multiboot_end_frame: Frame,
-    kernel_start_frame: Frame,
-    kernel_end_frame: Frame,
-    current_memory_area : ptr::NonNull<MemoryMapEntry>,
-    memory_areas: AvailableMemorySectionsIterator,
-    last_frame_number: Frame,
-    empty_frame_list: heap::WeakBox<LinkedList<Frame>>,
-    frame_list_allocator : ConstSizeBumpAllocator,
-    buddy_allocator_start_frame : Frame,
-    buddy_allocator_end_frame : Frame
-}
+    Frame bitmap marks frames that are handed out and not returned yet. It detects double free and
+    tells which frames are still in use when allocator state is handed over to buddy allocator, see `used_frames`.
+    Bitmap and free frame stack live in aux memory right after multiboot information structure:
+
+    | frame bitmap (1 bit per frame) | free frame stack (frame numbers) |
+*/
 
 pub trait FrameAllocatorFake {
-    fn bump_allocator(&self) -> ConstSizeBumpAllocator;
+    /// Returns inclusive address range of memory that holds allocator data structures,
+    /// it must stay mapped while allocator is in use
+    fn aux_memory_range(&self) -> (usize, usize);
 }
 
 pub struct FrameAllocator {
@@ -49,15 +44,19 @@ pub struct FrameAllocator {
     current_memory_area : ptr::NonNull<MemoryMapEntry>,
     memory_areas: AvailableMemorySectionsIterator,
     last_frame_number: Frame,
-    empty_frame_list: heap::WeakBox<LinkedList<Frame>>,
-    frame_list_allocator : ConstSizeBumpAllocator,
+    // frames that are handed out and not returned yet
+    frame_bitmap : FrameBitMap,
+    // numbers of returned frames, top of the stack is picked first
+    free_frames : Array<u32>,
+    free_frames_count : usize,
+    aux_memory : BumpAllocator,
     buddy_allocator_start_frame : Frame,
     buddy_allocator_end_frame : Frame
 }
 
 impl FrameAllocatorFake for FrameAllocator {
-    fn bump_allocator(&self) -> ConstSizeBumpAllocator {
-        self.frame_list_allocator.clone()
+    fn aux_memory_range(&self) -> (usize, usize) {
+        (self.aux_memory.start_address(), self.aux_memory.end_address())
     }
 }
 
@@ -94,9 +93,18 @@ impl FrameAllocator {
     }
 
     pub fn end_address(&self) -> usize {
-        self.frame_list_allocator.end_address()
+        self.aux_memory.end_address()
     }
-        
+
+    /// Number of frames that were returned and wait in free frame stack for reuse
+    pub fn free_frames_count(&self) -> usize {
+        self.free_frames_count
+    }
+
+    pub fn is_in_use(&self, frame : Frame) -> bool {
+        frame.number() < self.frame_bitmap.frames_count() && self.frame_bitmap.is_in_use(frame.number())
+    }
+
     pub fn set_buddy_start(&mut self, f : Frame){
         self.buddy_allocator_start_frame = f
     }
@@ -105,11 +113,28 @@ impl FrameAllocator {
         self.buddy_allocator_end_frame = f
     }
 
-    fn empty_frame_list(&self) -> &heap::WeakBox<LinkedList<Frame>> {
-        &self.empty_frame_list
+    /// Returns inclusive address range of frames that were handed out by bump allocation,
+    /// None if nothing was allocated yet.
+    pub fn used_range(&self) -> Option<(usize, usize)> {
+        FrameAllocator::next_fitting_memory_area(self.memory_areas(), Frame::from_address(0))
+            .map(|first_memory_area| FrameAllocator::frame_for_base_address(first_memory_area.base_address() as usize).address())
+            .filter(|first_frame_address| self.last_frame_number.address() > *first_frame_address)
+            .map(|first_frame_address| (first_frame_address, self.last_frame_number.address() - 1))
+    }
+
+    /// Returns inclusive address ranges of frames that are still in use, frames that were freed
+    /// are left out. Used to hand allocator state over to buddy allocator: everything else in available memory,
+    /// except aux memory of this allocator, is free.
+    pub fn used_frames(&self) -> UsedFramesIterator {
+        UsedFramesIterator {
+            frame_bitmap : &self.frame_bitmap,
+            frame_number : 0,
+            end_number   : self.last_frame_number.number()
+        }
     }
 
-    pub fn new_test(multiboot_header: &MultibootHeader, bump_allocator1 : ConstSizeBumpAllocator) -> FrameAllocator {
+    /// Creates allocator that keeps its data structures in `aux_memory`
+    pub fn new_test(multiboot_header: &MultibootHeader, aux_memory : BumpAllocator) -> FrameAllocator {
         let elf_sections = multiboot_header.read_tag::<elf::ElfSections>()
             .expect("Cannot create frame allocator without multiboot elf sections");
         let memory_areas = multiboot_header.read_tag::<MemoryMap>()
@@ -135,7 +160,7 @@ impl FrameAllocator {
             
         let first_memory_area = FrameAllocator::next_fitting_memory_area(memory_areas.entries(), Frame::from_address(0)).expect("Cannot determine first memory area");            
         let last_frame_number = FrameAllocator::frame_for_base_address(first_memory_area.base_address() as usize);        
-        let mut bump_allocator = bump_allocator1;
+        let mut aux_memory = aux_memory;
 
         FrameAllocator {
             multiboot_start_frame: Frame::from_address(multiboot_header.start_address()),
@@ -145,8 +170,10 @@ impl FrameAllocator {
             current_memory_area : ptr::NonNull::from(first_memory_area),
             memory_areas: memory_areas.entries(),
             last_frame_number: last_frame_number,
-            empty_frame_list: heap::WeakBox::new(LinkedList::Nil, &mut bump_allocator),
-            frame_list_allocator : bump_allocator,
+            frame_bitmap : FrameBitMap::new(FrameAllocator::bitmap_frames_count(&memory_areas), &mut aux_memory),
+            free_frames : FrameAllocator::free_frames_stack(&memory_areas, &mut aux_memory),
+            free_frames_count : 0,
+            aux_memory,
             buddy_allocator_start_frame : Frame::from_address(0),
             buddy_allocator_end_frame : Frame::from_address(0)
         }
@@ -168,10 +195,10 @@ impl FrameAllocator {
         let first_memory_area = FrameAllocator::next_fitting_memory_area(memory_areas.entries(), Frame::from_address(0)).expect("Cannot determine first memory area");            
         let last_frame_number = FrameAllocator::frame_for_base_address(first_memory_area.base_address() as usize);        
 
-        let empty_frame_list_size = FrameAllocator::get_empty_frame_list_size(&memory_areas);
+        let aux_memory_size = FrameAllocator::aux_memory_size(&memory_areas);
         let kernel_end_frame = Frame::from_address(kernel_end_address);        
         // move it to some proper place!
-        let mut bump_allocator = ConstSizeBumpAllocator::from_address_for_type::<LinkedList<Frame>>(multiboot_header.end_address() + 1, empty_frame_list_size);
+        let mut aux_memory = BumpAllocator::from_address(multiboot_header.end_address() + 1, aux_memory_size);
 
         FrameAllocator {
             multiboot_start_frame: Frame::from_address(multiboot_header.start_address()),
@@ -181,36 +208,52 @@ impl FrameAllocator {
             current_memory_area : ptr::NonNull::from(first_memory_area),
             memory_areas: memory_areas.entries(),
             last_frame_number: last_frame_number,
-            empty_frame_list: heap::WeakBox::new(LinkedList::Nil, &mut bump_allocator),
-            frame_list_allocator : bump_allocator,
+            frame_bitmap : FrameBitMap::new(FrameAllocator::bitmap_frames_count(&memory_areas), &mut aux_memory),
+            free_frames : FrameAllocator::free_frames_stack(&memory_areas, &mut aux_memory),
+            free_frames_count : 0,
+            aux_memory,
             buddy_allocator_start_frame : Frame::from_address(0),
             buddy_allocator_end_frame : Frame::from_address(0)
         }
     }
-    
-    fn get_empty_frame_list_size(memory_map : &MemoryMap) -> usize {
-        let available_memory = memory_map.available_memory() as usize;
-        let total_frames_count = available_memory / FRAME_SIZE;
 
-        total_frames_count * mem::size_of::<LinkedList<Frame>>()
+    /// Returns size of memory needed for frame bitmap and free frame stack, including padding that aligns the stack
+    pub fn aux_memory_size(memory_map : &MemoryMap) -> usize {
+        FrameBitMap::mem_size_for(FrameAllocator::bitmap_frames_count(memory_map)) +
+            mem::align_of::<u32>() - 1 +
+            FrameAllocator::free_frames_capacity(memory_map) * mem::size_of::<u32>()
+    }
+
+    // aux memory may start at any byte and bitmap may take any number of bytes, so the stack start is aligned for u32
+    fn free_frames_stack(memory_map : &MemoryMap, aux_memory : &mut BumpAllocator) -> Array<u32> {
+        let padding = math::align_up(aux_memory.current_pointer(), mem::align_of::<u32>()) - aux_memory.current_pointer();
+
+        aux_memory.allocate(padding).expect("No memory for free frame stack");
+
+        Array::new(FrameAllocator::free_frames_capacity(memory_map), aux_memory)
+    }
+
+    // bitmap is indexed by frame number, thus it covers every frame up to the end of the highest memory area
+    fn bitmap_frames_count(memory_map : &MemoryMap) -> usize {
+        memory_map.entries()
+            .map(|e| Frame::from_address(e.end_address() as usize).number() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // frame can't be freed more than once, thus stack never holds more frames than there are in available memory
+    fn free_frames_capacity(memory_map : &MemoryMap) -> usize {
+        memory_map.available_memory() as usize / FRAME_SIZE
     }
 
     pub fn allocate(&mut self) -> Option<Frame> {
-                        
-        // check empty frame list first, if nothing perform bump allocation
-        match self.empty_frame_list.take() {
-            Some((value, prev)) => {
-                
-                // pick first result from empty frame list                
-                self.empty_frame_list = prev;                
-                
-                Some(value)
-            },
-            _ => {
+        // check free frame stack first, if nothing perform bump allocation
+        let result = match self.pop_free_frame() {
+            Some(frame) => Some(frame),
+            None => {
                 match self.bump_allocate() {
                     Some(allocate_result) => {                    
                         self.last_frame_number = allocate_result.next(); // next possible frame for bump allocator
-                        //self.frame_bit_map.set_in_use(allocate_result.number());
 
                         Some(allocate_result)
                     },
@@ -219,6 +262,25 @@ impl FrameAllocator {
                     None => None
                 }
             }
+        };
+
+        if let Some(frame) = result {
+            self.frame_bitmap.set_in_use(frame.number());
+        }
+
+        result
+    }
+
+    fn pop_free_frame(&mut self) -> Option<Frame> {
+        if self.free_frames_count == 0 {
+            None
+        }
+        else {
+            self.free_frames_count -= 1;
+
+            let frame_number = self.free_frames.value(self.free_frames_count) as usize;
+
+            Some(Frame::from_address(Frame::number_to_address(frame_number)))
         }
     }
 
@@ -273,10 +335,10 @@ impl FrameAllocator {
                 frame <= self.kernel_end_frame {
             self.step_over_reserved_memory_if_needed(self.kernel_end_frame.next()) // in case next will touch empty frame list
         }
-        // dont touch empty frame list
-        else if frame >= Frame::from_address(self.frame_list_allocator.start_address()) &&
-                frame <= Frame::from_address(self.frame_list_allocator.end_address()) {
-            let possible_frame = Frame::from_address(self.frame_list_allocator.end_address()).next();
+        // dont touch frame bitmap and free frame stack
+        else if frame >= Frame::from_address(self.aux_memory.start_address()) &&
+                frame <= Frame::from_address(self.aux_memory.end_address()) {
+            let possible_frame = Frame::from_address(self.aux_memory.end_address()).next();
             self.step_over_reserved_memory_if_needed(possible_frame) // in case next() will touch heap data structure
         }
         // don't touch heap
@@ -305,30 +367,30 @@ impl FrameAllocator {
             .min_by_key(|e| e.base_address())
     }
 
-    pub fn deallocate(&mut self, frame : Frame) {   
-        /*     
-        let new_list = self.empty_frame_list.map_or(
-            FreeList::new(frame, &mut self.frame_list_allocator),
-            |e| e.pointer().add(frame, &mut self.frame_list_allocator)          
-        );
+    /// Places frame into free frame stack, so that it is given out by the next allocation.
+    /// Panics if frame wasn't allocated or is already free.
+    pub fn deallocate(&mut self, frame : Frame) {
+        assert!(self.is_in_use(frame), "Frame {} wasn't allocated by frame allocator or is already free", frame);
+        assert!(self.free_frames_count < self.free_frames.length(), "Free frame stack is full");
 
-        self.empty_frame_list = Some(new_list);
-        */
+        self.frame_bitmap.set_free(frame.number());
+        self.free_frames.update(self.free_frames_count, frame.number() as u32);
+        self.free_frames_count += 1;
     }
 }
 
 impl MemoryAllocatorMeta for FrameAllocator {
 
     fn start_address(&self) -> usize {
-        self.frame_list_allocator.start_address()
+        self.aux_memory.start_address()
     }
 
     fn end_address(&self) -> usize {
-        self.frame_list_allocator.end_address()
+        self.aux_memory.end_address()
     }
 
     fn aux_data_structures_size(&self) -> usize {
-        self.frame_list_allocator.full_size()
+        self.aux_memory.full_size()
     }
 }
 
@@ -353,8 +415,8 @@ impl fmt::Display for FrameAllocator {
                kernel_end_frame : {},
                current_memory_area : {},
                memory_areas : {},
-               last_frame_number : {},               
-               empty_frame_list : {}",
+               last_frame_number : {},
+               free_frames_count : {}",
                self.multiboot_start_frame,
                self.multiboot_end_frame,
                self.kernel_start_frame,
@@ -362,8 +424,37 @@ impl fmt::Display for FrameAllocator {
                self.current_memory_area.as_ref(),
                self.memory_areas,
                self.last_frame_number,
-               //self.frame_bit_map,
-               "")
+               self.free_frames_count)
     }
     }
-}
\ No newline at end of file
+}
+
+/// Iterates over inclusive address ranges of adjacent frames that are in use
+pub struct UsedFramesIterator<'a> {
+    frame_bitmap : &'a FrameBitMap,
+    frame_number : usize,
+    // frames from this one on were never allocated
+    end_number   : usize
+}
+
+impl<'a> Iterator for UsedFramesIterator<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        while self.frame_number < self.end_number && self.frame_bitmap.is_free(self.frame_number) {
+            self.frame_number += 1;
+        }
+
+        if self.frame_number == self.end_number {
+            return None
+        }
+
+        let first_frame = self.frame_number;
+
+        while self.frame_number < self.end_number && self.frame_bitmap.is_in_use(self.frame_number) {
+            self.frame_number += 1;
+        }
+
+        Some((Frame::number_to_address(first_frame), Frame::number_to_address(self.frame_number) - 1))
+    }
+}
@@ -0,0 +1,221 @@
+use multiboot::multiboot_header::MultibootHeader;
+use multiboot::multiboot_header::tags::memory_map::MemoryMap;
+use multiboot::multiboot_header::tags::modules::Module;
+use multiboot::multiboot_header::tags::elf;
+use frame::{Frame, FRAME_SIZE};
+use core::fmt;
+use core::slice;
+
+/// Max number of regions that can be described. Memory map of a usual machine has
+/// less than 10 available entries, the rest is left for splits made by excluded ranges.
+pub const MAX_MEMORY_REGIONS : usize = 64;
+
+/// Memory below 1 mb holds real mode IVT, BIOS data area and EBDA, it is never given to allocators
+const LOW_MEMORY_END : usize = 0x100000;
+
+/// Frame aligned range of physical memory, end address is inclusive
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryRegion {
+    start_address : usize,
+    end_address   : usize
+}
+
+impl MemoryRegion {
+
+    const fn empty() -> Self {
+        MemoryRegion {
+            start_address : 0,
+            end_address   : 0
+        }
+    }
+
+    pub fn start_address(&self) -> usize {
+        self.start_address
+    }
+
+    pub fn end_address(&self) -> usize {
+        self.end_address
+    }
+
+    pub fn size(&self) -> usize {
+        self.end_address - self.start_address + 1
+    }
+
+    pub fn contains(&self, address : usize) -> bool {
+        address >= self.start_address && address <= self.end_address
+    }
+
+    fn overlaps(&self, start_address : usize, end_address : usize) -> bool {
+        start_address <= self.end_address && end_address >= self.start_address
+    }
+}
+
+impl fmt::Display for MemoryRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#012x}-{:#012x} {:>8} kb", self.start_address, self.end_address, self.size() / 1024)
+    }
+}
+
+/// Set of non overlapping physical memory regions that are free to be used by memory allocators.
+/// Regions are stored in fixed size array, because it is built before any allocator exists.
+#[derive(Clone)]
+pub struct MemoryRegions {
+    regions : [MemoryRegion; MAX_MEMORY_REGIONS],
+    count   : usize
+}
+
+impl MemoryRegions {
+
+    pub fn new() -> Self {
+        MemoryRegions {
+            regions : [MemoryRegion::empty(); MAX_MEMORY_REGIONS],
+            count   : 0
+        }
+    }
+
+    /// Creates regions from every available memory map entry, excluding memory below 1 mb,
+    /// kernel image, multiboot information structure, boot modules and `reserved` ranges
+    /// # Arguments
+    /// * `multiboot_header` - multiboot header
+    /// * `reserved` - additional inclusive address ranges that are already in use, like memory of boot frame allocator
+    pub fn from_multiboot(multiboot_header : &MultibootHeader, reserved : &[(usize, usize)]) -> Self {
+        let memory_map = multiboot_header.read_tag::<MemoryMap>()
+            .expect("Cannot determine memory regions without multiboot memory map");
+        let elf_sections = multiboot_header.read_tag::<elf::ElfSections>()
+            .expect("Cannot determine memory regions without multiboot elf sections");
+
+        let mut result = MemoryRegions::new();
+
+        for entry in memory_map.entries() {
+            result.add(entry.base_address() as usize, entry.end_address() as usize);
+        }
+
+        result.exclude(0, LOW_MEMORY_END - 1);
+
+        if let (Some(kernel_start), Some(kernel_end)) = (elf_sections.entries_start_address(), elf_sections.entries_end_address()) {
+            result.exclude(kernel_start as usize, kernel_end as usize);
+        }
+
+        result.exclude(multiboot_header.start_address(), multiboot_header.end_address());
+
+        for module in multiboot_header.read_tags::<Module>() {
+            result.exclude(module.start_address(), module.end_address());
+        }
+
+        for &(start_address, end_address) in reserved {
+            result.exclude(start_address, end_address);
+        }
+
+        result
+    }
+
+    /// Adds free region. Only frames that are fully inside the range are added.
+    /// # Arguments
+    /// * `start_address` - start address of the range
+    /// * `end_address` - inclusive end address of the range
+    pub fn add(&mut self, start_address : usize, end_address : usize) {
+        let aligned_start = Frame::address_align_up(start_address);
+
+        // end address is inclusive, thus last frame is the one that ends at or before `end_address`
+        if end_address < aligned_start || end_address - aligned_start + 1 < FRAME_SIZE {
+            return
+        }
+
+        let aligned_end = Frame::address_align_down(end_address - aligned_start + 1) + aligned_start - 1;
+
+        self.insert(self.count, MemoryRegion { start_address : aligned_start, end_address : aligned_end });
+    }
+
+    /// Removes range from regions. Every frame touched by the range is removed.
+    /// # Arguments
+    /// * `start_address` - start address of the range
+    /// * `end_address` - inclusive end address of the range
+    pub fn exclude(&mut self, start_address : usize, end_address : usize) {
+        if end_address < start_address {
+            return
+        }
+
+        let excluded_start = Frame::address_align_down(start_address);
+        let excluded_end   = Frame::from_address(end_address).end_address();
+
+        let mut i = 0;
+        while i < self.count {
+            let region = self.regions[i];
+
+            if !region.overlaps(excluded_start, excluded_end) {
+                i += 1;
+                continue;
+            }
+
+            let has_left_part  = region.start_address < excluded_start;
+            let has_right_part = region.end_address > excluded_end;
+
+            match (has_left_part, has_right_part) {
+                (true, true) => {
+                    self.regions[i].end_address = excluded_start - 1;
+                    self.insert(i + 1, MemoryRegion { start_address : excluded_end + 1, end_address : region.end_address });
+                    i += 2;
+                },
+                (true, false) => {
+                    self.regions[i].end_address = excluded_start - 1;
+                    i += 1;
+                },
+                (false, true) => {
+                    self.regions[i].start_address = excluded_end + 1;
+                    i += 1;
+                },
+                (false, false) => self.remove(i)
+            }
+        }
+    }
+
+    /// Splits region that contains `address` into two, so that no region crosses it
+    /// # Arguments
+    /// * `address` - frame aligned address, it becomes start address of the second region
+    pub fn split_at(&mut self, address : usize) {
+        assert!(address % FRAME_SIZE == 0, "Cannot split memory regions at not frame aligned address {}", address);
+
+        let region_index = self.iter().position(|region| region.start_address < address && region.contains(address));
+
+        if let Some(i) = region_index {
+            let region = self.regions[i];
+
+            self.regions[i].end_address = address - 1;
+            self.insert(i + 1, MemoryRegion { start_address : address, end_address : region.end_address });
+        }
+    }
+
+    pub fn iter(&self) -> slice::Iter<MemoryRegion> {
+        self.regions[..self.count].iter()
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns overall size of all regions in bytes
+    pub fn total_memory(&self) -> usize {
+        self.iter().fold(0, |total, region| total + region.size())
+    }
+
+    fn insert(&mut self, index : usize, region : MemoryRegion) {
+        assert!(self.count < MAX_MEMORY_REGIONS, "Cannot describe more than {} memory regions", MAX_MEMORY_REGIONS);
+
+        let mut i = self.count;
+        while i > index {
+            self.regions[i] = self.regions[i - 1];
+            i -= 1;
+        }
+
+        self.regions[index] = region;
+        self.count += 1;
+    }
+
+    fn remove(&mut self, index : usize) {
+        for i in index..self.count - 1 {
+            self.regions[i] = self.regions[i + 1];
+        }
+
+        self.count -= 1;
+    }
+}
@@ -1,4 +1,5 @@
 pub mod frame_allocator;
+pub mod memory_regions;
 
 use core::fmt;
 use core::iter;
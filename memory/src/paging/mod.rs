@@ -1,12 +1,17 @@
 pub mod page_table;
+pub mod physical_memory;
+pub mod walker;
 
 use paging::page_table::{ P4Table, PAGE_TABLE_SIZE, PAGE_TABLE_ENTRY_SIZE };
+use paging::physical_memory::{ PhysicalMemory, RECURSIVE_MAPPING };
 use frame::frame_allocator::*;
 use frame::Frame;
 use multiboot::multiboot_header::MultibootHeader;
 use multiboot::multiboot_header::tags::elf;
+use multiboot::multiboot_header::tags::framebuffer::{FramebufferInfo, FramebufferType};
 use hardware::x86_64::registers;
 use stdx_memory::MemoryAllocator;
+use core::fmt;
 
 /// Returns size of page tables to describe virtual memory
 /// # Arguments
@@ -17,8 +22,21 @@ pub fn table_size_for(virtual_memory_size : usize) -> usize {
 
 /// Returns current p4 table.
 pub fn p4_table() -> &'static mut P4Table {
-    const P4_TABLE_ADDRESS : usize = 0xfffffffffffff000;         // recursive mapping to P4s 0 entry
-    unsafe { &mut (*(P4_TABLE_ADDRESS as *mut P4Table)) } // reading predefined recursive address is safe
+    RECURSIVE_MAPPING.p4_table() // reading predefined recursive address is safe
+}
+
+/// Prints every present mapping of the current address space, consecutive pages are printed as one mapping
+/// # Arguments
+/// * `p4_table` - current p4 table
+/// * `writer` - output
+pub fn dump_address_space<W>(p4_table : &P4Table, writer : &mut W) -> fmt::Result where W : fmt::Write {
+    writeln!(writer, "---Address space---")?;
+
+    for mapping in p4_table.mappings() {
+        writeln!(writer, "{}", mapping)?;
+    }
+
+    writeln!(writer, "Total mapped: {} kb", p4_table.total_mapped_memory() / 1024)
 }
 
 /// Switches paging tables
@@ -81,11 +99,20 @@ fn remap_kernel0<M>(p4_table : &mut P4Table, frame_allocator : &mut M, multiboot
     let vga_frame = Frame::from_address(0xb8000);
     p4_table.map_page_1_to_1(vga_frame, page_table::PRESENT | page_table::WRITABLE, frame_allocator);
 
-    // remap bump allocator    
-    let bump_allocator = frame_allocator.bump_allocator();
+    // graphics framebuffer usually lies above the memory identity mapped at boot
+    if let Some(framebuffer) = multiboot_header.read_tag::<FramebufferInfo>() {
+        if framebuffer.framebuffer_type() == Some(FramebufferType::Rgb) {
+            for framebuffer_frame in Frame::range_inclusive(framebuffer.address(), framebuffer.address() + framebuffer.size() - 1) {
+                p4_table.map_page_1_to_1(framebuffer_frame, page_table::PRESENT | page_table::WRITABLE | page_table::WRITE_THROUGH, frame_allocator);
+            }
+        }
+    }
+
+    // remap frame allocator data structures
+    let (aux_memory_start, aux_memory_end) = frame_allocator.aux_memory_range();
     
-    for bump_allocator_frame in Frame::range_inclusive(bump_allocator.start_address(), bump_allocator.end_address()) {
-        p4_table.map_page_1_to_1(bump_allocator_frame, page_table::PRESENT | page_table::WRITABLE, frame_allocator);
+    for aux_memory_frame in Frame::range_inclusive(aux_memory_start, aux_memory_end) {
+        p4_table.map_page_1_to_1(aux_memory_frame, page_table::PRESENT | page_table::WRITABLE, frame_allocator);
     }
 }
 
@@ -1,22 +1,51 @@
 use core::marker;
 use core::ops;
 use core::fmt;
+use core::iter;
 use frame::Frame;
 use frame::FRAME_SIZE;
 use frame::frame_allocator::FrameAllocator;
 use hardware::x86_64::tlb;
+use paging::walker;
+use paging::physical_memory::{PhysicalMemory, RecursiveMapping, RECURSIVE_MAPPING};
 use stdx_memory::MemoryAllocator;
 
 pub const PAGE_TABLE_SIZE : usize = 4096; //4kb, x86-64 spec
 
 pub const PAGE_TABLE_ENTRY_SIZE : usize = 8;
 
+pub const HUGE_PAGE_SIZE : usize = FRAME_SIZE * 512; // 2mb
+
 pub type VirtualFrame = Frame;
 
 pub type PhysicalFrame = Frame;
 
 pub type P4Table = PageTable<P4>;
 
+/// Errors returned by fallible (range) mapping operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingError {
+    /// Virtual page with the address is already mapped
+    AlreadyMapped(usize),
+    /// Virtual page with the address is not mapped
+    NotMapped(usize),
+    /// Frame allocator has no frames left for new page tables
+    OutOfFrames,
+    /// Virtual page with the address belongs to a huge page, range operations work only with 4 kb pages
+    HugePage(usize),
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MappingError::AlreadyMapped(address) => write!(f, "virtual page {:#x} is already mapped", address),
+            MappingError::NotMapped(address)     => write!(f, "virtual page {:#x} is not mapped", address),
+            MappingError::OutOfFrames            => write!(f, "no frames left for page tables"),
+            MappingError::HugePage(address)      => write!(f, "virtual page {:#x} belongs to a huge page", address),
+        }
+    }
+}
+
 pub trait TableLevel {
     
     fn index_shift() -> usize;
@@ -116,49 +145,105 @@ impl <Level> PageTable<Level> where Level : TableLevel {
 impl<Level> PageTable<Level> where  Level : HasNextTableLevel {
 
     pub fn has_next_table(&self, index : usize) -> bool {        
-        let table_entry = &self[index];
-        let flags = table_entry.flags();
+        let flags = self[index].flags();
 
-        table_entry.flags().contains(PRESENT) && flags.contains(PRESENT)
+        // huge page entry points to memory itself, not to the next level table
+        flags.contains(PRESENT) && !flags.contains(HUGE_PAGE)
     }    
 
-    pub fn next_table_opt(&self, page : VirtualFrame) -> Option<&'static mut PageTable<Level::NextTableLevel>> {
-        let index = Level::page_index(page);
+    /// Returns next level table that is referenced by the entry with `index`, if such table exists
+    /// # Arguments
+    /// * `index` - entry index in this table
+    /// * `memory` - physical memory the tables are located in
+    pub fn next_table_at_in<P>(&self, index : usize, memory : &P) -> Option<&'static mut PageTable<Level::NextTableLevel>> where P : PhysicalMemory {
         if self.has_next_table(index) {
-            Some(self.next_table(index))
+            Some(self.next_table_in(index, memory))
         }
         else {
             None
         }
     }
 
+    pub fn next_table_at(&self, index : usize) -> Option<&'static mut PageTable<Level::NextTableLevel>> {
+        self.next_table_at_in(index, &RECURSIVE_MAPPING)
+    }
+
+    pub fn next_table_opt_in<P>(&self, page : VirtualFrame, memory : &P) -> Option<&'static mut PageTable<Level::NextTableLevel>> where P : PhysicalMemory {
+        self.next_table_at_in(Level::page_index(page), memory)
+    }
+
+    pub fn next_table_opt(&self, page : VirtualFrame) -> Option<&'static mut PageTable<Level::NextTableLevel>> {
+        self.next_table_opt_in(page, &RECURSIVE_MAPPING)
+    }
+
+    pub fn next_table_or_create_in<M, P>(&mut self, page : VirtualFrame, frame_allocator : &mut M, memory : &P) -> &'static mut PageTable<Level::NextTableLevel>
+    where M : MemoryAllocator,
+          P : PhysicalMemory
+    {
+        match self.try_next_table_or_create_in(page, frame_allocator, memory) {
+            Ok(table) => table,
+            Err(error) => panic!("Cannot create page table: {}", error)
+        }
+    }
+
     pub fn next_table_or_create<M>(&mut self, page : VirtualFrame, frame_allocator : &mut M) -> &'static mut PageTable<Level::NextTableLevel> where M : MemoryAllocator {
+        self.next_table_or_create_in(page, frame_allocator, &RECURSIVE_MAPPING)
+    }
+
+    /// Same as `next_table_or_create` but returns MappingError::OutOfFrames instead of panicking
+    /// when frame allocator can't provide a frame for the new table and MappingError::HugePage
+    /// if `page` belongs to a huge page.
+    pub fn try_next_table_or_create_in<M, P>(&mut self, page : VirtualFrame, frame_allocator : &mut M, memory : &P) -> Result<&'static mut PageTable<Level::NextTableLevel>, MappingError>
+    where M : MemoryAllocator,
+          P : PhysicalMemory
+    {
         // page number is destructured to check if its index points to 
-        // valid (present) page table entry. Recursive looping in P4 table is
-        // used to physically address the desired table/frame. 
+        // valid (present) page table entry. 
         let index = Level::page_index(page);
 
         if self.has_next_table(index) {
-            self.next_table(index)
+            Ok(self.next_table_in(index, memory))
+        }
+        else if self[index].flags().contains(PRESENT) {
+            // present entry that is not a table is a huge page,
+            // replacing it with a table will silently unmap the whole huge page
+            Err(MappingError::HugePage(page.address()))
         }
         else {
             // create next level table
-            let new_table_frame = frame_allocator.allocate(FRAME_SIZE).expect("No memory for page table");
+            let new_table_frame = frame_allocator.allocate(FRAME_SIZE).ok_or(MappingError::OutOfFrames)?;
 
             // set new entry in current table
             self[index].set_frame(Frame::from_address(new_table_frame), PRESENT | WRITABLE);
             
             // clear next level table
-            let result = self.next_table(index);
+            let result = self.next_table_in(index, memory);
             result.clear_all_entries();
 
-            result            
+            Ok(result)
+        }
+    }
+
+    pub fn try_next_table_or_create<M>(&mut self, page : VirtualFrame, frame_allocator : &mut M) -> Result<&'static mut PageTable<Level::NextTableLevel>, MappingError> where M : MemoryAllocator {
+        self.try_next_table_or_create_in(page, frame_allocator, &RECURSIVE_MAPPING)
+    }
+
+    // gives frame of the next level table back to the frame allocator if none of the table entries is set
+    fn free_next_table_if_empty_in<M, P>(&mut self, index : usize, frame_allocator : &mut M, memory : &P)
+    where M : MemoryAllocator,
+          P : PhysicalMemory
+    {
+        let is_empty = self.next_table_at_in(index, memory).map_or(false, |table| table.entries.iter().all(|entry| !entry.is_set()));
+
+        if is_empty {
+            frame_allocator.free(self[index].address());
+            self[index].set_unused();
         }
     }
 
-    fn next_table(&self, index : usize) -> &'static mut PageTable<Level::NextTableLevel> {
+    fn next_table_in<P>(&self, index : usize, memory : &P) -> &'static mut PageTable<Level::NextTableLevel> where P : PhysicalMemory {
         let table_address = self as *const _ as usize;
-        let addr = (table_address << 9) | (index << 12);
+        let addr = memory.next_table_address(table_address, index, self[index].address());
 
         unsafe { &mut (*(addr as *mut PageTable<Level::NextTableLevel>)) }  
     }
@@ -167,40 +252,32 @@ impl<Level> PageTable<Level> where  Level : HasNextTableLevel {
 impl PageTable<P4> {
 
     /// Returns overrall number of mapped memory in bytes  
-    // todo: rewrite  
     pub fn total_mapped_memory(&self) -> usize {
-        let mut result : usize = 0;
-
-        for entry in self.entries.iter().take(511).filter(|e| e.is_set()) {
-            let p4page = Frame::from_address(entry.address());
-
-            match self.next_table_opt(p4page) {
-                Some(p3) => {
-                    for p3Entry in p3.entries.iter().filter(|e| e.is_set()){
-                        let p3page = Frame::from_address(p3Entry.address());
-
-                        match p3.next_table_opt(p3page) {
-                            Some(p2) => {
-                                for p2Entry in p2.entries.iter().filter(|e| e.is_set()){
-                                    let p1page = Frame::from_address(p2Entry.address());
-
-                                    match p2.next_table_opt(p1page) {
-                                        Some(p1) =>{
-                                            result += p1.entries.iter().filter(|e| e.is_set()).count();
-                                        },
-                                        None => (),
-                                    }
-                                }
-                            },
-                            None => (),
-                        }
-                    }
-                },
-                None => (), 
-            }
-        };
+        self.total_mapped_memory_in(&RECURSIVE_MAPPING)
+    }
+
+    pub fn total_mapped_memory_in<P>(&self, memory : &P) -> usize where P : PhysicalMemory {
+        self.mapped_pages_in(memory).fold(0, |total, mapping| total + mapping.size)
+    }
 
-        result * FRAME_SIZE
+    /// Returns iterator over every present page (including huge pages) of this table.
+    /// Recursive entry is not included.
+    pub fn mapped_pages(&self) -> walker::MappedPages<RecursiveMapping> {
+        self.mapped_pages_in(&RECURSIVE_MAPPING)
+    }
+
+    pub fn mapped_pages_in<'a, P>(&'a self, memory : &'a P) -> walker::MappedPages<'a, P> where P : PhysicalMemory {
+        walker::MappedPages::new(self, memory)
+    }
+
+    /// Returns iterator over present mappings, where consecutive pages that map to
+    /// consecutive physical frames with the same flags are merged into one mapping.
+    pub fn mappings(&self) -> walker::Mappings<RecursiveMapping> {
+        self.mappings_in(&RECURSIVE_MAPPING)
+    }
+
+    pub fn mappings_in<'a, P>(&'a self, memory : &'a P) -> walker::Mappings<'a, P> where P : PhysicalMemory {
+        walker::Mappings::new(self.mapped_pages_in(memory))
     }
 
     /// maps virtual page to physical frame
@@ -209,19 +286,64 @@ impl PageTable<P4> {
     /// * `page` - virtual frame
     /// * `frame` - physical frame
     /// * `frame_allocator` - frame allocator
-    pub fn map_page<M>(&mut self, page : VirtualFrame, frame : PhysicalFrame, flags : EntryFlags, frame_allocator : &mut M)  where M : MemoryAllocator {
-        let p1 = self.next_table_or_create(page, frame_allocator)
-                         .next_table_or_create(page, frame_allocator)
-                         .next_table_or_create(page, frame_allocator);
+    /// * `memory` - physical memory the tables are located in
+    pub fn map_page_in<M, P>(&mut self, page : VirtualFrame, frame : PhysicalFrame, flags : EntryFlags, frame_allocator : &mut M, memory : &P)
+    where M : MemoryAllocator,
+          P : PhysicalMemory
+    {
+        let p1 = self.next_table_or_create_in(page, frame_allocator, memory)
+                         .next_table_or_create_in(page, frame_allocator, memory)
+                         .next_table_or_create_in(page, frame_allocator, memory);
 
         let p1_index = P1::page_index(page);
         p1[p1_index].set_frame(frame, flags)
     }
 
+    pub fn map_page<M>(&mut self, page : VirtualFrame, frame : PhysicalFrame, flags : EntryFlags, frame_allocator : &mut M)  where M : MemoryAllocator {
+        self.map_page_in(page, frame, flags, frame_allocator, &RECURSIVE_MAPPING)
+    }
+
     pub fn map<M>(&mut self, virtual_address : usize, physical_address : usize, flags : EntryFlags, frame_allocator : &mut M) where M : MemoryAllocator {
         self.map_page(Frame::from_address(virtual_address), Frame::from_address(physical_address), flags, frame_allocator)
     }
 
+    /// Maps 2 mb virtual huge page to 2 mb of physical memory
+    ///
+    /// # Arguments
+    /// * `page` - first virtual frame of the huge page, must be 2 mb aligned
+    /// * `frame` - first physical frame, must be 2 mb aligned
+    /// * `flags` - entry flags
+    /// * `frame_allocator` - frame allocator
+    /// * `memory` - physical memory the tables are located in
+    /// # Returns
+    /// MappingError::AlreadyMapped if any part of the huge page is already mapped, MappingError::HugePage if
+    /// it is inside of 1 gb page, MappingError::OutOfFrames if there are no frames left to create page tables.
+    pub fn map_huge_page_in<M, P>(&mut self, page : VirtualFrame, frame : PhysicalFrame, flags : EntryFlags, frame_allocator : &mut M, memory : &P) -> Result<(), MappingError>
+    where M : MemoryAllocator,
+          P : PhysicalMemory
+    {
+        assert!(page.address() % HUGE_PAGE_SIZE == 0, "Huge page address {} is not 2 mb aligned", page.address());
+        assert!(frame.address() % HUGE_PAGE_SIZE == 0, "Huge page frame address {} is not 2 mb aligned", frame.address());
+
+        let p2 = self.try_next_table_or_create_in(page, frame_allocator, memory)?
+                         .try_next_table_or_create_in(page, frame_allocator, memory)?;
+
+        let p2_index = P2::page_index(page);
+
+        // p2 entry can point to p1 table with mapped pages
+        if p2[p2_index].flags().contains(PRESENT) {
+            Err(MappingError::AlreadyMapped(page.address()))
+        }
+        else {
+            p2[p2_index].set_frame(frame, flags | HUGE_PAGE);
+            Ok(())
+        }
+    }
+
+    pub fn map_huge_page<M>(&mut self, page : VirtualFrame, frame : PhysicalFrame, flags : EntryFlags, frame_allocator : &mut M) -> Result<(), MappingError> where M : MemoryAllocator {
+        self.map_huge_page_in(page, frame, flags, frame_allocator, &RECURSIVE_MAPPING)
+    }
+
     /// Maps virtual page to physical frame in 1 to 1 fashion, e.g.
     /// virtual page will correspond to physical frame with the same address
     ///
@@ -246,19 +368,37 @@ impl PageTable<P4> {
         }
     }
 
-    /// Unmaps virtual page
+    /// Unmaps virtual page. If page belongs to a huge page then the whole huge page is unmapped.
     ///
     /// # Arguments
     /// * `page` - virtual frame
-    /// * `frame_allocator` - frame allocator
-    pub unsafe fn unmap_page(&self, page : VirtualFrame) {
-        let p1_option = self.next_table_opt(page)
-                            .and_then(|p3| p3.next_table_opt(page))
-                            .and_then(|p2| p2.next_table_opt(page));
+    /// * `memory` - physical memory the tables are located in
+    pub unsafe fn unmap_page_in<P>(&self, page : VirtualFrame, memory : &P) where P : PhysicalMemory {
+        let p3 = match self.next_table_opt_in(page, memory) {
+            Some(p3) => p3,
+            None => return
+        };
 
-        if let Some(p1) = p1_option {
-            let p1_index = P1::page_index(page);
-            p1[p1_index].set_unused();
+        let p3_index = P3::page_index(page);
+
+        let entry = if p3[p3_index].flags().contains(PRESENT | HUGE_PAGE) {
+            Some(&mut p3[p3_index])
+        }
+        else {
+            p3.next_table_opt_in(page, memory).and_then(|p2| {
+                let p2_index = P2::page_index(page);
+
+                if p2[p2_index].flags().contains(PRESENT | HUGE_PAGE) {
+                    Some(&mut p2[p2_index])
+                }
+                else {
+                    p2.next_table_opt_in(page, memory).map(|p1| &mut p1[P1::page_index(page)])
+                }
+            })
+        };
+
+        if let Some(entry) = entry {
+            entry.set_unused();
 
             /*
                 Important to flush TLB after unmapping entry to prevent reads from it!!
@@ -271,8 +411,16 @@ impl PageTable<P4> {
                         //   if we don't flush TLB 
                 let should_be_page_fault = *(page.address() as *const u64) // won't produce segfault
             */
-            tlb::flush(page.address());
-        }    
+            memory.invalidate(page.address(), 1);
+        }
+    }
+
+    /// Unmaps virtual page
+    ///
+    /// # Arguments
+    /// * `page` - virtual frame
+    pub unsafe fn unmap_page(&self, page : VirtualFrame) {
+        self.unmap_page_in(page, &RECURSIVE_MAPPING)
     }
 
     pub unsafe fn unmap(&self, virtual_address : usize) {
@@ -288,21 +436,273 @@ impl PageTable<P4> {
         }
     }
 
-    /// Translates virtual page to physical frame.
+    /// Maps virtual page to physical frame without overwriting existing mappings
     ///
     /// # Arguments
     /// * `page` - virtual frame
+    /// * `frame` - physical frame
+    /// * `flags` - entry flags
     /// * `frame_allocator` - frame allocator
+    /// * `memory` - physical memory the tables are located in
+    /// # Returns
+    /// MappingError::AlreadyMapped if page is already present, MappingError::HugePage if page belongs to a huge page,
+    /// MappingError::OutOfFrames if there are no frames left to create page tables.
+    pub fn try_map_page_in<M, P>(&mut self, page : VirtualFrame, frame : PhysicalFrame, flags : EntryFlags, frame_allocator : &mut M, memory : &P) -> Result<(), MappingError>
+    where M : MemoryAllocator,
+          P : PhysicalMemory
+    {
+        let p1 = self.try_next_table_or_create_in(page, frame_allocator, memory)?
+                         .try_next_table_or_create_in(page, frame_allocator, memory)?
+                         .try_next_table_or_create_in(page, frame_allocator, memory)?;
+
+        let p1_index = P1::page_index(page);
+
+        if p1[p1_index].flags().contains(PRESENT) {
+            Err(MappingError::AlreadyMapped(page.address()))
+        }
+        else {
+            p1[p1_index].set_frame(frame, flags);
+            Ok(())
+        }
+    }
+
+    pub fn try_map_page<M>(&mut self, page : VirtualFrame, frame : PhysicalFrame, flags : EntryFlags, frame_allocator : &mut M) -> Result<(), MappingError> where M : MemoryAllocator {
+        self.try_map_page_in(page, frame, flags, frame_allocator, &RECURSIVE_MAPPING)
+    }
+
+    /// Maps consecutive virtual pages to the provided physical frames. Frames don't need to be contiguous,
+    /// page number `i` of the range is mapped to frame number `i` returned by `frames`.
+    /// Either all pages get mapped or none: on error every page mapped by this call is unmapped back
+    /// and page tables of the range that are left without entries are freed.
+    ///
+    /// # Arguments
+    /// * `virtual_address_start` - start address of the virtual range
+    /// * `frames` - physical frames to map the range to
+    /// * `flags` - entry flags
+    /// * `frame_allocator` - frame allocator
+    /// * `memory` - physical memory the tables are located in
+    /// # Returns
+    /// Number of mapped pages, MappingError::AlreadyMapped if range overlaps existing mapping, MappingError::HugePage
+    /// if it overlaps a huge page, MappingError::OutOfFrames if there are no frames left to create page tables.
+    /// # Why unsafe
+    ///  Invalidates TLB entries which is unsafe
+    pub unsafe fn map_range_in<M, I, P>(&mut self, virtual_address_start : usize, frames : I, flags : EntryFlags, frame_allocator : &mut M, memory : &P) -> Result<usize, MappingError>
+    where M : MemoryAllocator,
+          I : iter::Iterator<Item = PhysicalFrame>,
+          P : PhysicalMemory
+    {
+        let first_page_address = Frame::address_align_down(virtual_address_start);
+        let mut mapped_count = 0;
+
+        for frame in frames {
+            let page = Frame::from_address(first_page_address + mapped_count * FRAME_SIZE);
+
+            if let Err(error) = self.try_map_page_in(page, frame, flags, frame_allocator, memory) {
+                // tables could be created for the failed page too
+                self.clear_entries_in(first_page_address, mapped_count, memory);
+                self.free_empty_tables_in(first_page_address, mapped_count + 1, frame_allocator, memory);
+                memory.invalidate(first_page_address, mapped_count + 1);
+
+                return Err(error)
+            }
+
+            mapped_count += 1;
+        }
+
+        Ok(mapped_count)
+    }
+
+    pub unsafe fn map_range<M, I>(&mut self, virtual_address_start : usize, frames : I, flags : EntryFlags, frame_allocator : &mut M) -> Result<usize, MappingError>
+    where M : MemoryAllocator,
+          I : iter::Iterator<Item = PhysicalFrame>
+    {
+        self.map_range_in(virtual_address_start, frames, flags, frame_allocator, &RECURSIVE_MAPPING)
+    }
+
+    /// Changes flags of already mapped virtual pages (like mprotect). PRESENT flag is always kept,
+    /// use `unmap_range` to remove the mapping. Flags are either changed for the whole range or not changed at all.
+    ///
+    /// # Arguments
+    /// * `virtual_range` - virtual addresses range, every page touched by the range is changed
+    /// * `flags` - new entry flags
+    /// * `memory` - physical memory the tables are located in
+    /// # Returns
+    /// MappingError::NotMapped with the first page in range that is not present, MappingError::HugePage
+    /// with the first page in range that belongs to a huge page.
+    /// # Why unsafe
+    ///  Invalidates TLB entries which is unsafe
+    pub unsafe fn protect_range_in<P>(&self, virtual_range : ops::Range<usize>, flags : EntryFlags, memory : &P) -> Result<(), MappingError> where P : PhysicalMemory {
+        let (first_page_address, pages_count) = PageTable::<P4>::range_to_pages(&virtual_range);
+
+        self.check_range_is_mapped_in(first_page_address, pages_count, memory)?;
+
+        for i in 0..pages_count {
+            let page = Frame::from_address(first_page_address + i * FRAME_SIZE);
+            let entry = self.present_p1_entry_in(page, memory).unwrap(); // presence checked above
+            let address = entry.address();
+
+            entry.set(address, flags | PRESENT);
+        }
+
+        memory.invalidate(first_page_address, pages_count);
+
+        Ok(())
+    }
+
+    pub unsafe fn protect_range(&self, virtual_range : ops::Range<usize>, flags : EntryFlags) -> Result<(), MappingError> {
+        self.protect_range_in(virtual_range, flags, &RECURSIVE_MAPPING)
+    }
+
+    /// Unmaps virtual pages. Either the whole range is unmapped or nothing is.
+    ///
+    /// # Arguments
+    /// * `virtual_range` - virtual addresses range, every page touched by the range is unmapped
+    /// * `memory` - physical memory the tables are located in
+    /// # Returns
+    /// MappingError::NotMapped with the first page in range that is not present, MappingError::HugePage
+    /// with the first page in range that belongs to a huge page, use `unmap_page` for huge pages.
+    /// # Why unsafe
+    ///  Invalidates TLB entries which is unsafe
+    pub unsafe fn unmap_range_in<P>(&self, virtual_range : ops::Range<usize>, memory : &P) -> Result<(), MappingError> where P : PhysicalMemory {
+        let (first_page_address, pages_count) = PageTable::<P4>::range_to_pages(&virtual_range);
+
+        self.check_range_is_mapped_in(first_page_address, pages_count, memory)?;
+        self.clear_range_in(first_page_address, pages_count, memory);
+
+        Ok(())
+    }
+
+    pub unsafe fn unmap_range(&self, virtual_range : ops::Range<usize>) -> Result<(), MappingError> {
+        self.unmap_range_in(virtual_range, &RECURSIVE_MAPPING)
+    }
+
+    fn check_range_is_mapped_in<P>(&self, first_page_address : usize, pages_count : usize, memory : &P) -> Result<(), MappingError> where P : PhysicalMemory {
+        for i in 0..pages_count {
+            let page = Frame::from_address(first_page_address + i * FRAME_SIZE);
+
+            if self.present_p1_entry_in(page, memory).is_none() {
+                if self.is_in_huge_page_in(page, memory) {
+                    return Err(MappingError::HugePage(page.address()))
+                }
+
+                return Err(MappingError::NotMapped(page.address()))
+            }
+        }
+
+        Ok(())
+    }
+
+    // true if page is mapped by 1 gb or 2 mb page entry
+    fn is_in_huge_page_in<P>(&self, page : VirtualFrame, memory : &P) -> bool where P : PhysicalMemory {
+        let is_huge = |entry : &PageTableEntry| entry.flags().contains(PRESENT | HUGE_PAGE);
+
+        self.next_table_opt_in(page, memory).map_or(false, |p3| {
+            is_huge(&p3[P3::page_index(page)]) ||
+                p3.next_table_opt_in(page, memory).map_or(false, |p2| is_huge(&p2[P2::page_index(page)]))
+        })
+    }
+
+    // sets entries of every page in range to unused and invalidates them with one batch
+    unsafe fn clear_range_in<P>(&self, first_page_address : usize, pages_count : usize, memory : &P) where P : PhysicalMemory {
+        self.clear_entries_in(first_page_address, pages_count, memory);
+
+        memory.invalidate(first_page_address, pages_count);
+    }
+
+    // sets entries of every page in range to unused, caller invalidates them
+    fn clear_entries_in<P>(&self, first_page_address : usize, pages_count : usize, memory : &P) where P : PhysicalMemory {
+        for i in 0..pages_count {
+            let page = Frame::from_address(first_page_address + i * FRAME_SIZE);
+
+            if let Some(entry) = self.present_p1_entry_in(page, memory) {
+                entry.set_unused();
+            }
+        }
+    }
+
+    // frees p1, p2 and p3 tables of the range that have no entries set, lower levels go first,
+    // so that upper table becomes empty when its last child is freed. Caller invalidates the range
+    fn free_empty_tables_in<M, P>(&mut self, first_page_address : usize, pages_count : usize, frame_allocator : &mut M, memory : &P)
+    where M : MemoryAllocator,
+          P : PhysicalMemory
+    {
+        for i in 0..pages_count {
+            let page = Frame::from_address(first_page_address + i * FRAME_SIZE);
+
+            // tables are checked once, at the last page of the range they map
+            if i + 1 < pages_count && P1::page_index(page) != 511 {
+                continue
+            }
+
+            if let Some(p3) = self.next_table_opt_in(page, memory) {
+                if let Some(p2) = p3.next_table_opt_in(page, memory) {
+                    p2.free_next_table_if_empty_in(P2::page_index(page), frame_allocator, memory);
+                }
+
+                p3.free_next_table_if_empty_in(P3::page_index(page), frame_allocator, memory);
+            }
+
+            self.free_next_table_if_empty_in(P4::page_index(page), frame_allocator, memory);
+        }
+    }
+
+    // returns first page address and number of pages touched by the address range
+    fn range_to_pages(virtual_range : &ops::Range<usize>) -> (usize, usize) {
+        let first_page_address = Frame::address_align_down(virtual_range.start);
+        let end_address        = Frame::address_align_up(virtual_range.end);
+
+        if end_address > first_page_address {
+            (first_page_address, (end_address - first_page_address) / FRAME_SIZE)
+        }
+        else {
+            (first_page_address, 0)
+        }
+    }
+
+    fn present_p1_entry_in<P>(&self, page : VirtualFrame, memory : &P) -> Option<&'static mut PageTableEntry> where P : PhysicalMemory {
+        self.next_table_opt_in(page, memory)
+            .and_then(|p3| p3.next_table_opt_in(page, memory))
+            .and_then(|p2| p2.next_table_opt_in(page, memory))
+            .map(|p1| &mut p1[P1::page_index(page)])
+            .filter(|entry| entry.flags().contains(PRESENT))
+    }
+
+    /// Translates virtual page to physical frame. Pages that belong to huge pages
+    /// are translated to the corresponding frame inside the huge page.
+    ///
+    /// # Arguments
+    /// * `page` - virtual frame
+    /// * `memory` - physical memory the tables are located in
     ///
     /// # Returns
     /// Some() with physical frame if entry is present for corresponding virtual frame,
     /// otherwise returns None.
-    pub fn translate_page(&self, page : VirtualFrame) -> Option<Frame> {
-        
-        self.next_table_opt(page)
-        .and_then(|p3| p3.next_table_opt(page))
-        .and_then(|p2| p2.next_table_opt(page))
-        .and_then(|p1| { 
+    pub fn translate_page_in<P>(&self, page : VirtualFrame, memory : &P) -> Option<Frame> where P : PhysicalMemory {
+        let p3 = match self.next_table_opt_in(page, memory) {
+            Some(p3) => p3,
+            None => return None
+        };
+
+        let p3_entry = &p3[P3::page_index(page)];
+
+        // 1 gb page, frame offset inside the page is stored in lower 18 bits of page number
+        if p3_entry.flags().contains(PRESENT | HUGE_PAGE) {
+            return Some(Frame::from_address(p3_entry.address() + (page.number() & 0x3ffff) * FRAME_SIZE))
+        }
+
+        let p2 = match p3.next_table_opt_in(page, memory) {
+            Some(p2) => p2,
+            None => return None
+        };
+
+        let p2_entry = &p2[P2::page_index(page)];
+
+        // 2 mb page, frame offset inside the page is stored in lower 9 bits of page number
+        if p2_entry.flags().contains(PRESENT | HUGE_PAGE) {
+            return Some(Frame::from_address(p2_entry.address() + (page.number() & 0x1ff) * FRAME_SIZE))
+        }
+
+        p2.next_table_opt_in(page, memory).and_then(|p1| { 
             let p1_index = P1::page_index(page);
             let p1_entry = &p1[p1_index];
 
@@ -315,11 +715,22 @@ impl PageTable<P4> {
         })
     }
 
+    /// Translates virtual page to physical frame.
+    ///
+    /// # Arguments
+    /// * `page` - virtual frame
+    ///
+    /// # Returns
+    /// Some() with physical frame if entry is present for corresponding virtual frame,
+    /// otherwise returns None.
+    pub fn translate_page(&self, page : VirtualFrame) -> Option<Frame> {
+        self.translate_page_in(page, &RECURSIVE_MAPPING)
+    }
+
     /// Checks whether virtual page points to existing physical frame
     ///
     /// # Arguments
     /// * `page` - virtual frame
-    /// * `frame_allocator` - frame allocator
     ///
     /// # Returns
     /// True if entry is present for corresponding virtual frame, otherwise returns false.
@@ -331,13 +742,17 @@ impl PageTable<P4> {
     ///
     /// # Arguments
     /// * `page` - virtual address
-    /// * `frame_allocator` - frame allocator
+    /// * `memory` - physical memory the tables are located in
     ///
     /// # Returns
     /// Some() with physical address if entry is present for corresponding virtual address,
     /// otherwise returns None.
+    pub fn translate_in<P>(&self, virtual_address : usize, memory : &P) -> Option<usize> where P : PhysicalMemory {    
+        self.translate_page_in(Frame::from_address(virtual_address), memory).map(|frame| frame.address() + virtual_address % FRAME_SIZE)
+    }
+
     pub fn translate(&self, virtual_address : usize) -> Option<usize> {    
-        self.translate_page(Frame::from_address(virtual_address)).map(|frame| frame.address() + virtual_address % FRAME_SIZE)
+        self.translate_in(virtual_address, &RECURSIVE_MAPPING)
     }
 
     pub fn set_recursive_entry(&mut self, frame : Frame, flags : EntryFlags) {
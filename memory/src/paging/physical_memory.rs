@@ -0,0 +1,54 @@
+use paging::page_table::P4Table;
+use hardware::x86_64::tlb;
+
+/// Describes how page tables located in physical memory are accessed through virtual addresses.
+/// Page table operations are generic over it, so they can run both on the active address space
+/// (through recursive mapping) and on page tables placed in ordinary memory (for example in unit tests).
+pub trait PhysicalMemory {
+
+    /// Returns virtual address of the p4 table
+    fn p4_table_address(&self) -> usize;
+
+    /// Returns virtual address of the next level table
+    /// # Arguments
+    /// * `table_address` - virtual address of the current table
+    /// * `index` - entry index in the current table
+    /// * `next_table_physical_address` - physical address stored in the entry
+    fn next_table_address(&self, table_address : usize, index : usize, next_table_physical_address : usize) -> usize;
+
+    /// Invalidates cached translations of consecutive pages after their entries changed
+    /// # Arguments
+    /// * `virtual_address_start` - virtual address of the first page
+    /// * `pages_count` - number of pages
+    /// # Why unsafe
+    ///  Implementations flush TLB which is unsafe
+    unsafe fn invalidate(&self, virtual_address_start : usize, pages_count : usize);
+
+    /// Returns p4 table
+    fn p4_table(&self) -> &'static mut P4Table {
+        unsafe { &mut (*(self.p4_table_address() as *mut P4Table)) }
+    }
+}
+
+/// Accesses currently active page tables through recursive entry (511) of p4 table
+#[derive(Clone, Copy)]
+pub struct RecursiveMapping;
+
+pub static RECURSIVE_MAPPING : RecursiveMapping = RecursiveMapping;
+
+impl PhysicalMemory for RecursiveMapping {
+
+    fn p4_table_address(&self) -> usize {
+        0xfffffffffffff000 // recursive mapping to P4s 0 entry
+    }
+
+    fn next_table_address(&self, table_address : usize, index : usize, _next_table_physical_address : usize) -> usize {
+        // shifting table address moves every index one level down, recursive entry
+        // becomes the new p4 index and `index` becomes p1 index
+        (table_address << 9) | (index << 12)
+    }
+
+    unsafe fn invalidate(&self, virtual_address_start : usize, pages_count : usize) {
+        tlb::flush_range(virtual_address_start, pages_count)
+    }
+}
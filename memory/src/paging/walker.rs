@@ -0,0 +1,239 @@
+use core::fmt;
+use core::iter;
+use paging::page_table::{P4Table, EntryFlags, PageTableEntry};
+use paging::page_table;
+use paging::physical_memory::PhysicalMemory;
+
+const ENTRIES_COUNT : usize = 512;
+
+// p4's 511 entry points to p4 itself, walking it will list page tables as mapped memory
+const RECURSIVE_ENTRY_INDEX : usize = 511;
+
+const P1_PAGE_SIZE : usize = 4096;              // 4 kb
+const P2_PAGE_SIZE : usize = P1_PAGE_SIZE * 512; // 2 mb
+const P3_PAGE_SIZE : usize = P2_PAGE_SIZE * 512; // 1 gb
+
+/// Describes a range of virtual memory that is mapped to a range of physical memory
+#[derive(Clone, Copy)]
+pub struct Mapping {
+    pub virtual_address  : usize,
+    pub physical_address : usize,
+    pub size             : usize,
+    pub flags            : EntryFlags,
+}
+
+impl Mapping {
+
+    fn from_entry(virtual_address : usize, entry : &PageTableEntry, size : usize) -> Self {
+        // accessed and dirty bits are set by processor and will differ between
+        // otherwise identical neighbour pages, so they are ignored
+        let mut flags = entry.flags();
+        flags.remove(page_table::ACCESSED | page_table::DIRTY);
+
+        Mapping {
+            virtual_address,
+            physical_address : entry.address(),
+            size,
+            flags
+        }
+    }
+
+    pub fn virtual_end_address(&self) -> usize {
+        self.virtual_address + self.size - 1
+    }
+
+    /// Determines if `other` mapping continues this mapping both in virtual and physical memory
+    fn is_continued_by(&self, other : &Mapping) -> bool {
+        self.virtual_address.wrapping_add(self.size) == other.virtual_address &&
+        self.physical_address + self.size == other.physical_address &&
+        self.flags == other.flags
+    }
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+            "{:#018x}-{:#018x} -> {:#012x} {:>8} kb {}{}{}{}{}",
+            self.virtual_address,
+            self.virtual_end_address(),
+            self.physical_address,
+            self.size / 1024,
+            if self.flags.contains(page_table::WRITABLE) { "w" } else { "r" },
+            if self.flags.contains(page_table::NO_EXECUTE) { "-" } else { "x" },
+            if self.flags.contains(page_table::USER_ACCESSIBLE) { "u" } else { "-" },
+            if self.flags.contains(page_table::GLOBAL) { "g" } else { "-" },
+            if self.flags.contains(page_table::NO_CACHE) { "c" } else { "-" })
+    }
+}
+
+/// Iterator over present pages of p4 table. Walks all table levels through `memory`,
+/// with recursive mapping it can only be used for currently active p4 table.
+pub struct MappedPages<'a, P> where P : PhysicalMemory + 'a {
+    p4_table : &'a P4Table,
+    memory   : &'a P,
+    p4_index : usize,
+    p3_index : usize,
+    p2_index : usize,
+    p1_index : usize,
+}
+
+impl<'a, P> MappedPages<'a, P> where P : PhysicalMemory {
+    pub fn new(p4_table : &'a P4Table, memory : &'a P) -> Self {
+        MappedPages {
+            p4_table,
+            memory,
+            p4_index : 0,
+            p3_index : 0,
+            p2_index : 0,
+            p1_index : 0
+        }
+    }
+
+    fn next_p4_entry(&mut self) {
+        self.p4_index += 1;
+        self.p3_index = 0;
+        self.p2_index = 0;
+        self.p1_index = 0;
+    }
+
+    fn next_p3_entry(&mut self) {
+        self.p3_index += 1;
+        self.p2_index = 0;
+        self.p1_index = 0;
+    }
+
+    fn next_p2_entry(&mut self) {
+        self.p2_index += 1;
+        self.p1_index = 0;
+    }
+
+    fn virtual_address(&self) -> usize {
+        let address = (self.p4_index << 39) |
+                      (self.p3_index << 30) |
+                      (self.p2_index << 21) |
+                      (self.p1_index << 12);
+
+        // bits 48-63 must be copies of bit 47, otherwise address is not canonical
+        if self.p4_index >= 256 {
+            address | 0xffff_0000_0000_0000
+        }
+        else {
+            address
+        }
+    }
+}
+
+impl<'a, P> iter::Iterator for MappedPages<'a, P> where P : PhysicalMemory {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Mapping> {
+        loop {
+            if self.p4_index >= RECURSIVE_ENTRY_INDEX {
+                return None
+            }
+
+            let p3 = match self.p4_table.next_table_at_in(self.p4_index, self.memory) {
+                Some(p3) => p3,
+                None => { self.next_p4_entry(); continue; }
+            };
+
+            if self.p3_index >= ENTRIES_COUNT {
+                self.next_p4_entry();
+                continue;
+            }
+
+            let p3_entry = &p3[self.p3_index];
+
+            if !p3_entry.flags().contains(page_table::PRESENT) {
+                self.next_p3_entry();
+                continue;
+            }
+
+            if p3_entry.flags().contains(page_table::HUGE_PAGE) {
+                let result = Mapping::from_entry(self.virtual_address(), p3_entry, P3_PAGE_SIZE);
+                self.next_p3_entry();
+                return Some(result)
+            }
+
+            let p2 = p3.next_table_at_in(self.p3_index, self.memory).unwrap(); // entry is present and not huge
+
+            if self.p2_index >= ENTRIES_COUNT {
+                self.next_p3_entry();
+                continue;
+            }
+
+            let p2_entry = &p2[self.p2_index];
+
+            if !p2_entry.flags().contains(page_table::PRESENT) {
+                self.next_p2_entry();
+                continue;
+            }
+
+            if p2_entry.flags().contains(page_table::HUGE_PAGE) {
+                let result = Mapping::from_entry(self.virtual_address(), p2_entry, P2_PAGE_SIZE);
+                self.next_p2_entry();
+                return Some(result)
+            }
+
+            let p1 = p2.next_table_at_in(self.p2_index, self.memory).unwrap(); // entry is present and not huge
+
+            if self.p1_index >= ENTRIES_COUNT {
+                self.next_p2_entry();
+                continue;
+            }
+
+            let p1_entry = &p1[self.p1_index];
+            let result = if p1_entry.flags().contains(page_table::PRESENT) {
+                Some(Mapping::from_entry(self.virtual_address(), p1_entry, P1_PAGE_SIZE))
+            }
+            else {
+                None
+            };
+
+            self.p1_index += 1;
+
+            if result.is_some() {
+                return result
+            }
+        }
+    }
+}
+
+/// Iterator over present mappings, that merges consecutive pages with the same flags
+/// into a single mapping if they also map to consecutive physical frames
+pub struct Mappings<'a, P> where P : PhysicalMemory + 'a {
+    pages   : MappedPages<'a, P>,
+    pending : Option<Mapping>
+}
+
+impl<'a, P> Mappings<'a, P> where P : PhysicalMemory {
+    pub fn new(pages : MappedPages<'a, P>) -> Self {
+        Mappings {
+            pages,
+            pending : None
+        }
+    }
+}
+
+impl<'a, P> iter::Iterator for Mappings<'a, P> where P : PhysicalMemory {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Mapping> {
+        let mut result = match self.pending.take().or_else(|| self.pages.next()) {
+            Some(mapping) => mapping,
+            None => return None
+        };
+
+        while let Some(page) = self.pages.next() {
+            if result.is_continued_by(&page) {
+                result.size += page.size;
+            }
+            else {
+                self.pending = Some(page);
+                break;
+            }
+        }
+
+        Some(result)
+    }
+}
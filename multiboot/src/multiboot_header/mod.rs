@@ -1,6 +1,7 @@
 pub mod tags;
 pub mod tag;
 use core::iter;
+use core::marker;
 use multiboot_header::tags::memory_map::MemoryMap;
 use multiboot_header::tags::memory_map::MemoryMapEntry;
 
@@ -41,12 +42,17 @@ impl MultibootHeader {
     pub fn read_tag<T>(&self) -> Option<&'static T>
         where T: MultibootHeaderTag
     {
-        let mut tags = self.tags();
-        let tag = tags.find(|t| t.tag_type == T::numeric_type());
-        tag.map(|e| {
-            let tag_address = e as *const _ as usize;
-            unsafe { &(*(tag_address as *const T)) }
-        })        
+        self.read_tags::<T>().next()
+    }
+
+    /// Returns every tag of type T, for tags that can be present multiple times (like boot modules)
+    pub fn read_tags<T>(&self) -> TypedTagIterator<T>
+        where T: MultibootHeaderTag
+    {
+        TypedTagIterator {
+            tags : self.tags(),
+            phantom : marker::PhantomData
+        }
     }
 
     pub fn biggest_memory_area(&self) -> (usize, usize) {
@@ -83,4 +89,22 @@ impl iter::Iterator for TagIterator {
             Some(tag)
         }
     }
+}
+
+pub struct TypedTagIterator<T> {
+    tags: TagIterator,
+    phantom: marker::PhantomData<T>,
+}
+
+impl<T> iter::Iterator for TypedTagIterator<T> where T: MultibootHeaderTag {
+    type Item = &'static T;
+
+    fn next(&mut self) -> Option<&'static T> {
+        self.tags
+            .find(|t| t.tag_type == T::numeric_type())
+            .map(|e| {
+                let tag_address = e as *const _ as usize;
+                unsafe { &(*(tag_address as *const T)) }
+            })
+    }
 }
\ No newline at end of file
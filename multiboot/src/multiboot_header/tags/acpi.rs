@@ -0,0 +1,44 @@
+use multiboot_header::MultibootHeaderTag;
+use core::slice;
+
+/// Copy of ACPI 1.0 root system description pointer, points to RSDT
+#[repr(C)]
+pub struct AcpiOldRsdp {
+    tag_type: u32,
+    tag_size: u32,
+    first_rsdp_byte: u8,
+}
+
+impl MultibootHeaderTag for AcpiOldRsdp {
+    fn numeric_type() -> u32 {
+        14
+    }
+}
+
+impl AcpiOldRsdp {
+    /// Returns RSDP bytes, they are located between tag header and the end of the tag
+    pub fn rsdp(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(&self.first_rsdp_byte as *const u8, self.tag_size as usize - 2 * 4) }
+    }
+}
+
+/// Copy of ACPI 2.0+ root system description pointer, points to XSDT as well as to RSDT
+#[repr(C)]
+pub struct AcpiNewRsdp {
+    tag_type: u32,
+    tag_size: u32,
+    first_rsdp_byte: u8,
+}
+
+impl MultibootHeaderTag for AcpiNewRsdp {
+    fn numeric_type() -> u32 {
+        15
+    }
+}
+
+impl AcpiNewRsdp {
+    /// Returns RSDP bytes, they are located between tag header and the end of the tag
+    pub fn rsdp(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(&self.first_rsdp_byte as *const u8, self.tag_size as usize - 2 * 4) }
+    }
+}
@@ -0,0 +1,52 @@
+use multiboot_header::MultibootHeaderTag;
+use core::fmt;
+use core::slice;
+use core::str;
+
+/// Kernel command line passed by bootloader, e.g. options after kernel path in grub `multiboot2` command
+#[repr(C)]
+pub struct CommandLine {
+    tag_type: u32,
+    tag_size: u32,
+    first_string_byte: u8,
+}
+
+impl MultibootHeaderTag for CommandLine {
+    fn numeric_type() -> u32 {
+        1
+    }
+}
+
+impl CommandLine {
+    /// Returns command line, empty string if it is not valid utf8
+    pub fn command_line(&self) -> &str {
+        // string is null terminated and is located between tag header and the end of the tag
+        let string_start  = &self.first_string_byte as *const u8;
+        let max_length    = self.tag_size as usize - 2 * 4;
+        let bytes         = unsafe { slice::from_raw_parts(string_start, max_length) };
+        let length        = bytes.iter().position(|b| *b == 0).unwrap_or(max_length);
+
+        str::from_utf8(&bytes[..length]).unwrap_or("")
+    }
+
+    /// Returns value of `name=value` option, options are separated by spaces
+    pub fn option(&self, name : &str) -> Option<&str> {
+        self.command_line()
+            .split(' ')
+            .filter_map(|option| {
+                let mut parts = option.splitn(2, '=');
+
+                match (parts.next(), parts.next()) {
+                    (Some(option_name), Some(value)) if option_name == name => Some(value),
+                    _ => None
+                }
+            })
+            .next()
+    }
+}
+
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "command_line: {}", self.command_line())
+    }
+}
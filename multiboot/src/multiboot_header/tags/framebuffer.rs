@@ -0,0 +1,117 @@
+use multiboot_header::MultibootHeaderTag;
+use core::fmt;
+
+/// Video mode set by bootloader, given when kernel requests framebuffer in its multiboot header
+#[repr(C)]
+pub struct FramebufferInfo {
+    tag_type: u32,
+    tag_size: u32,
+    framebuffer_address: u64,
+    framebuffer_pitch: u32,
+    framebuffer_width: u32,
+    framebuffer_height: u32,
+    framebuffer_bpp: u8,
+    framebuffer_type: u8,
+    reserved: u16,
+    // color info, its layout depends on framebuffer type
+    red_field_position: u8,
+    red_mask_size: u8,
+    green_field_position: u8,
+    green_mask_size: u8,
+    blue_field_position: u8,
+    blue_mask_size: u8,
+}
+
+impl MultibootHeaderTag for FramebufferInfo {
+    fn numeric_type() -> u32 {
+        8
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FramebufferType {
+    // pixels are indices of color palette
+    Indexed,
+    // pixels are rgb values
+    Rgb,
+    // VGA text mode, width and height are in characters
+    EgaText,
+}
+
+/// Position and size in bits of every color channel inside of pixel
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RgbFormat {
+    pub red_position: u8,
+    pub red_size: u8,
+    pub green_position: u8,
+    pub green_size: u8,
+    pub blue_position: u8,
+    pub blue_size: u8,
+}
+
+impl FramebufferInfo {
+    /// Physical address of framebuffer memory
+    pub fn address(&self) -> usize {
+        self.framebuffer_address as usize
+    }
+
+    /// Bytes per framebuffer line
+    pub fn pitch(&self) -> usize {
+        self.framebuffer_pitch as usize
+    }
+
+    pub fn width(&self) -> usize {
+        self.framebuffer_width as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.framebuffer_height as usize
+    }
+
+    pub fn bits_per_pixel(&self) -> u8 {
+        self.framebuffer_bpp
+    }
+
+    /// Size of framebuffer memory in bytes
+    pub fn size(&self) -> usize {
+        self.pitch() * self.height()
+    }
+
+    pub fn framebuffer_type(&self) -> Option<FramebufferType> {
+        match self.framebuffer_type {
+            0 => Some(FramebufferType::Indexed),
+            1 => Some(FramebufferType::Rgb),
+            2 => Some(FramebufferType::EgaText),
+            _ => None,
+        }
+    }
+
+    /// Returns pixel layout, `None` if pixels aren't rgb values
+    pub fn rgb_format(&self) -> Option<RgbFormat> {
+        if self.framebuffer_type() != Some(FramebufferType::Rgb) {
+            return None;
+        }
+
+        Some(RgbFormat {
+            red_position: self.red_field_position,
+            red_size: self.red_mask_size,
+            green_position: self.green_field_position,
+            green_size: self.green_mask_size,
+            blue_position: self.blue_field_position,
+            blue_size: self.blue_mask_size,
+        })
+    }
+}
+
+impl fmt::Display for FramebufferInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "framebuffer: {:#x}, {}x{}x{}, pitch: {}, type: {:?}",
+               self.address(),
+               self.width(),
+               self.height(),
+               self.bits_per_pixel(),
+               self.pitch(),
+               self.framebuffer_type())
+    }
+}
@@ -20,6 +20,15 @@ impl MemoryMap {
         AvailableMemorySectionsIterator::new(entry_address, tag_end_address, self.entry_size as usize)
     }
 
+    /// Returns every entry including reserved ones
+    pub fn all_entries(&self) -> MemoryMapEntryIterator {
+        MemoryMapEntryIterator {
+            entry_address: (&self.first_entry) as *const _ as usize,
+            tag_end_address: (self as *const _ as usize) + self.tag_size as usize,
+            entry_size: self.entry_size as usize,
+        }
+    }
+
     pub fn available_memory(&self) -> u64 {
         self.entries().sum_by(|e| e.length())    
     }
@@ -125,6 +134,28 @@ impl iter::Iterator for AvailableMemorySectionsIterator {
     }
 }
 
+#[derive(Clone)]
+pub struct MemoryMapEntryIterator {
+    entry_address: usize,
+    tag_end_address: usize,
+    entry_size: usize,
+}
+
+impl iter::Iterator for MemoryMapEntryIterator {
+    type Item = &'static MemoryMapEntry;
+
+    fn next(&mut self) -> Option<&'static MemoryMapEntry> {
+        if self.entry_address >= self.tag_end_address {
+            None
+        } else {
+            let result = unsafe { &(*(self.entry_address as *const MemoryMapEntry)) };
+            self.entry_address += self.entry_size;
+
+            Some(result)
+        }
+    }
+}
+
 impl IteratorExt for AvailableMemorySectionsIterator {
 
 }
\ No newline at end of file
@@ -1,4 +1,8 @@
+pub mod acpi;
 pub mod basic_memory_info;
+pub mod command_line;
 pub mod elf;
+pub mod framebuffer;
 pub mod memory_map;
+pub mod modules;
 pub mod tag_entry_iterator;
\ No newline at end of file
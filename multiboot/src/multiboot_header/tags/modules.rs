@@ -0,0 +1,55 @@
+use multiboot_header::MultibootHeaderTag;
+use core::fmt;
+use core::slice;
+use core::str;
+
+/// Boot module loaded by bootloader. Every module is described by its own tag,
+/// use MultibootHeader::read_tags() to get all of them.
+#[repr(C)]
+pub struct Module {
+    tag_type: u32,
+    tag_size: u32,
+    module_start: u32,
+    module_end: u32,
+    first_string_byte: u8,
+}
+
+impl MultibootHeaderTag for Module {
+    fn numeric_type() -> u32 {
+        3
+    }
+}
+
+impl Module {
+    pub fn start_address(&self) -> usize {
+        self.module_start as usize
+    }
+
+    // module_end points to the first byte after the module
+    pub fn end_address(&self) -> usize {
+        self.module_end as usize - 1
+    }
+
+    /// Returns module command line, empty string if it is not valid utf8
+    pub fn command_line(&self) -> &str {
+        // string is null terminated and is located between tag header and the end of the tag
+        let string_start  = &self.first_string_byte as *const u8;
+        let max_length    = self.tag_size as usize - 4 * 4;
+        let bytes         = unsafe { slice::from_raw_parts(string_start, max_length) };
+        let length        = bytes.iter().position(|b| *b == 0).unwrap_or(max_length);
+
+        str::from_utf8(&bytes[..length]).unwrap_or("")
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "module_start: {},
+        module_end: {},
+        command_line: {}",
+               self.module_start,
+               self.module_end,
+               self.command_line())
+    }
+}
@@ -3,6 +3,7 @@ use alloc::collections::vec_deque::VecDeque;
 use alloc::vec::Vec;
 use alloc::boxed::Box;
 use alloc::rc::Rc;
+use alloc::alloc::{alloc, Layout};
 use core::marker;
 use core::cell;
 use core::ptr;
@@ -32,25 +33,48 @@ impl ops::DerefMut for ExecutorHelp {
     }
 }
 
+/// Errors of fallible executor operations
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExecutorError {
+    /// memory for process descriptor or executor bookkeeping cannot be allocated
+    OutOfMemory,
+    /// there is no process with such id
+    ProcessNotFound(u64),
+    /// operation can't be applied to the process that is executing now
+    ProcessIsCurrent(u64),
+}
+
+/// Snapshot of process state, see `Executor::processes`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub id: u64,
+    pub state: ProcessState,
+    pub pending_messages: usize,
+    pub children: usize,
+}
+
 pub struct Executor {
     id_counter: u64,
 
-    currently_executing: u64,
+    // `None` until the first process is scheduled
+    currently_executing: Option<u64>,
 
     execution_line: VecDeque<u64>,
 
-    existing: BTreeMap<u64, ProcessDescriptor>,
+    // descriptors are boxed, the map moves its values when nodes are split or merged, but saved stack pointers
+    // and the descriptor pointer `start_new_process` pushes onto the process stack point inside the descriptor
+    existing: BTreeMap<u64, Box<ProcessDescriptor>>,
 }
 
 impl Executor {
     pub fn new() -> Self {
         let id_counter = 0;
         let execution_line: VecDeque<u64> = VecDeque::new();
-        let existing: BTreeMap<u64, ProcessDescriptor> = BTreeMap::new();
+        let existing: BTreeMap<u64, Box<ProcessDescriptor>> = BTreeMap::new();
 
         Executor {
             id_counter,
-            currently_executing: 0,
+            currently_executing: None,
             execution_line,
             existing,
         }
@@ -62,10 +86,76 @@ impl Executor {
         }
     }
 
+    /// Posts message to process mailbox, failing instead of aborting when the mailbox cannot grow
+    /// # Arguments
+    ///  `id` - id of the receiving process
+    ///  `message` - message to post
+    pub fn try_post_message(&mut self, id: u64, message: Message) -> Result<(), ExecutorError> {
+        let process = self.existing.get_mut(&id).ok_or(ExecutorError::ProcessNotFound(id))?;
+
+        process.mailbox.try_reserve(1).map_err(|_| ExecutorError::OutOfMemory)?;
+        process.mailbox.push_back(message);
+
+        Ok(())
+    }
+
+    /// Takes the oldest message from process mailbox. Lets a running process receive messages,
+    /// only the first message is passed to `Process::process_message`.
+    /// # Arguments
+    ///  `id` - id of the receiving process
+    pub fn receive_message(&mut self, id: u64) -> Option<Message> {
+        self.existing.get_mut(&id).and_then(|process| process.mailbox.pop_front())
+    }
+
+    /// Returns id of the process that is executing now, `None` before the first process is scheduled
+    pub fn current_process_id(&self) -> Option<u64> {
+        self.currently_executing
+    }
+
+    /// Returns state of every process ordered by id
+    pub fn processes<'a>(&'a self) -> impl Iterator<Item = ProcessInfo> + 'a {
+        self.existing.iter().map(|(id, process)| ProcessInfo {
+            id: *id,
+            state: process.state,
+            pending_messages: process.mailbox.len(),
+            children: process.children.len(),
+        })
+    }
+
+    /// Removes process together with its children, their memory is freed.
+    /// The current process can't be killed, because it runs on the stack inside its descriptor.
+    /// # Arguments
+    ///  `id` - id of the process to kill
+    pub fn kill_process(&mut self, id: u64) -> Result<(), ExecutorError> {
+        if self.currently_executing == Some(id) {
+            return Err(ExecutorError::ProcessIsCurrent(id));
+        }
+
+        if !self.existing.contains_key(&id) {
+            return Err(ExecutorError::ProcessNotFound(id));
+        }
+
+        self.remove_process_with_children(id);
+
+        let existing = &self.existing;
+        self.execution_line.retain(|process_id| existing.contains_key(process_id));
+
+        Ok(())
+    }
+
     pub(crate) fn remove_process_with_children(&mut self, id: u64) {
+        self.remove_process_tree(id);
+
+        // parent must not keep the id, otherwise it would be visited again when the parent is removed
+        for process in self.existing.values_mut() {
+            process.children.retain(|child_id| *child_id != id);
+        }
+    }
+
+    fn remove_process_tree(&mut self, id: u64) {
         if let Some(node) = self.existing.remove(&id) {
             for child_id in node.children {
-                self.remove_process_with_children(child_id);
+                self.remove_process_tree(child_id);
             }
         }
     }
@@ -75,8 +165,25 @@ impl Executor {
     }
 
     pub fn create_process(&mut self, process_message: ProcessBox) -> u64 {
-        let mut node = ProcessDescriptor::new(process_message);
+        let node = Box::new(ProcessDescriptor::new(process_message));
         //node.create_guard();
+
+        self.add_process(node)
+    }
+
+    /// Creates process, failing instead of aborting when there is no memory for its descriptor.
+    /// Descriptor holds process stack, so it is the biggest allocation made for a process.
+    /// # Arguments
+    ///  `process_message` - process to create
+    pub fn try_create_process(&mut self, process_message: ProcessBox) -> Result<u64, ExecutorError> {
+        self.execution_line.try_reserve(1).map_err(|_| ExecutorError::OutOfMemory)?;
+
+        let node = ProcessDescriptor::try_new_boxed(process_message).ok_or(ExecutorError::OutOfMemory)?;
+
+        Ok(self.add_process(node))
+    }
+
+    fn add_process(&mut self, node: Box<ProcessDescriptor>) -> u64 {
         let id = self.id_counter;
 
         self.existing.insert(id, node);
@@ -102,7 +209,12 @@ impl Executor {
     }*/
 
     pub fn update_current_process(&mut self, interrupted_process_state: ProcessRegisters) {
-        if let Some(existing_process) = self.existing.get_mut(&self.currently_executing) {
+        let current = match self.currently_executing {
+            Some(current) => current,
+            None => return,
+        };
+
+        if let Some(existing_process) = self.existing.get_mut(&current) {
 
             if existing_process.state == ProcessState::Running {
                 existing_process.registers = interrupted_process_state;
@@ -115,12 +227,16 @@ impl Executor {
         // pick one process to execute from execution line,
         // execute it and put it back into the queue
 
-        self.execution_line.push_back(self.currently_executing);
+        if let Some(current) = self.currently_executing {
+            if self.existing.contains_key(&current) {
+                self.execution_line.push_back(current);
+            }
+        }
 
         self.execution_line.pop_front().and_then(move |head_id| {
-            self.currently_executing = head_id;
+            self.currently_executing = Some(head_id);
 
-            self.existing.get_mut(&head_id)
+            self.existing.get_mut(&head_id).map(|descriptor| &mut **descriptor)
         })
     }
 }
@@ -188,6 +304,23 @@ impl ProcessDescriptor {
         }
     }
 
+    fn try_new_boxed(process: ProcessBox) -> Option<Box<Self>> {
+        let layout = Layout::new::<ProcessDescriptor>();
+
+        unsafe {
+            let pointer = alloc(layout) as *mut ProcessDescriptor;
+
+            if pointer.is_null() {
+                None
+            }
+            else {
+                ptr::write(pointer, ProcessDescriptor::new(process));
+
+                Some(Box::from_raw(pointer))
+            }
+        }
+    }
+
     pub fn create_guard(&mut self) {
         use memory::paging;
         use memory::frame::Frame;
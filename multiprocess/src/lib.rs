@@ -1,5 +1,6 @@
 #![feature(lang_items)]
 #![feature(asm)]
+#![feature(try_reserve)]
 #![no_std]
 
 extern crate alloc;
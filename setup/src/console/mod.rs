@@ -0,0 +1,131 @@
+/*
+    Kernel console, the single owner of screen output: VGA text writer or framebuffer console
+    when bootloader has set up graphics mode. Output device is protected by a spin lock that is taken
+    with interrupts disabled, so an interrupt handler that prints can't spin forever on the lock held by the code it interrupted.
+    Console doesn't allocate, so it can be used before heap is initialized.
+
+    Panic handler can't rely on the lock: panic may happen while the lock is held, e.g. inside the writer itself.
+    After `enter_emergency_mode` writes go straight to the writer without locking, output of other CPUs may interleave with it.
+*/
+use core::fmt;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use display::vga::writer::Writer;
+use display::framebuffer::{Framebuffer, PixelFormat};
+use display::framebuffer::console::FramebufferConsole;
+use multiboot::multiboot_header::MultibootHeader;
+use multiboot::multiboot_header::tags::framebuffer::FramebufferInfo;
+use hardware::x86_64::interrupts::InterruptGuard;
+use stdx::sync::SpinLock;
+
+/// Screen the console prints to
+pub enum ConsoleDevice {
+    Text(Writer),
+    Framebuffer(FramebufferConsole)
+}
+
+impl fmt::Write for ConsoleDevice {
+    fn write_str(&mut self, s : &str) -> fmt::Result {
+        match self {
+            ConsoleDevice::Text(writer)        => writer.write_str(s),
+            ConsoleDevice::Framebuffer(console) => console.write_str(s)
+        }
+    }
+}
+
+pub struct Console {
+    writer    : SpinLock<Option<ConsoleDevice>>,
+    emergency : AtomicBool
+}
+
+impl Console {
+
+    /// Creates console that ignores output until it is initialized
+    pub const fn new() -> Self {
+        Console {
+            writer    : SpinLock::new(None),
+            emergency : AtomicBool::new(false)
+        }
+    }
+
+    /// Clears VGA text screen and starts printing to it
+    pub fn initialize(&self) {
+        self.set_device(ConsoleDevice::Text(Writer::new()))
+    }
+
+    /// Switches output to framebuffer console
+    /// # Arguments
+    /// * `framebuffer` - framebuffer, its memory must be mapped
+    pub fn initialize_framebuffer(&self, framebuffer : Framebuffer) {
+        self.set_device(ConsoleDevice::Framebuffer(FramebufferConsole::new(framebuffer)))
+    }
+
+    pub fn is_framebuffer(&self) -> bool {
+        self.with_writer(|device| match device {
+            ConsoleDevice::Framebuffer(_) => true,
+            ConsoleDevice::Text(_)        => false
+        })
+        .unwrap_or(false)
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.with_writer(|_| ()).is_some()
+    }
+
+    /// Runs `f` with output device locked and interrupts disabled
+    /// # Returns
+    /// Result of `f` or `None` if console isn't initialized
+    pub fn with_writer<F, R>(&self, f : F) -> Option<R> where F : FnOnce(&mut ConsoleDevice) -> R {
+        if self.is_in_emergency_mode() {
+            return unsafe { self.writer.get_unchecked().as_mut().map(f) }
+        }
+
+        let _guard = InterruptGuard::new();
+
+        // lock is released before interrupts are enabled back
+        let result = self.writer.lock().as_mut().map(f);
+
+        result
+    }
+
+    /// Prints formatted text, output is dropped if console isn't initialized
+    pub fn write_fmt(&self, args : fmt::Arguments) -> fmt::Result {
+        self.with_writer(|writer| writer.write_fmt(args)).unwrap_or(Ok(()))
+    }
+
+    /// Stops locking the writer, all following output is written without waiting. Used by panic handler.
+    pub fn enter_emergency_mode(&self) {
+        self.emergency.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_in_emergency_mode(&self) -> bool {
+        self.emergency.load(Ordering::SeqCst)
+    }
+
+    fn set_device(&self, device : ConsoleDevice) {
+        let _guard = InterruptGuard::new();
+
+        *self.writer.lock() = Some(device);
+    }
+}
+
+/// Returns framebuffer set up by bootloader, `None` if it is absent or its pixels aren't rgb values
+/// # Arguments
+/// * `multiboot_header` - multiboot header
+/// # Why unsafe
+/// Framebuffer memory must be identity mapped, see `memory::paging::remap_kernel`
+pub unsafe fn framebuffer_from_multiboot(multiboot_header : &MultibootHeader) -> Option<Framebuffer> {
+    let info   = multiboot_header.read_tag::<FramebufferInfo>()?;
+    let format = info.rgb_format()?;
+
+    let pixel_format = PixelFormat {
+        red_position   : format.red_position,
+        red_size       : format.red_size,
+        green_position : format.green_position,
+        green_size     : format.green_size,
+        blue_position  : format.blue_position,
+        blue_size      : format.blue_size
+    };
+
+    Some(Framebuffer::new(info.address(), info.pitch(), info.width(), info.height(), info.bits_per_pixel(), pixel_format))
+}
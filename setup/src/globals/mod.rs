@@ -1,8 +1,6 @@
 use core::ptr;
-use core::fmt::Write;
 use pic8259_simple::ChainedPics;
 
-use display::vga::writer::Writer;
 use multiprocess::executor;
 use hardware::x86_64::interrupts::idt::{
     InterruptTable,
@@ -10,22 +8,40 @@ use hardware::x86_64::interrupts::idt::{
     InterruptTableEntry
 };
 use hardware::x86_64::interrupts::pic;
-use memory::allocator::slab::{
-    SlabHelp,
-    SlabAllocator
-};
-use memory::allocator::bump::ConstSizeBumpAllocator;
-use memory::frame::{
-    Frame,
-    FRAME_SIZE
-};
+use hardware::x86_64::serial::{SerialPort, ComPort, DEFAULT_BAUD_RATE};
+use hardware::x86_64::keyboard::{Keyboard, KEYBOARD_IRQ};
+use hardware::x86_64::keyboard::keymap::UsKeymap;
+use memory::allocator::slab::SlabAllocator;
+use memory::allocator::kernel_heap::KernelHeap;
+use memory::allocator::physical::{PhysicalMemoryManager, Zone};
+use memory::frame::Frame;
+use memory::frame::frame_allocator::FrameAllocator;
 use memory::paging;
 use memory::paging::page_table;
+use memory::paging::page_table::MappingError;
+use memory::frame::memory_regions::MemoryRegions;
+use stdx_memory::MemoryAllocatorMeta;
+use stdx::time::{MonotonicClock, WallClock};
 use multiboot::multiboot_header::MultibootHeader;
 use crate::interrupts::handlers;
+use crate::console::Console;
+
+
+/// Screen output, shared by the kernel and interrupt handlers
+pub static CONSOLE: Console = Console::new();
+
+/// Time since timer was started, advanced by timer interrupt handler
+pub static MONOTONIC_CLOCK: MonotonicClock = MonotonicClock::new();
 
+/// Calendar time, synchronized with real time clock during boot
+pub static WALL_CLOCK: WallClock = WallClock::new();
 
-pub static mut VGA_WRITER: Option<Writer> = None;
+pub static mut SERIAL_PORT: Option<SerialPort> = None;
+
+pub static mut KEYBOARD: Keyboard<UsKeymap> = Keyboard::new(UsKeymap);
+
+/// Process that receives keyboard input as `KeyInput` messages
+pub static mut KEYBOARD_LISTENER: Option<u64> = None;
 
 pub static mut PROCESS_EXECUTOR: executor::ExecutorHelp = executor::ExecutorHelp { value : ptr::NonNull::dangling() };
 
@@ -34,7 +50,7 @@ pub static mut INTERRUPT_TABLE: InterruptTable = InterruptTable::new();
 pub static mut CHAINED_PICS: ChainedPics = unsafe { pic::new() } ;
 
 #[global_allocator]
-pub static mut HEAP_ALLOCATOR: SlabHelp = SlabHelp { value : ptr::NonNull::dangling() };
+pub static HEAP_ALLOCATOR: KernelHeap = KernelHeap::new();
 
 pub unsafe fn initialize_interrupt_table() {
 
@@ -43,48 +59,95 @@ pub unsafe fn initialize_interrupt_table() {
     INTERRUPT_TABLE.divide_by_zero = InterruptTableEntry::create_present_entry(handlers::divide_by_zero_handler);
 
     INTERRUPT_TABLE.set_interrupt_handler(HardwareInterrupts::Timer as usize, handlers::timer_interrupt_handler);
+    INTERRUPT_TABLE.set_interrupt_handler(HardwareInterrupts::Com1 as usize, handlers::serial_interrupt_handler);
+    INTERRUPT_TABLE.set_interrupt_handler(HardwareInterrupts::Keyboard as usize, handlers::keyboard_interrupt_handler);
 
     CHAINED_PICS.initialize();
+
+    pic::unmask(ComPort::Com1.irq());
+    pic::unmask(KEYBOARD_IRQ);
+}
+
+/// Initializes PS/2 keyboard, must be called before interrupts are enabled.
+/// Kernel works without keyboard if controller is absent or broken.
+pub unsafe fn initialize_keyboard() {
+    match KEYBOARD.initialize() {
+        Ok(scancode_set) => info!("PS/2 keyboard initialized, scancode {:?}", scancode_set),
+        Err(error)       => warn!("PS/2 keyboard is not available: {:?}", error)
+    }
+}
+
+/// Sends keyboard input to process
+/// # Arguments
+/// * `process_id` - id of process that receives `KeyInput` messages
+pub unsafe fn set_keyboard_listener(process_id : u64) {
+    KEYBOARD_LISTENER = Some(process_id);
 }
 
-pub fn initialize_memory_allocator(multiboot_header : &MultibootHeader) -> SlabAllocator {
-    let (memory_start, memory_end1) = multiboot_header.biggest_memory_area();
-    let memory_end = memory_start + 31457280; //30 mb, something bigger than that produces 0x6 crash
-    let total_memory = memory_end - memory_start + 1;
+/// Initializes COM1, kernel output is duplicated there
+pub unsafe fn initialize_serial_port() {
+    let mut serial_port = SerialPort::new(ComPort::Com1);
 
-    let aux_structures_start_address = preallocate_memory_for_allocator_aux_data_structures(memory_start, memory_end);
+    serial_port.initialize(DEFAULT_BAUD_RATE);
 
-    SlabAllocator::new(aux_structures_start_address + 4096, total_memory, memory_end)
+    SERIAL_PORT = Some(serial_port);
 }
 
-fn preallocate_memory_for_allocator_aux_data_structures(memory_start : usize, memory_end : usize) -> usize {
-    let aux_data_structures_size = SlabAllocator::total_aux_data_structures_size(memory_start, memory_end);
+/// Creates heap allocator that uses every free memory region
+/// # Arguments
+/// * `multiboot_header` - multiboot header
+/// * `boot_frame_allocator` - frame allocator used during boot, frames it still has in use are excluded,
+/// frames it freed are given to the heap. It must not be used after this call.
+pub fn initialize_memory_allocator(multiboot_header : &MultibootHeader, boot_frame_allocator : &FrameAllocator) -> SlabAllocator {
+    // memory of boot allocator's frame bitmap and free frame stack
+    let boot_allocator_aux_memory = (boot_frame_allocator.start_address(), boot_frame_allocator.end_address());
 
-    let premade_bump_end_address  = Frame::address_align_up(memory_start + aux_data_structures_size);
-    let mut premade_bump                = ConstSizeBumpAllocator::from_address(memory_start, premade_bump_end_address, FRAME_SIZE);
+    let mut memory_regions = MemoryRegions::from_multiboot(multiboot_header, &[boot_allocator_aux_memory]);
 
-    // |aux structures page tables|aux structures working memory|allocator working memory|
-    // premap memory for memory allocator inner data structures
-    let aux_structures_start_address = premade_bump_end_address + FRAME_SIZE; // next frame
-    let aux_structures_end_address = Frame::address_align_up(aux_structures_start_address + aux_data_structures_size);
+    // frames given to page tables during kernel remap
+    for (start_address, end_address) in boot_frame_allocator.used_frames() {
+        memory_regions.exclude(start_address, end_address);
+    }
 
-    for frame in Frame::range_inclusive(aux_structures_start_address, aux_structures_end_address) {
-        let p4_table = paging::p4_table();
-        p4_table.map_page_1_to_1(frame, page_table::PRESENT | page_table::WRITABLE, &mut premade_bump);
+    for region in memory_regions.iter() {
+        debug!("Memory region {}", region);
     }
 
-    test_allocator_aux_data_structures_memory(aux_structures_start_address, aux_structures_end_address);
+    let memory_manager = PhysicalMemoryManager::new(&memory_regions);
 
-    aux_structures_start_address
-}
+    for zone in [Zone::Dma, Zone::Dma32, Zone::Normal].iter() {
+        debug!("Zone {}: {} kb", zone, memory_manager.total_memory_in(*zone) / 1024);
+    }
 
-fn test_allocator_aux_data_structures_memory(aux_structures_start_address : usize, aux_structures_end_address : usize) {
-    for frame in Frame::range_inclusive(aux_structures_start_address, aux_structures_end_address) {
-        let p4_table = paging::p4_table();
-        let present = p4_table.is_present(frame);
+    SlabAllocator::new(memory_manager)
+}
 
-        unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Is present {}, val {}", frame, present); }
+/// Initializes kernel heap with every free memory region, must be called once before the first heap allocation
+/// # Arguments
+/// * `multiboot_header` - multiboot header
+/// * `boot_frame_allocator` - frame allocator used during boot, frames it gave out are excluded. It must not be used after this call.
+pub fn initialize_heap(multiboot_header : &MultibootHeader, boot_frame_allocator : &FrameAllocator) {
+    HEAP_ALLOCATOR.initialize(initialize_memory_allocator(multiboot_header, boot_frame_allocator))
+}
 
-        Frame::zero_frame(&frame);
+/// Maps device memory, e.g. memory mapped registers or firmware tables, 1 to 1 and uncached.
+/// Pages that are already mapped are left as they are. Page tables are taken from the heap, so it must be initialized.
+/// # Arguments
+/// * `physical_address` - start of device memory
+/// * `size` - size of device memory in bytes
+/// # Why unsafe
+/// Changes active page tables
+pub unsafe fn map_device_memory(physical_address : usize, size : usize) -> Result<(), MappingError> {
+    let p4_table = paging::p4_table();
+    let mut heap = &HEAP_ALLOCATOR;
+    let flags    = page_table::PRESENT | page_table::WRITABLE | page_table::WRITE_THROUGH | page_table::NO_CACHE;
+
+    for frame in Frame::range_inclusive(physical_address, physical_address + size.max(1) - 1) {
+        match p4_table.try_map_page(frame, frame, flags, &mut heap) {
+            Ok(()) | Err(MappingError::AlreadyMapped(_)) => (),
+            Err(error) => return Err(error)
+        }
     }
-}
\ No newline at end of file
+
+    Ok(())
+}
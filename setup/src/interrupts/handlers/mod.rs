@@ -1,5 +1,3 @@
-use core::fmt::Write;
-
 use hardware::x86_64::registers;
 use hardware::x86_64::interrupts;
 use hardware::x86_64::interrupts::idt::{
@@ -19,40 +17,81 @@ use crate::globals::{
     PROCESS_EXECUTOR
 };
 
-use crate::globals::VGA_WRITER;
+use crate::globals::{SERIAL_PORT, KEYBOARD, KEYBOARD_LISTENER, MONOTONIC_CLOCK};
+use crate::storage;
+use crate::time::TIMER_FREQUENCY;
+use alloc::boxed::Box;
 
 pub extern "x86-interrupt" fn divide_by_zero_handler(stack_frame: &mut InterruptStackFrameValue) {
-    unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Divide by zero occured"); }
+    error!("Divide by zero occured at {:#x}", stack_frame.instruction_pointer);
 }
 
 pub extern "x86-interrupt" fn breakpoint_handler(stack_frame: &mut InterruptStackFrameValue) {
-    unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "BREAKPOINT"); }
+    warn!("Breakpoint at {:#x}", stack_frame.instruction_pointer);
 }
 
 pub extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: &mut InterruptStackFrameValue) {
-    unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "INVALID OPCODE OCCURED"); }
+    error!("Invalid opcode occured at {:#x}", stack_frame.instruction_pointer);
 }
 
 pub extern "x86-interrupt" fn page_fault_handler(stack_frame: &mut InterruptStackFrameValue, code : u64) {
-    unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "PAGE FAULT OCCURED"); }
+    error!("Page fault occured at {:#x}, error code {:#x}", stack_frame.instruction_pointer, code);
 }
 
 pub extern "x86-interrupt" fn double_fault_handler(stack_frame: &mut InterruptStackFrameValue, error_code : u64) {
-    unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "DOUBLE FAULT OCCURED"); }
+    error!("Double fault occured at {:#x}", stack_frame.instruction_pointer);
 }
 
+pub extern "x86-interrupt" fn serial_interrupt_handler(stack_frame: &mut InterruptStackFrameValue) {
+    unsafe {
+        if let Some(serial_port) = SERIAL_PORT.as_mut() {
+            serial_port.handle_interrupt();
+        }
+
+        CHAINED_PICS.notify_end_of_interrupt(HardwareInterrupts::Com1 as u8);
+    }
+}
+
+pub extern "x86-interrupt" fn keyboard_interrupt_handler(stack_frame: &mut InterruptStackFrameValue) {
+    unsafe {
+        KEYBOARD.handle_interrupt();
+
+        // without listener input waits in keyboard queue
+        if let Some(listener) = KEYBOARD_LISTENER {
+            while let Some(input) = KEYBOARD.read_input() {
+                if let Err(error) = PROCESS_EXECUTOR.try_post_message(listener, Box::new(input)) {
+                    warn!("Keyboard input {:?} is lost: {:?}", input, error);
+                }
+            }
+        }
+
+        CHAINED_PICS.notify_end_of_interrupt(HardwareInterrupts::Keyboard as u8);
+    }
+}
+
+pub extern "x86-interrupt" fn block_device_interrupt_handler(stack_frame: &mut InterruptStackFrameValue) {
+    if let Some(irq) = storage::handle_interrupt() {
+        unsafe { CHAINED_PICS.notify_end_of_interrupt(pic::vector(irq)); }
+    }
+}
+
+// timer ticks between process switches, about 2 secs
+const SCHEDULING_INTERVAL : usize = 2 * TIMER_FREQUENCY as usize;
+
 static mut timer_ctr : usize = 0;
 
 pub extern "x86-interrupt" fn timer_interrupt_handler(stack_frame: &mut InterruptStackFrameValue) {
+    MONOTONIC_CLOCK.tick();
+
     unsafe {
 
-        if timer_ctr > 40 { // emulates tick every 3 secs
+        if timer_ctr > SCHEDULING_INTERVAL {
 
             timer_ctr = 0;
 
-            writeln!(VGA_WRITER.as_mut().unwrap(), "TICK");
+            trace!("Tick");
 
-            writeln!(VGA_WRITER.as_mut().unwrap(), "Tick interrupt frame {:?}", stack_frame);
+            trace!("Tick interrupt frame {:?}", stack_frame);
 
             let interrupted_process_registers = executor::ProcessRegisters {
                 instruction_pointer: stack_frame.instruction_pointer,
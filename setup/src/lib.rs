@@ -1,9 +1,20 @@
 #![no_std]
 #![feature(abi_x86_interrupt)]
+#![feature(const_fn)]
 
+extern crate alloc;
+extern crate drivers;
 extern crate hardware;
 extern crate multiprocess;
 extern crate multiboot;
+extern crate stdx;
 
+#[macro_use]
+pub mod log;
+pub mod console;
 pub mod interrupts;
-pub mod globals;
\ No newline at end of file
+pub mod globals;
+pub mod pci;
+pub mod shell;
+pub mod storage;
+pub mod time;
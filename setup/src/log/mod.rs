@@ -0,0 +1,195 @@
+/*
+    Kernel logging. Messages are written with `error!`, `warn!`, `info!`, `debug!` and `trace!` macros,
+    filtered by level of the module they come from, prefixed with time since boot and fanned out to every enabled sink:
+    VGA screen, serial port and in-memory log buffer. `print!` and `println!` write to the same sinks without filtering.
+    Filters are taken from `log` option of the kernel command line, see `stdx::log`.
+
+    Logger with its log buffer is protected by a spin lock taken with interrupts disabled, writes to all sinks happen
+    under it, so messages of different CPUs and interrupt handlers don't interleave. Once console enters emergency mode
+    (panic) logger is used without locking, the same way console is.
+*/
+use core::fmt;
+use core::fmt::Write;
+use stdx::log::{LogFilters, LogBuffer};
+use stdx::sync::SpinLock;
+use hardware::x86_64::interrupts::InterruptGuard;
+use multiboot::multiboot_header::MultibootHeader;
+use multiboot::multiboot_header::tags::command_line::CommandLine;
+use crate::globals::{CONSOLE, SERIAL_PORT};
+use crate::time;
+
+pub use stdx::log::Level;
+
+pub const DEFAULT_LEVEL : Level = Level::Info;
+
+const SINKS_COUNT : usize = 3;
+
+/// Destination of log output
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sink {
+    Vga = 0,
+    Serial,
+    Memory
+}
+
+pub struct Logger {
+    filters : LogFilters,
+    sinks   : [bool; SINKS_COUNT],
+    // latest log output, can be dumped after a crash
+    buffer  : LogBuffer
+}
+
+static LOGGER: SpinLock<Logger> = SpinLock::new(Logger::new());
+
+impl Logger {
+
+    /// Creates logger with default level and all sinks enabled
+    pub const fn new() -> Self {
+        Logger {
+            filters : LogFilters::new(DEFAULT_LEVEL),
+            sinks   : [true; SINKS_COUNT],
+            buffer  : LogBuffer::new()
+        }
+    }
+
+    pub fn filters(&mut self) -> &mut LogFilters {
+        &mut self.filters
+    }
+
+    pub fn enable_sink(&mut self, sink : Sink) {
+        self.sinks[sink as usize] = true
+    }
+
+    pub fn disable_sink(&mut self, sink : Sink) {
+        self.sinks[sink as usize] = false
+    }
+
+    pub fn is_sink_enabled(&self, sink : Sink) -> bool {
+        self.sinks[sink as usize]
+    }
+
+    // sinks that are not initialized yet are skipped, a sink that fails doesn't stop the others
+    fn write(&mut self, args : fmt::Arguments) {
+        if self.is_sink_enabled(Sink::Vga) {
+            let _ = CONSOLE.write_fmt(args);
+        }
+
+        if self.is_sink_enabled(Sink::Serial) {
+            if let Some(serial_port) = unsafe { SERIAL_PORT.as_mut() } {
+                let _ = serial_port.write_fmt(args);
+            }
+        }
+
+        if self.is_sink_enabled(Sink::Memory) {
+            let _ = self.buffer.write_fmt(args);
+        }
+    }
+}
+
+/// Runs `f` with logger locked and interrupts disabled, without locking if console is in emergency mode
+pub fn with_logger<F, R>(f : F) -> R where F : FnOnce(&mut Logger) -> R {
+    if CONSOLE.is_in_emergency_mode() {
+        return f(unsafe { LOGGER.get_unchecked() })
+    }
+
+    let _guard = InterruptGuard::new();
+
+    // lock is released before interrupts are enabled back
+    let result = f(&mut LOGGER.lock());
+
+    result
+}
+
+/// Sets log filters from `log` option of the kernel command line
+/// # Arguments
+/// * `multiboot_header` - multiboot header
+pub fn initialize(multiboot_header : &MultibootHeader) {
+    let description = multiboot_header.read_tag::<CommandLine>()
+        .and_then(|command_line| command_line.option("log"))
+        .unwrap_or("");
+
+    with_logger(|logger| logger.filters = LogFilters::parse(description, DEFAULT_LEVEL));
+}
+
+/// Writes message if `level` is enabled for module, used by logging macros
+/// # Arguments
+/// * `level` - message level
+/// * `module_path` - path of module that writes the message
+/// * `args` - message
+pub fn log(level : Level, module_path : &str, args : fmt::Arguments) {
+    with_logger(|logger| {
+        if logger.filters.is_enabled(level, module_path) {
+            let uptime = time::uptime();
+
+            logger.write(format_args!("[{:>5}.{:03}] [{} {}] {}\n", uptime.as_secs(), uptime.subsec_millis(), level, module_path, args));
+        }
+    })
+}
+
+/// Writes text to every enabled sink, used by `print!` and `println!`
+pub fn print(args : fmt::Arguments) {
+    with_logger(|logger| logger.write(args))
+}
+
+/// Writes to every enabled sink like `print!`, for code that prints to `fmt::Write`
+pub struct Printer;
+
+impl fmt::Write for Printer {
+    fn write_str(&mut self, s : &str) -> fmt::Result {
+        print(format_args!("{}", s));
+        Ok(())
+    }
+}
+
+/// Writes content of log buffer to `writer`, which must not log itself
+pub fn dump_log_buffer<W>(writer : &mut W) -> fmt::Result where W : fmt::Write {
+    with_logger(|logger| logger.buffer.dump(writer))
+}
+
+/// Prints content of log buffer on console, e.g. after console has switched to a new screen.
+/// Logger is locked before console, like it is for any log message.
+pub fn dump_log_buffer_to_console() {
+    with_logger(|logger| CONSOLE.with_writer(|writer| logger.buffer.dump(writer)));
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::log::print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($fmt:expr) => ($crate::print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => ($crate::print!(concat!($fmt, "\n"), $($arg)*));
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => ($crate::log::log($level, module_path!(), format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => ($crate::log!($crate::log::Level::Error, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => ($crate::log!($crate::log::Level::Warn, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => ($crate::log!($crate::log::Level::Info, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => ($crate::log!($crate::log::Level::Debug, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => ($crate::log!($crate::log::Level::Trace, $($arg)*));
+}
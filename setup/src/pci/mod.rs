@@ -0,0 +1,164 @@
+/*
+    PCI subsystem of the kernel. Configuration space is accessed through ECAM when ACPI MCFG table describes it,
+    otherwise through ports. Only segment 0 is used, which is the only one on ordinary PCs.
+    Drivers are registered in `PCI_DRIVERS` before `probe_devices` gives them functions found on the bus.
+    Sizing BARs turns decoding off for a moment, so `probe_devices` sizes them once and keeps them with the device,
+    drivers and the shell use the cached values afterwards.
+*/
+use hardware::x86_64::acpi::{EcamRegion, RootTable, MCFG_SIGNATURE};
+use hardware::x86_64::interrupts::InterruptGuard;
+use alloc::vec::Vec;
+use hardware::x86_64::pci::{Bar, PciAddress, PciDevice, Devices, MAX_BARS};
+use hardware::x86_64::pci::config::{ConfigSpace, PortConfigSpace, EcamConfigSpace};
+use hardware::x86_64::pci::driver::{DriverRegistry, PciDriver};
+use multiboot::multiboot_header::MultibootHeader;
+use multiboot::multiboot_header::tags::acpi::{AcpiNewRsdp, AcpiOldRsdp};
+use crate::globals;
+
+pub enum PciConfigSpace {
+    Port(PortConfigSpace),
+    Ecam(EcamConfigSpace)
+}
+
+impl ConfigSpace for PciConfigSpace {
+
+    fn read(&self, address : PciAddress, offset : u16) -> u32 {
+        match *self {
+            // address and data ports must not be used by interrupt handler in between
+            PciConfigSpace::Port(ref config) => {
+                let _guard = InterruptGuard::new();
+
+                config.read(address, offset)
+            },
+            PciConfigSpace::Ecam(ref config) => config.read(address, offset)
+        }
+    }
+
+    fn write(&self, address : PciAddress, offset : u16, value : u32) {
+        match *self {
+            PciConfigSpace::Port(ref config) => {
+                let _guard = InterruptGuard::new();
+
+                config.write(address, offset, value)
+            },
+            PciConfigSpace::Ecam(ref config) => config.write(address, offset, value)
+        }
+    }
+
+    fn buses(&self) -> (u8, u8) {
+        match *self {
+            PciConfigSpace::Port(ref config) => config.buses(),
+            PciConfigSpace::Ecam(ref config) => config.buses()
+        }
+    }
+}
+
+/// Function found on the bus together with its BARs sized by `probe_devices`
+pub struct ProbedDevice {
+    pub device : PciDevice,
+    pub bars   : [Option<Bar>; MAX_BARS]
+}
+
+static mut CONFIG_SPACE: Option<PciConfigSpace> = None;
+
+static mut PROBED_DEVICES: Option<Vec<ProbedDevice>> = None;
+
+pub static mut PCI_DRIVERS: DriverRegistry = DriverRegistry::new();
+
+/// Chooses configuration mechanism, must be called after heap is initialized because ECAM and ACPI tables get mapped
+/// # Arguments
+/// * `multiboot_header` - multiboot header, source of ACPI RSDP
+pub unsafe fn initialize(multiboot_header : &MultibootHeader) {
+    let ecam_region = find_ecam_region(multiboot_header).filter(|region| {
+        match globals::map_device_memory(region.start_address(), region.size()) {
+            Ok(())     => true,
+            Err(error) => {
+                warn!("Failed to map PCI configuration space at {:#x}: {}", region.start_address(), error);
+                false
+            }
+        }
+    });
+
+    let config_space = match ecam_region {
+        Some(region) => {
+            info!("PCI configuration space of buses {}-{} is at {:#x}", region.start_bus, region.end_bus, region.start_address());
+
+            PciConfigSpace::Ecam(EcamConfigSpace::new(region.start_address(), region.segment, region.start_bus, region.end_bus))
+        },
+        None => {
+            info!("PCI configuration space is accessed through ports");
+
+            PciConfigSpace::Port(PortConfigSpace::new())
+        }
+    };
+
+    CONFIG_SPACE = Some(config_space);
+}
+
+fn find_ecam_region(multiboot_header : &MultibootHeader) -> Option<EcamRegion> {
+    let rsdp = multiboot_header.read_tag::<AcpiNewRsdp>().map(|tag| tag.rsdp())
+        .or_else(|| multiboot_header.read_tag::<AcpiOldRsdp>().map(|tag| tag.rsdp()))?;
+
+    let root_table = RootTable::from_rsdp(rsdp)?;
+
+    let mcfg = unsafe {
+        root_table.find_table(MCFG_SIGNATURE, |address, size| {
+            if let Err(error) = globals::map_device_memory(address, size) {
+                warn!("Failed to map ACPI table at {:#x}: {}", address, error);
+            }
+        })
+    }?;
+
+    mcfg.mcfg_regions().find(|region| region.segment == 0)
+}
+
+/// Returns configuration space, `None` before initialization
+pub fn config_space() -> Option<&'static PciConfigSpace> {
+    unsafe { CONFIG_SPACE.as_ref() }
+}
+
+/// Returns every function on the bus, nothing before initialization
+pub fn devices() -> impl Iterator<Item = PciDevice> {
+    config_space()
+        .into_iter()
+        .flat_map(|config_space| Devices::new(config_space, 0))
+}
+
+/// Returns functions found by `probe_devices`, `None` before probing
+pub fn probed_devices() -> Option<&'static [ProbedDevice]> {
+    unsafe { PROBED_DEVICES.as_ref().map(|devices| devices.as_slice()) }
+}
+
+/// Adds driver that will be probed for supported devices
+pub unsafe fn register_driver(driver : &'static dyn PciDriver) {
+    if let Err(error) = PCI_DRIVERS.register(driver) {
+        error!("Failed to register PCI driver {}: {:?}", driver.name(), error);
+    }
+}
+
+/// Sizes BARs of every function found on the bus and gives it to the first driver that supports it
+pub unsafe fn probe_devices() {
+    let config_space = match config_space() {
+        Some(config_space) => config_space,
+        None               => return
+    };
+
+    let mut probed_devices = Vec::new();
+
+    for device in devices() {
+        info!("PCI {} {:04x}:{:04x} {}", device.address, device.vendor_id, device.device_id, device.class_name());
+
+        let bars = device.bars(config_space);
+
+        if let Some(driver) = PCI_DRIVERS.driver_for(&device) {
+            match driver.probe(&device, &bars, config_space) {
+                Ok(())     => info!("PCI {} is driven by {}", device.address, driver.name()),
+                Err(error) => warn!("Driver {} failed to probe PCI {}: {:?}", driver.name(), device.address, error)
+            }
+        }
+
+        probed_devices.push(ProbedDevice { device, bars });
+    }
+
+    PROBED_DEVICES = Some(probed_devices);
+}
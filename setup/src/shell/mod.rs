@@ -0,0 +1,347 @@
+/*
+    Kernel shell, runs as a process. It is started by any message, afterwards it doesn't return from `process_message`
+    and reads its input itself: keyboard input comes as `KeyInput` messages into its mailbox
+    (the shell must be set as keyboard listener), serial console input is read from COM1.
+    Output goes to every print sink, so the shell is usable from the screen and from the serial console.
+
+    Executor is also used by timer interrupt handler, so it is accessed with interrupts disabled.
+*/
+use alloc::boxed::Box;
+use alloc::string::String;
+use hardware::x86_64::interrupts;
+use hardware::x86_64::interrupts::InterruptGuard;
+use hardware::x86_64::keyboard::KeyInput;
+use hardware::x86_64::pci::Bar;
+use drivers::block::SECTOR_SIZE;
+use memory::paging;
+use multiboot::multiboot_header::MultibootHeader;
+use multiboot::multiboot_header::tags::memory_map::MemoryMap;
+use multiprocess::executor::{Executor, ExecutorError, ProcessState};
+use multiprocess::process::{Message, Process, ProcessBox, StartProcess};
+use crate::globals::{HEAP_ALLOCATOR, PROCESS_EXECUTOR, SERIAL_PORT};
+use crate::log::Printer;
+use crate::pci;
+use crate::storage;
+use crate::time;
+
+const PROMPT          : &str = "> ";
+const MAX_LINE_LENGTH : usize = 256;
+const BACKSPACE       : char = '\x08';
+const DELETE          : char = '\x7f';
+
+const HELP : &str = "Commands:
+  help              - show this help
+  ps                - list processes
+  mem               - show heap statistics
+  memmap            - show memory map given by bootloader
+  mappings          - show page table mappings
+  date              - show current time and uptime
+  lspci             - list PCI devices
+  disk [sector]     - show block device, dump sector
+  spawn <program>   - start program
+  kill <id>         - stop process and its children";
+
+/// Program the shell can spawn
+pub struct Program {
+    pub name   : &'static str,
+    pub create : fn() -> ProcessBox
+}
+
+pub struct Shell {
+    multiboot_header : &'static MultibootHeader,
+    programs         : &'static [Program],
+    line             : String
+}
+
+impl Shell {
+
+    /// # Arguments
+    /// * `multiboot_header` - multiboot header, source of memory map
+    /// * `programs` - programs that `spawn` command can start
+    pub fn new(multiboot_header : &'static MultibootHeader, programs : &'static [Program]) -> Self {
+        Shell {
+            multiboot_header,
+            programs,
+            line : String::new()
+        }
+    }
+
+    fn run(&mut self) -> ! {
+        println!("Kernel shell, type 'help' for the list of commands");
+        print!("{}", PROMPT);
+
+        loop {
+            while let Some(character) = self.next_character() {
+                self.handle_character(character);
+            }
+
+            interrupts::wait_for_interrupt();
+        }
+    }
+
+    // keyboard input goes first, serial console is read when there is none
+    fn next_character(&mut self) -> Option<char> {
+        loop {
+            let message = with_executor(|executor| {
+                executor.current_process_id().and_then(|id| executor.receive_message(id))
+            });
+
+            match message {
+                Some(message) => {
+                    if let Ok(input) = message.downcast::<KeyInput>() {
+                        if let Some(character) = input.character {
+                            return Some(character)
+                        }
+                    }
+                },
+                None => break
+            }
+        }
+
+        unsafe {
+            SERIAL_PORT.as_mut()
+                .and_then(|serial_port| serial_port.read_byte())
+                .map(|byte| if byte == b'\r' { '\n' } else { byte as char })
+        }
+    }
+
+    fn handle_character(&mut self, character : char) {
+        match character {
+            '\n' => {
+                println!();
+
+                let line = self.line.clone();
+                self.line.clear();
+
+                self.execute(line.trim());
+
+                print!("{}", PROMPT);
+            },
+            BACKSPACE | DELETE => {
+                if self.line.pop().is_some() {
+                    print!("{} {}", BACKSPACE, BACKSPACE);
+                }
+            },
+            _ if !character.is_control() && self.line.len() < MAX_LINE_LENGTH => {
+                self.line.push(character);
+                print!("{}", character);
+            },
+            _ => ()
+        }
+    }
+
+    fn execute(&mut self, line : &str) {
+        let mut words = line.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (None, _)                    => (),
+            (Some("help"), _)            => println!("{}", HELP),
+            (Some("ps"), _)              => self.list_processes(),
+            (Some("mem"), _)             => print!("{}", HEAP_ALLOCATOR.statistics()),
+            (Some("memmap"), _)          => self.print_memory_map(),
+            (Some("mappings"), _)        => self.print_mappings(),
+            (Some("date"), _)            => self.print_date(),
+            (Some("lspci"), _)           => self.list_pci_devices(),
+            (Some("disk"), sector)       => self.print_disk(sector),
+            (Some("spawn"), program)     => self.spawn(program),
+            (Some("kill"), Some(id))     => self.kill(id),
+            (Some("kill"), None)         => println!("Usage: kill <id>"),
+            (Some(command), _)           => println!("Unknown command '{}', type 'help' for the list of commands", command)
+        }
+    }
+
+    fn list_processes(&self) {
+        with_executor(|executor| {
+            let current = executor.current_process_id();
+
+            println!("  ID STATE    MESSAGES CHILDREN");
+
+            for process in executor.processes() {
+                let marker = if current == Some(process.id) { '*' } else { ' ' };
+
+                println!("{}{:>3} {:<8} {:>8} {:>8}", marker, process.id, state_name(process.state), process.pending_messages, process.children);
+            }
+        })
+    }
+
+    fn print_memory_map(&self) {
+        match self.multiboot_header.read_tag::<MemoryMap>() {
+            Some(memory_map) => {
+                for entry in memory_map.all_entries() {
+                    println!("{:#014x} - {:#014x} {:>10} kb {}",
+                        entry.base_address(),
+                        entry.end_address(),
+                        entry.length() / 1024,
+                        memory_type_name(entry.entry_type()));
+                }
+
+                println!("Available: {} kb", memory_map.available_memory() / 1024);
+            },
+            None => println!("Bootloader didn't provide memory map")
+        }
+    }
+
+    fn print_mappings(&self) {
+        paging::dump_address_space(paging::p4_table(), &mut Printer).ok();
+    }
+
+    fn print_date(&self) {
+        match time::now() {
+            Some(now) => println!("{} UTC", now),
+            None      => println!("Wall clock is not available")
+        }
+
+        let uptime = time::uptime();
+
+        println!("Up {}.{:03} s", uptime.as_secs(), uptime.subsec_millis());
+    }
+
+    fn list_pci_devices(&self) {
+        // sizing BARs again would disturb devices drivers already use
+        let probed_devices = match pci::probed_devices() {
+            Some(probed_devices) => probed_devices,
+            None                 => {
+                println!("PCI devices were not probed");
+                return
+            }
+        };
+
+        for &pci::ProbedDevice { ref device, ref bars } in probed_devices {
+            let driver = unsafe { pci::PCI_DRIVERS.driver_for(device) }.map_or("-", |driver| driver.name());
+
+            println!("{} {:04x}:{:04x} {:<18} {}", device.address, device.vendor_id, device.device_id, device.class_name(), driver);
+
+            for (index, bar) in bars.iter().enumerate() {
+                match *bar {
+                    Some(Bar::Memory { address, size, .. }) => println!("    BAR{} memory {:#x}, {} kb", index, address, size / 1024),
+                    Some(Bar::Io { port, size })            => println!("    BAR{} ports {:#x}, {} bytes", index, port, size),
+                    None                                    => ()
+                }
+            }
+        }
+    }
+
+    fn print_disk(&self, sector : Option<&str>) {
+        // the shell is the only user of the block device
+        let block_device = match unsafe { storage::block_device() } {
+            Some(block_device) => block_device,
+            None               => {
+                println!("There is no block device");
+                return
+            }
+        };
+
+        println!("{} sectors, {} kb{}", block_device.sector_count(), block_device.size() / 1024,
+            if block_device.is_read_only() { ", read only" } else { "" });
+
+        let sector = match sector {
+            Some(sector) => match sector.parse::<u64>() {
+                Ok(sector) => sector,
+                Err(_)     => {
+                    println!("'{}' is not a sector number", sector);
+                    return
+                }
+            },
+            None => return
+        };
+
+        let mut buffer = [0u8; SECTOR_SIZE];
+
+        if let Err(error) = block_device.read_sectors(sector, &mut buffer) {
+            println!("Failed to read sector {}: {}", sector, error);
+            return
+        }
+
+        for (index, line) in buffer.chunks(16).enumerate() {
+            print!("{:04x}:", index * 16);
+
+            for byte in line {
+                print!(" {:02x}", byte);
+            }
+
+            print!("  ");
+
+            for byte in line {
+                print!("{}", if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' });
+            }
+
+            println!();
+        }
+    }
+
+    fn spawn(&self, name : Option<&str>) {
+        let program = name.and_then(|name| self.programs.iter().find(|program| program.name == name));
+
+        match program {
+            Some(program) => {
+                let result : Result<u64, ExecutorError> = with_executor(|executor| {
+                    let id = executor.try_create_process((program.create)())?;
+
+                    // process that didn't get its start message would stay in the executor forever,
+                    // it has never run, so it can't be the current one and killing it can't fail
+                    if let Err(error) = executor.try_post_message(id, Box::new(StartProcess {})) {
+                        let _ = executor.kill_process(id);
+
+                        return Err(error)
+                    }
+
+                    Ok(id)
+                });
+
+                match result {
+                    Ok(id)     => println!("Started {} with id {}", program.name, id),
+                    Err(error) => println!("Failed to start {}: {:?}", program.name, error)
+                }
+            },
+            None => {
+                print!("Programs:");
+
+                for program in self.programs.iter() {
+                    print!(" {}", program.name);
+                }
+
+                println!();
+            }
+        }
+    }
+
+    fn kill(&self, id : &str) {
+        match id.parse::<u64>() {
+            Ok(id) => match with_executor(|executor| executor.kill_process(id)) {
+                Ok(())     => println!("Process {} is killed", id),
+                Err(error) => println!("Failed to kill process {}: {:?}", id, error)
+            },
+            Err(_) => println!("'{}' is not a process id", id)
+        }
+    }
+}
+
+impl Process for Shell {
+    fn process_message(&mut self, _message : Message) -> () {
+        self.run()
+    }
+}
+
+fn with_executor<F, R>(f : F) -> R where F : FnOnce(&mut Executor) -> R {
+    let _guard = InterruptGuard::new();
+
+    unsafe { f(&mut *PROCESS_EXECUTOR) }
+}
+
+fn state_name(state : ProcessState) -> &'static str {
+    match state {
+        ProcessState::New      => "new",
+        ProcessState::Running  => "running",
+        ProcessState::Finished => "finished"
+    }
+}
+
+fn memory_type_name(entry_type : u32) -> &'static str {
+    match entry_type {
+        1 => "available",
+        3 => "ACPI reclaimable",
+        4 => "ACPI NVS",
+        5 => "bad",
+        _ => "reserved"
+    }
+}
@@ -0,0 +1,126 @@
+/*
+    Storage of the kernel. Virtio block driver is registered as PCI driver, the first virtio block device
+    it probes becomes the kernel block device. Queue and request memory come from physical memory manager.
+    The device signals completion through its legacy interrupt line routed to PIC, MSI would need local APIC,
+    which the kernel doesn't enable. The line can be shared, interrupt handler acknowledges the device and sends EOI.
+*/
+use drivers::block::BlockDevice;
+use drivers::virtio::{Transport, VirtioError, ISR_CONFIGURATION, VIRTIO_VENDOR_ID};
+use drivers::virtio::block::{VirtioBlock, Completion, LEGACY_DEVICE_ID, MODERN_DEVICE_ID};
+use drivers::virtio::pci::{PciTransport, InterruptStatus};
+use hardware::x86_64::interrupts::pic;
+use hardware::x86_64::pci::{Bar, PciDevice, MAX_BARS, COMMAND_IO_SPACE, COMMAND_MEMORY_SPACE, COMMAND_BUS_MASTER, COMMAND_INTX_DISABLE};
+use hardware::x86_64::pci::config::ConfigSpace;
+use hardware::x86_64::pci::driver::{DeviceId, PciDriver, ProbeError};
+use crate::globals;
+use crate::globals::{HEAP_ALLOCATOR, INTERRUPT_TABLE, MONOTONIC_CLOCK};
+use crate::interrupts::handlers;
+
+// interrupt line value firmware leaves when the pin isn't routed
+const LINE_NOT_ROUTED : u8 = 0xff;
+
+static VIRTIO_BLOCK_IDS : [DeviceId; 2] = [
+    DeviceId::device(VIRTIO_VENDOR_ID, LEGACY_DEVICE_ID),
+    DeviceId::device(VIRTIO_VENDOR_ID, MODERN_DEVICE_ID)
+];
+
+pub struct VirtioBlockDriver;
+
+pub static VIRTIO_BLOCK_DRIVER: VirtioBlockDriver = VirtioBlockDriver;
+
+static mut BLOCK_DEVICE: Option<VirtioBlock<PciTransport>> = None;
+
+/// Interrupt status register and PIC line of the block device
+static mut BLOCK_DEVICE_INTERRUPT: Option<(InterruptStatus, u8)> = None;
+
+impl PciDriver for VirtioBlockDriver {
+
+    fn name(&self) -> &'static str {
+        "virtio-blk"
+    }
+
+    fn supported_devices(&self) -> &'static [DeviceId] {
+        &VIRTIO_BLOCK_IDS
+    }
+
+    fn probe(&self, device : &PciDevice, bars : &[Option<Bar>; MAX_BARS], config : &dyn ConfigSpace) -> Result<(), ProbeError> {
+        unsafe {
+            // kernel has a single block device
+            if BLOCK_DEVICE.is_some() {
+                return Err(ProbeError::Unsupported)
+            }
+
+            let irq = device.interrupt_line;
+
+            if device.interrupt_pin == 0 || irq == LINE_NOT_ROUTED || irq >= 16 {
+                return Err(ProbeError::Failed("interrupt pin is not routed to PIC"))
+            }
+
+            let command = device.command(config) & !COMMAND_INTX_DISABLE;
+            device.set_command(config, command | COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER);
+
+            let transport = PciTransport::from_device(device, bars, config, |address, size| {
+                match globals::map_device_memory(address, size) {
+                    Ok(())     => true,
+                    Err(error) => {
+                        warn!("Failed to map virtio registers at {:#x}: {}", address, error);
+                        false
+                    }
+                }
+            }).map_err(probe_error)?;
+
+            let interrupt_status = transport.interrupt_status();
+            let is_modern        = transport.is_modern();
+
+            let block_device = HEAP_ALLOCATOR.with_slab_allocator(|slab_allocator| {
+                VirtioBlock::new(transport, slab_allocator.frame_allocator(), Completion::Interrupt, &MONOTONIC_CLOCK)
+            }).map_err(probe_error)?;
+
+            info!("Virtio block device ({}) has {} sectors, {} mb{}, IRQ {}",
+                if is_modern { "modern" } else { "legacy" },
+                block_device.sector_count(),
+                block_device.size() / (1024 * 1024),
+                if block_device.is_read_only() { ", read only" } else { "" },
+                irq);
+
+            BLOCK_DEVICE           = Some(block_device);
+            BLOCK_DEVICE_INTERRUPT = Some((interrupt_status, irq));
+
+            INTERRUPT_TABLE.set_interrupt_handler(pic::vector(irq) as usize, handlers::block_device_interrupt_handler);
+            pic::unmask(irq);
+
+            Ok(())
+        }
+    }
+}
+
+fn probe_error(error : VirtioError) -> ProbeError {
+    match error {
+        VirtioError::FeaturesRejected          => ProbeError::Unsupported,
+        VirtioError::QueueUnavailable(_)
+        | VirtioError::InvalidQueueSize(_)     => ProbeError::Failed("request queue can't be set up"),
+        VirtioError::NoMemory                  => ProbeError::Failed("out of memory"),
+        VirtioError::InvalidTransport(message) => ProbeError::Failed(message)
+    }
+}
+
+/// Returns kernel block device, `None` if there is no device
+/// # Why unsafe
+/// Device must have one user at a time
+pub unsafe fn block_device() -> Option<&'static mut dyn BlockDevice> {
+    BLOCK_DEVICE.as_mut().map(|block_device| block_device as &mut dyn BlockDevice)
+}
+
+/// Acknowledges interrupt of the block device, called by its interrupt handler
+/// # Returns
+/// PIC line of the device, `None` if there is no device
+pub fn handle_interrupt() -> Option<u8> {
+    let (interrupt_status, irq) = unsafe { BLOCK_DEVICE_INTERRUPT }?;
+
+    // the driver checks used ring itself, the handler only has to deassert the line
+    if interrupt_status.read() & ISR_CONFIGURATION != 0 {
+        info!("Virtio block device configuration changed");
+    }
+
+    Some(irq)
+}
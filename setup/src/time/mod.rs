@@ -0,0 +1,38 @@
+/*
+    Kernel time. PIT drives timer interrupt with known frequency and every interrupt advances monotonic clock.
+    Wall clock is set once from CMOS real time clock, afterwards calendar time is derived from monotonic time.
+*/
+use core::time::Duration;
+use hardware::x86_64::pit;
+use hardware::x86_64::rtc;
+use stdx::time::DateTime;
+use crate::globals::{MONOTONIC_CLOCK, WALL_CLOCK};
+
+/// Requested frequency of timer interrupt in Hz
+pub const TIMER_FREQUENCY : u32 = 100;
+
+/// Starts monotonic clock and synchronizes wall clock, must be called before interrupts are enabled
+pub unsafe fn initialize() {
+    let divisor = pit::set_frequency(TIMER_FREQUENCY);
+
+    MONOTONIC_CLOCK.set_tick_length(pit::period_nanoseconds(divisor));
+
+    match rtc::read() {
+        Some(now) => {
+            WALL_CLOCK.synchronize(now, uptime());
+
+            info!("Timer runs at {} Hz, real time clock reads {}", pit::BASE_FREQUENCY / divisor, now);
+        },
+        None => warn!("Real time clock gives no valid time, wall clock is not available")
+    }
+}
+
+/// Returns time since timer was started
+pub fn uptime() -> Duration {
+    MONOTONIC_CLOCK.uptime()
+}
+
+/// Returns calendar time, `None` if real time clock wasn't read
+pub fn now() -> Option<DateTime> {
+    WALL_CLOCK.now(uptime())
+}
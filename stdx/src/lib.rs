@@ -1,10 +1,15 @@
 #![no_std]
+#![feature(const_fn)]
 
 pub mod iterator;
 pub mod util;
 pub mod monoid;
 pub mod math;
 pub mod sequence;
+pub mod ring_buffer;
+pub mod log;
+pub mod sync;
+pub mod time;
 
 use core::iter;
 use core::mem;
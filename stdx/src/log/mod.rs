@@ -0,0 +1,212 @@
+/*
+    Building blocks of kernel logging: message levels, per module level filters and
+    in-memory log buffer. Filters are described by `log` option of the kernel command line:
+
+    log=warn,memory=debug,setup::globals=trace
+
+    Bare level sets default level, `module=level` pairs set level of modules whose path starts with `module`.
+    The most specific (longest) matching module wins. Nothing here allocates, thus logging works before heap exists.
+*/
+use core::fmt;
+
+pub const MAX_LOG_FILTERS : usize = 16;
+pub const LOG_BUFFER_SIZE : usize = 16 * 1024;
+
+/// Message importance, messages of levels above the enabled one are dropped
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace
+}
+
+impl Level {
+    pub fn from_name(name : &str) -> Option<Level> {
+        match name {
+            "error" => Some(Level::Error),
+            "warn"  => Some(Level::Warn),
+            "info"  => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _       => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "ERROR",
+            Level::Warn  => "WARN",
+            Level::Info  => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE"
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ModuleFilter {
+    module : &'static str,
+    level  : Level
+}
+
+/// Decides which messages are logged
+pub struct LogFilters {
+    default_level : Level,
+    modules       : [Option<ModuleFilter>; MAX_LOG_FILTERS],
+    modules_count : usize
+}
+
+impl LogFilters {
+
+    pub const fn new(default_level : Level) -> Self {
+        LogFilters {
+            default_level,
+            modules       : [None; MAX_LOG_FILTERS],
+            modules_count : 0
+        }
+    }
+
+    /// Creates filters from value of `log` command line option, unknown levels are ignored
+    /// # Arguments
+    /// * `description` - comma separated list of default level and `module=level` pairs
+    /// * `default_level` - level used if description doesn't set it
+    pub fn parse(description : &'static str, default_level : Level) -> Self {
+        let mut result = LogFilters::new(default_level);
+
+        for filter in description.split(',').filter(|filter| !filter.is_empty()) {
+            let mut parts = filter.splitn(2, '=');
+
+            match (parts.next(), parts.next()) {
+                (Some(level), None) => {
+                    if let Some(level) = Level::from_name(level) {
+                        result.set_default_level(level);
+                    }
+                },
+                (Some(module), Some(level)) => {
+                    if let Some(level) = Level::from_name(level) {
+                        result.add(module, level);
+                    }
+                },
+                _ => ()
+            }
+        }
+
+        result
+    }
+
+    pub fn default_level(&self) -> Level {
+        self.default_level
+    }
+
+    pub fn set_default_level(&mut self, level : Level) {
+        self.default_level = level
+    }
+
+    /// Sets level of modules whose path starts with `module`
+    /// # Returns
+    /// `false` if there is no space for another filter
+    pub fn add(&mut self, module : &'static str, level : Level) -> bool {
+        if self.modules_count == MAX_LOG_FILTERS {
+            false
+        }
+        else {
+            self.modules[self.modules_count] = Some(ModuleFilter { module, level });
+            self.modules_count += 1;
+
+            true
+        }
+    }
+
+    /// Returns max level of messages logged by module
+    /// # Arguments
+    /// * `module_path` - module path as returned by `module_path!`
+    pub fn level_for(&self, module_path : &str) -> Level {
+        self.modules[..self.modules_count]
+            .iter()
+            .filter_map(|filter| *filter)
+            .filter(|filter| LogFilters::matches(filter.module, module_path))
+            .max_by_key(|filter| filter.module.len())
+            .map(|filter| filter.level)
+            .unwrap_or(self.default_level)
+    }
+
+    pub fn is_enabled(&self, level : Level, module_path : &str) -> bool {
+        level <= self.level_for(module_path)
+    }
+
+    // `memory` matches `memory` and `memory::slab`, but not `memory_regions`
+    fn matches(module : &str, module_path : &str) -> bool {
+        module_path.starts_with(module) &&
+            (module_path.len() == module.len() || module_path[module.len()..].starts_with("::"))
+    }
+}
+
+/// Keeps the latest LOG_BUFFER_SIZE bytes of log output, older output is overwritten.
+/// Survives a crash together with kernel memory, so that it can be dumped e.g. by panic handler.
+pub struct LogBuffer {
+    bytes       : [u8; LOG_BUFFER_SIZE],
+    // position of the next write
+    end         : usize,
+    wrapped     : bool
+}
+
+impl LogBuffer {
+
+    pub const fn new() -> Self {
+        LogBuffer {
+            bytes   : [0; LOG_BUFFER_SIZE],
+            end     : 0,
+            wrapped : false
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        if self.wrapped { LOG_BUFFER_SIZE } else { self.end }
+    }
+
+    pub fn clear(&mut self) {
+        self.end     = 0;
+        self.wrapped = false;
+    }
+
+    /// Writes buffer content from the oldest byte to the newest one. Non ASCII bytes are replaced with `?`,
+    /// because the oldest character could have been partially overwritten.
+    pub fn dump<W>(&self, writer : &mut W) -> fmt::Result where W : fmt::Write {
+        let (older, newer) = if self.wrapped {
+            (&self.bytes[self.end..], &self.bytes[..self.end])
+        }
+        else {
+            (&self.bytes[..0], &self.bytes[..self.end])
+        };
+
+        for byte in older.iter().chain(newer.iter()) {
+            writer.write_char(if byte.is_ascii() { *byte as char } else { '?' })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Write for LogBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.bytes[self.end] = byte;
+            self.end += 1;
+
+            if self.end == LOG_BUFFER_SIZE {
+                self.end     = 0;
+                self.wrapped = true;
+            }
+        }
+
+        Ok(())
+    }
+}
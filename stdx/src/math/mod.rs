@@ -47,6 +47,15 @@ fn log_inner(base : usize, x : usize) -> (usize, Ordering) {
     }
 }
 
+pub fn is_power_of_two(x : usize) -> bool {
+    x != 0 && (x & (x - 1)) == 0
+}
+
+/// Aligns `x` upwards to a multiple of `align`, which must be a power of two
+pub fn align_up(x : usize, align : usize) -> usize {
+    (x + align - 1) & !(align - 1)
+}
+
 pub fn is_even(x : usize) -> bool {
     x % 2 == 0
 }
\ No newline at end of file
@@ -0,0 +1,69 @@
+pub const RING_BUFFER_SIZE : usize = 256;
+
+/// Fixed size FIFO queue of bytes that doesn't allocate, thus it can be used
+/// by device drivers before heap exists and from interrupt handlers.
+/// When buffer is full new bytes are dropped.
+pub struct ByteRingBuffer {
+    bytes : [u8; RING_BUFFER_SIZE],
+    // index of the oldest byte
+    head  : usize,
+    count : usize
+}
+
+impl ByteRingBuffer {
+
+    pub const fn new() -> Self {
+        ByteRingBuffer {
+            bytes : [0; RING_BUFFER_SIZE],
+            head  : 0,
+            count : 0
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count == RING_BUFFER_SIZE
+    }
+
+    /// Adds byte to the end of the queue
+    /// # Returns
+    /// `false` if buffer is full and byte was dropped
+    pub fn push(&mut self, byte : u8) -> bool {
+        if self.is_full() {
+            false
+        }
+        else {
+            self.bytes[(self.head + self.count) % RING_BUFFER_SIZE] = byte;
+            self.count += 1;
+
+            true
+        }
+    }
+
+    /// Takes the oldest byte from the queue
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            None
+        }
+        else {
+            let result = self.bytes[self.head];
+
+            self.head   = (self.head + 1) % RING_BUFFER_SIZE;
+            self.count -= 1;
+
+            Some(result)
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.head  = 0;
+        self.count = 0;
+    }
+}
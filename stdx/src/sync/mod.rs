@@ -0,0 +1,90 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering, spin_loop_hint};
+
+/// Mutual exclusion lock that busy waits until it is released.
+/// Doesn't disable interrupts, so it must not be taken by interrupt handlers
+/// if the code it interrupts can hold it.
+pub struct SpinLock<T> {
+    locked : AtomicBool,
+    value  : UnsafeCell<T>
+}
+
+unsafe impl<T> Sync for SpinLock<T> where T : Send {}
+unsafe impl<T> Send for SpinLock<T> where T : Send {}
+
+impl<T> SpinLock<T> {
+
+    pub const fn new(value : T) -> Self {
+        SpinLock {
+            locked : AtomicBool::new(false),
+            value  : UnsafeCell::new(value)
+        }
+    }
+
+    /// Waits until lock is released and takes it
+    /// # Returns
+    /// Guard that releases the lock when dropped
+    pub fn lock(&self) -> SpinLockGuard<T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard
+            }
+
+            // wait without trying to write to lock memory
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop_hint();
+            }
+        }
+    }
+
+    /// Takes the lock if it is free
+    pub fn try_lock(&self) -> Option<SpinLockGuard<T>> {
+        if self.locked.compare_and_swap(false, true, Ordering::Acquire) == false {
+            Some(SpinLockGuard { lock : self })
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// Returns the value without taking the lock
+    /// # Why unsafe
+    /// Value can be accessed concurrently by the lock owner. Meant for the code that can't wait,
+    /// e.g. panic handler that must print even if the lock owner will never release it.
+    pub unsafe fn get_unchecked(&self) -> &mut T {
+        &mut *self.value.get()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+pub struct SpinLockGuard<'a, T : 'a> {
+    lock : &'a SpinLock<T>
+}
+
+impl<'a, T> Deref for SpinLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<'a, T> Drop for SpinLockGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
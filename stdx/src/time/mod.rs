@@ -0,0 +1,168 @@
+/*
+    Kernel time. Monotonic clock counts timer interrupts since boot, it never goes back and doesn't depend on calendar.
+    Wall clock keeps calendar time of the moment the monotonic clock started, so current calendar time is
+    that moment plus uptime. It is synchronized once from the real time clock, which is too slow to read on every call.
+*/
+use core::fmt;
+use core::time::Duration;
+use core::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
+
+const SECONDS_PER_MINUTE : u64 = 60;
+const SECONDS_PER_HOUR   : u64 = 60 * SECONDS_PER_MINUTE;
+const SECONDS_PER_DAY    : u64 = 24 * SECONDS_PER_HOUR;
+const NANOS_PER_SECOND   : u64 = 1_000_000_000;
+
+/// Calendar date and time, UTC
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DateTime {
+    pub year   : u16,
+    pub month  : u8,
+    pub day    : u8,
+    pub hour   : u8,
+    pub minute : u8,
+    pub second : u8
+}
+
+impl DateTime {
+
+    /// Converts seconds since 1970-01-01 00:00:00
+    pub fn from_unix_timestamp(timestamp : u64) -> Self {
+        let (year, month, day) = DateTime::civil_from_days(timestamp / SECONDS_PER_DAY);
+        let seconds_of_day     = timestamp % SECONDS_PER_DAY;
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour   : (seconds_of_day / SECONDS_PER_HOUR) as u8,
+            minute : (seconds_of_day % SECONDS_PER_HOUR / SECONDS_PER_MINUTE) as u8,
+            second : (seconds_of_day % SECONDS_PER_MINUTE) as u8
+        }
+    }
+
+    /// Returns seconds since 1970-01-01 00:00:00, dates before it are not supported
+    pub fn unix_timestamp(&self) -> u64 {
+        DateTime::days_from_civil(self.year as u64, self.month as u64, self.day as u64) * SECONDS_PER_DAY
+            + self.hour as u64 * SECONDS_PER_HOUR
+            + self.minute as u64 * SECONDS_PER_MINUTE
+            + self.second as u64
+    }
+
+    // days since 1970-01-01 of proleptic gregorian date, from "chrono-Compatible Low-Level Date Algorithms" by Howard Hinnant.
+    // Year starts in March, so that leap day is the last day of a year. Era is 400 years.
+    fn days_from_civil(year : u64, month : u64, day : u64) -> u64 {
+        let year          = if month <= 2 { year - 1 } else { year };
+        let era           = year / 400;
+        let year_of_era   = year - era * 400;
+        let month_index   = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year   = (153 * month_index + 2) / 5 + day - 1;
+        let day_of_era    = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+        era * 146097 + day_of_era - 719468
+    }
+
+    fn civil_from_days(days : u64) -> (u16, u8, u8) {
+        let days          = days + 719468;
+        let era           = days / 146097;
+        let day_of_era    = days - era * 146097;
+        let year_of_era   = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year   = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index   = (5 * day_of_year + 2) / 153;
+        let day           = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month         = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+        let year          = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        (year as u16, month as u8, day as u8)
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+/// Time since boot measured in timer ticks
+pub struct MonotonicClock {
+    ticks                : AtomicUsize,
+    nanoseconds_per_tick : AtomicUsize
+}
+
+impl MonotonicClock {
+
+    /// Creates stopped clock, it starts when tick length is set
+    pub const fn new() -> Self {
+        MonotonicClock {
+            ticks                : AtomicUsize::new(0),
+            nanoseconds_per_tick : AtomicUsize::new(0)
+        }
+    }
+
+    /// Sets time between ticks, must be called before the first tick
+    /// # Arguments
+    /// * `nanoseconds` - tick length
+    pub fn set_tick_length(&self, nanoseconds : u64) {
+        self.nanoseconds_per_tick.store(nanoseconds as usize, Ordering::SeqCst)
+    }
+
+    /// Advances clock by one tick, called by timer interrupt handler
+    pub fn tick(&self) {
+        self.ticks.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn ticks(&self) -> usize {
+        self.ticks.load(Ordering::SeqCst)
+    }
+
+    /// Returns time since the first tick
+    pub fn uptime(&self) -> Duration {
+        let nanoseconds = self.ticks() as u64 * self.nanoseconds_per_tick.load(Ordering::SeqCst) as u64;
+
+        Duration::new(nanoseconds / NANOS_PER_SECOND, (nanoseconds % NANOS_PER_SECOND) as u32)
+    }
+}
+
+/// Calendar time computed from monotonic time
+pub struct WallClock {
+    // unix time in seconds at zero uptime
+    boot_timestamp : AtomicUsize,
+    synchronized   : AtomicBool
+}
+
+impl WallClock {
+
+    /// Creates clock that doesn't know calendar time until it is synchronized
+    pub const fn new() -> Self {
+        WallClock {
+            boot_timestamp : AtomicUsize::new(0),
+            synchronized   : AtomicBool::new(false)
+        }
+    }
+
+    /// Sets calendar time
+    /// # Arguments
+    /// * `now` - current calendar time, e.g. read from real time clock
+    /// * `uptime` - monotonic time at the moment `now` was read
+    pub fn synchronize(&self, now : DateTime, uptime : Duration) {
+        let boot_timestamp = now.unix_timestamp().saturating_sub(uptime.as_secs());
+
+        self.boot_timestamp.store(boot_timestamp as usize, Ordering::SeqCst);
+        self.synchronized.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_synchronized(&self) -> bool {
+        self.synchronized.load(Ordering::SeqCst)
+    }
+
+    /// Returns calendar time, `None` if clock is not synchronized
+    /// # Arguments
+    /// * `uptime` - current monotonic time
+    pub fn now(&self, uptime : Duration) -> Option<DateTime> {
+        if self.is_synchronized() {
+            Some(DateTime::from_unix_timestamp(self.boot_timestamp.load(Ordering::SeqCst) as u64 + uptime.as_secs()))
+        }
+        else {
+            None
+        }
+    }
+}
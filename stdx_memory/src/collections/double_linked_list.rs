@@ -408,6 +408,11 @@ impl<T> UsizeLinkedMap<T> where T : Copy {
         self.free_blocks.is_cell()
     }
 
+    /// Returns number of keys present in the map
+    pub fn keys_count(&self) -> usize {
+        self.frame_to_free_buddy.indices().filter(|i| self.has_key(*i)).count()
+    }
+
     /// Sets the block as occupied
     /// # Arguments
     /// * `block_start_address` - start address of memory block
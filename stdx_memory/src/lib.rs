@@ -47,6 +47,24 @@ pub trait MemoryAllocator : MemoryAllocatorMeta {
         self.allocate(mem::size_of::<T>())        
     }    
 
+    /// Allocates memory which address is a multiple of `align`. Default implementation
+    /// only checks the result of `allocate`, allocators that can do better should override it.
+    /// # Arguments
+    /// * `size` - allocation size
+    /// * `align` - alignment, must be a power of two
+    /// # Returns
+    /// None if there is no memory or allocator can't provide memory with such alignment
+    fn allocate_aligned(&mut self, size : usize, align : usize) -> Option<usize> {
+        match self.allocate(size) {
+            Some(pointer) if pointer % align == 0 => Some(pointer),
+            Some(pointer) => {
+                self.free(pointer);
+                None
+            },
+            None => None
+        }
+    }
+
     fn free(&mut self, pointer : usize);
 }
 
@@ -14,6 +14,7 @@ extern crate stdx_memory;
 extern crate stdx;
 extern crate pic8259_simple;
 extern crate multiprocess;
+#[macro_use]
 extern crate setup;
 
 use multiboot::multiboot_header::MultibootHeader;
@@ -37,8 +38,13 @@ use alloc::vec::Vec;
 use malloc::TestAllocator;
 use stdx_memory::collections::immutable::double_linked_list::DoubleLinkedList;
 use memory::allocator::slab::SlabAllocator;
-use memory::allocator::slab::SlabHelp;
-use memory::allocator::buddy::BuddyAllocator;
+use memory::allocator::kernel_heap::KernelHeap;
+use memory::allocator::physical::PhysicalMemoryManager;
+use memory::allocator::statistics::AllocatorStatistics;
+use memory::allocator::oom::OOM_HANDLER;
+use memory::allocator::cache::ObjectCache;
+#[cfg(feature = "heap_debug")]
+use memory::allocator::leaks;
 
 use hardware::x86_64::registers;
 use hardware::x86_64::interrupts;
@@ -59,8 +65,15 @@ use pic8259_simple::ChainedPics;
 
 use setup::interrupts::handlers;
 use setup::globals;
+use setup::log;
+use setup::time;
+use setup::pci;
+use setup::storage;
+use setup::console;
+use setup::shell::{Shell, Program};
 use setup::globals::{
-    VGA_WRITER,
+    CONSOLE,
+    SERIAL_PORT,
     PROCESS_EXECUTOR,
     INTERRUPT_TABLE,
     CHAINED_PICS,
@@ -74,22 +87,54 @@ pub extern "C" fn rust_main(multiboot_header_address: usize) {
 
         let multiboot_header = MultibootHeader::load(multiboot_header_address);
 
-        VGA_WRITER = Some(Writer::new());
+        CONSOLE.initialize();
 
-        //print_multiboot_data(multiboot_header, VGA_WRITERG.as_mut().unwrap());
+        globals::initialize_serial_port();
+
+        log::initialize(multiboot_header);
+
+        info!("Kernel started, multiboot header at {:#x}", multiboot_header_address);
+
+        //CONSOLE.with_writer(|writer| print_multiboot_data(multiboot_header, writer));
 
         let mut frame_allocator = FrameAllocator::new(multiboot_header);
 
         paging::remap_kernel(&mut paging::p4_table(), &mut frame_allocator, multiboot_header);
 
-        let mut slab_allocator = globals::initialize_memory_allocator(&multiboot_header);
+        // framebuffer is mapped by kernel remap, boot output so far is replayed from log buffer
+        if let Some(framebuffer) = console::framebuffer_from_multiboot(multiboot_header) {
+            CONSOLE.initialize_framebuffer(framebuffer);
+            log::dump_log_buffer_to_console();
+
+            info!("Switched to framebuffer console");
+        }
+
+        CONSOLE.with_writer(|writer| paging::dump_address_space(paging::p4_table(), writer));
+
+        globals::initialize_heap(&multiboot_header, &frame_allocator);
+        OOM_HANDLER.set_reporter(report_out_of_memory);
 
-        HEAP_ALLOCATOR.value = ptr::NonNull::new_unchecked(&mut slab_allocator as *mut SlabAllocator);
+        #[cfg(feature = "heap_debug")]
+        leaks::with_subsystem(&ALLOCATOR_TESTS, run_allocator_tests);
+        #[cfg(not(feature = "heap_debug"))]
+        run_allocator_tests();
 
-        memory_allocator_should_properly_allocate_and_free_memory();
+        info!("Heap after allocator tests\n{}", HEAP_ALLOCATOR.statistics());
+        #[cfg(feature = "heap_debug")]
+        info!("Heap held by subsystems\n{}", leaks::report());
+
+        pci::initialize(multiboot_header);
+
+        globals::initialize_keyboard();
+
+        time::initialize();
 
         globals::initialize_interrupt_table();
 
+        // drivers install interrupt handlers, so devices are probed after interrupt table and PIC are set up
+        pci::register_driver(&storage::VIRTIO_BLOCK_DRIVER);
+        pci::probe_devices();
+
         interrupts::load_interrupt_table(&INTERRUPT_TABLE);
 
         let mut executor = Rc::new(cell::UnsafeCell::new(executor::Executor::new()));
@@ -102,8 +147,21 @@ pub extern "C" fn rust_main(multiboot_header_address: usize) {
         let dummy_process = DummyProcess { value : 1000 };
         let dummy_process_state_box = Box::new(dummy_process);
        
-        PROCESS_EXECUTOR.create_process( dummy_process_state_box);
-        PROCESS_EXECUTOR.post_message(0, Box::new(IncreaseCtr { some : 299}));
+        let dummy_process_id = PROCESS_EXECUTOR.try_create_process(dummy_process_state_box)
+            .expect("No memory for dummy process");
+
+        if let Err(error) = PROCESS_EXECUTOR.try_post_message(dummy_process_id, Box::new(IncreaseCtr { some : 299})) {
+            error!("Failed to post message to process {}: {:?}", dummy_process_id, error);
+        }
+
+        let shell_id = PROCESS_EXECUTOR.try_create_process(Box::new(Shell::new(multiboot_header, &SHELL_PROGRAMS)))
+            .expect("No memory for shell process");
+
+        if let Err(error) = PROCESS_EXECUTOR.try_post_message(shell_id, Box::new(process::StartProcess {})) {
+            error!("Failed to start shell: {:?}", error);
+        }
+
+        globals::set_keyboard_listener(shell_id);
 
         /*let mut root_process = process::RootProcess::new(Rc::clone(&executor));
 
@@ -144,12 +202,21 @@ pub extern "C" fn rust_main(multiboot_header_address: usize) {
         paging_translate_page_should_properly_translate_pages(p4_table, slab_allocator.frame_allocator());
         paging_unmap_should_properly_unmap_elements(p4_table, slab_allocator.frame_allocator());
         paging_translate_address_should_properly_translate_virtual_address(p4_table, slab_allocator.frame_allocator());*/
+        // processes run from timer interrupts, shell takes over the interaction
         loop {
-            unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Main thread end loop!"); };
+            interrupts::wait_for_interrupt();
         }
     }
 }
 
+static SHELL_PROGRAMS : [Program; 1] = [
+    Program { name : "dummy", create : create_dummy_process }
+];
+
+fn create_dummy_process() -> process::ProcessBox {
+    Box::new(DummyProcess { value : 1000 })
+}
+
 #[repr(C)]
 pub struct DummyProcess {
 
@@ -167,7 +234,7 @@ impl DummyProcess {
             let vl = msg.some;
             let x = vl;
 
-           unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "I am dummy! inside process 1 {}", x); }
+           info!("I am dummy! inside process 1 {}", x);
         }
     }
 }
@@ -177,7 +244,7 @@ impl Process for DummyProcess {
         unsafe {
             self.process_message1(message);
             loop {
-                // unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Inside dummy end"); }
+                // println!("Inside dummy end");
             }
         }
     }
@@ -197,13 +264,24 @@ pub struct SenderProcess {
 impl Process for SenderProcess {
     fn process_message(&mut self, message: Message) -> () {
         unsafe {
-            writeln!(VGA_WRITER.as_mut().unwrap(), "Sending inc to Id = {}!", self.child.id());
+            info!("Sending inc to Id = {}!", self.child.id());
 
             self.child.post_message(Box::new(IncreaseCtr { some : 1488 }));
         }
     }
 }
 
+#[cfg(feature = "heap_debug")]
+static ALLOCATOR_TESTS : leaks::Subsystem = leaks::Subsystem::new("allocator tests");
+
+fn run_allocator_tests() {
+    memory_allocator_should_properly_allocate_and_free_memory();
+    memory_allocator_should_free_memory_without_size_hint();
+    memory_allocator_should_reallocate_in_place();
+    object_cache_should_return_memory_to_heap_on_shrink();
+    magazines_should_reuse_object_freed_on_the_same_cpu();
+}
+
 fn memory_allocator_should_properly_allocate_and_free_memory() {
     // everything inside inner block will get deleted after block exit
     {
@@ -216,41 +294,42 @@ fn memory_allocator_should_properly_allocate_and_free_memory() {
         }
     }
 
-    let result = unsafe { HEAP_ALLOCATOR.is_fully_free() };
+    let result = HEAP_ALLOCATOR.is_fully_free();
 
     assert_eq!(result, true, "Allocator wasn't fully free after allocating memory in isolated block");
 }
 
-fn preallocate_memory_for_allocator_aux_data_structures(memory_start : usize, memory_end : usize) -> usize {
-    let aux_data_structures_size = SlabAllocator::total_aux_data_structures_size(memory_start, memory_end);
+fn memory_allocator_should_free_memory_without_size_hint() {
+    let result = HEAP_ALLOCATOR.with_slab_allocator(|allocator| {
+        let small_allocation = allocator.allocate(48).expect("No memory for small allocation");
+        let frame_allocation = allocator.allocate(FRAME_SIZE * 2).expect("No memory for frame allocation");
 
-    let premade_bump_end_address  = Frame::address_align_up(memory_start + aux_data_structures_size);
-    let mut premade_bump                = ConstSizeBumpAllocator::from_address(memory_start, premade_bump_end_address, FRAME_SIZE);
+        allocator.free(small_allocation);
+        allocator.free(frame_allocation);
 
-    // |aux structures page tables|aux structures working memory|allocator working memory|
-    // premap memory for memory allocator inner data structures
-    let aux_structures_start_address = premade_bump_end_address + FRAME_SIZE; // next frame
-    let aux_structures_end_address = Frame::address_align_up(aux_structures_start_address + aux_data_structures_size);
+        allocator.is_fully_free()
+    });
 
-    for frame in Frame::range_inclusive(aux_structures_start_address, aux_structures_end_address) {
-        let p4_table = paging::p4_table();
-        p4_table.map_page_1_to_1(frame, page_table::PRESENT | page_table::WRITABLE, &mut premade_bump);
-    }
+    assert_eq!(result, true, "Allocator wasn't fully free after freeing memory without size hint");
+}
 
-    test_allocator_aux_data_structures_memory(aux_structures_start_address, aux_structures_end_address);
+fn memory_allocator_should_reallocate_in_place() {
+    let result = HEAP_ALLOCATOR.with_slab_allocator(|allocator| {
+        let small_allocation = allocator.allocate(40).expect("No memory for small allocation");
+        let same_class       = unsafe { allocator.reallocate(small_allocation, 40, 8, 64) };
+        assert_eq!(same_class, Some(small_allocation), "Allocation was moved while new size fits the same slab class");
 
-    aux_structures_start_address
-}
+        let moved = unsafe { allocator.reallocate(small_allocation, 64, 8, 100) }.expect("No memory to move small allocation");
+        allocator.free(moved);
 
-fn test_allocator_aux_data_structures_memory(aux_structures_start_address : usize, aux_structures_end_address : usize) {
-    for frame in Frame::range_inclusive(aux_structures_start_address, aux_structures_end_address) {
-        let p4_table = paging::p4_table();
-        let present = p4_table.is_present(frame);
+        let frame_allocation = allocator.allocate(FRAME_SIZE).expect("No memory for frame allocation");
+        let grown = unsafe { allocator.reallocate(frame_allocation, FRAME_SIZE, 8, FRAME_SIZE * 2) }.expect("No memory to grow frame allocation");
+        allocator.free(grown);
 
-        unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Is present {}, val {}", frame, present); }
+        allocator.is_fully_free()
+    });
 
-        Frame::zero_frame(&frame);
-    }
+    assert_eq!(result, true, "Allocator wasn't fully free after reallocations");
 }
 
 use core::panic::PanicInfo;
@@ -260,15 +339,71 @@ extern "C" fn eh_personality() {}
 #[lang = "panic_impl"]
 #[no_mangle]
 pub extern "C" fn panic_impl(pi: &PanicInfo) -> ! {
+    // panic may happen while console is locked, don't wait for it
+    CONSOLE.enter_emergency_mode();
 
-    //unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Rust code panicked with {}", pi.message()); }
+    // panic info contains message and location, e.g. address reported by heap debugging checks
+    error!("Rust code panicked with {}", pi);
+
+    // serial port is the only output that can be captured after the crash, give it the latest log output
+    unsafe {
+        if !log::with_logger(|logger| logger.is_sink_enabled(log::Sink::Serial)) {
+            if let Some(serial_port) = SERIAL_PORT.as_mut() {
+                writeln!(serial_port, "Latest log output:");
+                log::dump_log_buffer(serial_port);
+            }
+        }
+    }
 
     loop {}
 }
 #[lang = "oom"]
 #[no_mangle]
 pub extern "C" fn oom(l: Layout) -> ! {
-    loop {}
+    // failure was already reported by OOM_HANDLER, panic handler prints the layout once more and stops
+    panic!("Out of memory: allocation of {} bytes aligned by {} failed", l.size(), l.align())
+}
+
+static mut MESSAGE_CACHE : Option<ObjectCache<IncreaseCtr, &'static KernelHeap>> = None;
+
+fn shrink_message_cache() -> usize {
+    unsafe { MESSAGE_CACHE.as_mut().map(|cache| cache.shrink()).unwrap_or(0) }
+}
+
+fn object_cache_should_return_memory_to_heap_on_shrink() {
+    unsafe {
+        MESSAGE_CACHE = Some(ObjectCache::new("messages", None, None, &HEAP_ALLOCATOR));
+        OOM_HANDLER.register_shrinker(shrink_message_cache);
+
+        let cache   = MESSAGE_CACHE.as_mut().unwrap();
+        let message = cache.allocate_with(IncreaseCtr { some : 1 }).expect("No memory for message cache");
+
+        assert_eq!(message.as_ref().some, 1, "Message cache returned object with wrong value");
+
+        cache.free(message);
+
+        assert_eq!(shrink_message_cache(), cache.slab_size(), "Message cache didn't release its empty slab");
+        assert_eq!(HEAP_ALLOCATOR.is_fully_free(), true, "Heap wasn't fully free after message cache shrink");
+    }
+}
+
+fn magazines_should_reuse_object_freed_on_the_same_cpu() {
+    let layout = Layout::from_size_align(48, 8).unwrap();
+
+    unsafe {
+        let first = alloc::alloc::alloc(layout);
+        alloc::alloc::dealloc(first, layout);
+
+        let second = alloc::alloc::alloc(layout);
+        alloc::alloc::dealloc(second, layout);
+
+        assert_eq!(first, second, "Magazine didn't return object freed on the same CPU");
+        assert_eq!(HEAP_ALLOCATOR.is_fully_free(), true, "Heap wasn't fully free after magazines were drained");
+    }
+}
+
+fn report_out_of_memory(layout : &Layout, statistics : &AllocatorStatistics) {
+    error!("Out of memory: allocation of {} bytes aligned by {} failed\n{}", layout.size(), layout.align(), statistics);
 }
 
 fn print_multiboot_data(multiboot_header : &MultibootHeader, vga_writer : &mut Writer) {
@@ -303,7 +438,7 @@ fn print_multiboot_data(multiboot_header : &MultibootHeader, vga_writer : &mut W
     }
 }
 
-unsafe fn paging_map_should_properly_map_pages(page_table : &mut page_table::P4Table, frame_alloc : &mut BuddyAllocator, vga_writer : &mut Writer) {
+unsafe fn paging_map_should_properly_map_pages(page_table : &mut page_table::P4Table, frame_alloc : &mut PhysicalMemoryManager, vga_writer : &mut Writer) {
 
     let virtual_frame = Frame::from_address(0x400000000000);
     let physical_frame = Frame::from_address(frame_alloc.allocate(FRAME_SIZE).expect("No frames for paging test"));
@@ -325,7 +460,7 @@ unsafe fn paging_map_should_properly_map_pages(page_table : &mut page_table::P4T
     page_table.unmap_page(virtual_frame);
 }
 
-unsafe fn paging_translate_page_should_properly_translate_pages(page_table : &mut page_table::P4Table, frame_alloc : &mut BuddyAllocator) {
+unsafe fn paging_translate_page_should_properly_translate_pages(page_table : &mut page_table::P4Table, frame_alloc : &mut PhysicalMemoryManager) {
     let virtual_frame = Frame::from_address(42 * 512 * 512 * 4096);
     let physical_frame = Frame::from_address(frame_alloc.allocate(FRAME_SIZE).expect("No frames for paging test"));
 
@@ -339,7 +474,7 @@ unsafe fn paging_translate_page_should_properly_translate_pages(page_table : &mu
     page_table.unmap_page(virtual_frame);
 }
 
-unsafe fn paging_translate_address_should_properly_translate_virtual_address(page_table : &mut page_table::P4Table, frame_alloc : &mut BuddyAllocator) {
+unsafe fn paging_translate_address_should_properly_translate_virtual_address(page_table : &mut page_table::P4Table, frame_alloc : &mut PhysicalMemoryManager) {
     let virtual_frame = Frame::from_address(42 * 512 * 512 * 4096);
     let physical_frame = Frame::from_address(frame_alloc.allocate(FRAME_SIZE).expect("No frames for paging test"));
 
@@ -360,7 +495,7 @@ unsafe fn paging_translate_address_should_properly_translate_virtual_address(pag
     page_table.unmap_page(virtual_frame);
 }
 
-unsafe fn paging_unmap_should_properly_unmap_elements(page_table : &mut page_table::P4Table, frame_alloc : &mut BuddyAllocator) {
+unsafe fn paging_unmap_should_properly_unmap_elements(page_table : &mut page_table::P4Table, frame_alloc : &mut PhysicalMemoryManager) {
     let virtual_frame = Frame::from_address(42 * 512 * 512 * 4096);
     let physical_frame = Frame::from_address(frame_alloc.allocate(FRAME_SIZE).expect("No frames for paging test"));
 
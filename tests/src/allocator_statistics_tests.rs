@@ -0,0 +1,61 @@
+use memory::frame::FRAME_SIZE;
+use memory::allocator::statistics::{AllocatorStatistics, SlabClassStatistics};
+
+#[test]
+pub fn slab_class_should_compute_free_objects() {
+    let slab_class = SlabClassStatistics::new(64, 2, 10);
+
+    assert!(slab_class.capacity() == 2 * FRAME_SIZE / 64, "Slab class capacity is {}, expected {}", slab_class.capacity(), 2 * FRAME_SIZE / 64);
+    assert!(slab_class.free_objects() == 2 * FRAME_SIZE / 64 - 10, "Slab class has {} free objects, expected {}", slab_class.free_objects(), 2 * FRAME_SIZE / 64 - 10);
+}
+
+#[test]
+pub fn free_memory_should_sum_blocks_of_all_orders() {
+    let mut statistics = AllocatorStatistics::new(FRAME_SIZE * 16, 0, 0);
+
+    statistics.add_free_blocks(0, 2);
+    statistics.add_free_blocks(2, 1);
+    statistics.add_free_blocks(2, 1);
+
+    assert!(statistics.free_blocks() == &[2, 0, 2], "Free blocks per order are {:?}", statistics.free_blocks());
+    assert!(statistics.free_memory() == FRAME_SIZE * 10, "Free memory is {}, expected {}", statistics.free_memory(), FRAME_SIZE * 10);
+    assert!(statistics.largest_free_block() == FRAME_SIZE * 4, "Largest free block is {}, expected {}", statistics.largest_free_block(), FRAME_SIZE * 4);
+}
+
+#[test]
+pub fn fragmentation_should_be_zero_for_single_free_block() {
+    let mut statistics = AllocatorStatistics::new(FRAME_SIZE * 16, 0, 0);
+
+    statistics.add_free_blocks(4, 1);
+
+    assert!(statistics.fragmentation() == 0, "Fragmentation is {}% when all free memory is one block", statistics.fragmentation());
+}
+
+#[test]
+pub fn fragmentation_should_count_memory_outside_largest_block() {
+    let mut statistics = AllocatorStatistics::new(FRAME_SIZE * 16, FRAME_SIZE * 8, FRAME_SIZE * 8);
+
+    statistics.add_free_blocks(0, 4);
+    statistics.add_free_blocks(2, 1);
+
+    assert!(statistics.fragmentation() == 50, "Fragmentation is {}%, expected 50%", statistics.fragmentation());
+}
+
+#[test]
+pub fn fragmentation_should_be_zero_without_free_memory() {
+    let statistics = AllocatorStatistics::new(FRAME_SIZE * 16, FRAME_SIZE * 16, FRAME_SIZE * 16);
+
+    assert!(statistics.fragmentation() == 0, "Fragmentation is {}% when there is no free memory", statistics.fragmentation());
+    assert!(statistics.largest_free_block() == 0, "Largest free block is {} when there is no free memory", statistics.largest_free_block());
+}
+
+#[test]
+pub fn slab_fragmentation_should_count_free_objects_of_all_classes() {
+    let mut statistics = AllocatorStatistics::new(FRAME_SIZE * 16, 0, 0);
+
+    statistics.add_slab_class(SlabClassStatistics::new(32, 1, FRAME_SIZE / 32));
+    statistics.add_slab_class(SlabClassStatistics::new(1024, 1, 0));
+
+    assert!(statistics.slab_classes().len() == 2, "Statistics have {} slab classes, expected 2", statistics.slab_classes().len());
+    assert!(statistics.slab_fragmentation() == 50, "Slab fragmentation is {}%, expected 50%", statistics.slab_fragmentation());
+}
@@ -2,6 +2,7 @@ use memory::frame::Frame;
 use memory::frame::FRAME_SIZE;
 use stdx::iterator::IteratorExt;
 use stdx::Sequence;
+use stdx::math;
 use stdx_memory::MemoryAllocator;
 use stdx_memory::collections::double_linked_list::{DoubleLinkedList, DoubleLinkedListIterator, BuddyMap};
 use memory::allocator::bump::BumpAllocator;
@@ -211,4 +212,113 @@ pub fn should_return_none_if_allocation_request_is_zero() {
     let result = allocator.allocate(0);
 
     assert!(result.is_none(), "Buddy allocator allocated memory from unknown source for request of size {}", 0)
-}
\ No newline at end of file
+}
+
+// creates allocator over vector memory that is already accessible, so no paging is involved
+fn unmapped_allocator(total_memory : usize) -> (BuddyAllocator, Vec<u8>) {
+    let aux_size   = BuddyAllocator::aux_data_structures_size_for(total_memory);
+    let heap       = vec![0u8; aux_size + total_memory + FRAME_SIZE];
+    let heap_start = Frame::address_align_up(heap.as_ptr() as usize);
+    let allocator  = BuddyAllocator::new_unmapped(heap_start, total_memory, heap_start + aux_size + total_memory - 1);
+
+    (allocator, heap)
+}
+
+#[test]
+pub fn grow_in_place_should_take_free_right_buddy() {
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+
+    let block  = allocator.allocate(FRAME_SIZE).unwrap();
+    let result = allocator.grow_in_place(block, FRAME_SIZE * 4);
+
+    assert!(result, "Buddy allocator failed to grow block {} whose buddies are free", block);
+    assert!(allocator.allocation_size(block) == FRAME_SIZE * 4, "Grown block has size {}, expected {}", allocator.allocation_size(block), FRAME_SIZE * 4);
+
+    let next = allocator.allocate(FRAME_SIZE).unwrap();
+
+    assert!(next == block + FRAME_SIZE * 4, "Buddy allocator returned memory {} that belongs to grown block {}", next, block);
+}
+
+#[test]
+pub fn grow_in_place_should_fail_if_buddy_is_in_use() {
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+
+    let block = allocator.allocate(FRAME_SIZE).unwrap();
+    allocator.allocate(FRAME_SIZE).unwrap();
+
+    let result = allocator.grow_in_place(block, FRAME_SIZE * 2);
+
+    assert!(!result, "Buddy allocator grew block {} over allocated buddy", block);
+    assert!(allocator.allocation_size(block) == FRAME_SIZE, "Block size changed to {} after failed grow", allocator.allocation_size(block));
+}
+
+#[test]
+pub fn grow_in_place_should_fail_for_right_block() {
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+
+    let left  = allocator.allocate(FRAME_SIZE).unwrap();
+    let right = allocator.allocate(FRAME_SIZE).unwrap();
+    allocator.free(left);
+
+    let result = allocator.grow_in_place(right, FRAME_SIZE * 2);
+
+    assert!(!result, "Buddy allocator grew right block {} towards lower addresses", right);
+}
+
+#[test]
+pub fn grow_in_place_should_succeed_if_block_is_big_enough() {
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+
+    let block = allocator.allocate(FRAME_SIZE * 2).unwrap();
+    allocator.allocate(FRAME_SIZE * 2).unwrap();
+
+    let result = allocator.grow_in_place(block, FRAME_SIZE + 1);
+
+    assert!(result, "Buddy allocator failed to grow block {} within its size", block);
+}
+
+#[test]
+pub fn grown_block_should_merge_back_after_free() {
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+
+    let block = allocator.allocate(FRAME_SIZE).unwrap();
+    allocator.grow_in_place(block, FRAME_SIZE * 8);
+    allocator.free(block);
+
+    let result = allocator.allocate(FRAME_SIZE * 16);
+
+    assert!(result == Some(block), "Buddy allocator returned {:?} for all memory after grown block was freed, expected {}", result, block);
+}
+
+#[test]
+pub fn free_blocks_count_should_reflect_split_blocks() {
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+
+    allocator.allocate(FRAME_SIZE).unwrap();
+
+    let free_blocks : Vec<usize> = (0 .. allocator.orders_count()).map(|order| allocator.free_blocks_count(order)).collect();
+
+    assert!(free_blocks == vec![1, 1, 1, 1, 0], "Buddy allocator has free blocks {:?} after a single frame allocation", free_blocks);
+}
+
+#[test]
+pub fn allocate_aligned_should_succeed_if_region_start_is_not_aligned() {
+    let total_memory = FRAME_SIZE * 16;
+    let aux_size     = BuddyAllocator::aux_data_structures_size_for(total_memory);
+    let heap         = vec![0u8; aux_size + total_memory + FRAME_SIZE * 3];
+
+    // one of the starts leaves memory after inner data structures unaligned by 2 frames
+    for offset in [0, FRAME_SIZE].iter() {
+        let heap_start    = math::align_up(heap.as_ptr() as usize, FRAME_SIZE * 2) + offset;
+        let mut allocator = BuddyAllocator::new_unmapped(heap_start, total_memory, heap_start + aux_size + total_memory - 1);
+
+        for align in [FRAME_SIZE * 2, FRAME_SIZE * 4, FRAME_SIZE * 16].iter() {
+            let result = allocator.allocate_aligned(FRAME_SIZE, *align);
+
+            assert!(result.map(|pointer| pointer % align == 0).unwrap_or(false),
+                "Buddy allocator starting at {:#x} returned {:?} for {} bytes aligned allocation", heap_start, result, align);
+
+            allocator.free(result.unwrap());
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use stdx_memory::MemoryAllocator;
+use memory::allocator::bump::BumpAllocator;
+
+#[test]
+pub fn allocate_aligned_should_skip_memory_up_to_alignment() {
+    let heap      = vec![0u8; 512];
+    let heap_addr = (heap.as_ptr() as usize + 63) & !63;
+    let mut allocator = BumpAllocator::from_address(heap_addr, 256);
+
+    let first  = allocator.allocate(10);
+    let second = allocator.allocate_aligned(16, 64);
+
+    assert!(first == Some(heap_addr), "Bump allocator returned {:?} for the first allocation", first);
+    assert!(second == Some(heap_addr + 64), "Bump allocator returned {:?} for 64 bytes aligned allocation, expected {}", second, heap_addr + 64);
+    assert!(allocator.current_pointer() == heap_addr + 80, "Bump allocator pointer is {} after aligned allocation", allocator.current_pointer());
+}
+
+#[test]
+pub fn allocate_aligned_should_fail_if_aligned_block_does_not_fit() {
+    let heap      = vec![0u8; 512];
+    let heap_addr = (heap.as_ptr() as usize + 63) & !63;
+    let mut allocator = BumpAllocator::from_address(heap_addr, 100);
+
+    allocator.allocate(1);
+
+    let result = allocator.allocate_aligned(64, 64);
+
+    assert!(result.is_none(), "Bump allocator returned {:?} for allocation outside of its memory", result);
+}
@@ -0,0 +1,93 @@
+use multiprocess::executor::{Executor, ExecutorError, ProcessState};
+use multiprocess::process::{Message, Process};
+
+struct IdleProcess;
+
+impl Process for IdleProcess {
+    fn process_message(&mut self, _message : Message) -> () {}
+}
+
+#[test]
+pub fn processes_should_list_every_process_with_pending_messages() {
+    let mut executor = Executor::new();
+
+    let first = executor.create_process(Box::new(IdleProcess));
+    let second = executor.create_process(Box::new(IdleProcess));
+
+    executor.try_post_message(second, Box::new(1u32)).unwrap();
+
+    let processes : Vec<_> = executor.processes().map(|p| (p.id, p.state, p.pending_messages)).collect();
+
+    assert!(processes == vec![(first, ProcessState::New, 0), (second, ProcessState::New, 1)], "Executor listed {:?}", processes);
+}
+
+#[test]
+pub fn receive_message_should_return_messages_in_post_order() {
+    let mut executor = Executor::new();
+    let id = executor.create_process(Box::new(IdleProcess));
+
+    executor.try_post_message(id, Box::new(1u32)).unwrap();
+    executor.try_post_message(id, Box::new(2u32)).unwrap();
+
+    let received : Vec<u32> = (0..3)
+        .filter_map(|_| executor.receive_message(id))
+        .map(|message| *message.downcast::<u32>().unwrap())
+        .collect();
+
+    assert!(received == vec![1, 2], "Process received {:?}", received);
+}
+
+#[test]
+pub fn kill_process_should_remove_process_but_not_the_current_one() {
+    let mut executor = Executor::new();
+
+    let current = executor.create_process(Box::new(IdleProcess));
+    let other = executor.create_process(Box::new(IdleProcess));
+
+    executor.schedule_next();
+
+    assert!(executor.current_process_id() == Some(current), "Unexpected current process {:?}", executor.current_process_id());
+    assert!(executor.kill_process(current) == Err(ExecutorError::ProcessIsCurrent(current)), "Current process was killed");
+    assert!(executor.kill_process(other) == Ok(()), "Process wasn't killed");
+    assert!(executor.kill_process(other) == Err(ExecutorError::ProcessNotFound(other)), "Killed process still exists");
+    assert!(executor.processes().all(|p| p.id != other), "Killed process is still listed");
+}
+
+#[test]
+pub fn process_stack_should_stay_in_place_when_other_processes_are_created() {
+    let mut executor = Executor::new();
+    let id = executor.create_process(Box::new(IdleProcess));
+
+    let stack_address = executor.schedule_next().map(|process| process.stack_address()).unwrap();
+
+    // enough processes to split nodes of the map several times
+    for _ in 0..64 {
+        executor.create_process(Box::new(IdleProcess));
+    }
+
+    // round robin comes back to the process after all new ones
+    let new_stack_address = loop {
+        let stack_address = executor.schedule_next().map(|process| process.stack_address()).unwrap();
+
+        if executor.current_process_id() == Some(id) {
+            break stack_address;
+        }
+    };
+
+    assert!(new_stack_address == stack_address, "Stack moved from {:#x} to {:#x}", stack_address, new_stack_address);
+}
+
+#[test]
+pub fn process_that_never_ran_should_be_killed_and_never_scheduled() {
+    let mut executor = Executor::new();
+    let first = executor.create_process(Box::new(IdleProcess));
+    let second = executor.create_process(Box::new(IdleProcess));
+
+    assert!(executor.current_process_id().is_none(), "Process {:?} is current before scheduling", executor.current_process_id());
+    assert!(executor.kill_process(first) == Ok(()), "Process that never ran wasn't killed");
+
+    let scheduled = executor.schedule_next().is_some();
+
+    assert!(scheduled && executor.current_process_id() == Some(second), "Process {:?} was scheduled instead of {}", executor.current_process_id(), second);
+    assert!(executor.schedule_next().is_some() && executor.current_process_id() == Some(second), "Killed process was scheduled");
+}
@@ -77,7 +77,7 @@ fn should_properly_determine_first_memory_area() {
     let addr = bytes.as_ptr() as usize;
     let kernel_heap_addr = kernel_heap.as_ptr() as usize;
     let multiboot_header1 = MultibootHeader::load(addr);
-    let KERNEL_BASIC_HEAP_ALLOCATOR = ConstSizeBumpAllocator::from_address(kernel_heap_addr, 256, 20);
+    let KERNEL_BASIC_HEAP_ALLOCATOR = BumpAllocator::from_address(kernel_heap_addr, 1024);
 
     let frame_allocator = FrameAllocator::new_test(multiboot_header1, KERNEL_BASIC_HEAP_ALLOCATOR);
 
@@ -195,7 +195,7 @@ fn should_properly_determine_kernel_start_and_end_address() {
     let addr = bytes.as_ptr() as usize;
     let kernel_heap_addr = kernel_heap.as_ptr() as usize;
     let multiboot_header1 = MultibootHeader::load(addr);
-    let KERNEL_BASIC_HEAP_ALLOCATOR = ConstSizeBumpAllocator::from_address(kernel_heap_addr, 256, 20);
+    let KERNEL_BASIC_HEAP_ALLOCATOR = BumpAllocator::from_address(kernel_heap_addr, 1024);
 
     let frame_allocator = FrameAllocator::new_test(multiboot_header1, KERNEL_BASIC_HEAP_ALLOCATOR);
     let kernel_start_valid_result = Frame::from_address(0);
@@ -278,7 +278,7 @@ fn should_make_allocation_in_current_memory_area() {
     let kernel_heap_addr = kernel_heap.as_ptr() as usize;
     let multiboot_header1 = MultibootHeader::load(addr);
     unsafe { 
-        let KERNEL_BASIC_HEAP_ALLOCATOR = ConstSizeBumpAllocator::from_address(kernel_heap_addr, 256, 20);
+        let KERNEL_BASIC_HEAP_ALLOCATOR = BumpAllocator::from_address(kernel_heap_addr, 1024);
         let mut frame_allocator = FrameAllocator::new_test(multiboot_header1, KERNEL_BASIC_HEAP_ALLOCATOR);
         let allocation_result1 = frame_allocator.allocate();
         let allocation_result2 = frame_allocator.allocate();
@@ -373,7 +373,7 @@ fn should_move_to_next_memory_area() {
     let kernel_heap_addr = kernel_heap.as_ptr() as usize;
     let multiboot_header1 = MultibootHeader::load(addr);
     unsafe { 
-        let KERNEL_BASIC_HEAP_ALLOCATOR = ConstSizeBumpAllocator::from_address(kernel_heap_addr, 256, 20);
+        let KERNEL_BASIC_HEAP_ALLOCATOR = BumpAllocator::from_address(kernel_heap_addr, 1024);
         
         let mut frame_allocator = FrameAllocator::new_test(multiboot_header1, KERNEL_BASIC_HEAP_ALLOCATOR);        
 
@@ -459,7 +459,7 @@ fn should_return_last_freed_frame() {
     let kernel_heap_addr = kernel_heap.as_ptr() as usize;
     let multiboot_header1 = MultibootHeader::load(addr);
     unsafe { 
-        let KERNEL_BASIC_HEAP_ALLOCATOR = ConstSizeBumpAllocator::from_address(kernel_heap_addr, 1024, 20);
+        let KERNEL_BASIC_HEAP_ALLOCATOR = BumpAllocator::from_address(kernel_heap_addr, 4096);
         
         let mut frame_allocator = FrameAllocator::new_test(multiboot_header1, KERNEL_BASIC_HEAP_ALLOCATOR);        
         let mut allocated_frames = Vec::<Option<Frame>>::new();
@@ -491,4 +491,138 @@ fn should_return_last_freed_frame() {
                 allocated_from_free_list);
         }
     };    
-}
\ No newline at end of file
+}
+// single memory area of 100000 bytes starting at 0, kernel is outside of it
+fn single_memory_area_multiboot() -> [u32; 35] {
+    let elf_section_entry_size = mem::size_of::<elf::ElfSectionHeader>();
+    let elf_size = 5 * mem::size_of::<u32>() + elf_section_entry_size;
+    let memory_map_entry_size = mem::size_of::<memory_map::MemoryMapEntry>();
+    let memory_map_size = 4 * mem::size_of::<u32>() + memory_map_entry_size;
+    let multiboot_size = 2 * mem::size_of::<u32>() + memory_map_size + elf_size;
+
+    [
+        multiboot_size as u32,          // multiboot length
+        1,  // multiboot reserved
+
+        6,  // memory map type
+        memory_map_size as u32,         // memory map size
+        memory_map_entry_size as u32,  // memory map entry size
+        1,   // memory map version
+
+        0,  // [ memory map entry base addr
+        0, // ]
+        100000,  // [ memory map entry length
+        0, // ]
+        1,  // memory map entry type
+        1, // memory map entry reserved
+
+        9,  // elf
+        elf_size as u32,
+        1,   // entries num
+        elf_section_entry_size as u32,
+        1,  //shndx
+
+        1,  //name
+        1,  //section type
+        0,  //[ flags ]
+        0,  //
+        2000000,  //[ address ] //somewhere outside testing zone
+        0,  //
+        0,  // [ offset ]
+        0,  //
+        1000000,  // [ size ]
+        0,  //
+        0,  // link
+        0,  // info
+        0,  // [ address align ]
+        0,  //
+        0,  // [ entry size ]
+        0,   //
+
+        0,  // end tag
+        0,  //
+    ]
+}
+
+#[test]
+fn should_reuse_freed_frame_before_bump_allocation() {
+    let bytes = single_memory_area_multiboot();
+    let aux_memory = [0u8; 1024];
+    let multiboot_header = MultibootHeader::load(bytes.as_ptr() as usize);
+    let mut frame_allocator = FrameAllocator::new_test(multiboot_header, BumpAllocator::from_address(aux_memory.as_ptr() as usize, 1024));
+
+    frame_allocator.allocate();
+    let freed_frame = frame_allocator.allocate().unwrap();
+    frame_allocator.allocate();
+
+    frame_allocator.deallocate(freed_frame);
+
+    assert!(frame_allocator.free_frames_count() == 1, "Freed frame wasn't placed into free frame stack. Frame allocator fields {}", frame_allocator);
+
+    let reused_frame = frame_allocator.allocate().unwrap();
+    let bumped_frame = frame_allocator.allocate().unwrap();
+
+    assert!(reused_frame == freed_frame,
+        "Allocator didn't reuse freed frame. Returned frame {}, but should be {}",
+        reused_frame,
+        freed_frame);
+
+    assert!(bumped_frame == Frame::from_address(3 * FRAME_SIZE),
+        "Allocator didn't continue bump allocation after free frame stack became empty. Returned frame {}",
+        bumped_frame);
+}
+
+#[test]
+#[should_panic(expected = "is already free")]
+fn deallocate_should_panic_on_double_free() {
+    let bytes = single_memory_area_multiboot();
+    let aux_memory = [0u8; 1024];
+    let multiboot_header = MultibootHeader::load(bytes.as_ptr() as usize);
+    let mut frame_allocator = FrameAllocator::new_test(multiboot_header, BumpAllocator::from_address(aux_memory.as_ptr() as usize, 1024));
+
+    let frame = frame_allocator.allocate().unwrap();
+
+    frame_allocator.deallocate(frame);
+    frame_allocator.deallocate(frame);
+}
+
+#[test]
+fn used_frames_should_leave_out_freed_frames() {
+    let bytes = single_memory_area_multiboot();
+    let aux_memory = [0u8; 1024];
+    let multiboot_header = MultibootHeader::load(bytes.as_ptr() as usize);
+    let mut frame_allocator = FrameAllocator::new_test(multiboot_header, BumpAllocator::from_address(aux_memory.as_ptr() as usize, 1024));
+
+    let frames : Vec<Frame> = (0..4).map(|_| frame_allocator.allocate().unwrap()).collect();
+
+    frame_allocator.deallocate(frames[1]);
+
+    let used_frames : Vec<(usize, usize)> = frame_allocator.used_frames().collect();
+    let expected = vec![(0, FRAME_SIZE - 1), (2 * FRAME_SIZE, 4 * FRAME_SIZE - 1)];
+
+    assert!(used_frames == expected,
+        "Used frames were {:?}, but should be {:?}",
+        used_frames,
+        expected);
+}
+
+#[test]
+fn aux_memory_size_should_fit_free_frame_stack_aligned_after_unaligned_start() {
+    let bytes = single_memory_area_multiboot();
+    let multiboot_header = MultibootHeader::load(bytes.as_ptr() as usize);
+    let memory_map = multiboot_header.read_tag::<memory_map::MemoryMap>().unwrap();
+    let aux_memory_size = FrameAllocator::aux_memory_size(memory_map);
+    let aux_memory = vec![0u8; aux_memory_size + 8];
+    // start right after an aligned address, so that the stack needs the most padding
+    let aux_start = ((aux_memory.as_ptr() as usize + 7) & !7) + 1;
+    let mut frame_allocator = FrameAllocator::new_test(multiboot_header, BumpAllocator::from_address(aux_start, aux_memory_size));
+
+    let frames : Vec<Frame> = (0..3).map(|_| frame_allocator.allocate().unwrap()).collect();
+
+    for frame in frames.iter() {
+        frame_allocator.deallocate(*frame);
+    }
+
+    assert!(frame_allocator.free_frames_count() == 3, "Free frame stack holds {} frames, expected 3", frame_allocator.free_frames_count());
+    assert!(frame_allocator.allocate() == Some(frames[2]), "Free frame stack didn't return the last freed frame");
+}
@@ -0,0 +1,85 @@
+use display::framebuffer::{Framebuffer, PixelFormat, Rgb};
+use display::framebuffer::console::{FramebufferConsole, CELL_WIDTH, CELL_HEIGHT};
+use multiboot::multiboot_header::MultibootHeader;
+use multiboot::multiboot_header::tags::framebuffer::{FramebufferInfo, FramebufferType, RgbFormat};
+use std::fmt::Write;
+use std::ptr;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+// lines are longer than visible pixels
+const PITCH: usize = WIDTH * 4 + 16;
+
+#[test]
+pub fn framebuffer_tag_should_be_read_from_multiboot_header() {
+    let mut bytes = [0u64; 8];
+
+    unsafe {
+        let base = bytes.as_mut_ptr() as *mut u8;
+
+        ptr::write(base as *mut u32, 56);                      // multiboot length
+        ptr::write(base.offset(8) as *mut u32, 8);             // framebuffer tag type
+        ptr::write(base.offset(12) as *mut u32, 38);           // framebuffer tag size, end tag starts at 8 bytes boundary
+        ptr::write(base.offset(16) as *mut u64, 0xfd00_0000);  // address
+        ptr::write(base.offset(24) as *mut u32, 4096);         // pitch
+        ptr::write(base.offset(28) as *mut u32, 1024);         // width
+        ptr::write(base.offset(32) as *mut u32, 768);          // height
+        *base.offset(36) = 32;                                 // bits per pixel
+        *base.offset(37) = 1;                                  // rgb type
+        ptr::copy_nonoverlapping([16u8, 8, 8, 8, 0, 8].as_ptr(), base.offset(40), 6);
+        // end tag is left zeroed
+    }
+
+    let multiboot_header = MultibootHeader::load(bytes.as_ptr() as usize);
+    let tag = multiboot_header.read_tag::<FramebufferInfo>().expect("Framebuffer tag wasn't found");
+    let expected_format = RgbFormat { red_position: 16, red_size: 8, green_position: 8, green_size: 8, blue_position: 0, blue_size: 8 };
+
+    assert!(tag.address() == 0xfd00_0000, "Framebuffer address is {:#x}", tag.address());
+    assert!((tag.width(), tag.height(), tag.bits_per_pixel()) == (1024, 768, 32), "Framebuffer mode is {}", tag);
+    assert!(tag.size() == 4096 * 768, "Framebuffer size is {}", tag.size());
+    assert!(tag.framebuffer_type() == Some(FramebufferType::Rgb), "Framebuffer type is {:?}", tag.framebuffer_type());
+    assert!(tag.rgb_format() == Some(expected_format), "Rgb format is {:?}", tag.rgb_format());
+}
+
+#[test]
+pub fn pixel_format_should_truncate_channels_to_their_size() {
+    let rgb565 = PixelFormat { red_position: 11, red_size: 5, green_position: 5, green_size: 6, blue_position: 0, blue_size: 5 };
+
+    assert!(rgb565.encode(Rgb::new(0xff, 0xff, 0xff)) == 0xffff, "White is {:#x} in rgb565", rgb565.encode(Rgb::new(0xff, 0xff, 0xff)));
+    assert!(rgb565.encode(Rgb::new(0x08, 0x04, 0x08)) == 0x0821, "Color is {:#x} in rgb565", rgb565.encode(Rgb::new(0x08, 0x04, 0x08)));
+    assert!(PixelFormat::bgr32().encode(Rgb::new(0x12, 0x34, 0x56)) == 0x123456, "Color is wrong in bgr32");
+}
+
+#[test]
+pub fn fill_rect_should_be_clipped_by_screen() {
+    let mut framebuffer = test_framebuffer();
+    let red = PixelFormat::bgr32().encode(Rgb::new(0xff, 0, 0));
+
+    framebuffer.fill_rect(WIDTH - 2, HEIGHT - 2, 10, 10, Rgb::new(0xff, 0, 0));
+
+    assert!(framebuffer.pixel(WIDTH - 1, HEIGHT - 1) == red, "Rectangle wasn't drawn");
+    assert!(framebuffer.pixel(WIDTH - 3, HEIGHT - 1) == 0, "Pixel to the left of rectangle was drawn");
+}
+
+#[test]
+pub fn console_should_draw_glyphs_and_scroll() {
+    let mut console = FramebufferConsole::new(test_framebuffer());
+    let green = PixelFormat::bgr32().encode(Rgb::new(0x00, 0xaa, 0x00));
+
+    assert!(console.size() == (WIDTH / CELL_WIDTH, HEIGHT / CELL_HEIGHT), "Console size is {:?}", console.size());
+
+    // the last line of '_' glyph is set
+    write!(console, "_").unwrap();
+    assert!(console.framebuffer().pixel(0, CELL_HEIGHT - 1) == green, "Glyph wasn't drawn");
+
+    write!(console, "\n\n").unwrap();
+    assert!(console.cursor_position() == (1, 0), "Cursor is at {:?} after scroll", console.cursor_position());
+    assert!(console.framebuffer().pixel(0, CELL_HEIGHT - 1) == 0, "Screen wasn't scrolled");
+}
+
+fn test_framebuffer() -> Framebuffer {
+    let memory = vec![0u32; PITCH * HEIGHT / 4].into_boxed_slice();
+    let address = Box::into_raw(memory) as *mut u32 as usize;
+
+    unsafe { Framebuffer::new(address, PITCH, WIDTH, HEIGHT, 32, PixelFormat::bgr32()) }
+}
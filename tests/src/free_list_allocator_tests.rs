@@ -1,5 +1,6 @@
 use stdx_memory::MemoryAllocator;
 use memory::allocator::free_list::FreeListAllocator;
+use memory::allocator::bump::ConstSizeBumpAllocator;
 
 macro_rules! heap_raw {
     ($x:expr) => {{
@@ -80,4 +81,33 @@ pub fn allocator_should_not_allocate_if_there_is_no_more_place_to_allocate() {
     assert!(result.is_none(), "FreeList allocator allocated memory from unknown source. Test buffer has size = {}, when block size is {}",
         0,
         10);
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn allocate_aligned_should_return_aligned_block() {
+    let heap       = vec![0u8; 1024];
+    let free_list  = vec![0u8; 1024];
+    let heap_addr  = (heap.as_ptr() as usize + 63) & !63;
+    let mut allocator = FreeListAllocator::from_size_with_separate_free_list(
+        ConstSizeBumpAllocator::from_size(heap_addr, 64 * 4, 64),
+        free_list.as_ptr() as usize);
+
+    let result = allocator.allocate_aligned(64, 64);
+
+    assert!(result.map(|r| r % 64 == 0).unwrap_or(false), "FreeList allocator returned {:?} for 64 bytes aligned allocation", result);
+}
+
+#[test]
+pub fn allocate_aligned_should_keep_block_if_alignment_is_impossible() {
+    let heap       = vec![0u8; 1024];
+    let free_list  = vec![0u8; 1024];
+    let heap_addr  = ((heap.as_ptr() as usize + 63) & !63) + 8;
+    let mut allocator = FreeListAllocator::from_size_with_separate_free_list(
+        ConstSizeBumpAllocator::from_size(heap_addr, 64 * 4, 64),
+        free_list.as_ptr() as usize);
+
+    let result = allocator.allocate_aligned(64, 64);
+
+    assert!(result.is_none(), "FreeList allocator returned {:?} for impossible alignment", result);
+    assert!(allocator.allocate(64) == Some(heap_addr), "Block wasn't returned to FreeList allocator after failed aligned allocation");
+}
@@ -0,0 +1,116 @@
+use memory::allocator::debug;
+use memory::allocator::leaks;
+
+// returns block start aligned by `align` inside of `heap`
+fn block_in(heap : &Vec<u8>, align : usize) -> usize {
+    (heap.as_ptr() as usize + align - 1) & !(align - 1)
+}
+
+#[test]
+pub fn allocation_should_be_aligned_and_surrounded_by_red_zone() {
+    let heap  = vec![0u8; 512];
+    let block = block_in(&heap, 64);
+
+    let pointer = unsafe { debug::on_allocate(block, 10, 64) };
+
+    assert!(pointer % 64 == 0, "Debug allocation {:#x} isn't aligned by 64", pointer);
+    assert!(pointer + 10 + debug::RED_ZONE_SIZE <= block + debug::block_size_for(10, 64), "Debug allocation {:#x} doesn't fit into its block", pointer);
+    assert!(unsafe { debug::allocation_size(pointer) } == 10, "Debug allocation size is {}, expected 10", unsafe { debug::allocation_size(pointer) });
+
+    for i in 0..debug::RED_ZONE_SIZE {
+        let leading  = unsafe { *((pointer - debug::RED_ZONE_SIZE + i) as *const u8) };
+        let trailing = unsafe { *((pointer + 10 + i) as *const u8) };
+
+        assert!(leading == debug::RED_ZONE_BYTE, "Leading red zone byte {} is {:#x}, expected {:#x}", i, leading, debug::RED_ZONE_BYTE);
+        assert!(trailing == debug::RED_ZONE_BYTE, "Red zone byte {} is {:#x}, expected {:#x}", i, trailing, debug::RED_ZONE_BYTE);
+    }
+}
+
+#[test]
+pub fn free_should_return_block_start_and_poison_memory() {
+    let heap  = vec![0u8; 512];
+    let block = block_in(&heap, 8);
+
+    let pointer = unsafe { debug::on_allocate(block, 32, 8) };
+    let result  = unsafe { debug::on_free(pointer) };
+
+    assert!(result == block, "Debug free returned {:#x}, expected block start {:#x}", result, block);
+
+    for i in 0..32 {
+        let value = unsafe { *((pointer + i) as *const u8) };
+        assert!(value == debug::POISON_BYTE, "Freed byte {} is {:#x}, expected poison {:#x}", i, value, debug::POISON_BYTE);
+    }
+}
+
+#[test]
+#[should_panic(expected = "double free")]
+pub fn free_should_detect_double_free() {
+    let heap  = vec![0u8; 512];
+    let block = block_in(&heap, 8);
+
+    unsafe {
+        let pointer = debug::on_allocate(block, 32, 8);
+        debug::on_free(pointer);
+        debug::on_free(pointer);
+    }
+}
+
+#[test]
+#[should_panic(expected = "buffer overflow")]
+pub fn free_should_detect_write_past_the_end() {
+    let heap  = vec![0u8; 512];
+    let block = block_in(&heap, 8);
+
+    unsafe {
+        let pointer = debug::on_allocate(block, 32, 8);
+        *((pointer + 32) as *mut u8) = 0;
+        debug::on_free(pointer);
+    }
+}
+
+#[test]
+#[should_panic(expected = "buffer underflow")]
+pub fn free_should_detect_write_before_the_start() {
+    let heap  = vec![0u8; 512];
+    let block = block_in(&heap, 8);
+
+    unsafe {
+        let pointer = debug::on_allocate(block, 32, 8);
+        *((pointer - 1) as *mut u8) = 0;
+        debug::on_free(pointer);
+    }
+}
+
+#[test]
+#[should_panic(expected = "wasn't allocated")]
+pub fn free_should_detect_unknown_address() {
+    let heap  = vec![0u8; 512];
+    let block = block_in(&heap, 8);
+
+    unsafe { debug::on_free(block + 64); }
+}
+
+static LEAKING_SUBSYSTEM : leaks::Subsystem = leaks::Subsystem::new("leaking");
+
+#[test]
+pub fn allocations_should_be_charged_to_subsystem_until_freed() {
+    let heap   = vec![0u8; 512];
+    let first  = block_in(&heap, 8);
+    let second = first + debug::block_size_for(40, 8);
+
+    let (leaked, freed) = leaks::with_subsystem(&LEAKING_SUBSYSTEM, || unsafe {
+        (debug::on_allocate(first, 40, 8), debug::on_allocate(second, 24, 8))
+    });
+
+    assert!(LEAKING_SUBSYSTEM.objects() == 2 && LEAKING_SUBSYSTEM.bytes() == 64,
+            "Subsystem holds {} objects, {} b after two allocations", LEAKING_SUBSYSTEM.objects(), LEAKING_SUBSYSTEM.bytes());
+
+    // free outside of the subsystem still uncharges it
+    unsafe { debug::on_free(freed); }
+
+    assert!(LEAKING_SUBSYSTEM.objects() == 1 && LEAKING_SUBSYSTEM.bytes() == 40,
+            "Subsystem holds {} objects, {} b after free", LEAKING_SUBSYSTEM.objects(), LEAKING_SUBSYSTEM.bytes());
+    assert!(leaks::report().subsystems().any(|subsystem| subsystem.name() == "leaking"), "Subsystem is missing from leak report");
+
+    unsafe { debug::on_free(leaked); }
+}
@@ -0,0 +1,25 @@
+use memory::allocator::kernel_heap::KernelHeap;
+use std::alloc::{GlobalAlloc, Layout};
+
+#[test]
+pub fn new_heap_should_not_be_initialized() {
+    let heap = KernelHeap::new();
+
+    assert!(!heap.is_initialized(), "Heap was initialized without memory");
+}
+
+#[test]
+#[should_panic(expected = "Kernel heap is used before initialization")]
+pub fn allocation_before_initialization_should_panic() {
+    let heap = KernelHeap::new();
+
+    unsafe { heap.alloc(Layout::from_size_align(64, 8).unwrap()); }
+}
+
+#[test]
+#[should_panic(expected = "Kernel heap is used before initialization")]
+pub fn statistics_before_initialization_should_panic() {
+    let heap = KernelHeap::new();
+
+    heap.statistics();
+}
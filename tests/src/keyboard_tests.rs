@@ -0,0 +1,63 @@
+use hardware::x86_64::keyboard::{Keyboard, INPUT_QUEUE_SIZE};
+use hardware::x86_64::keyboard::keymap::{Keymap, UsKeymap};
+use hardware::x86_64::keyboard::scancode::*;
+
+#[test]
+pub fn set1_decoder_should_track_shift() {
+    let mut decoder = ScancodeDecoder::new(ScancodeSet::Set1);
+
+    assert!(decoder.feed(0x2a).map(|e| e.code) == Some(KeyCode::LeftShift), "Left shift wasn't decoded");
+
+    let event = decoder.feed(0x1e).expect("A press wasn't decoded");
+    assert!(event == KeyEvent { code : KeyCode::A, state : KeyState::Pressed, modifiers : SHIFT }, "A press was decoded as {:?}", event);
+
+    let event = decoder.feed(0x9e).expect("A release wasn't decoded");
+    assert!(event.state == KeyState::Released, "A release was decoded as {:?}", event);
+
+    decoder.feed(0xaa);
+    assert!(decoder.modifiers().is_empty(), "Shift is still active after release: {:?}", decoder.modifiers());
+}
+
+#[test]
+pub fn set2_decoder_should_decode_extended_release() {
+    let mut decoder = ScancodeDecoder::new(ScancodeSet::Set2);
+
+    assert!(decoder.feed(0xe0).is_none(), "Extended prefix produced an event");
+    assert!(decoder.feed(0xf0).is_none(), "Break prefix produced an event");
+
+    let event = decoder.feed(0x75).expect("Arrow up release wasn't decoded");
+    assert!(event.code == KeyCode::ArrowUp && event.state == KeyState::Released, "Arrow up release was decoded as {:?}", event);
+
+    let event = decoder.feed(0x75).expect("Keypad 8 wasn't decoded");
+    assert!(event.code == KeyCode::Keypad8 && event.state == KeyState::Pressed, "Prefixes weren't reset, decoded {:?}", event);
+}
+
+#[test]
+pub fn us_keymap_should_apply_shift_and_caps_lock() {
+    let keymap = UsKeymap;
+
+    assert!(keymap.character(KeyCode::A, Modifiers::empty()) == Some('a'), "Unshifted A is wrong");
+    assert!(keymap.character(KeyCode::A, SHIFT) == Some('A'), "Shifted A is wrong");
+    assert!(keymap.character(KeyCode::A, SHIFT | CAPS_LOCK) == Some('a'), "Shift didn't cancel caps lock");
+    assert!(keymap.character(KeyCode::Digit1, CAPS_LOCK) == Some('1'), "Caps lock changed digit");
+    assert!(keymap.character(KeyCode::Digit1, SHIFT) == Some('!'), "Shifted 1 is wrong");
+    assert!(keymap.character(KeyCode::Keypad5, Modifiers::empty()) == None, "Keypad produced digit without num lock");
+    assert!(keymap.character(KeyCode::F1, Modifiers::empty()) == None, "F1 produced character");
+}
+
+#[test]
+pub fn keyboard_should_drop_input_when_queue_is_full() {
+    let mut keyboard = Keyboard::new(UsKeymap);
+
+    let input = keyboard.handle_byte(0x1e).expect("A press wasn't decoded");
+    assert!(input.character == Some('a'), "A press produced {:?}", input.character);
+
+    let input = keyboard.handle_byte(0x9e).expect("A release wasn't decoded");
+    assert!(input.character == None, "Key release produced character {:?}", input.character);
+
+    for _ in 0..INPUT_QUEUE_SIZE {
+        keyboard.handle_byte(0x1e);
+    }
+
+    assert!(keyboard.dropped_inputs() == 2, "Keyboard dropped {} inputs, expected 2", keyboard.dropped_inputs());
+}
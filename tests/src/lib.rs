@@ -4,6 +4,10 @@ extern crate memory;
 extern crate multiboot;
 extern crate stdx_memory;
 extern crate stdx;
+extern crate display;
+extern crate hardware;
+extern crate multiprocess;
+extern crate drivers;
 extern crate alloc;
 
 #[cfg(test)]
@@ -12,5 +16,25 @@ mod frame_allocator_tests;
 mod linked_list_tests;
 mod double_linked_list_tests;
 mod free_list_allocator_tests;
+mod bump_allocator_tests;
 mod buddy_free_list_tests;
 mod buddy_allocator_tests;
+mod page_table_tests;
+mod memory_regions_tests;
+mod physical_memory_manager_tests;
+mod allocator_statistics_tests;
+#[cfg(feature = "heap_debug")]
+mod heap_debug_tests;
+mod oom_tests;
+mod object_cache_tests;
+mod magazine_tests;
+mod kernel_heap_tests;
+mod ring_buffer_tests;
+mod log_tests;
+mod vga_writer_tests;
+mod framebuffer_tests;
+mod keyboard_tests;
+mod executor_tests;
+mod time_tests;
+mod pci_tests;
+mod virtio_tests;
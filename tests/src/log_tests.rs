@@ -0,0 +1,74 @@
+use stdx::log::{Level, LogFilters, LogBuffer, LOG_BUFFER_SIZE};
+use multiboot::multiboot_header::MultibootHeader;
+use multiboot::multiboot_header::tags::command_line::CommandLine;
+use std::fmt::Write;
+use std::ptr;
+
+#[test]
+pub fn parse_should_read_default_level_and_module_levels() {
+    let filters = LogFilters::parse("warn,memory=debug,setup::globals=trace", Level::Info);
+
+    assert!(filters.default_level() == Level::Warn, "Default level is {}, expected WARN", filters.default_level());
+    assert!(filters.level_for("memory::allocator::slab") == Level::Debug, "Level of memory submodule is {}", filters.level_for("memory::allocator::slab"));
+    assert!(filters.level_for("setup::globals") == Level::Trace, "Level of setup::globals is {}", filters.level_for("setup::globals"));
+    assert!(filters.level_for("setup::interrupts") == Level::Warn, "Level of module without filter is {}", filters.level_for("setup::interrupts"));
+}
+
+#[test]
+pub fn parse_should_ignore_unknown_levels() {
+    let filters = LogFilters::parse("loud,memory=verbose", Level::Info);
+
+    assert!(filters.default_level() == Level::Info, "Default level was changed by unknown level");
+    assert!(filters.level_for("memory") == Level::Info, "Module level was set to unknown level");
+}
+
+#[test]
+pub fn the_most_specific_module_filter_should_win() {
+    let mut filters = LogFilters::new(Level::Info);
+
+    filters.add("memory::allocator", Level::Error);
+    filters.add("memory", Level::Trace);
+
+    assert!(!filters.is_enabled(Level::Warn, "memory::allocator::buddy"), "Less specific filter was used");
+    assert!(filters.is_enabled(Level::Trace, "memory::paging"), "Module filter wasn't used");
+    assert!(filters.is_enabled(Level::Info, "memory_regions"), "Filter matched module with the same name prefix");
+    assert!(!filters.is_enabled(Level::Debug, "memory_regions"), "Filter matched module with the same name prefix");
+}
+
+#[test]
+pub fn log_buffer_should_keep_the_latest_output() {
+    let mut buffer = LogBuffer::new();
+
+    for _ in 0..LOG_BUFFER_SIZE / 4 {
+        write!(buffer, "old ").unwrap();
+    }
+
+    write!(buffer, "new!").unwrap();
+
+    let mut dump = String::new();
+    buffer.dump(&mut dump).unwrap();
+
+    assert!(dump.len() == LOG_BUFFER_SIZE, "Dump length is {}, expected {}", dump.len(), LOG_BUFFER_SIZE);
+    assert!(dump.starts_with("old old"), "Dump doesn't start with the oldest output that was kept");
+    assert!(dump.ends_with("old new!"), "Dump doesn't end with the latest output");
+}
+
+#[test]
+pub fn command_line_option_should_return_option_value() {
+    let command_line = b"root=sda log=warn,memory=debug\0";
+    let tag_size = 2 * 4 + command_line.len();
+    let mut bytes = [0u32; 16];
+
+    bytes[0] = (2 * 4 + 40 + 8) as u32; // multiboot length
+    bytes[2] = 1;                      // command line tag type
+    bytes[3] = tag_size as u32;        // command line tag size, the next tag starts at 8 bytes boundary
+    unsafe { ptr::copy_nonoverlapping(command_line.as_ptr(), (bytes.as_mut_ptr() as *mut u8).offset(16), command_line.len()); }
+    // end tag is left zeroed
+
+    let multiboot_header = MultibootHeader::load(bytes.as_ptr() as usize);
+    let tag = multiboot_header.read_tag::<CommandLine>().expect("Command line tag wasn't found");
+
+    assert!(tag.command_line() == "root=sda log=warn,memory=debug", "Command line was {}", tag.command_line());
+    assert!(tag.option("log") == Some("warn,memory=debug"), "Log option was {:?}", tag.option("log"));
+    assert!(tag.option("init") == None, "Missing option was found");
+}
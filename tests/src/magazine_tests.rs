@@ -0,0 +1,277 @@
+use memory::allocator::magazine::{MagazineAllocator, CpuContext, MAGAZINE_SIZE, MAX_CPUS};
+use memory::allocator::free_list::FreeListAllocator;
+use memory::allocator::bump::ConstSizeBumpAllocator;
+use stdx_memory::{MemoryAllocator, MemoryAllocatorMeta};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+
+const BLOCK_SIZE   : usize = 2048;
+const BLOCKS_COUNT : usize = 512;
+
+// depot that counts calls, so that tests can tell if request reached it
+struct CountingAllocator {
+    inner       : FreeListAllocator,
+    allocations : usize,
+    frees       : usize,
+    _memory     : Vec<u8>
+}
+
+unsafe impl Send for CountingAllocator {}
+
+impl CountingAllocator {
+    fn new() -> Self {
+        let memory    = vec![0u8; (BLOCKS_COUNT + 1) * BLOCK_SIZE + FreeListAllocator::aux_data_structures_size_for(BLOCKS_COUNT * BLOCK_SIZE, BLOCK_SIZE)];
+        let start     = (memory.as_ptr() as usize + BLOCK_SIZE - 1) & !(BLOCK_SIZE - 1);
+        let free_list = start + BLOCKS_COUNT * BLOCK_SIZE;
+
+        CountingAllocator {
+            inner       : FreeListAllocator::from_size_with_separate_free_list(ConstSizeBumpAllocator::from_size(start, BLOCKS_COUNT * BLOCK_SIZE, BLOCK_SIZE), free_list),
+            allocations : 0,
+            frees       : 0,
+            _memory     : memory
+        }
+    }
+
+    fn objects_in_use(&self) -> usize {
+        self.allocations - self.frees
+    }
+}
+
+impl MemoryAllocatorMeta for CountingAllocator {
+    fn start_address(&self) -> usize {
+        self.inner.start_address()
+    }
+
+    fn end_address(&self) -> usize {
+        self.inner.end_address()
+    }
+
+    fn aux_data_structures_size(&self) -> usize {
+        self.inner.aux_data_structures_size()
+    }
+}
+
+impl MemoryAllocator for CountingAllocator {
+    fn allocate(&mut self, size : usize) -> Option<usize> {
+        let result = self.inner.allocate(size);
+
+        if result.is_some() {
+            self.allocations += 1;
+        }
+
+        result
+    }
+
+    fn free(&mut self, pointer : usize) {
+        self.frees += 1;
+        self.inner.free(pointer)
+    }
+}
+
+// CPU that is switched by the test through the shared cell
+struct FakeCpu {
+    current : Rc<Cell<usize>>
+}
+
+impl CpuContext for FakeCpu {
+    fn cpu_id(&self) -> usize {
+        self.current.get()
+    }
+}
+
+thread_local! {
+    static THREAD_CPU_ID : Cell<usize> = Cell::new(0);
+}
+
+// every thread plays a separate CPU
+struct ThreadCpu;
+
+impl CpuContext for ThreadCpu {
+    fn cpu_id(&self) -> usize {
+        THREAD_CPU_ID.with(|id| id.get())
+    }
+}
+
+fn fake_cpu_allocator() -> MagazineAllocator<CountingAllocator, FakeCpu> {
+    MagazineAllocator::new(CountingAllocator::new(), FakeCpu { current : Rc::new(Cell::new(0)) })
+}
+
+#[test]
+pub fn allocation_should_reuse_object_freed_on_the_same_cpu() {
+    let allocator = fake_cpu_allocator();
+
+    let first = allocator.allocate(48, 8).unwrap();
+    allocator.free(first, 48, 8);
+
+    let depot_allocations = allocator.depot().allocations;
+    let second = allocator.allocate(48, 8).unwrap();
+
+    assert!(second == first, "Magazine returned {} instead of object {} freed on the same CPU", second, first);
+    assert!(allocator.depot().allocations == depot_allocations, "Allocation went to depot while magazine had a free object");
+}
+
+#[test]
+pub fn object_freed_on_one_cpu_should_not_be_visible_to_another() {
+    let current_cpu = Rc::new(Cell::new(0));
+    let allocator   = MagazineAllocator::new(CountingAllocator::new(), FakeCpu { current : Rc::clone(&current_cpu) });
+
+    let object = allocator.allocate(64, 8).unwrap();
+    allocator.free(object, 64, 8);
+
+    current_cpu.set(1);
+    let result = allocator.allocate(64, 8).unwrap();
+
+    assert!(result != object, "CPU 1 got object {} cached by CPU 0", object);
+    assert!(allocator.cached_objects(0) == MAGAZINE_SIZE / 2, "CPU 0 caches {} objects, expected {}", allocator.cached_objects(0), MAGAZINE_SIZE / 2);
+}
+
+#[test]
+pub fn empty_magazines_should_be_refilled_from_depot_in_batches() {
+    let allocator = fake_cpu_allocator();
+
+    allocator.allocate(100, 8).unwrap();
+
+    assert!(allocator.depot().allocations == MAGAZINE_SIZE / 2, "Depot handed out {} objects for refill, expected {}", allocator.depot().allocations, MAGAZINE_SIZE / 2);
+    assert!(allocator.cached_objects(0) == MAGAZINE_SIZE / 2 - 1, "CPU caches {} objects after allocation", allocator.cached_objects(0));
+}
+
+#[test]
+pub fn full_magazines_should_be_flushed_to_depot() {
+    let allocator = fake_cpu_allocator();
+    let objects : Vec<usize> = (0 .. MAGAZINE_SIZE * 3).map(|_| allocator.allocate(32, 8).unwrap()).collect();
+
+    for object in objects.iter() {
+        allocator.free(*object, 32, 8);
+    }
+
+    assert!(allocator.cached_objects(0) <= MAGAZINE_SIZE * 2, "CPU caches {} objects, more than two magazines", allocator.cached_objects(0));
+    assert!(allocator.depot().frees >= MAGAZINE_SIZE, "Depot got {} objects back, full magazine wasn't flushed", allocator.depot().frees);
+    assert!(allocator.depot().objects_in_use() == allocator.cached_objects(0), "Depot has {} objects in use, but CPU caches {}", allocator.depot().objects_in_use(), allocator.cached_objects(0));
+}
+
+#[test]
+pub fn drain_should_return_all_cached_objects_to_depot() {
+    let allocator = fake_cpu_allocator();
+
+    let small = allocator.allocate(32, 8).unwrap();
+    let big   = allocator.allocate(1024, 8).unwrap();
+    allocator.free(small, 32, 8);
+    allocator.free(big, 1024, 8);
+
+    let cached  = allocator.cached_objects(0);
+    let drained = allocator.drain();
+
+    assert!(drained == cached, "Drain returned {} objects, but {} were cached", drained, cached);
+    assert!(allocator.depot().objects_in_use() == 0, "Depot has {} objects in use after drain", allocator.depot().objects_in_use());
+}
+
+#[test]
+pub fn big_objects_should_bypass_magazines() {
+    let allocator = fake_cpu_allocator();
+
+    assert!(MagazineAllocator::<CountingAllocator, FakeCpu>::class_for(4096, 8).is_none(), "Object of 4096 bytes has a magazine class");
+    assert!(MagazineAllocator::<CountingAllocator, FakeCpu>::class_for(16, 2048).is_some(), "Object aligned by 2048 bytes has no magazine class");
+
+    let result = allocator.allocate(4096, 8);
+
+    assert!(result.is_none() || allocator.cached_objects(0) == 0, "Big object allocation filled magazines");
+}
+
+#[test]
+pub fn cpus_should_not_share_objects() {
+    let allocator = Arc::new(MagazineAllocator::new(CountingAllocator::new(), ThreadCpu));
+    let threads : Vec<_> = (0 .. MAX_CPUS).map(|cpu| {
+        let allocator = Arc::clone(&allocator);
+
+        thread::spawn(move || {
+            THREAD_CPU_ID.with(|id| id.set(cpu));
+
+            for _ in 0 .. 1000 {
+                let objects : Vec<usize> = (0 .. 3).map(|_| allocator.allocate(64, 8).unwrap()).collect();
+
+                for object in objects.iter() {
+                    unsafe { *(*object as *mut usize) = cpu; }
+                }
+
+                thread::yield_now();
+
+                for object in objects.iter() {
+                    let owner = unsafe { *(*object as *const usize) };
+
+                    assert!(owner == cpu, "Object {} of CPU {} was overwritten by CPU {}", object, cpu, owner);
+
+                    allocator.free(*object, 64, 8);
+                }
+            }
+        })
+    }).collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let cached : usize = (0 .. MAX_CPUS).map(|cpu| allocator.cached_objects(cpu)).sum();
+
+    assert!(allocator.depot().objects_in_use() == cached, "Depot has {} objects in use, but CPUs cache {}", allocator.depot().objects_in_use(), cached);
+}
+
+#[test]
+pub fn cpus_without_free_slot_should_use_depot() {
+    let current_cpu = Rc::new(Cell::new(0));
+    let allocator   = MagazineAllocator::new(CountingAllocator::new(), FakeCpu { current : Rc::clone(&current_cpu) });
+
+    // sparse ids, like local APIC ids
+    for cpu in 0 .. MAX_CPUS {
+        current_cpu.set(cpu * 2 + 100);
+        allocator.allocate(64, 8).unwrap();
+    }
+
+    current_cpu.set(1000);
+
+    let depot_allocations = allocator.depot().allocations;
+    let object = allocator.allocate(64, 8).unwrap();
+
+    assert!(allocator.depot().allocations == depot_allocations + 1, "CPU without magazines took {} objects from depot", allocator.depot().allocations - depot_allocations);
+    assert!(allocator.cached_objects(1000) == 0, "CPU without magazines caches {} objects", allocator.cached_objects(1000));
+    assert!(allocator.cached_objects(100) == MAGAZINE_SIZE / 2 - 1, "CPU with sparse id caches {} objects", allocator.cached_objects(100));
+
+    allocator.free(object, 64, 8);
+
+    assert!(allocator.depot().frees == 1, "Object of CPU without magazines wasn't returned to depot");
+    assert!(allocator.drain() == 0, "CPU without magazines drained objects");
+}
+
+#[test]
+pub fn reallocation_should_move_object_through_magazines_when_size_class_changes() {
+    let allocator = fake_cpu_allocator();
+    let object    = allocator.allocate(40, 8).unwrap();
+
+    unsafe { *(object as *mut u64) = 0x1234; }
+
+    let same_class = unsafe { allocator.reallocate(object, 40, 8, 64, |_| panic!("Small object was resized by depot")) };
+
+    assert!(same_class == Some(object), "Object was moved to {:?} within its size class", same_class);
+
+    let grown = unsafe { allocator.reallocate(object, 64, 8, 1000, |_| panic!("Small object was resized by depot")) }.unwrap();
+
+    assert!(grown != object, "Object stayed at {} after moving to a bigger size class", object);
+    assert!(unsafe { *(grown as *const u64) } == 0x1234, "Object content wasn't copied");
+    assert!(allocator.allocate(40, 8) == Some(object), "Old object wasn't returned to magazine of its size class");
+
+    let shrunk = unsafe { allocator.reallocate(grown, 1000, 8, 40, |_| panic!("Small object was resized by depot")) }.unwrap();
+
+    assert!(shrunk != grown, "Object stayed in 1024 bytes size class after shrinking to 40 bytes");
+    assert!(allocator.allocate(1000, 8) == Some(grown), "Old object wasn't returned to magazine of its size class");
+}
+
+#[test]
+pub fn reallocation_of_big_objects_should_be_done_by_depot() {
+    let allocator = fake_cpu_allocator();
+    let mut depot_called = false;
+
+    let result = unsafe { allocator.reallocate(0x1000, 4096, 8, 8192, |_| { depot_called = true; Some(0x1000) }) };
+
+    assert!(depot_called && result == Some(0x1000), "Big object wasn't resized by depot");
+}
@@ -0,0 +1,152 @@
+use memory::frame::FRAME_SIZE;
+use memory::frame::memory_regions::MemoryRegions;
+use memory::allocator::physical::{Zone, DMA_ZONE_END, DMA32_ZONE_END};
+use multiboot::multiboot_header::MultibootHeader;
+use multiboot::multiboot_header::tags::{elf, memory_map};
+use std::mem;
+
+fn regions_to_vec(regions : &MemoryRegions) -> Vec<(usize, usize)> {
+    regions.iter().map(|r| (r.start_address(), r.end_address())).collect()
+}
+
+#[test]
+pub fn add_should_keep_only_whole_frames() {
+    let mut regions = MemoryRegions::new();
+
+    regions.add(100, FRAME_SIZE * 3 + 10);
+    regions.add(FRAME_SIZE * 10, FRAME_SIZE * 10 + 100); // less than a frame
+
+    let result = regions_to_vec(&regions);
+
+    assert!(result == vec![(FRAME_SIZE, FRAME_SIZE * 3 - 1)], "Unexpected regions {:?}", result);
+}
+
+#[test]
+pub fn exclude_should_split_region() {
+    let mut regions = MemoryRegions::new();
+
+    regions.add(0, FRAME_SIZE * 10 - 1);
+    regions.exclude(FRAME_SIZE * 4 + 1, FRAME_SIZE * 5 + 1);
+
+    let result = regions_to_vec(&regions);
+
+    assert!(result == vec![(0, FRAME_SIZE * 4 - 1), (FRAME_SIZE * 6, FRAME_SIZE * 10 - 1)], "Unexpected regions {:?}", result);
+}
+
+#[test]
+pub fn exclude_should_trim_and_remove_regions() {
+    let mut regions = MemoryRegions::new();
+
+    regions.add(0, FRAME_SIZE * 2 - 1);
+    regions.add(FRAME_SIZE * 3, FRAME_SIZE * 4 - 1);
+    regions.add(FRAME_SIZE * 5, FRAME_SIZE * 8 - 1);
+
+    // touches end of the first region, whole second and start of the third
+    regions.exclude(FRAME_SIZE, FRAME_SIZE * 6 - 1);
+
+    let result = regions_to_vec(&regions);
+
+    assert!(result == vec![(0, FRAME_SIZE - 1), (FRAME_SIZE * 6, FRAME_SIZE * 8 - 1)], "Unexpected regions {:?}", result);
+    assert!(regions.total_memory() == FRAME_SIZE * 3, "Unexpected total memory {}", regions.total_memory());
+}
+
+#[test]
+pub fn from_multiboot_should_exclude_low_memory_kernel_and_modules() {
+    let memory_map_entry_size = mem::size_of::<memory_map::MemoryMapEntry>();
+    let memory_map_size = 4 * mem::size_of::<u32>() + memory_map_entry_size * 2;
+    let module_size = 6 * mem::size_of::<u32>();
+    let elf_section_entry_size = mem::size_of::<elf::ElfSectionHeader>();
+    let elf_size = 5 * mem::size_of::<u32>() + elf_section_entry_size;
+    let multiboot_size = 2 * mem::size_of::<u32>() + memory_map_size + module_size + elf_size + 3 * mem::size_of::<u32>();
+
+    let bytes : [u32; 48] = [
+        multiboot_size as u32,  // multiboot length
+        0,  // multiboot reserved
+
+        6,  // memory map type
+        memory_map_size as u32,
+        memory_map_entry_size as u32,
+        0,  // memory map version
+
+        0,  // [ memory map entry base addr
+        0,  // ]
+        0x9fc00,  // [ memory map entry length
+        0,  // ]
+        1,  // memory map entry type
+        0,  // memory map entry reserved
+
+        0x100000,
+        0,
+        0x1000000, // 16 mb
+        0,
+        1,
+        0,
+
+        3,  // module
+        module_size as u32,
+        0x200000,  // module start
+        0x201000,  // module end
+        0x6d,  // "m"
+        0,
+
+        9,  // elf
+        elf_size as u32,
+        1,  // entries num
+        elf_section_entry_size as u32,
+        0,  // shndx
+
+        1,  // name
+        1,  // section type
+        2,  // [ flags
+        0,  // ]
+        0x100000,  // [ address
+        0,  // ]
+        0,  // [ offset
+        0,  // ]
+        0x5000,  // [ size
+        0,  // ]
+        0,  // link
+        0,  // info
+        0,  // [ address align
+        0,  // ]
+        0,  // [ entry size
+        0,  // ]
+
+        0,  // padding to 8 bytes
+
+        0,  // end tag
+        8,
+        ];
+
+    let multiboot_header = MultibootHeader::load(bytes.as_ptr() as usize);
+
+    let regions = MemoryRegions::from_multiboot(multiboot_header, &[(0x300000, 0x300fff)]);
+    let result  = regions_to_vec(&regions);
+
+    assert!(result == vec![(0x105000, 0x1fffff), (0x201000, 0x2fffff), (0x301000, 0x10fffff)], "Unexpected regions {:?}", result);
+}
+
+#[test]
+pub fn split_at_should_split_only_crossing_region() {
+    let mut regions = MemoryRegions::new();
+
+    regions.add(0, DMA_ZONE_END + FRAME_SIZE * 2 - 1);
+    regions.add(DMA32_ZONE_END, DMA32_ZONE_END + FRAME_SIZE - 1);
+
+    regions.split_at(DMA_ZONE_END);
+    regions.split_at(DMA32_ZONE_END); // region already starts there
+
+    let result = regions_to_vec(&regions);
+
+    assert!(result == vec![(0, DMA_ZONE_END - 1), (DMA_ZONE_END, DMA_ZONE_END + FRAME_SIZE * 2 - 1), (DMA32_ZONE_END, DMA32_ZONE_END + FRAME_SIZE - 1)],
+        "Unexpected regions {:?}", result);
+}
+
+#[test]
+pub fn zone_should_be_determined_by_address() {
+    assert!(Zone::for_address(0) == Zone::Dma, "Address 0 should be in DMA zone");
+    assert!(Zone::for_address(DMA_ZONE_END - 1) == Zone::Dma, "Last address below 16 mb should be in DMA zone");
+    assert!(Zone::for_address(DMA_ZONE_END) == Zone::Dma32, "16 mb should be in DMA32 zone");
+    assert!(Zone::for_address(DMA32_ZONE_END - 1) == Zone::Dma32, "Last address below 4 gb should be in DMA32 zone");
+    assert!(Zone::for_address(DMA32_ZONE_END) == Zone::Normal, "4 gb should be in Normal zone");
+}
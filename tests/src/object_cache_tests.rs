@@ -0,0 +1,259 @@
+use memory::allocator::cache::ObjectCache;
+use memory::allocator::free_list::FreeListAllocator;
+use memory::allocator::bump::ConstSizeBumpAllocator;
+use memory::frame::{Frame, FRAME_SIZE};
+use stdx::math;
+use stdx_memory::{MemoryAllocator, MemoryAllocatorMeta};
+use std::cell::Cell;
+use std::ptr;
+use std::rc::Rc;
+
+const SLABS_COUNT : usize = 4;
+
+// gives frame aligned frames from vector memory
+fn page_source(heap : &Vec<u8>) -> FreeListAllocator {
+    let frames_start = Frame::address_align_up(heap.as_ptr() as usize);
+    let free_list    = frames_start + SLABS_COUNT * FRAME_SIZE;
+
+    FreeListAllocator::from_size_with_separate_free_list(
+        ConstSizeBumpAllocator::from_size(frames_start, SLABS_COUNT * FRAME_SIZE, FRAME_SIZE),
+        free_list)
+}
+
+fn page_source_memory() -> Vec<u8> {
+    vec![0u8; (SLABS_COUNT + 2) * FRAME_SIZE]
+}
+
+// page source that shares the number of slabs it gave out with the test, so that it can be checked after the cache is dropped
+struct TrackingPageSource {
+    inner  : FreeListAllocator,
+    in_use : Rc<Cell<usize>>
+}
+
+impl MemoryAllocatorMeta for TrackingPageSource {
+    fn start_address(&self) -> usize {
+        self.inner.start_address()
+    }
+
+    fn end_address(&self) -> usize {
+        self.inner.end_address()
+    }
+
+    fn aux_data_structures_size(&self) -> usize {
+        self.inner.aux_data_structures_size()
+    }
+}
+
+impl MemoryAllocator for TrackingPageSource {
+    fn allocate(&mut self, size : usize) -> Option<usize> {
+        let result = self.inner.allocate(size);
+
+        if result.is_some() {
+            self.in_use.set(self.in_use.get() + 1);
+        }
+
+        result
+    }
+
+    fn free(&mut self, pointer : usize) {
+        self.in_use.set(self.in_use.get() - 1);
+        self.inner.free(pointer)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Descriptor {
+    id    : u64,
+    state : [u64; 7]
+}
+
+fn new_descriptor() -> Descriptor {
+    Descriptor { id : 42, state : [0; 7] }
+}
+
+static mut DESTROYED : usize = 0;
+
+fn destroy_descriptor(_descriptor : Descriptor) {
+    unsafe { DESTROYED += 1; }
+}
+
+#[test]
+pub fn cache_should_return_distinct_objects_from_one_slab() {
+    let heap      = page_source_memory();
+    let mut cache = ObjectCache::<Descriptor, _>::new("descriptors", None, None, page_source(&heap));
+
+    let first  = cache.allocate().unwrap();
+    let second = cache.allocate().unwrap();
+
+    assert!(first != second, "Cache returned the same object {:?} twice", first);
+    assert!(cache.slabs_count() == 1, "Cache created {} slabs for two objects", cache.slabs_count());
+    assert!(cache.objects_in_use() == 2, "Cache has {} objects in use, expected 2", cache.objects_in_use());
+    assert!(first.as_ptr() as usize % 8 == 0, "Cache object {:?} isn't aligned", first);
+}
+
+#[test]
+pub fn cache_should_reuse_freed_object() {
+    let heap      = page_source_memory();
+    let mut cache = ObjectCache::<Descriptor, _>::new("descriptors", None, None, page_source(&heap));
+
+    let first = cache.allocate_with(new_descriptor()).unwrap();
+    cache.free(first);
+
+    let second = cache.allocate().unwrap();
+
+    assert!(first == second, "Cache returned {:?} instead of freed object {:?}", second, first);
+}
+
+#[test]
+pub fn cache_should_create_new_slab_when_full() {
+    let heap      = page_source_memory();
+    let mut cache = ObjectCache::<Descriptor, _>::new("descriptors", None, None, page_source(&heap));
+
+    for _ in 0..cache.objects_per_slab() + 1 {
+        cache.allocate().unwrap();
+    }
+
+    assert!(cache.slabs_count() == 2, "Cache has {} slabs after first slab got full", cache.slabs_count());
+}
+
+#[test]
+pub fn cache_should_fail_when_page_source_is_exhausted() {
+    let heap      = page_source_memory();
+    let mut cache = ObjectCache::<[u8; 256], _>::new("buffers", None, None, page_source(&heap));
+
+    let capacity = cache.objects_per_slab() * SLABS_COUNT * FRAME_SIZE / cache.slab_size();
+
+    for _ in 0..capacity {
+        cache.allocate().unwrap();
+    }
+
+    assert!(cache.allocate().is_none(), "Cache allocated more than {} objects from {} frames", capacity, SLABS_COUNT);
+}
+
+#[test]
+pub fn constructed_cache_should_keep_objects_constructed() {
+    let heap      = page_source_memory();
+    let mut cache = ObjectCache::new("descriptors", Some(new_descriptor as fn() -> Descriptor), None, page_source(&heap));
+
+    let object = cache.allocate().unwrap();
+
+    assert!(unsafe { ptr::read(object.as_ptr()) } == new_descriptor(), "Cache returned object that wasn't constructed");
+}
+
+#[test]
+pub fn shrink_should_release_only_empty_slabs_and_destroy_objects() {
+    let heap      = page_source_memory();
+    let mut cache = ObjectCache::new("descriptors", Some(new_descriptor as fn() -> Descriptor), Some(destroy_descriptor as fn(Descriptor)), page_source(&heap));
+    let per_slab  = cache.objects_per_slab();
+
+    let objects : Vec<_> = (0..per_slab + 1).map(|_| cache.allocate().unwrap()).collect();
+
+    // empty the slab that was created first, the second one still has an object in use
+    for object in objects.iter().take(per_slab) {
+        cache.free(*object);
+    }
+
+    let released = cache.shrink();
+
+    assert!(released == cache.slab_size(), "Cache released {} bytes, expected one slab of {}", released, cache.slab_size());
+    assert!(cache.slabs_count() == 1, "Cache has {} slabs after shrink, expected 1", cache.slabs_count());
+    assert!(unsafe { DESTROYED } == per_slab, "Destructor was called {} times, expected {}", unsafe { DESTROYED }, per_slab);
+}
+
+#[test]
+#[should_panic]
+pub fn free_should_reject_foreign_object() {
+    let heap      = page_source_memory();
+    let mut cache = ObjectCache::<Descriptor, _>::new("descriptors", None, None, page_source(&heap));
+    let mut foreign = new_descriptor();
+
+    cache.allocate().unwrap();
+    cache.free(ptr::NonNull::from(&mut foreign));
+}
+
+#[test]
+pub fn cache_should_use_slabs_bigger_than_frame_that_are_not_aligned_by_their_size() {
+    let slab_size = FRAME_SIZE * 2;
+    let heap      = vec![0u8; slab_size * 5];
+    let free_list = vec![0u8; FRAME_SIZE];
+    // frame aligned, but not aligned by slab size
+    let slabs_start = math::align_up(heap.as_ptr() as usize, slab_size) + FRAME_SIZE;
+    let page_source = FreeListAllocator::from_size_with_separate_free_list(
+        ConstSizeBumpAllocator::from_size(slabs_start, slab_size * 2, slab_size),
+        free_list.as_ptr() as usize);
+    let mut cache = ObjectCache::<[u8; 600], _>::new("buffers", None, None, page_source);
+
+    assert!(cache.slab_size() == slab_size, "Cache picked slab of {} bytes, expected {}", cache.slab_size(), slab_size);
+
+    let objects : Vec<_> = (0..cache.objects_per_slab() + 1).map(|_| cache.allocate().unwrap()).collect();
+
+    assert!(cache.slabs_count() == 2, "Cache has {} slabs after first slab got full", cache.slabs_count());
+
+    for object in objects.iter() {
+        let address = object.as_ptr() as usize;
+
+        assert!(address >= slabs_start && address + 600 <= slabs_start + slab_size * 2, "Cache object {:#x} is outside of its slabs", address);
+
+        cache.free(*object);
+    }
+
+    assert!(cache.objects_in_use() == 0, "Cache has {} objects in use after all were freed", cache.objects_in_use());
+    assert!(cache.shrink() == slab_size * 2, "Cache didn't release both slabs");
+}
+
+#[test]
+#[should_panic]
+pub fn free_should_detect_double_free_while_slab_has_objects_in_use() {
+    let heap      = page_source_memory();
+    let mut cache = ObjectCache::<Descriptor, _>::new("descriptors", None, None, page_source(&heap));
+
+    let first = cache.allocate().unwrap();
+    cache.allocate().unwrap();
+
+    cache.free(first);
+    cache.free(first);
+}
+
+#[test]
+#[should_panic]
+pub fn free_should_reject_pointer_inside_of_object() {
+    let heap      = page_source_memory();
+    let mut cache = ObjectCache::<Descriptor, _>::new("descriptors", None, None, page_source(&heap));
+
+    let object   = cache.allocate().unwrap();
+    cache.allocate().unwrap();
+    let interior = ptr::NonNull::new((object.as_ptr() as usize + 8) as *mut Descriptor).unwrap();
+
+    cache.free(interior);
+}
+
+#[repr(align(8192))]
+struct OveralignedDescriptor {
+    _id : u64
+}
+
+#[test]
+#[should_panic]
+pub fn cache_should_reject_objects_aligned_above_frame_size() {
+    let heap = page_source_memory();
+
+    ObjectCache::<OveralignedDescriptor, _>::new("overaligned", None, None, page_source(&heap));
+}
+
+#[test]
+pub fn dropped_cache_should_release_all_slabs() {
+    let heap        = page_source_memory();
+    let in_use      = Rc::new(Cell::new(0));
+    let page_source = TrackingPageSource { inner : page_source(&heap), in_use : in_use.clone() };
+    let mut cache   = ObjectCache::new("descriptors", Some(new_descriptor as fn() -> Descriptor), None, page_source);
+    let per_slab    = cache.objects_per_slab();
+
+    let objects : Vec<_> = (0..per_slab + 1).map(|_| cache.allocate().unwrap()).collect();
+    cache.free(objects[0]);
+
+    assert!(in_use.get() == 2, "Page source gave out {} slabs, expected 2", in_use.get());
+
+    drop(cache);
+
+    assert!(in_use.get() == 0, "Dropped cache kept {} slabs", in_use.get());
+}
@@ -0,0 +1,90 @@
+use memory::allocator::oom::{OomHandler, MAX_SHRINKERS};
+use memory::allocator::statistics::AllocatorStatistics;
+use memory::frame::FRAME_SIZE;
+use std::alloc::Layout;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn reclaims_frame() -> usize {
+    FRAME_SIZE
+}
+
+fn reclaims_nothing() -> usize {
+    0
+}
+
+fn failed_allocation() -> (Layout, AllocatorStatistics) {
+    (Layout::from_size_align(FRAME_SIZE * 4, 8).unwrap(), AllocatorStatistics::new(FRAME_SIZE * 16, FRAME_SIZE * 16, FRAME_SIZE * 16))
+}
+
+#[test]
+pub fn shrink_should_sum_memory_released_by_all_shrinkers() {
+    let handler = OomHandler::new();
+
+    handler.register_shrinker(reclaims_frame);
+    handler.register_shrinker(reclaims_nothing);
+    handler.register_shrinker(reclaims_frame);
+
+    assert!(handler.shrink() == FRAME_SIZE * 2, "Shrinkers released {} bytes, expected {}", handler.shrink(), FRAME_SIZE * 2);
+}
+
+#[test]
+pub fn allocation_should_be_retried_if_memory_was_reclaimed() {
+    let handler = OomHandler::new();
+    let (layout, statistics) = failed_allocation();
+
+    handler.register_shrinker(reclaims_frame);
+
+    assert!(handler.handle_allocation_failure(&layout, || statistics), "Out of memory handler didn't retry allocation after memory was reclaimed");
+}
+
+#[test]
+pub fn allocation_should_not_be_retried_if_nothing_was_reclaimed() {
+    let handler = OomHandler::new();
+    let (layout, statistics) = failed_allocation();
+
+    handler.register_shrinker(reclaims_nothing);
+
+    assert!(!handler.handle_allocation_failure(&layout, || statistics), "Out of memory handler retried allocation without reclaimed memory");
+}
+
+#[test]
+pub fn allocation_should_not_be_retried_if_reclaim_is_disabled() {
+    let handler = OomHandler::new();
+    let (layout, statistics) = failed_allocation();
+
+    handler.register_shrinker(reclaims_frame);
+    handler.set_reclaim(false);
+
+    assert!(!handler.handle_allocation_failure(&layout, || statistics), "Out of memory handler called shrinkers while reclaim is disabled");
+}
+
+static REPORTED : AtomicUsize = AtomicUsize::new(0);
+
+fn count_report(_layout : &Layout, _statistics : &AllocatorStatistics) {
+    REPORTED.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+pub fn statistics_should_be_collected_only_for_reporter() {
+    let handler = OomHandler::new();
+    let (layout, statistics) = failed_allocation();
+
+    handler.set_reclaim(false);
+    handler.handle_allocation_failure(&layout, || -> AllocatorStatistics { panic!("Statistics were collected without reporter") });
+
+    handler.set_reporter(count_report);
+    handler.handle_allocation_failure(&layout, || statistics);
+
+    assert!(REPORTED.load(Ordering::SeqCst) == 1, "Reporter was called {} times, expected once", REPORTED.load(Ordering::SeqCst));
+}
+
+#[test]
+pub fn register_shrinker_should_fail_when_registry_is_full() {
+    let handler = OomHandler::new();
+
+    for _ in 0..MAX_SHRINKERS {
+        assert!(handler.register_shrinker(reclaims_nothing), "Shrinker registry is full before {} shrinkers were registered", MAX_SHRINKERS);
+    }
+
+    assert!(!handler.register_shrinker(reclaims_nothing), "Shrinker registry accepted more than {} shrinkers", MAX_SHRINKERS);
+}
@@ -0,0 +1,287 @@
+use memory::frame::Frame;
+use memory::frame::FRAME_SIZE;
+use memory::paging::page_table;
+use memory::paging::page_table::{P4Table, MappingError, HUGE_PAGE_SIZE};
+use memory::paging::physical_memory::PhysicalMemory;
+use memory::allocator::bump::ConstSizeBumpAllocator;
+use stdx_memory::MemoryAllocator;
+
+/// Physical memory emulated by a vector, physical address is an offset from the first frame aligned byte.
+/// P4 table is located at physical address 0.
+struct VecPhysicalMemory {
+    memory : Vec<u8>,
+    base   : usize
+}
+
+impl VecPhysicalMemory {
+    fn new(frames_count : usize) -> Self {
+        // one more frame to be able to align the base
+        let memory = vec![0; (frames_count + 1) * FRAME_SIZE];
+        let base   = Frame::address_align_up(memory.as_ptr() as usize);
+
+        VecPhysicalMemory {
+            memory,
+            base
+        }
+    }
+
+    // frames after p4 table are given to page tables
+    fn frame_allocator(&self) -> ConstSizeBumpAllocator {
+        let frames_count = self.memory.len() / FRAME_SIZE - 1;
+
+        ConstSizeBumpAllocator::from_address(FRAME_SIZE, frames_count * FRAME_SIZE, FRAME_SIZE)
+    }
+}
+
+impl PhysicalMemory for VecPhysicalMemory {
+    fn p4_table_address(&self) -> usize {
+        self.base
+    }
+
+    fn next_table_address(&self, _table_address : usize, _index : usize, next_table_physical_address : usize) -> usize {
+        self.base + next_table_physical_address
+    }
+
+    unsafe fn invalidate(&self, _virtual_address_start : usize, _pages_count : usize) {
+    }
+}
+
+macro_rules! setup {
+    ($frames_count:expr) => {{
+        let memory          = VecPhysicalMemory::new($frames_count);
+        let frame_allocator = memory.frame_allocator();
+        let p4_table : &'static mut P4Table = memory.p4_table();
+
+        (memory, frame_allocator, p4_table)
+    }}
+}
+
+#[test]
+pub fn map_page_should_be_translated_to_frame() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+    let page  = Frame::from_address(0x40_0000);
+    let frame = Frame::from_address(0x1234_5000);
+
+    p4_table.map_page_in(page, frame, page_table::PRESENT | page_table::WRITABLE, &mut frame_allocator, &memory);
+
+    let result = p4_table.translate_page_in(page, &memory);
+
+    assert!(result == Some(frame), "Page {} was translated to {:?}, but expected {}", page.address(), result.map(|f| f.address()), frame.address());
+}
+
+#[test]
+pub fn translate_should_keep_offset_inside_page() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+
+    p4_table.map_page_in(Frame::from_address(0x40_0000), Frame::from_address(0x7000), page_table::PRESENT, &mut frame_allocator, &memory);
+
+    let result = p4_table.translate_in(0x40_0123, &memory);
+
+    assert!(result == Some(0x7123), "Address 0x400123 was translated to {:?}, but expected 0x7123", result);
+}
+
+#[test]
+pub fn translate_should_return_none_for_not_mapped_page() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+
+    p4_table.map_page_in(Frame::from_address(0x40_0000), Frame::from_address(0x7000), page_table::PRESENT, &mut frame_allocator, &memory);
+
+    // same p1 table, different entry
+    assert!(p4_table.translate_in(0x40_1000, &memory).is_none(), "Not mapped page in existing p1 table was translated");
+
+    // no p3 table at all
+    assert!(p4_table.translate_in(0x80_0000_0000, &memory).is_none(), "Not mapped page without page tables was translated");
+}
+
+#[test]
+pub fn unmap_page_should_remove_translation() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+    let page = Frame::from_address(0x40_0000);
+
+    p4_table.map_page_in(page, Frame::from_address(0x7000), page_table::PRESENT, &mut frame_allocator, &memory);
+
+    unsafe { p4_table.unmap_page_in(page, &memory) };
+
+    assert!(p4_table.translate_page_in(page, &memory).is_none(), "Page {} is still translated after unmap", page.address());
+}
+
+#[test]
+pub fn huge_page_2mb_should_be_translated_with_offset() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+    let page  = Frame::from_address(HUGE_PAGE_SIZE * 3);
+    let frame = Frame::from_address(HUGE_PAGE_SIZE * 5);
+
+    let result = p4_table.map_huge_page_in(page, frame, page_table::PRESENT, &mut frame_allocator, &memory);
+
+    assert!(result.is_ok(), "Failed to map huge page: {:?}", result);
+
+    let translated = p4_table.translate_in(HUGE_PAGE_SIZE * 3 + 0x12345, &memory);
+
+    assert!(translated == Some(HUGE_PAGE_SIZE * 5 + 0x12345), "Address inside huge page was translated to {:?}", translated);
+}
+
+#[test]
+pub fn huge_page_1gb_should_be_translated_with_offset() {
+    const GB : usize = HUGE_PAGE_SIZE * 512;
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+    let page = Frame::from_address(GB * 2);
+
+    // there is no api to map 1 gb pages, so entry is set manually
+    let p3 = p4_table.next_table_or_create_in(page, &mut frame_allocator, &memory);
+    p3[2].set_frame(Frame::from_address(GB * 7), page_table::PRESENT | page_table::HUGE_PAGE);
+
+    let translated = p4_table.translate_in(GB * 2 + 0x3456_7890, &memory);
+
+    assert!(translated == Some(GB * 7 + 0x3456_7890), "Address inside 1 gb page was translated to {:?}", translated);
+}
+
+#[test]
+pub fn map_page_inside_huge_page_should_fail() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+
+    p4_table.map_huge_page_in(Frame::from_address(HUGE_PAGE_SIZE), Frame::from_address(0), page_table::PRESENT, &mut frame_allocator, &memory).unwrap();
+
+    let page   = Frame::from_address(HUGE_PAGE_SIZE + FRAME_SIZE);
+    let result = p4_table.try_map_page_in(page, Frame::from_address(0x7000), page_table::PRESENT, &mut frame_allocator, &memory);
+
+    assert!(result == Err(MappingError::HugePage(page.address())), "Page inside huge page was mapped with result {:?}", result);
+}
+
+#[test]
+pub fn unmap_page_should_unmap_whole_huge_page() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+
+    p4_table.map_huge_page_in(Frame::from_address(HUGE_PAGE_SIZE), Frame::from_address(0), page_table::PRESENT, &mut frame_allocator, &memory).unwrap();
+
+    unsafe { p4_table.unmap_page_in(Frame::from_address(HUGE_PAGE_SIZE + FRAME_SIZE), &memory) };
+
+    assert!(p4_table.translate_in(HUGE_PAGE_SIZE, &memory).is_none(), "Huge page is still translated after unmap");
+}
+
+#[test]
+pub fn try_map_page_should_fail_if_no_frames_for_tables() {
+    // p4 only, p3 table can't be allocated
+    let (memory, mut frame_allocator, p4_table) = setup!(1);
+
+    let result = p4_table.try_map_page_in(Frame::from_address(0x40_0000), Frame::from_address(0x7000), page_table::PRESENT, &mut frame_allocator, &memory);
+
+    assert!(result == Err(MappingError::OutOfFrames), "Page was mapped without frames for page tables with result {:?}", result);
+}
+
+#[test]
+pub fn map_range_should_roll_back_on_already_mapped_page() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+
+    p4_table.map_page_in(Frame::from_address(0x40_2000), Frame::from_address(0x9000), page_table::PRESENT, &mut frame_allocator, &memory);
+
+    let frames = (0..4).map(|i| Frame::from_address(0x10_0000 + i * FRAME_SIZE));
+    let result = unsafe { p4_table.map_range_in(0x40_0000, frames, page_table::PRESENT, &mut frame_allocator, &memory) };
+
+    assert!(result == Err(MappingError::AlreadyMapped(0x40_2000)), "Overlapping range was mapped with result {:?}", result);
+    assert!(p4_table.translate_in(0x40_0000, &memory).is_none(), "Page mapped before the failure wasn't unmapped");
+    assert!(p4_table.translate_in(0x40_1000, &memory).is_none(), "Page mapped before the failure wasn't unmapped");
+    assert!(p4_table.translate_in(0x40_2000, &memory) == Some(0x9000), "Existing mapping was changed by failed map_range");
+}
+
+#[test]
+pub fn map_range_should_free_page_tables_created_before_failure() {
+    // p3 and p2 tables fit, p1 table doesn't
+    let (memory, mut frame_allocator, p4_table) = setup!(3);
+
+    let frames = (0..2).map(|i| Frame::from_address(0x10_0000 + i * FRAME_SIZE));
+    let result = unsafe { p4_table.map_range_in(0x40_0000, frames, page_table::PRESENT, &mut frame_allocator, &memory) };
+
+    assert!(result == Err(MappingError::OutOfFrames), "Range was mapped without frames for page tables with result {:?}", result);
+    assert!(!p4_table[0].is_set(), "P3 table created for the failed range is still referenced by p4 table");
+    assert!(frame_allocator.allocate(FRAME_SIZE).is_some() && frame_allocator.allocate(FRAME_SIZE).is_some(), "Frames of page tables created for the failed range weren't freed");
+}
+
+#[test]
+pub fn protect_range_should_change_flags() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+
+    let frames = (0..2).map(|i| Frame::from_address(0x10_0000 + i * FRAME_SIZE));
+    unsafe { p4_table.map_range_in(0x40_0000, frames, page_table::PRESENT, &mut frame_allocator, &memory).unwrap() };
+
+    let result = unsafe { p4_table.protect_range_in(0x40_0000..0x40_2000, page_table::WRITABLE, &memory) };
+
+    assert!(result.is_ok(), "Failed to protect mapped range: {:?}", result);
+
+    for mapping in p4_table.mapped_pages_in(&memory) {
+        assert!(mapping.flags.contains(page_table::PRESENT | page_table::WRITABLE), "Page {:#x} has wrong flags after protect", mapping.virtual_address);
+    }
+}
+
+#[test]
+pub fn mappings_should_merge_consecutive_pages() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+
+    let frames = (0..3).map(|i| Frame::from_address(0x10_0000 + i * FRAME_SIZE));
+    unsafe { p4_table.map_range_in(0x40_0000, frames, page_table::PRESENT, &mut frame_allocator, &memory).unwrap() };
+
+    p4_table.map_page_in(Frame::from_address(0x40_3000), Frame::from_address(0x20_0000), page_table::PRESENT, &mut frame_allocator, &memory);
+
+    let mappings : Vec<_> = p4_table.mappings_in(&memory).map(|m| (m.virtual_address, m.physical_address, m.size)).collect();
+
+    assert!(mappings == vec![(0x40_0000, 0x10_0000, 3 * FRAME_SIZE), (0x40_3000, 0x20_0000, FRAME_SIZE)], "Unexpected mappings {:?}", mappings);
+    assert!(p4_table.total_mapped_memory_in(&memory) == 4 * FRAME_SIZE, "Unexpected total mapped memory");
+}
+
+#[test]
+pub fn map_range_should_map_discontiguous_frames() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+
+    let frames = [Frame::from_address(0x20_0000), Frame::from_address(0x10_0000)];
+    let result = unsafe { p4_table.map_range_in(0x40_0000, frames.iter().cloned(), page_table::PRESENT, &mut frame_allocator, &memory) };
+
+    assert!(result == Ok(2), "Map range failed for discontiguous frames with result {:?}", result);
+    assert!(p4_table.translate_in(0x40_0000, &memory) == Some(0x20_0000), "First page is mapped to wrong frame");
+    assert!(p4_table.translate_in(0x40_1000, &memory) == Some(0x10_0000), "Second page is mapped to wrong frame");
+}
+
+#[test]
+pub fn protect_range_should_fail_for_not_mapped_page() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+
+    p4_table.map_page_in(Frame::from_address(0x40_0000), Frame::from_address(0x10_0000), page_table::PRESENT, &mut frame_allocator, &memory);
+
+    let result = unsafe { p4_table.protect_range_in(0x40_0000..0x40_2000, page_table::WRITABLE, &memory) };
+
+    assert!(result == Err(MappingError::NotMapped(0x40_1000)), "Protect range of not mapped page returned {:?}", result);
+
+    for mapping in p4_table.mapped_pages_in(&memory) {
+        assert!(!mapping.flags.contains(page_table::WRITABLE), "Page {:#x} was changed by failed protect", mapping.virtual_address);
+    }
+}
+
+#[test]
+pub fn unmap_range_should_unmap_all_pages() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+
+    let frames = (0..3).map(|i| Frame::from_address(0x10_0000 + i * FRAME_SIZE));
+    unsafe { p4_table.map_range_in(0x40_0000, frames, page_table::PRESENT, &mut frame_allocator, &memory).unwrap() };
+
+    let result = unsafe { p4_table.unmap_range_in(0x40_0000..0x40_3000, &memory) };
+
+    assert!(result.is_ok(), "Failed to unmap mapped range: {:?}", result);
+    assert!(p4_table.mapped_pages_in(&memory).next().is_none(), "Pages are still mapped after unmap range");
+
+    let result = unsafe { p4_table.unmap_range_in(0x40_0000..0x40_1000, &memory) };
+
+    assert!(result == Err(MappingError::NotMapped(0x40_0000)), "Unmap of not mapped page returned {:?}", result);
+}
+
+#[test]
+pub fn range_operations_should_reject_huge_pages() {
+    let (memory, mut frame_allocator, p4_table) = setup!(8);
+    let page_in_huge_page = HUGE_PAGE_SIZE + FRAME_SIZE;
+
+    p4_table.map_huge_page_in(Frame::from_address(HUGE_PAGE_SIZE), Frame::from_address(0), page_table::PRESENT, &mut frame_allocator, &memory).unwrap();
+
+    let protect_result = unsafe { p4_table.protect_range_in(page_in_huge_page..page_in_huge_page + FRAME_SIZE, page_table::WRITABLE, &memory) };
+    let unmap_result   = unsafe { p4_table.unmap_range_in(page_in_huge_page..page_in_huge_page + FRAME_SIZE, &memory) };
+
+    assert!(protect_result == Err(MappingError::HugePage(page_in_huge_page)), "Protect range inside huge page returned {:?}", protect_result);
+    assert!(unmap_result == Err(MappingError::HugePage(page_in_huge_page)), "Unmap range inside huge page returned {:?}", unmap_result);
+    assert!(p4_table.translate_in(page_in_huge_page, &memory) == Some(FRAME_SIZE), "Huge page was changed by rejected range operations");
+}
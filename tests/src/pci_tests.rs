@@ -0,0 +1,237 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use hardware::x86_64::acpi::{RootTable, Table, EcamRegion};
+use hardware::x86_64::pci::*;
+use hardware::x86_64::pci::capability::{Capability, MsiMessage};
+use hardware::x86_64::pci::config::{ConfigSpace, ABSENT};
+use hardware::x86_64::pci::driver::*;
+
+// configuration space in memory, BARs keep only address bits that are writable in `bar_masks`
+struct FakeConfigSpace {
+    functions : RefCell<HashMap<(u8, u8, u8), [u32; 64]>>,
+    bar_masks : HashMap<(u8, u8, u8, u16), u32>
+}
+
+impl FakeConfigSpace {
+    fn new() -> Self {
+        FakeConfigSpace { functions : RefCell::new(HashMap::new()), bar_masks : HashMap::new() }
+    }
+
+    fn add_function(&mut self, bus : u8, device : u8, function : u8, vendor_id : u16, device_id : u16, class : u32, header_type : u8) {
+        let mut registers = [0u32; 64];
+
+        registers[0] = (device_id as u32) << 16 | vendor_id as u32;
+        registers[2] = class << 8;
+        registers[3] = (header_type as u32) << 16;
+
+        self.functions.borrow_mut().insert((bus, device, function), registers);
+    }
+
+    fn set(&self, address : (u8, u8, u8), offset : u16, value : u32) {
+        self.functions.borrow_mut().get_mut(&address).unwrap()[offset as usize / 4] = value;
+    }
+
+    fn get(&self, address : (u8, u8, u8), offset : u16) -> u32 {
+        self.functions.borrow()[&address][offset as usize / 4]
+    }
+
+    fn add_bar(&mut self, address : (u8, u8, u8), offset : u16, value : u32, mask : u32) {
+        self.set(address, offset, value);
+        self.bar_masks.insert((address.0, address.1, address.2, offset), mask);
+    }
+}
+
+impl ConfigSpace for FakeConfigSpace {
+    fn read(&self, address : PciAddress, offset : u16) -> u32 {
+        self.functions.borrow()
+            .get(&(address.bus, address.device, address.function))
+            .map_or(ABSENT, |registers| registers[offset as usize / 4])
+    }
+
+    fn write(&self, address : PciAddress, offset : u16, value : u32) {
+        let key = (address.bus, address.device, address.function);
+        let old = self.get(key, offset);
+
+        // unimplemented BARs are hardwired to zero
+        let mask = match self.bar_masks.get(&(key.0, key.1, key.2, offset & !3)) {
+            Some(mask)                              => *mask,
+            None if offset >= 0x10 && offset < 0x28 => 0,
+            None                                    => !0
+        };
+
+        let value = value & mask | old & !mask;
+
+        self.set(key, offset, value)
+    }
+
+    fn buses(&self) -> (u8, u8) {
+        (0, 7)
+    }
+}
+
+fn address(bus : u8, device : u8, function : u8) -> PciAddress {
+    PciAddress::new(0, bus, device, function)
+}
+
+#[test]
+pub fn devices_should_skip_functions_of_single_function_devices() {
+    let mut config = FakeConfigSpace::new();
+
+    config.add_function(0, 0, 0, 0x8086, 0x1237, 0x060000, 0);
+    // not reachable, device 0 is not multifunction
+    config.add_function(0, 0, 1, 0x8086, 0x7000, 0x060100, 0);
+    config.add_function(0, 1, 0, 0x8086, 0x7010, 0x010180, 0x80);
+    config.add_function(0, 1, 3, 0x8086, 0x7113, 0x068000, 0);
+    config.add_function(5, 31, 0, 0x1af4, 0x1001, 0x010000, 0);
+
+    let found : Vec<_> = Devices::new(&config, 0).map(|device| device.address).collect();
+
+    assert!(found == vec![address(0, 0, 0), address(0, 1, 0), address(0, 1, 3), address(5, 31, 0)], "Found {:?}", found);
+
+    let device = PciDevice::read(&config, address(0, 1, 0)).unwrap();
+    assert!(device.class == 0x01 && device.subclass == 0x01 && device.prog_if == 0x80 && device.multifunction, "Header was read as {:?}", device);
+}
+
+#[test]
+pub fn bars_should_decode_sizes_and_restore_registers() {
+    let mut config = FakeConfigSpace::new();
+    let function = (0, 3, 0);
+
+    config.add_function(0, 3, 0, 0x1af4, 0x1000, 0x020000, 0);
+    config.set(function, 0x04, COMMAND_IO_SPACE as u32 | COMMAND_MEMORY_SPACE as u32);
+    config.add_bar(function, 0x10, 0xfebf_0000, !0xfff);
+    config.add_bar(function, 0x14, 0xc001, !0x1f);
+    config.add_bar(function, 0x18, 0x0000_000c, !0x3fff);
+    config.add_bar(function, 0x1c, 0x8, !0);
+
+    let device = PciDevice::read(&config, address(0, 3, 0)).unwrap();
+    let bars = device.bars(&config);
+
+    assert!(bars[0] == Some(Bar::Memory { address : 0xfebf_0000, size : 0x1000, prefetchable : false, is_64bit : false }), "BAR0 is {:?}", bars[0]);
+    assert!(bars[1] == Some(Bar::Io { port : 0xc000, size : 0x20 }), "BAR1 is {:?}", bars[1]);
+    assert!(bars[2] == Some(Bar::Memory { address : 0x8_0000_0000, size : 0x4000, prefetchable : true, is_64bit : true }), "BAR2 is {:?}", bars[2]);
+    assert!(bars[3..].iter().all(|bar| bar.is_none()), "Unused BARs are {:?}", &bars[3..]);
+
+    assert!(config.get(function, 0x10) == 0xfebf_0000 && config.get(function, 0x1c) == 0x8, "BARs weren't restored");
+    assert!(device.command(&config) == COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE, "Command wasn't restored");
+}
+
+#[test]
+pub fn capabilities_should_list_msi_and_msi_x_and_enable_msi() {
+    let mut config = FakeConfigSpace::new();
+    let function = (0, 4, 0);
+
+    config.add_function(0, 4, 0, 0x1af4, 0x1041, 0x010000, 0);
+    config.set(function, 0x04, 1 << 20); // capabilities list status bit
+    config.set(function, 0x34, 0x40);
+    config.set(function, 0x40, 0x0080_5005); // MSI, 64 bit
+    config.set(function, 0x50, 0x0000_6009); // vendor specific
+    config.set(function, 0x60, 0x0003_0011); // MSI-X, 4 vectors
+    config.set(function, 0x64, 0x2001);
+    config.set(function, 0x68, 0x3001);
+
+    let device = PciDevice::read(&config, address(0, 4, 0)).unwrap();
+    let capabilities : Vec<_> = device.capabilities(&config).collect();
+
+    assert!(capabilities.len() == 3, "Found capabilities {:?}", capabilities);
+    assert!(capabilities[1] == Capability::VendorSpecific(0x50), "Second capability is {:?}", capabilities[1]);
+
+    match capabilities[2] {
+        Capability::MsiX(msi_x) => assert!(msi_x.table_size() == 4 && msi_x.table_bar() == 1 && msi_x.table_offset() == 0x2000,
+                                           "MSI-X was decoded as {:?}", msi_x),
+        other                   => panic!("Third capability is {:?}", other)
+    }
+
+    match capabilities[0] {
+        Capability::Msi(msi) => {
+            assert!(msi.is_64bit(), "MSI is not 64 bit");
+
+            msi.enable(&config, device.address, MsiMessage::x86(2, 0x40));
+
+            assert!(config.get(function, 0x44) == 0xfee0_2000 && config.get(function, 0x4c) == 0x40, "MSI message wasn't written");
+            assert!(config.get(function, 0x40) >> 16 & 1 == 1, "MSI wasn't enabled");
+        },
+        other => panic!("First capability is {:?}", other)
+    }
+}
+
+struct TestDriver {
+    name : &'static str,
+    ids  : &'static [DeviceId]
+}
+
+impl PciDriver for TestDriver {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn supported_devices(&self) -> &'static [DeviceId] {
+        self.ids
+    }
+
+    fn probe(&self, _device : &PciDevice, _bars : &[Option<Bar>; MAX_BARS], _config : &dyn ConfigSpace) -> Result<(), ProbeError> {
+        Ok(())
+    }
+}
+
+static VIRTIO_IDS : [DeviceId; 1] = [DeviceId::device(0x1af4, 0x1001)];
+static STORAGE_IDS : [DeviceId; 1] = [DeviceId::class(0x01, 0x00)];
+static VIRTIO_DRIVER : TestDriver = TestDriver { name : "virtio", ids : &VIRTIO_IDS };
+static STORAGE_DRIVER : TestDriver = TestDriver { name : "storage", ids : &STORAGE_IDS };
+
+#[test]
+pub fn driver_registry_should_pick_the_first_matching_driver() {
+    let mut config = FakeConfigSpace::new();
+    let mut registry = DriverRegistry::new();
+
+    config.add_function(0, 0, 0, 0x1af4, 0x1001, 0x010000, 0);
+    config.add_function(0, 1, 0, 0x8086, 0x2922, 0x010000, 0);
+    config.add_function(0, 2, 0, 0x8086, 0x100e, 0x020000, 0);
+
+    registry.register(&VIRTIO_DRIVER).unwrap();
+    registry.register(&STORAGE_DRIVER).unwrap();
+
+    assert!(registry.register(&VIRTIO_DRIVER) == Err(RegistryError::AlreadyRegistered("virtio")), "Driver was registered twice");
+
+    let drivers : Vec<_> = Devices::new(&config, 0)
+        .map(|device| registry.driver_for(&device).map(|driver| driver.name()))
+        .collect();
+
+    assert!(drivers == vec![Some("virtio"), Some("storage"), None], "Devices got drivers {:?}", drivers);
+}
+
+// makes the first `length` bytes sum up to zero
+fn with_checksum(mut bytes : Vec<u8>, length : usize, checksum_offset : usize) -> Vec<u8> {
+    let sum = bytes[..length].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+    bytes[checksum_offset] = 0u8.wrapping_sub(sum);
+    bytes
+}
+
+#[test]
+pub fn acpi_should_parse_rsdp_and_mcfg() {
+    let mut rsdp = b"RSD PTR ".to_vec();
+    rsdp.extend_from_slice(&[0, b'B', b'O', b'C', b'H', b'S', b' ', 2]);  // checksum, OEM id, revision
+    rsdp.extend_from_slice(&[0x00, 0x10, 0x00, 0x00]);                    // RSDT
+    rsdp.extend_from_slice(&[36, 0, 0, 0]);                               // length
+    rsdp.extend_from_slice(&[0x00, 0x20, 0x00, 0x00, 0, 0, 0, 0]);        // XSDT
+    rsdp.extend_from_slice(&[0, 0, 0, 0]);                                // extended checksum, reserved
+
+    let rsdp = with_checksum(rsdp, 20, 8);
+    assert!(RootTable::from_rsdp(&rsdp) == Some(RootTable::Rsdt(0x1000)), "RSDP without extended checksum wasn't parsed as RSDT");
+
+    let rsdp = with_checksum(rsdp, 36, 32);
+    assert!(RootTable::from_rsdp(&rsdp) == Some(RootTable::Xsdt(0x2000)), "RSDP wasn't parsed as XSDT");
+
+    let mut mcfg = b"MCFG".to_vec();
+    mcfg.extend_from_slice(&[60, 0, 0, 0]);
+    mcfg.extend_from_slice(&[0; 28]);                                     // rest of the header
+    mcfg.extend_from_slice(&[0; 8]);                                      // reserved
+    mcfg.extend_from_slice(&[0, 0, 0, 0xb0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0, 0, 0, 0]);
+
+    let mcfg = with_checksum(mcfg, 60, 9);
+    let regions : Vec<_> = Table::new(&mcfg).expect("MCFG wasn't parsed").mcfg_regions().collect();
+
+    assert!(regions == vec![EcamRegion { base_address : 0xb000_0000, segment : 0, start_bus : 0, end_bus : 0xff }], "MCFG regions are {:?}", regions);
+    assert!(regions[0].size() == 256 << 20, "Region size is {:#x}", regions[0].size());
+}
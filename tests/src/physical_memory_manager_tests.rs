@@ -0,0 +1,66 @@
+use memory::allocator::buddy::BuddyAllocator;
+use memory::allocator::physical::{PhysicalMemoryManager, Zone};
+use memory::frame::{Frame, FRAME_SIZE};
+use stdx_memory::{MemoryAllocator, MemoryAllocatorMeta};
+
+// pool over vector memory that is already accessible, so no paging is involved
+fn unmapped_pool(total_memory : usize) -> (BuddyAllocator, Vec<u8>) {
+    let aux_size   = BuddyAllocator::aux_data_structures_size_for(total_memory);
+    let heap       = vec![0u8; aux_size + total_memory + FRAME_SIZE];
+    let heap_start = Frame::address_align_up(heap.as_ptr() as usize);
+    let pool       = BuddyAllocator::new_unmapped(heap_start, total_memory, heap_start + aux_size + total_memory - 1);
+
+    (pool, heap)
+}
+
+fn is_in_pool(manager : &PhysicalMemoryManager, pool_index : usize, address : usize) -> bool {
+    let pool = &manager.pools()[pool_index];
+
+    address >= pool.start_address() && address <= pool.end_address()
+}
+
+#[test]
+pub fn physical_memory_manager_should_fall_back_to_dma32_when_normal_is_exhausted() {
+    let (dma32_pool, _dma32_heap)   = unmapped_pool(FRAME_SIZE * 16);
+    let (normal_pool, _normal_heap) = unmapped_pool(FRAME_SIZE * 16);
+    let mut manager = PhysicalMemoryManager::from_pools(vec![(dma32_pool, Zone::Dma32), (normal_pool, Zone::Normal)]);
+
+    assert!(manager.total_memory_in(Zone::Normal) == FRAME_SIZE * 16, "Normal zone has {} bytes", manager.total_memory_in(Zone::Normal));
+
+    for _ in 0 .. 16 {
+        let frame = manager.allocate_frame().expect("Normal zone was exhausted too early");
+
+        assert!(is_in_pool(&manager, 1, frame), "Frame {:#x} came from another zone while Normal had memory", frame);
+    }
+
+    let fallback = manager.allocate_frame().expect("Allocation didn't fall back to DMA32 zone");
+
+    assert!(is_in_pool(&manager, 0, fallback), "Frame {:#x} doesn't belong to DMA32 zone", fallback);
+
+    manager.free(fallback);
+
+    let frame = manager.allocate_frame_in(Zone::Dma32).expect("Freed DMA32 frame wasn't reused");
+
+    assert!(frame == fallback, "DMA32 allocation returned {:#x} instead of freed {:#x}", frame, fallback);
+}
+
+#[test]
+pub fn physical_memory_manager_should_serve_dma_requests_only_from_dma_zone() {
+    let (dma_pool, _dma_heap)       = unmapped_pool(FRAME_SIZE * 16);
+    let (dma32_pool, _dma32_heap)   = unmapped_pool(FRAME_SIZE * 16);
+    let (normal_pool, _normal_heap) = unmapped_pool(FRAME_SIZE * 16);
+    let mut manager = PhysicalMemoryManager::from_pools(vec![(normal_pool, Zone::Normal), (dma32_pool, Zone::Dma32), (dma_pool, Zone::Dma)]);
+
+    let mut frames = Vec::new();
+
+    while let Some(frame) = manager.allocate_frame_in(Zone::Dma) {
+        assert!(is_in_pool(&manager, 2, frame), "DMA request returned frame {:#x} outside of DMA zone", frame);
+
+        frames.push(frame);
+    }
+
+    assert!(frames.len() == 16, "DMA zone gave {} frames out of 16", frames.len());
+    assert!(manager.allocate_in(FRAME_SIZE, Zone::Dma32).map_or(false, |frame| is_in_pool(&manager, 1, frame)),
+            "DMA32 request wasn't served by DMA32 zone");
+    assert!(manager.allocate_frame().map_or(false, |frame| is_in_pool(&manager, 0, frame)), "Normal request wasn't served by Normal zone");
+}
@@ -0,0 +1,40 @@
+use stdx::ring_buffer::{ByteRingBuffer, RING_BUFFER_SIZE};
+
+#[test]
+pub fn pop_should_return_bytes_in_push_order() {
+    let mut buffer = ByteRingBuffer::new();
+
+    buffer.push(1);
+    buffer.push(2);
+    buffer.push(3);
+
+    let popped = [buffer.pop(), buffer.pop(), buffer.pop(), buffer.pop()];
+
+    assert!(popped == [Some(1), Some(2), Some(3), None], "Ring buffer returned {:?}, expected bytes in push order", popped);
+}
+
+#[test]
+pub fn push_should_drop_bytes_when_buffer_is_full() {
+    let mut buffer = ByteRingBuffer::new();
+
+    for i in 0..RING_BUFFER_SIZE {
+        assert!(buffer.push(i as u8), "Byte {} was dropped while buffer had space", i);
+    }
+
+    assert!(!buffer.push(0xff), "Byte was pushed into full buffer");
+    assert!(buffer.len() == RING_BUFFER_SIZE, "Full buffer length is {}, expected {}", buffer.len(), RING_BUFFER_SIZE);
+    assert!(buffer.pop() == Some(0), "Oldest byte was overwritten");
+}
+
+#[test]
+pub fn buffer_should_wrap_around_its_end() {
+    let mut buffer = ByteRingBuffer::new();
+
+    for i in 0..RING_BUFFER_SIZE * 2 + 10 {
+        buffer.push(i as u8);
+
+        assert!(buffer.pop() == Some(i as u8), "Ring buffer lost byte {} after wrapping around", i);
+    }
+
+    assert!(buffer.is_empty(), "Ring buffer isn't empty after all bytes were taken");
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+use stdx::time::{DateTime, MonotonicClock, WallClock};
+use hardware::x86_64::rtc::{RtcRegisters, STATUS_B_24_HOUR, STATUS_B_BINARY};
+
+fn date_time(year : u16, month : u8, day : u8, hour : u8, minute : u8, second : u8) -> DateTime {
+    DateTime { year, month, day, hour, minute, second }
+}
+
+#[test]
+pub fn date_time_should_convert_to_and_from_unix_timestamp() {
+    let dates = [
+        (date_time(1970, 1, 1, 0, 0, 0), 0),
+        (date_time(2000, 2, 29, 12, 30, 15), 951827415),
+        (date_time(2024, 12, 31, 23, 59, 59), 1735689599)
+    ];
+
+    for &(date, timestamp) in dates.iter() {
+        assert!(date.unix_timestamp() == timestamp, "{} converted to {}, expected {}", date, date.unix_timestamp(), timestamp);
+        assert!(DateTime::from_unix_timestamp(timestamp) == date, "{} converted to {}, expected {}", timestamp, DateTime::from_unix_timestamp(timestamp), date);
+    }
+}
+
+#[test]
+pub fn rtc_registers_should_decode_bcd_12_hour_time() {
+    let registers = RtcRegisters {
+        second   : 0x59,
+        minute   : 0x07,
+        hour     : 0x80 | 0x11, // 11 PM
+        day      : 0x31,
+        month    : 0x12,
+        year     : 0x25,
+        century  : 0x20,
+        status_b : 0
+    };
+
+    let result = registers.date_time();
+    assert!(result == Some(date_time(2025, 12, 31, 23, 7, 59)), "Registers were decoded as {:?}", result);
+
+    let midnight = RtcRegisters { hour : 0x12, ..registers }.date_time().map(|date| date.hour);
+    assert!(midnight == Some(0), "12 AM was decoded as {:?}", midnight);
+}
+
+#[test]
+pub fn rtc_registers_should_decode_binary_24_hour_time_and_reject_garbage() {
+    let registers = RtcRegisters {
+        second   : 5,
+        minute   : 45,
+        hour     : 18,
+        day      : 9,
+        month    : 3,
+        year     : 26,
+        century  : 0xff, // no century register
+        status_b : STATUS_B_BINARY | STATUS_B_24_HOUR
+    };
+
+    let result = registers.date_time();
+    assert!(result == Some(date_time(2026, 3, 9, 18, 45, 5)), "Registers were decoded as {:?}", result);
+
+    let invalid = RtcRegisters { month : 13, ..registers }.date_time();
+    assert!(invalid.is_none(), "Invalid month was decoded as {:?}", invalid);
+}
+
+#[test]
+pub fn wall_clock_should_advance_with_monotonic_clock() {
+    let monotonic_clock = MonotonicClock::new();
+    let wall_clock = WallClock::new();
+
+    monotonic_clock.set_tick_length(10_000_000);
+
+    assert!(wall_clock.now(monotonic_clock.uptime()).is_none(), "Clock returned time before synchronization");
+
+    for _ in 0..150 {
+        monotonic_clock.tick();
+    }
+
+    wall_clock.synchronize(date_time(2026, 10, 18, 12, 0, 0), monotonic_clock.uptime());
+
+    for _ in 0..100 {
+        monotonic_clock.tick();
+    }
+
+    assert!(monotonic_clock.uptime() == Duration::from_millis(2500), "Uptime is {:?}", monotonic_clock.uptime());
+
+    let now = wall_clock.now(monotonic_clock.uptime());
+    assert!(now == Some(date_time(2026, 10, 18, 12, 0, 1)), "Wall clock shows {:?}", now);
+}
@@ -0,0 +1,61 @@
+use display::vga::writer::{Writer, BUFFER_HEIGHT, BUFFER_WIDTH};
+use display::vga::color::{Color, ColorVariant};
+use std::fmt::Write;
+
+#[test]
+pub fn sgr_sequences_should_change_colors() {
+    let mut writer = test_writer();
+
+    write!(writer, "a\x1b[31;44mb\x1b[1mc\x1b[0md").unwrap();
+
+    let colors = [writer.character_at(0, 0).color, writer.character_at(0, 1).color, writer.character_at(0, 2).color, writer.character_at(0, 3).color];
+    let expected = [
+        Color::new(ColorVariant::Green, ColorVariant::Black),
+        Color::new(ColorVariant::Red, ColorVariant::Blue),
+        Color::new(ColorVariant::LightRed, ColorVariant::Blue),
+        Color::new(ColorVariant::Green, ColorVariant::Black)
+    ];
+
+    assert!(colors == expected, "Writer used colors {:?}, expected {:?}", colors, expected);
+    assert!(writer.cursor_position() == (0, 4), "Escape sequences were printed, cursor is at {:?}", writer.cursor_position());
+}
+
+#[test]
+pub fn control_characters_should_move_cursor() {
+    let mut writer = test_writer();
+
+    write!(writer, "ab\tc").unwrap();
+    assert!(writer.character_at(0, 8).character_code == b'c', "Tab didn't move cursor to the next tab stop");
+
+    write!(writer, "\x08x").unwrap();
+    assert!(writer.character_at(0, 8).character_code == b'x', "Backspace didn't move cursor back");
+
+    write!(writer, "\ry").unwrap();
+    assert!(writer.character_at(0, 0).character_code == b'y', "Carriage return didn't move cursor to line start");
+    assert!(writer.character_at(0, 1).character_code == b'b', "Carriage return erased the line");
+}
+
+#[test]
+pub fn lines_above_the_screen_should_go_to_scrollback() {
+    let mut writer = test_writer();
+
+    for i in 0..BUFFER_HEIGHT + 2 {
+        write!(writer, "{}\n", i % 10).unwrap();
+    }
+
+    assert!(writer.scrollback_len() == 3, "Scrollback has {} lines, expected 3", writer.scrollback_len());
+    assert!(writer.character_at(0, 0).character_code == b'3', "Screen wasn't scrolled up");
+
+    writer.scroll_up(3);
+    assert!(writer.character_at(0, 0).character_code == b'0', "Scrolled view doesn't show the oldest line");
+
+    write!(writer, "z").unwrap();
+    assert!(writer.character_at(0, 0).character_code == b'3', "New output didn't return view to live screen");
+}
+
+fn test_writer() -> Writer {
+    let buffer = vec![0u16; BUFFER_HEIGHT * BUFFER_WIDTH].into_boxed_slice();
+    let address = Box::into_raw(buffer) as *mut u16 as usize;
+
+    unsafe { Writer::with_buffer(address, false) }
+}
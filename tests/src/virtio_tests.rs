@@ -0,0 +1,314 @@
+use std::ptr;
+use drivers::block::{BlockDevice, BlockError, SECTOR_SIZE};
+use drivers::virtio::*;
+use drivers::virtio::block::*;
+use drivers::virtio::queue::{Buffer, UsedChain, Virtqueue};
+use memory::allocator::buddy::BuddyAllocator;
+use memory::frame::{Frame, FRAME_SIZE};
+use stdx::time::MonotonicClock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// clock that never starts, requests of devices that complete them right away don't time out
+static STOPPED_CLOCK : MonotonicClock = MonotonicClock::new();
+
+// allocator over vector memory that is already accessible, so no paging is involved
+fn unmapped_allocator(total_memory : usize) -> (BuddyAllocator, Vec<u8>) {
+    let aux_size   = BuddyAllocator::aux_data_structures_size_for(total_memory);
+    let heap       = vec![0u8; aux_size + total_memory + FRAME_SIZE];
+    let heap_start = Frame::address_align_up(heap.as_ptr() as usize);
+    let allocator  = BuddyAllocator::new_unmapped(heap_start, total_memory, heap_start + aux_size + total_memory - 1);
+
+    (allocator, heap)
+}
+
+fn read<T>(address : usize) -> T {
+    unsafe { ptr::read_volatile(address as *const T) }
+}
+
+fn write<T>(address : usize, value : T) {
+    unsafe { ptr::write_volatile(address as *mut T, value) }
+}
+
+// (address, length, flags, next) of descriptor
+fn descriptor(queue : &Virtqueue, index : u16) -> (u64, u32, u16, u16) {
+    let address = queue.descriptors_address() + 16 * index as usize;
+
+    (read(address), read(address + 8), read(address + 12), read(address + 14))
+}
+
+#[test]
+pub fn virtqueue_should_chain_buffers_and_free_them_when_used() {
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+    let mut queue = Virtqueue::new(0, 8, &mut allocator).expect("No memory for virtqueue");
+
+    assert!(queue.used_ring_address() % 4096 == 0, "Used ring at {:#x} is not page aligned", queue.used_ring_address());
+
+    let head = queue.add_buffers(&[Buffer::readable(0x1000, 16), Buffer::writable(0x2000, 512), Buffer::writable(0x3000, 1)]);
+
+    assert!(head == Some(0), "Chain head is {:?}", head);
+    assert!(queue.free_descriptors() == 5, "{} descriptors are free after 3 were taken", queue.free_descriptors());
+    assert!(descriptor(&queue, 0) == (0x1000, 16, 1, 1), "Header descriptor is {:?}", descriptor(&queue, 0));
+    assert!(descriptor(&queue, 1) == (0x2000, 512, 3, 2), "Data descriptor is {:?}", descriptor(&queue, 1));
+    assert!(descriptor(&queue, 2).0 == 0x3000 && descriptor(&queue, 2).2 == 2, "Status descriptor is {:?}", descriptor(&queue, 2));
+
+    let available = queue.available_ring_address();
+    assert!(read::<u16>(available + 2) == 1 && read::<u16>(available + 4) == 0, "Chain is not in available ring");
+
+    assert!(queue.pop_used().is_none(), "Chain was used before device returned it");
+
+    // device returns the chain
+    let used = queue.used_ring_address();
+    write::<u32>(used + 4, 0);
+    write::<u32>(used + 8, 513);
+    write::<u16>(used + 2, 1);
+
+    let chain = queue.pop_used();
+
+    assert!(chain == Some(UsedChain { head : 0, length : 513 }), "Popped {:?}", chain);
+    assert!(queue.free_descriptors() == 8, "{} descriptors are free after the chain was used", queue.free_descriptors());
+    assert!(queue.pop_used().is_none(), "Chain was popped twice");
+
+    let buffers = [Buffer::readable(0x1000, 16); 9];
+    assert!(queue.add_buffers(&buffers).is_none(), "Chain longer than the queue was added");
+    assert!(queue.add_buffers(&buffers[..8]) == Some(0), "Freed descriptors weren't reused");
+
+    queue.release(&mut allocator);
+}
+
+// block device that handles requests as soon as it is notified, disk lives in memory
+struct FakeBlockTransport {
+    modern          : bool,
+    features        : u64,
+    driver_features : u64,
+    status          : u8,
+    // descriptors, available ring, used ring and size of the request queue
+    queue           : Option<(usize, usize, usize, u16)>,
+    last_available  : u16,
+    // device ignores notifications and never completes requests
+    stalled         : bool,
+    disk            : Vec<u8>
+}
+
+impl FakeBlockTransport {
+    fn new(modern : bool, features : u64, sectors : usize) -> Self {
+        FakeBlockTransport {
+            modern,
+            features,
+            driver_features : 0,
+            status          : 0,
+            queue           : None,
+            last_available  : 0,
+            stalled         : false,
+            disk            : (0 .. sectors * SECTOR_SIZE).map(|byte| (byte / SECTOR_SIZE) as u8).collect()
+        }
+    }
+
+    fn handle_request(&mut self, head : u16, descriptors : usize) -> u32 {
+        let descriptor = |index : u16| {
+            let address = descriptors + 16 * index as usize;
+            (read::<u64>(address) as usize, read::<u32>(address + 8) as usize, read::<u16>(address + 14))
+        };
+
+        let (header, _, data_index)        = descriptor(head);
+        let (data, length, status_index)   = descriptor(data_index);
+        let (status, _, _)                 = descriptor(status_index);
+        let request_type                   = read::<u32>(header);
+        let start                          = read::<u64>(header + 8) as usize * SECTOR_SIZE;
+
+        if start + length > self.disk.len() {
+            write(status, REQUEST_STATUS_IOERR);
+            return 1
+        }
+
+        unsafe {
+            match request_type {
+                REQUEST_IN  => ptr::copy_nonoverlapping(self.disk[start..].as_ptr(), data as *mut u8, length),
+                REQUEST_OUT => ptr::copy_nonoverlapping(data as *const u8, self.disk[start..].as_mut_ptr(), length),
+                _           => ()
+            }
+        }
+
+        write(status, REQUEST_STATUS_OK);
+
+        if request_type == REQUEST_IN { length as u32 + 1 } else { 1 }
+    }
+}
+
+impl Transport for FakeBlockTransport {
+    fn is_modern(&self) -> bool {
+        self.modern
+    }
+
+    fn device_features(&mut self) -> u64 {
+        self.features
+    }
+
+    fn set_driver_features(&mut self, features : u64) {
+        self.driver_features = features;
+    }
+
+    fn status(&mut self) -> u8 {
+        self.status
+    }
+
+    fn set_status(&mut self, status : u8) {
+        // modern device doesn't accept features it doesn't offer
+        let rejected = status & STATUS_FEATURES_OK != 0 && self.driver_features & !self.features != 0;
+
+        self.status = if rejected { status & !STATUS_FEATURES_OK } else { status };
+    }
+
+    fn max_queue_size(&mut self, queue : u16) -> u16 {
+        if queue == 0 { 32 } else { 0 }
+    }
+
+    fn can_resize_queues(&self) -> bool {
+        self.modern
+    }
+
+    fn setup_queue(&mut self, queue : &Virtqueue) -> Result<(), VirtioError> {
+        self.queue = Some((queue.descriptors_address(), queue.available_ring_address(), queue.used_ring_address(), queue.size()));
+
+        Ok(())
+    }
+
+    fn notify(&mut self, _queue : u16) {
+        if self.stalled {
+            return
+        }
+
+        let (descriptors, available, used, size) = self.queue.expect("Device was notified before queue setup");
+
+        while self.last_available != read::<u16>(available + 2) {
+            let head       = read::<u16>(available + 4 + 2 * (self.last_available % size) as usize);
+            let length     = self.handle_request(head, descriptors);
+            let used_index = read::<u16>(used + 2);
+            let element    = used + 4 + 8 * (used_index % size) as usize;
+
+            write(element, head as u32);
+            write(element + 4, length);
+            write(used + 2, used_index.wrapping_add(1));
+
+            self.last_available = self.last_available.wrapping_add(1);
+        }
+    }
+
+    fn read_isr(&mut self) -> u8 {
+        ISR_QUEUE
+    }
+
+    fn config_generation(&mut self) -> u8 {
+        0
+    }
+
+    fn read_config_u8(&mut self, offset : usize) -> u8 {
+        (self.read_config_u32(offset & !3) >> (offset & 3) * 8) as u8
+    }
+
+    fn read_config_u32(&mut self, offset : usize) -> u32 {
+        let capacity = (self.disk.len() / SECTOR_SIZE) as u64;
+
+        match offset {
+            CONFIG_CAPACITY => capacity as u32,
+            4               => (capacity >> 32) as u32,
+            _               => 0
+        }
+    }
+}
+
+#[test]
+pub fn virtio_block_should_read_and_write_sectors_across_requests() {
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+    let transport = FakeBlockTransport::new(true, VIRTIO_F_VERSION_1 | 1 << 9, 64);
+    let mut block = VirtioBlock::new(transport, &mut allocator, Completion::Polling, &STOPPED_CLOCK).expect("Virtio block device wasn't initialized");
+
+    assert!(block.sector_count() == 64 && !block.is_read_only(), "Device has {} sectors, read only {}", block.sector_count(), block.is_read_only());
+    assert!(block.transport().driver_features == VIRTIO_F_VERSION_1, "Driver accepted features {:#x}", block.transport().driver_features);
+    assert!(block.transport().status == STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK | STATUS_DRIVER_OK,
+            "Device status is {:#x}", block.transport().status);
+    assert!(block.transport().queue.map(|queue| queue.3) == Some(16), "Queue wasn't resized");
+
+    // 9 sectors take two requests
+    let written : Vec<u8> = (0 .. 9 * SECTOR_SIZE).map(|byte| (byte % 251) as u8).collect();
+    block.write_sectors(3, &written).expect("Sectors weren't written");
+
+    assert!(&block.transport().disk[3 * SECTOR_SIZE .. 12 * SECTOR_SIZE] == &written[..], "Disk doesn't have written sectors");
+    assert!(block.transport().disk[12 * SECTOR_SIZE] == 12, "Sector after written ones was changed");
+
+    let mut read_back = vec![0u8; 10 * SECTOR_SIZE];
+    block.read_sectors(2, &mut read_back).expect("Sectors weren't read");
+
+    assert!(read_back[..SECTOR_SIZE].iter().all(|byte| *byte == 2), "Sector 2 was read wrong");
+    assert!(&read_back[SECTOR_SIZE..] == &written[..], "Read sectors differ from written ones");
+
+    block.release(&mut allocator);
+}
+
+#[test]
+pub fn virtio_block_should_reject_invalid_requests() {
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+    let transport = FakeBlockTransport::new(false, VIRTIO_BLK_F_RO, 8);
+    let mut block = VirtioBlock::new(transport, &mut allocator, Completion::Polling, &STOPPED_CLOCK).expect("Virtio block device wasn't initialized");
+    let mut buffer = vec![0u8; 2 * SECTOR_SIZE];
+
+    assert!(block.is_read_only(), "Read only feature wasn't negotiated");
+    assert!(block.transport().queue.map(|queue| queue.3) == Some(32), "Legacy queue size wasn't used");
+    assert!(block.write_sectors(0, &buffer) == Err(BlockError::ReadOnly), "Read only device was written");
+    assert!(block.read_sectors(7, &mut buffer) == Err(BlockError::OutOfRange { sector : 7, count : 2 }), "Read past the end of device");
+    assert!(block.read_sectors(0, &mut buffer[..100]) == Err(BlockError::UnalignedBuffer(100)), "Read part of a sector");
+    assert!(block.read_sectors(6, &mut buffer) == Ok(()) && buffer[SECTOR_SIZE] == 7, "Last sectors weren't read");
+
+    block.release(&mut allocator);
+}
+
+#[test]
+pub fn virtio_should_fail_modern_device_without_version_1() {
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+    let mut transport = FakeBlockTransport::new(true, VIRTIO_BLK_F_RO, 8);
+
+    assert!(negotiate_features(&mut transport, VIRTIO_BLK_F_RO) == Err(VirtioError::FeaturesRejected), "Modern device worked without virtio 1.0");
+    assert!(transport.status & STATUS_FAILED != 0, "Device wasn't marked as failed");
+
+    let result = VirtioBlock::new(transport, &mut allocator, Completion::Polling, &STOPPED_CLOCK);
+
+    assert!(result.is_err(), "Block device was created");
+}
+
+#[test]
+pub fn virtio_block_should_fail_request_that_is_not_completed_in_time() {
+    static CLOCK   : MonotonicClock = MonotonicClock::new();
+    static STOPPED : AtomicBool     = AtomicBool::new(false);
+
+    let (mut allocator, _heap) = unmapped_allocator(FRAME_SIZE * 16);
+    let mut transport = FakeBlockTransport::new(true, VIRTIO_F_VERSION_1, 8);
+    transport.stalled = true;
+
+    let mut block  = VirtioBlock::new(transport, &mut allocator, Completion::Polling, &CLOCK).expect("Virtio block device wasn't initialized");
+    let mut buffer = vec![0u8; SECTOR_SIZE];
+
+    // one second ticks, so the timeout takes a few milliseconds of the test
+    CLOCK.set_tick_length(1_000_000_000);
+
+    let timer = thread::spawn(|| {
+        while !STOPPED.load(Ordering::SeqCst) {
+            CLOCK.tick();
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+
+    let first  = block.read_sectors(0, &mut buffer);
+    let ticks  = CLOCK.ticks();
+    let second = block.read_sectors(0, &mut buffer);
+
+    STOPPED.store(true, Ordering::SeqCst);
+    timer.join().unwrap();
+
+    assert!(first == Err(BlockError::Io), "Request that device didn't complete returned {:?}", first);
+    assert!(ticks as u64 >= REQUEST_TIMEOUT_SECONDS, "Request failed after {} ticks, before the timeout", ticks);
+    assert!(second == Err(BlockError::Io), "Device that timed out accepted another request with result {:?}", second);
+    assert!(block.transport().status & STATUS_FAILED != 0, "Device that timed out wasn't marked as failed");
+
+    block.release(&mut allocator);
+}